@@ -22,6 +22,10 @@ fn infer_static(name: &str) -> bool {
     }
 }
 
+/// The Cargo features that are reported by `build_info`, in the order that
+/// they're reported.
+const REPORTED_FEATURES: [&str; 6] = ["cli", "cuda", "gpu-single", "hdf5-static", "hip", "python"];
+
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
 
@@ -35,11 +39,29 @@ fn main() {
     #[cfg(any(feature = "cuda", feature = "hip"))]
     gpu::build_and_link();
 
+    // Record the enabled Cargo features that change what hyperbeam can do, so
+    // that they can be reported at runtime (see src/build_info.rs).
+    let features: Vec<&str> = REPORTED_FEATURES
+        .iter()
+        .copied()
+        .filter(|f| {
+            env::var_os(format!(
+                "CARGO_FEATURE_{}",
+                f.to_uppercase().replace('-', "_")
+            ))
+            .is_some()
+        })
+        .collect();
+    println!("cargo:rustc-env=HYPERBEAM_FEATURES={}", features.join(","));
+
     // Generate a C header for hyperbeam and write it to the include
-    // directory. This routine only need to be done if the ffi module has
+    // directory. This routine only need to be done if an FFI module has
     // changed.
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/ffi.rs");
     println!("cargo:rerun-if-changed=src/fee/ffi/mod.rs");
+    println!("cargo:rerun-if-changed=src/analytic/ffi/mod.rs");
+    println!("cargo:rerun-if-changed=src/build_info.rs");
     // Only do this if we're not on docs.rs (doesn't like writing files outside
     // of OUT_DIR).
     match env::var("DOCS_RS").as_deref() {
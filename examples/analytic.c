@@ -52,12 +52,15 @@ int main(int argc, char *argv[]) {
     double latitude_rad = -0.4660608448386394;
     // Should we normalise the beam response?
     int norm_to_zenith = 1;
+    // Should the Jones matrix be in IAU order (NS dipole first)?
+    int iau_order = 0;
 
     // Calculate the Jones matrix for this direction and pointing. This Jones
     // matrix is on the stack.
     complex double jones[4];
     // hyperbeam expects a pointer to doubles. Casting the pointer works fine.
-    if (analytic_calc_jones(beam, az, za, freq_hz, delays, amps, 16, latitude_rad, norm_to_zenith, (double *)&jones))
+    if (analytic_calc_jones(beam, az, za, freq_hz, delays, amps, 16, &latitude_rad, norm_to_zenith, iau_order,
+                            (double *)&jones))
         handle_hyperbeam_error(__FILE__, __LINE__, "analytic_calc_jones");
 
     printf("The returned Jones matrix:\n");
@@ -72,7 +75,8 @@ int main(int argc, char *argv[]) {
                          1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1};
     // This Jones matrix is on the heap.
     complex double *jones_2 = malloc(4 * sizeof(complex double));
-    if (analytic_calc_jones(beam, az, za, freq_hz, delays, amps_2, 32, latitude_rad, norm_to_zenith, (double *)jones_2))
+    if (analytic_calc_jones(beam, az, za, freq_hz, delays, amps_2, 32, &latitude_rad, norm_to_zenith, iau_order,
+                            (double *)jones_2))
         handle_hyperbeam_error(__FILE__, __LINE__, "analytic_calc_jones");
 
     // The resulting Jones matrix has different elements on the second row,
@@ -100,7 +104,7 @@ int main(int argc, char *argv[]) {
                             1, 1, 1, 1, 1, 1, 1, 1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
                             1, 1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 1};
     complex double *jones_cram = malloc(4 * sizeof(complex double));
-    if (analytic_calc_jones(beam, az, za, freq_hz, delays_cram, amps_cram, 64, latitude_rad, norm_to_zenith,
+    if (analytic_calc_jones(beam, az, za, freq_hz, delays_cram, amps_cram, 64, &latitude_rad, norm_to_zenith, iau_order,
                             (double *)jones_cram))
         handle_hyperbeam_error(__FILE__, __LINE__, "analytic_calc_jones");
     printf("The CRAM Jones matrix:\n");
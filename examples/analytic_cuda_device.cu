@@ -145,6 +145,8 @@ int main(int argc, char *argv[]) {
 
     // Should we normalise the beam response?
     int norm_to_zenith = 1;
+    // Should the Jones matrices be in IAU order (NS dipole first)?
+    int iau_order = 0;
 
     // Now get a new CUDA analytic beam object.
     AnalyticBeamGpu *gpu_beam;
@@ -171,8 +173,8 @@ int main(int argc, char *argv[]) {
     cudaMalloc(&d_jones, num_unique_tiles * num_freqs * num_directions * sizeof(JONES));
     // hyperbeam expects a pointer to our FLOAT macro. Casting the pointer works
     // fine.
-    if (analytic_calc_jones_gpu_device(gpu_beam, num_directions, az, za, num_freqs, freqs_hz, latitude_rad,
-                                       norm_to_zenith, (FLOAT *)d_jones))
+    if (analytic_calc_jones_gpu_device(gpu_beam, num_directions, az, za, num_freqs, freqs_hz, &latitude_rad,
+                                       norm_to_zenith, iau_order, (FLOAT *)d_jones))
         handle_hyperbeam_error(__FILE__, __LINE__, "analytic_calc_jones_gpu_device");
 
     // The beam responses are now on the device. Let's launch our own kernel and
@@ -91,6 +91,8 @@ int main(int argc, char *argv[]) {
     double latitude_rad = -0.4660608448386394;
     // Should we normalise the beam response?
     int norm_to_zenith = 1;
+    // Should the Jones matrices be in IAU order (NS dipole first)?
+    int iau_order = 0;
 
     // Now get a new GPU analytic beam object.
     AnalyticBeamGpu *gpu_beam;
@@ -111,8 +113,8 @@ int main(int argc, char *argv[]) {
     complex FLOAT *jones = malloc(num_unique_tiles * num_freqs * num_azzas * 8 * sizeof(FLOAT));
     // hyperbeam expects a pointer to our FLOAT macro. Casting the pointer works
     // fine.
-    if (analytic_calc_jones_gpu(gpu_beam, num_azzas, az, za, num_freqs, freqs_hz, latitude_rad, norm_to_zenith,
-                                (FLOAT *)jones))
+    if (analytic_calc_jones_gpu(gpu_beam, num_azzas, az, za, num_freqs, freqs_hz, &latitude_rad, norm_to_zenith,
+                                iau_order, (FLOAT *)jones))
         handle_hyperbeam_error(__FILE__, __LINE__, "analytic_calc_jones_gpu");
 
     printf("The first Jones matrix:\n");
@@ -61,13 +61,15 @@ int main(int argc, char *argv[]) {
     double latitude_rad = -0.4660608448386394;
     // Should we normalise the beam response?
     int norm_to_zenith = 1;
+    // Should the Jones matrix be in IAU order (NS dipole first)?
+    int iau_order = 0;
 
     // Calculate the Jones matrices for all directions. Rust will do this in
     // parallel.
     complex double *jones = malloc(num_directions * 4 * sizeof(complex double));
     // hyperbeam expects a pointer to doubles. Casting the pointer works fine.
-    if (analytic_calc_jones_array(beam, num_directions, az, za, freq_hz, delays, amps, 16, latitude_rad, norm_to_zenith,
-                                  (double *)jones))
+    if (analytic_calc_jones_array(beam, num_directions, az, za, freq_hz, delays, amps, 16, &latitude_rad,
+                                  norm_to_zenith, iau_order, (double *)jones))
         handle_hyperbeam_error(__FILE__, __LINE__, "analytic_calc_jones_array");
 
     printf("The first Jones matrix:\n");
@@ -79,8 +81,8 @@ int main(int argc, char *argv[]) {
     double amps_2[32] = {1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
                          1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0};
     complex double *jones_2 = malloc(num_directions * 4 * sizeof(complex double));
-    if (analytic_calc_jones_array(beam, num_directions, az, za, freq_hz, delays, amps_2, 32, latitude_rad,
-                                  norm_to_zenith, (double *)jones_2))
+    if (analytic_calc_jones_array(beam, num_directions, az, za, freq_hz, delays, amps_2, 32, &latitude_rad,
+                                  norm_to_zenith, iau_order, (double *)jones_2))
         handle_hyperbeam_error(__FILE__, __LINE__, "analytic_calc_jones_array");
 
     printf("The first Jones matrix with altered Y amps:\n");
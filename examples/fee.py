@@ -72,3 +72,23 @@ print(jones)
 
 # Supply only mandatory arguments (latitude_rad and iau_order are optional).
 jones = beam.calc_jones(az[0], za[0], freq, delays, amps, norm_to_zenith)
+
+# Directions can have any shape, e.g. that of an image. The Jones matrices
+# can also be returned as 2x2 matrices.
+az_grid, za_grid = np.meshgrid(
+    np.linspace(0, 2 * np.pi, 64), np.linspace(0, 0.9 * np.pi / 2, 32)
+)
+jones = beam.calc_jones_array(
+    az_grid, za_grid, freq, delays, amps, norm_to_zenith, as_matrices=True
+)
+print("Shape of image-shaped Jones matrices: {}".format(jones.shape))
+
+# Multiple frequencies and tiles can be calculated at once on the CPU; the
+# result has the same layout as calc_jones_gpu: (tiles, freqs, *dirs, 4).
+freqs = [150e6, 200e6]
+delays_array = np.array([delays, [1] * 16])
+amps_array = np.ones((2, 16))
+jones = beam.calc_jones_cpu(
+    az_grid, za_grid, freqs, delays_array, amps_array, norm_to_zenith
+)
+print("Shape of CPU tile/freq Jones matrices: {}".format(jones.shape))
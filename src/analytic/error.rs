@@ -21,6 +21,9 @@ pub enum AnalyticBeamError {
     #[error("Got a zenith angle ({za} radians), but this is below the horizon")]
     BelowHorizon { za: f64 },
 
+    #[error("A latitude must be supplied to calculate RTS-style beam responses")]
+    MissingLatitude,
+
     #[cfg(any(feature = "cuda", feature = "hip"))]
     #[error("The number of delays wasn't {expected} (got {rows} tiles with {num_delays} each); each tile's {expected} delays these must correspond to bowties in the M&C order")]
     IncorrectDelaysArrayColLength {
@@ -11,7 +11,10 @@ mod tests;
 use std::slice;
 
 use super::{AnalyticBeam, AnalyticType};
-use crate::ffi::{ffi_error, update_last_error};
+use crate::{
+    ffi::{ffi_error, hb_error_code::*, update_last_error, update_last_error_from},
+    Parallelism,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(any(feature = "cuda", feature = "hip"))] {
@@ -44,10 +47,11 @@ cfg_if::cfg_if! {
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[no_mangle]
 pub unsafe extern "C" fn new_analytic_beam(
@@ -60,8 +64,10 @@ pub unsafe extern "C" fn new_analytic_beam(
         0 => AnalyticType::MwaPb,
         1 => AnalyticType::Rts,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for rts_style".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for rts_style".to_string(),
+            );
         }
     };
     let dipole_height_metres = dipole_height_metres.as_ref().copied();
@@ -106,18 +112,25 @@ pub unsafe extern "C" fn new_analytic_beam(
 /// * `amps` - A pointer to a 16- or 32-element array of dipole gains for an MWA
 ///   tile. The number of elements is indicated by `num_amps`.
 /// * `num_amps` - The number of dipole gains used (either 16 or 32).
-/// * `latitude_rad` - The telescope latitude to use in beam calculations.
+/// * `latitude_rad` - A pointer to the telescope latitude to use in beam
+///   calculations. This must not be null for RTS-style beams; mwa_pb-style
+///   beams don't use it, so it may be null.
 /// * `norm_to_zenith` - A boolean indicating whether the beam response should
 ///   be normalised with respect to zenith.
+/// * `iau_order` - A boolean indicating whether the Jones matrix should be
+///   arranged [NS-NS NS-EW EW-NS EW-EW] (true) or left in the order of the
+///   beam type (false); mwa_pb-style responses have the EW dipole first and
+///   RTS-style responses the NS dipole first.
 /// * `jones` - A pointer to a buffer with at least `8 * sizeof(double)`
 ///   allocated. The Jones matrix beam response is written here.
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[no_mangle]
 pub unsafe extern "C" fn analytic_calc_jones(
@@ -128,20 +141,34 @@ pub unsafe extern "C" fn analytic_calc_jones(
     delays: *const u32,
     amps: *const f64,
     num_amps: u32,
-    latitude_rad: f64,
+    latitude_rad: *const f64,
     norm_to_zenith: u8,
+    iau_order: u8,
     jones: *mut f64,
 ) -> i32 {
     let norm_bool = match norm_to_zenith {
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
+        }
+    };
+    let iau_bool = match iau_order {
+        0 => false,
+        1 => true,
+        _ => {
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
     let beam = &*analytic_beam;
+    let latitude_rad = ffi_error!(beam.get_latitude(latitude_rad.as_ref().copied()));
     let delays_s = slice::from_raw_parts(delays, usize::from(beam.bowties_per_row).pow(2));
     let amps_s = slice::from_raw_parts(amps, num_amps as usize);
 
@@ -155,17 +182,17 @@ pub unsafe extern "C" fn analytic_calc_jones(
         latitude_rad,
         norm_bool,
     ) {
-        Ok(j) => {
+        Ok(mut j) => {
+            if iau_bool {
+                beam.set_jones_order(true, slice::from_mut(&mut j));
+            }
             let jones_buf = slice::from_raw_parts_mut(jones, 8);
             jones_buf[..].copy_from_slice(&[
                 j[0].re, j[0].im, j[1].re, j[1].im, j[2].re, j[2].im, j[3].re, j[3].im,
             ]);
             0
         }
-        Err(e) => {
-            update_last_error(e.to_string());
-            1
-        }
+        Err(e) => update_last_error_from(&e),
     }
 }
 
@@ -199,19 +226,26 @@ pub unsafe extern "C" fn analytic_calc_jones(
 /// * `amps` - A pointer to a 16- or 32-element array of dipole gains for an MWA
 ///   tile. The number of elements is indicated by `num_amps`.
 /// * `num_amps` - The number of dipole gains used (either 16 or 32).
-/// * `latitude_rad` - The telescope latitude to use in beam calculations.
+/// * `latitude_rad` - A pointer to the telescope latitude to use in beam
+///   calculations. This must not be null for RTS-style beams; mwa_pb-style
+///   beams don't use it, so it may be null.
 /// * `norm_to_zenith` - A boolean indicating whether the beam response should
 ///   be normalised with respect to zenith.
+/// * `iau_order` - A boolean indicating whether the Jones matrix should be
+///   arranged [NS-NS NS-EW EW-NS EW-EW] (true) or left in the order of the
+///   beam type (false); mwa_pb-style responses have the EW dipole first and
+///   RTS-style responses the NS dipole first.
 /// * `jones` - A pointer to a buffer with at least `8 * num_azza *
 ///   sizeof(double)` bytes allocated. The Jones matrix beam responses are
 ///   written here.
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[no_mangle]
 pub unsafe extern "C" fn analytic_calc_jones_array(
@@ -223,20 +257,34 @@ pub unsafe extern "C" fn analytic_calc_jones_array(
     delays: *const u32,
     amps: *const f64,
     num_amps: u32,
-    latitude_rad: f64,
+    latitude_rad: *const f64,
     norm_to_zenith: u8,
+    iau_order: u8,
     jones: *mut f64,
 ) -> i32 {
     let norm_bool = match norm_to_zenith {
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
+        }
+    };
+    let iau_bool = match iau_order {
+        0 => false,
+        1 => true,
+        _ => {
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
     let beam = &*analytic_beam;
+    let latitude_rad = ffi_error!(beam.get_latitude(latitude_rad.as_ref().copied()));
     let az = slice::from_raw_parts(az_rad, num_azza as usize);
     let za = slice::from_raw_parts(za_rad, num_azza as usize);
     let delays_s = slice::from_raw_parts(delays, usize::from(beam.bowties_per_row).pow(2));
@@ -253,6 +301,120 @@ pub unsafe extern "C" fn analytic_calc_jones_array(
         norm_bool,
         results_s
     ));
+    if iau_bool {
+        beam.set_jones_order(true, results_s);
+    }
+    0
+}
+
+/// The analytic beam can be used at any frequency, so unlike
+/// `get_fee_beam_freqs`, there are no frequencies to get; `freqs_ptr` is set to
+/// null and `num_freqs` to 0. It exists so that the analytic and FEE beams have
+/// the same interface.
+///
+/// # Arguments
+///
+/// * `analytic_beam` - the pointer to the `AnalyticBeam` struct.
+/// * `freqs_ptr` - a double pointer to the beam frequencies, which is set to
+///   null.
+/// * `num_freqs` - a pointer to a `size_t` whose contents are set to 0.
+///
+#[no_mangle]
+pub unsafe extern "C" fn get_analytic_beam_freqs(
+    _analytic_beam: *mut AnalyticBeam,
+    freqs_ptr: *mut *const u32,
+    num_freqs: &mut usize,
+) {
+    *freqs_ptr = std::ptr::null();
+    *num_freqs = 0;
+}
+
+/// The analytic beam can be used at any frequency, so unlike
+/// `fee_closest_freq`, the given frequency is returned. It exists so that the
+/// analytic and FEE beams have the same interface.
+///
+/// # Arguments
+///
+/// * `analytic_beam` - the pointer to the `AnalyticBeam` struct.
+/// * `freq` - the frequency to match [Hz].
+///
+/// # Returns
+///
+/// * `freq`.
+///
+#[no_mangle]
+pub unsafe extern "C" fn analytic_closest_freq(
+    _analytic_beam: *mut AnalyticBeam,
+    freq: u32,
+) -> u32 {
+    freq
+}
+
+/// Get the configuration of an `AnalyticBeam`, i.e. the values that were used
+/// (or defaulted) when it was created with `new_analytic_beam`.
+///
+/// # Arguments
+///
+/// * `analytic_beam` - the pointer to the `AnalyticBeam` struct.
+/// * `rts_style` - a pointer to an 8-bit unsigned int whose contents are set to
+///   1 if the beam has RTS-style responses, otherwise 0.
+/// * `dipole_height_metres` - a pointer to a `double` whose contents are set to
+///   the dipole height (units of metres).
+/// * `bowties_per_row` - a pointer to an 8-bit unsigned int whose contents are
+///   set to the number of bowties in a single row of a tile.
+///
+#[no_mangle]
+pub unsafe extern "C" fn get_analytic_beam_config(
+    analytic_beam: *mut AnalyticBeam,
+    rts_style: &mut u8,
+    dipole_height_metres: &mut f64,
+    bowties_per_row: &mut u8,
+) {
+    let beam = &*analytic_beam;
+    *rts_style = matches!(beam.get_beam_type(), AnalyticType::Rts).into();
+    *dipole_height_metres = beam.get_dipole_height();
+    *bowties_per_row = beam.get_bowties_per_row();
+}
+
+/// The analytic beam doesn't cache anything, so this does nothing. It exists
+/// so that the analytic and FEE beams have the same interface (see
+/// `fee_empty_cache`).
+///
+/// # Arguments
+///
+/// * `analytic_beam` - the pointer to the `AnalyticBeam` struct.
+///
+#[no_mangle]
+pub unsafe extern "C" fn analytic_empty_cache(analytic_beam: *mut AnalyticBeam) {
+    let beam = &*analytic_beam;
+    beam.empty_cache();
+}
+
+/// Set the number of threads used by an `AnalyticBeam` to calculate beam
+/// responses on the CPU (see `fee_set_num_threads`).
+///
+/// # Arguments
+///
+/// * `analytic_beam` - the pointer to the `AnalyticBeam` struct.
+/// * `num_threads` - 0 to use the global pool of threads, 1 to do all
+///   calculations on a single thread, or any other number to give the beam its
+///   own pool with that many threads.
+///
+/// # Returns
+///
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
+///
+#[no_mangle]
+pub unsafe extern "C" fn analytic_set_num_threads(
+    analytic_beam: *mut AnalyticBeam,
+    num_threads: u32,
+) -> i32 {
+    let beam = &mut *analytic_beam;
+    ffi_error!(beam.set_parallelism(Parallelism::from_num_threads(num_threads as usize)));
     0
 }
 
@@ -291,10 +453,11 @@ pub unsafe extern "C" fn free_analytic_beam(analytic_beam: *mut AnalyticBeam) {
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -331,6 +494,15 @@ pub unsafe extern "C" fn new_gpu_analytic_beam(
 ///   radians)
 /// * `za_rad` - The zenith angle directions to get the beam response (units of
 ///   radians)
+/// * `latitude_rad` - A pointer to the telescope latitude to use in beam
+///   calculations. This must not be null for RTS-style beams; mwa_pb-style
+///   beams don't use it, so it may be null.
+/// * `norm_to_zenith` - A boolean indicating whether the beam responses should
+///   be normalised with respect to zenith.
+/// * `iau_order` - A boolean indicating whether the Jones matrices should be
+///   arranged [NS-NS NS-EW EW-NS EW-EW] (true) or left in the order of the
+///   beam type (false); mwa_pb-style responses have the EW dipole first and
+///   RTS-style responses the NS dipole first.
 /// * `jones` - A pointer to a buffer with at least `num_unique_tiles *
 ///   num_freqs * num_azza * 8 * sizeof(FLOAT)` bytes allocated.
 ///   `FLOAT` is either `float` or `double`, depending on how `hyperbeam` was
@@ -340,10 +512,11 @@ pub unsafe extern "C" fn new_gpu_analytic_beam(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -354,39 +527,59 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu(
     za_rad: *const GpuFloat,
     num_freqs: u32,
     freqs_hz: *const u32,
-    latitude_rad: GpuFloat,
+    latitude_rad: *const f64,
     norm_to_zenith: u8,
+    iau_order: u8,
     jones: *mut GpuFloat,
 ) -> i32 {
     let num_azza_usize = match num_azza.try_into() {
         Ok(n) => n,
         Err(_) => {
-            update_last_error("num_azza couldn't be converted to a usize".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_ARGUMENT,
+                "num_azza couldn't be converted to a usize".to_string(),
+            );
         }
     };
     let num_freqs_usize = match num_freqs.try_into() {
         Ok(n) => n,
         Err(_) => {
-            update_last_error("num_freqs couldn't be converted to a usize".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_ARGUMENT,
+                "num_freqs couldn't be converted to a usize".to_string(),
+            );
         }
     };
     let norm_to_zenith = match norm_to_zenith {
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
+        }
+    };
+    let iau_order = match iau_order {
+        0 => false,
+        1 => true,
+        _ => {
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
     // Turn the pointers into slices and/or arrays.
     let beam = &mut *gpu_analytic_beam;
+    let latitude_rad = ffi_error!(beam
+        .analytic_type
+        .get_latitude(latitude_rad.as_ref().copied()));
     let az = slice::from_raw_parts(az_rad, num_azza_usize);
     let za = slice::from_raw_parts(za_rad, num_azza_usize);
     let freqs = slice::from_raw_parts(freqs_hz, num_freqs_usize);
-    let results = ArrayViewMut3::from_shape_ptr(
+    let mut results = ArrayViewMut3::from_shape_ptr(
         (
             beam.num_unique_tiles as usize,
             num_freqs_usize,
@@ -394,7 +587,18 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu(
         ),
         jones.cast(),
     );
-    ffi_error!(beam.calc_jones_pair_inner(az, za, freqs, latitude_rad, norm_to_zenith, results));
+    ffi_error!(beam.calc_jones_pair_inner(
+        az,
+        za,
+        freqs,
+        latitude_rad as GpuFloat,
+        norm_to_zenith,
+        results.view_mut()
+    ));
+    if iau_order {
+        beam.analytic_type
+            .set_jones_order(true, results.as_slice_mut().expect("is contiguous"));
+    }
     0
 }
 
@@ -410,6 +614,15 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu(
 ///   radians)
 /// * `za_rad` - The zenith angle directions to get the beam response (units of
 ///   radians)
+/// * `latitude_rad` - A pointer to the telescope latitude to use in beam
+///   calculations. This must not be null for RTS-style beams; mwa_pb-style
+///   beams don't use it, so it may be null.
+/// * `norm_to_zenith` - A boolean indicating whether the beam responses should
+///   be normalised with respect to zenith.
+/// * `iau_order` - A boolean indicating whether the Jones matrices should be
+///   arranged [NS-NS NS-EW EW-NS EW-EW] (true) or left in the order of the
+///   beam type (false); mwa_pb-style responses have the EW dipole first and
+///   RTS-style responses the NS dipole first.
 /// * `d_jones` - A pointer to a device buffer with at least `8 *
 ///   num_unique_tiles * num_freqs * num_azza * sizeof(FLOAT)` bytes
 ///   allocated. `FLOAT` is either `float` or `double`, depending on how
@@ -419,10 +632,11 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -433,31 +647,40 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu_device(
     za_rad: *const GpuFloat,
     num_freqs: i32,
     freqs_hz: *const u32,
-    latitude_rad: GpuFloat,
+    latitude_rad: *const f64,
     norm_to_zenith: u8,
+    iau_order: u8,
     d_jones: *mut GpuFloat,
 ) -> i32 {
     let num_azza_usize = if num_azza < 0 {
-        update_last_error("num_azza was less than 0; it must be positive".to_string());
-        return 1;
+        return update_last_error(
+            HB_ERROR_INVALID_ARGUMENT,
+            "num_azza was less than 0; it must be positive".to_string(),
+        );
     } else {
         match num_azza.try_into() {
             Ok(n) => n,
             Err(_) => {
-                update_last_error("num_azza couldn't be converted to a usize".to_string());
-                return 1;
+                return update_last_error(
+                    HB_ERROR_INVALID_ARGUMENT,
+                    "num_azza couldn't be converted to a usize".to_string(),
+                );
             }
         }
     };
     let num_freqs_usize = if num_freqs < 0 {
-        update_last_error("num_freqs was less than 0; it must be positive".to_string());
-        return 1;
+        return update_last_error(
+            HB_ERROR_INVALID_ARGUMENT,
+            "num_freqs was less than 0; it must be positive".to_string(),
+        );
     } else {
         match num_freqs.try_into() {
             Ok(n) => n,
             Err(_) => {
-                update_last_error("num_freqs couldn't be converted to a usize".to_string());
-                return 1;
+                return update_last_error(
+                    HB_ERROR_INVALID_ARGUMENT,
+                    "num_freqs couldn't be converted to a usize".to_string(),
+                );
             }
         }
     };
@@ -465,12 +688,27 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu_device(
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
+        }
+    };
+    let iau_order = match iau_order {
+        0 => false,
+        1 => true,
+        _ => {
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
     let beam = &*gpu_analytic_beam;
+    let latitude_rad = ffi_error!(beam
+        .analytic_type
+        .get_latitude(latitude_rad.as_ref().copied()));
     let az = slice::from_raw_parts(az_rad, num_azza_usize);
     let za = slice::from_raw_parts(za_rad, num_azza_usize);
     let freqs = slice::from_raw_parts(freqs_hz, num_freqs_usize);
@@ -483,8 +721,9 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu_device(
         num_azza,
         d_freqs.get(),
         num_freqs,
-        latitude_rad,
+        latitude_rad as GpuFloat,
         norm_to_zenith,
+        iau_order,
         d_jones.cast()
     ));
     0
@@ -502,6 +741,15 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu_device(
 ///   radians)
 /// * `d_za_rad` - The zenith angle directions to get the beam response (units
 ///   of radians)
+/// * `latitude_rad` - A pointer to the telescope latitude to use in beam
+///   calculations. This must not be null for RTS-style beams; mwa_pb-style
+///   beams don't use it, so it may be null.
+/// * `norm_to_zenith` - A boolean indicating whether the beam responses should
+///   be normalised with respect to zenith.
+/// * `iau_order` - A boolean indicating whether the Jones matrices should be
+///   arranged [NS-NS NS-EW EW-NS EW-EW] (true) or left in the order of the
+///   beam type (false); mwa_pb-style responses have the EW dipole first and
+///   RTS-style responses the NS dipole first.
 /// * `d_jones` - A pointer to a device buffer with at least `8 *
 ///   num_unique_tiles * num_freqs * num_azza * sizeof(FLOAT)` bytes
 ///   allocated. `FLOAT` is either `float` or `double`, depending on how
@@ -511,10 +759,11 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu_device(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -525,36 +774,57 @@ pub unsafe extern "C" fn analytic_calc_jones_gpu_device_inner(
     d_za_rad: *const GpuFloat,
     num_freqs: i32,
     d_freqs_hz: *const u32,
-    latitude_rad: GpuFloat,
+    latitude_rad: *const f64,
     norm_to_zenith: u8,
+    iau_order: u8,
     d_jones: *mut GpuFloat,
 ) -> i32 {
     if num_azza < 0 {
-        update_last_error("num_azza was less than 0; it must be positive".to_string());
-        return 1;
+        return update_last_error(
+            HB_ERROR_INVALID_ARGUMENT,
+            "num_azza was less than 0; it must be positive".to_string(),
+        );
     };
     if num_freqs < 0 {
-        update_last_error("num_freqs was less than 0; it must be positive".to_string());
-        return 1;
+        return update_last_error(
+            HB_ERROR_INVALID_ARGUMENT,
+            "num_freqs was less than 0; it must be positive".to_string(),
+        );
     };
     let norm_to_zenith = match norm_to_zenith {
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
+        }
+    };
+    let iau_order = match iau_order {
+        0 => false,
+        1 => true,
+        _ => {
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
     let beam = &*gpu_analytic_beam;
+    let latitude_rad = ffi_error!(beam
+        .analytic_type
+        .get_latitude(latitude_rad.as_ref().copied()));
     ffi_error!(beam.calc_jones_device_pair_inner(
         d_az_rad,
         d_za_rad,
         num_azza,
         d_freqs_hz,
         num_freqs,
-        latitude_rad,
+        latitude_rad as GpuFloat,
         norm_to_zenith,
+        iau_order,
         d_jones.cast()
     ));
     0
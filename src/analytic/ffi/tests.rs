@@ -15,7 +15,10 @@ use crate::{
     analytic::tests::{
         AnalyticArgsAndExpectation, MWA_PB_1, MWA_PB_2, MWA_PB_3, MWA_PB_4, MWA_PB_5,
     },
-    ffi::{hb_last_error_length, hb_last_error_message},
+    ffi::{
+        hb_error_code, hb_error_details, hb_last_error_code, hb_last_error_details,
+        hb_last_error_length, hb_last_error_message,
+    },
 };
 
 use super::*;
@@ -49,6 +52,139 @@ fn test_ffi_analytic_new() {
     }
 }
 
+#[test]
+fn test_ffi_analytic_config() {
+    unsafe {
+        let dipole_height_metres = 1.5;
+        let bowties_per_row = 8;
+        let mut beam = null_mut();
+        let result = new_analytic_beam(1, &dipole_height_metres, &bowties_per_row, &mut beam);
+        assert_eq!(result, 0);
+
+        let (mut rts_style, mut height, mut bowties) = (0, 0.0, 0);
+        get_analytic_beam_config(beam, &mut rts_style, &mut height, &mut bowties);
+        assert_eq!(rts_style, 1);
+        assert_abs_diff_eq!(height, dipole_height_metres);
+        assert_eq!(bowties, bowties_per_row);
+
+        // This does nothing, but it should be callable.
+        analytic_empty_cache(beam);
+        free_analytic_beam(beam);
+
+        let mut beam = null_mut();
+        let result = new_analytic_beam(0, null(), null(), &mut beam);
+        assert_eq!(result, 0);
+        get_analytic_beam_config(beam, &mut rts_style, &mut height, &mut bowties);
+        assert_eq!(rts_style, 0);
+        assert_eq!(bowties, 4);
+        free_analytic_beam(beam);
+    }
+}
+
+#[test]
+fn test_ffi_analytic_latitude_and_iau_order() {
+    let az_rad = 45.0_f64.to_radians();
+    let za_rad = 10.0_f64.to_radians();
+    let delays = [0; 16];
+    let amps = [1.0; 16];
+    let calc = |beam, latitude_rad: *const f64, iau_order, jones: &mut [f64; 8]| unsafe {
+        analytic_calc_jones(
+            beam,
+            az_rad,
+            za_rad,
+            180_000_000,
+            delays.as_ptr(),
+            amps.as_ptr(),
+            16,
+            latitude_rad,
+            1,
+            iau_order,
+            jones.as_mut_ptr(),
+        )
+    };
+    let swap_rows = |j: [f64; 8]| [j[4], j[5], j[6], j[7], j[0], j[1], j[2], j[3]];
+
+    unsafe {
+        for rts_style in [0, 1] {
+            let mut beam = null_mut();
+            assert_eq!(new_analytic_beam(rts_style, null(), null(), &mut beam), 0);
+            let rust_jones = (*beam)
+                .calc_jones_pair(
+                    az_rad,
+                    za_rad,
+                    180_000_000,
+                    &delays,
+                    &amps,
+                    MWA_LAT_RAD,
+                    true,
+                )
+                .unwrap();
+            let rust_jones = [
+                rust_jones[0].re,
+                rust_jones[0].im,
+                rust_jones[1].re,
+                rust_jones[1].im,
+                rust_jones[2].re,
+                rust_jones[2].im,
+                rust_jones[3].re,
+                rust_jones[3].im,
+            ];
+
+            let mut jones = [0.0; 8];
+            assert_eq!(calc(beam, &MWA_LAT_RAD, 0, &mut jones), 0);
+            let mut jones_iau = [0.0; 8];
+            assert_eq!(calc(beam, &MWA_LAT_RAD, 1, &mut jones_iau), 0);
+            // By default, the responses are in the order of the beam type.
+            assert_eq!(jones, rust_jones);
+            if rts_style == 1 {
+                // RTS-style responses are natively in IAU order.
+                assert_eq!(jones_iau, jones);
+
+                // They also need a latitude.
+                let result = calc(beam, null(), 0, &mut jones);
+                assert_eq!(result, hb_error_code::HB_ERROR_INVALID_ARGUMENT as i32);
+            } else {
+                assert_eq!(jones_iau, swap_rows(jones));
+
+                // mwa_pb-style responses don't use the latitude.
+                let mut jones_no_lat = [0.0; 8];
+                assert_eq!(calc(beam, null(), 0, &mut jones_no_lat), 0);
+                assert_eq!(jones_no_lat, jones);
+            }
+
+            // The array function does the same.
+            let mut jones_array = [0.0; 16];
+            let result = analytic_calc_jones_array(
+                beam,
+                2,
+                [az_rad; 2].as_ptr(),
+                [za_rad; 2].as_ptr(),
+                180_000_000,
+                delays.as_ptr(),
+                amps.as_ptr(),
+                16,
+                &MWA_LAT_RAD,
+                1,
+                1,
+                jones_array.as_mut_ptr(),
+            );
+            assert_eq!(result, 0);
+            assert_eq!(jones_array[..8], jones_iau);
+            assert_eq!(jones_array[8..], jones_iau);
+
+            // Any frequency can be used.
+            let mut freqs_ptr = [0].as_ptr();
+            let mut num_freqs = 1;
+            get_analytic_beam_freqs(beam, &mut freqs_ptr, &mut num_freqs);
+            assert!(freqs_ptr.is_null());
+            assert_eq!(num_freqs, 0);
+            assert_eq!(analytic_closest_freq(beam, 123_456_789), 123_456_789);
+
+            free_analytic_beam(beam);
+        }
+    }
+}
+
 macro_rules! new_beam {
     () => {{
         unsafe {
@@ -81,8 +217,9 @@ macro_rules! test_analytic_calc_jones {
                 delays.as_ptr(),
                 amps.as_ptr(),
                 amps.len() as _,
-                MWA_LAT_RAD,
+                &MWA_LAT_RAD,
                 norm_to_zenith as _,
+                0,
                 jones.as_mut_ptr(),
             );
             assert_eq!(result, 0);
@@ -122,7 +259,8 @@ fn test_calc_jones_32_amps_via_ffi() {
             ]
             .as_ptr(),
             32,
-            MWA_LAT_RAD,
+            &MWA_LAT_RAD,
+            0,
             0,
             jones.as_mut_ptr(),
         );
@@ -159,8 +297,9 @@ macro_rules! test_analytic_calc_jones_array {
                 delays.as_ptr(),
                 amps.as_ptr(),
                 amps.len() as _,
-                MWA_LAT_RAD,
+                &MWA_LAT_RAD,
                 norm_to_zenith as _,
+                0,
                 jones_expected.as_mut_ptr(),
             );
             assert_eq!(result, 0);
@@ -174,8 +313,9 @@ macro_rules! test_analytic_calc_jones_array {
                 delays.as_ptr(),
                 amps.as_ptr(),
                 amps.len() as _,
-                MWA_LAT_RAD,
+                &MWA_LAT_RAD,
                 norm_to_zenith as _,
+                0,
                 jones.as_mut_ptr(),
             );
             assert_eq!(result, 0);
@@ -201,6 +341,22 @@ fn test_calc_jones_array_via_ffi() {
     };
 }
 
+#[test]
+fn test_calc_jones_array_with_num_threads_via_ffi() {
+    let beam = new_beam!();
+    for num_threads in [1, 3, 0] {
+        unsafe {
+            assert_eq!(analytic_set_num_threads(beam, num_threads), 0);
+        }
+        test_analytic_calc_jones_array!(beam, MWA_PB_1);
+        test_analytic_calc_jones_array!(beam, MWA_PB_4);
+    }
+
+    unsafe {
+        free_analytic_beam(beam);
+    };
+}
+
 #[test]
 fn test_calc_jones_array_32_amps_via_ffi() {
     let beam = new_beam!();
@@ -222,7 +378,8 @@ fn test_calc_jones_array_32_amps_via_ffi() {
             ]
             .as_ptr(),
             32,
-            MWA_LAT_RAD,
+            &MWA_LAT_RAD,
+            0,
             0,
             jones.as_mut_ptr(),
         );
@@ -276,8 +433,9 @@ fn test_calc_jones_gpu_via_ffi() {
             za.as_ptr(),
             freqs.len() as u32,
             freqs.as_ptr(),
-            MWA_LAT_RAD as GpuFloat,
+            &MWA_LAT_RAD,
             norm_to_zenith as _,
+            0,
             jones.as_mut_ptr().cast(),
         );
         assert_eq!(result, 0);
@@ -355,8 +513,9 @@ fn test_bool_errors() {
             ]
             .as_ptr(),
             5,
-            MWA_LAT_RAD,
+            &MWA_LAT_RAD,
             2,
+            0,
             jones.as_mut_ptr(),
         );
         assert_ne!(result, 0);
@@ -385,8 +544,9 @@ fn test_bool_errors() {
             ]
             .as_ptr(),
             32,
-            MWA_LAT_RAD,
+            &MWA_LAT_RAD,
             2,
+            0,
             jones.as_mut_ptr(),
         );
         assert_ne!(result, 0);
@@ -415,7 +575,8 @@ fn test_bool_errors() {
             [0; 16].as_ptr(),
             [1.0; 16].as_ptr(),
             10,
-            MWA_LAT_RAD,
+            &MWA_LAT_RAD,
+            0,
             0,
             jones.as_mut_ptr(),
         );
@@ -438,8 +599,9 @@ fn test_bool_errors() {
             [0; 16].as_ptr(),
             [1.0; 16].as_ptr(),
             16,
-            MWA_LAT_RAD,
+            &MWA_LAT_RAD,
             3,
+            0,
             jones.as_mut_ptr(),
         );
         assert_ne!(result, 0);
@@ -455,3 +617,103 @@ fn test_bool_errors() {
         );
     };
 }
+
+#[test]
+fn test_error_codes_are_stable() {
+    // These values are part of the ABI; they must never change.
+    use hb_error_code::*;
+    assert_eq!(HB_PANIC as i32, -1);
+    assert_eq!(HB_SUCCESS as i32, 0);
+    assert_eq!(HB_ERROR_OTHER as i32, 1);
+    assert_eq!(HB_ERROR_INVALID_STRING as i32, 2);
+    assert_eq!(HB_ERROR_INVALID_BOOL as i32, 3);
+    assert_eq!(HB_ERROR_INVALID_AMPS_LENGTH as i32, 4);
+    assert_eq!(HB_ERROR_INVALID_DELAYS_LENGTH as i32, 5);
+    assert_eq!(HB_ERROR_BELOW_HORIZON as i32, 6);
+    assert_eq!(HB_ERROR_BEAM_FILE_MISSING as i32, 7);
+    assert_eq!(HB_ERROR_INVALID_BEAM_FILE as i32, 8);
+    assert_eq!(HB_ERROR_HDF5 as i32, 9);
+    assert_eq!(HB_ERROR_GPU as i32, 10);
+    assert_eq!(HB_ERROR_INVALID_ARGUMENT as i32, 11);
+}
+
+#[test]
+fn test_error_details() {
+    let beam = new_beam!();
+    let mut jones = [0.0; 8];
+    let mut details = hb_error_details::new(hb_error_code::HB_SUCCESS);
+
+    unsafe {
+        // Bad number of amps.
+        let result = analytic_calc_jones(
+            beam,
+            45.0_f64.to_radians(),
+            10.0_f64.to_radians(),
+            51200000,
+            [0; 16].as_ptr(),
+            [1.0; 10].as_ptr(),
+            10,
+            &MWA_LAT_RAD,
+            0,
+            0,
+            jones.as_mut_ptr(),
+        );
+        assert_eq!(result, hb_error_code::HB_ERROR_INVALID_AMPS_LENGTH as i32);
+        assert_eq!(
+            hb_last_error_code(),
+            hb_error_code::HB_ERROR_INVALID_AMPS_LENGTH
+        );
+        assert_eq!(hb_last_error_details(&mut details), 0);
+        assert_eq!(details.code, hb_error_code::HB_ERROR_INVALID_AMPS_LENGTH);
+        assert_eq!(details.got, 10);
+        assert_eq!(details.expected, 16);
+        assert_eq!(details.expected_alt, 32);
+        assert!(details.za_rad.is_nan());
+
+        // Below the horizon.
+        let result = analytic_calc_jones(
+            beam,
+            45.0_f64.to_radians(),
+            100.0_f64.to_radians(),
+            51200000,
+            [0; 16].as_ptr(),
+            [1.0; 16].as_ptr(),
+            16,
+            &MWA_LAT_RAD,
+            0,
+            0,
+            jones.as_mut_ptr(),
+        );
+        assert_eq!(result, hb_error_code::HB_ERROR_BELOW_HORIZON as i32);
+        assert_eq!(hb_last_error_details(&mut details), 0);
+        assert_eq!(details.code, hb_error_code::HB_ERROR_BELOW_HORIZON);
+        assert_abs_diff_eq!(details.za_rad, 100.0_f64.to_radians());
+
+        // Bad bool.
+        let result = analytic_calc_jones(
+            beam,
+            45.0_f64.to_radians(),
+            10.0_f64.to_radians(),
+            51200000,
+            [0; 16].as_ptr(),
+            [1.0; 16].as_ptr(),
+            16,
+            &MWA_LAT_RAD,
+            2,
+            0,
+            jones.as_mut_ptr(),
+        );
+        assert_eq!(result, hb_error_code::HB_ERROR_INVALID_BOOL as i32);
+        // Reading the message doesn't clear the code.
+        let err_len = hb_last_error_length();
+        let err = CString::from_vec_unchecked(vec![1; err_len as usize]);
+        let err_ptr = err.into_raw();
+        hb_last_error_message(err_ptr, err_len);
+        drop(CString::from_raw(err_ptr));
+        assert_eq!(hb_last_error_code(), hb_error_code::HB_ERROR_INVALID_BOOL);
+
+        assert_eq!(hb_last_error_details(null_mut()), -1);
+
+        free_analytic_beam(beam);
+    }
+}
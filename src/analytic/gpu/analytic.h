@@ -12,7 +12,7 @@ const char *gpu_analytic_calc_jones(const ANALYTIC_TYPE at, const FLOAT dipole_h
                                     const FLOAT *d_zas, int num_directions, const unsigned int *d_freqs_hz,
                                     const int num_freqs, const FLOAT *d_delays, const FLOAT *d_amps,
                                     const int num_tiles, const FLOAT latitude_rad, const uint8_t norm_to_zenith,
-                                    const uint8_t bowties_per_row, void *d_results);
+                                    const uint8_t iau_order, const uint8_t bowties_per_row, void *d_results);
 
 #ifdef __cplusplus
 } // extern "C"
@@ -29,7 +29,8 @@ extern "C" {
 __global__ void analytic_kernel(const ANALYTIC_TYPE at, const FLOAT dipole_height_m, const FLOAT *azs, const FLOAT *zas,
                                 const int num_directions, const unsigned int *freqs_hz, const int num_freqs,
                                 const FLOAT *delays, const FLOAT *amps, const int num_tiles, const FLOAT latitude_rad,
-                                const bool norm_to_zenith, const uint8_t bowties_per_row, JONES *results) {
+                                const bool norm_to_zenith, const bool iau_order, const uint8_t bowties_per_row,
+                                JONES *results) {
     for (int i_direction = blockIdx.x * blockDim.x + threadIdx.x; i_direction < num_directions;
          i_direction += gridDim.x * blockDim.x) {
         const FLOAT az = azs[i_direction];
@@ -117,6 +118,17 @@ __global__ void analytic_kernel(const ANALYTIC_TYPE at, const FLOAT dipole_heigh
                     jones.j11.y = 0.0;
                 }
 
+                // mwa_pb-style responses have the E-W dipole first; swap the
+                // rows if IAU order was requested.
+                if (iau_order && at == MWA_PB) {
+                    jones = JONES{
+                        .j00 = jones.j10,
+                        .j01 = jones.j11,
+                        .j10 = jones.j00,
+                        .j11 = jones.j01,
+                    };
+                }
+
                 // Copy the Jones matrix to global memory.
                 results[((num_directions * num_freqs * i_tile) + num_directions * i_freq) + i_direction] = jones;
             }
@@ -128,13 +140,14 @@ extern "C" const char *gpu_analytic_calc_jones(const ANALYTIC_TYPE at, const FLO
                                                const FLOAT *d_zas, int num_directions, const unsigned int *d_freqs_hz,
                                                const int num_freqs, const FLOAT *d_delays, const FLOAT *d_amps,
                                                const int num_tiles, const FLOAT latitude_rad,
-                                               const uint8_t norm_to_zenith, const uint8_t bowties_per_row, void *d_results) {
+                                               const uint8_t norm_to_zenith, const uint8_t iau_order,
+                                               const uint8_t bowties_per_row, void *d_results) {
     dim3 gridDim, blockDim;
     blockDim.x = warpSize;
     gridDim.x = (int)ceil((double)num_directions / (double)blockDim.x);
     analytic_kernel<<<gridDim, blockDim>>>(at, dipole_height_m, d_azs, d_zas, num_directions, d_freqs_hz, num_freqs,
-                                           d_delays, d_amps, num_tiles, latitude_rad, (bool)norm_to_zenith, bowties_per_row,
-                                           (JONES *)d_results);
+                                           d_delays, d_amps, num_tiles, latitude_rad, (bool)norm_to_zenith, (bool)iau_order,
+                                           bowties_per_row, (JONES *)d_results);
 
     gpuError_t error_id;
 #ifdef DEBUG
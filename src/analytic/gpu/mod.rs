@@ -31,7 +31,7 @@ use crate::gpu::{DevicePointer, GpuError, GpuFloat};
 
 /// A GPU beam object ready to calculate beam responses.
 pub struct AnalyticBeamGpu {
-    analytic_type: super::AnalyticType,
+    pub(super) analytic_type: super::AnalyticType,
     dipole_height: GpuFloat,
     bowties_per_row: u8,
 
@@ -174,6 +174,7 @@ impl AnalyticBeamGpu {
                 freqs_hz.len().try_into().expect("much fewer than i32::MAX"),
                 latitude_rad as GpuFloat,
                 norm_to_zenith,
+                false,
                 d_results.get_mut() as *mut std::ffi::c_void,
             )?;
             Ok(d_results)
@@ -212,6 +213,7 @@ impl AnalyticBeamGpu {
                 freqs_hz.len().try_into().expect("much fewer than i32::MAX"),
                 latitude_rad,
                 norm_to_zenith,
+                false,
                 d_results.get_mut() as *mut std::ffi::c_void,
             )?;
             Ok(d_results)
@@ -225,7 +227,10 @@ impl AnalyticBeamGpu {
     /// [`AnalyticBeamGpu::get_num_unique_tiles`]. `d_latitude_rad` is
     /// populated with the array latitude, if the caller wants the parallactic-
     /// angle correction to be applied. If the pointer is null, then no
-    /// correction is applied.
+    /// correction is applied. If `iau_order` is `true`, the Jones matrices are
+    /// arranged with the N-S dipole first (see
+    /// [`AnalyticType::set_jones_order`](super::AnalyticType::set_jones_order)),
+    /// otherwise they're left in the order of the beam type.
     ///
     /// # Safety
     ///
@@ -241,6 +246,7 @@ impl AnalyticBeamGpu {
         num_freqs: i32,
         latitude_rad: GpuFloat,
         norm_to_zenith: bool,
+        iau_order: bool,
         d_results: *mut std::ffi::c_void,
     ) -> Result<(), AnalyticBeamError> {
         // Don't do anything if there aren't any directions.
@@ -266,6 +272,7 @@ impl AnalyticBeamGpu {
             self.num_unique_tiles,
             latitude_rad,
             norm_to_zenith as _,
+            iau_order as _,
             self.bowties_per_row,
             d_results,
         );
@@ -18,10 +18,15 @@ pub use gpu::AnalyticBeamGpu;
 
 use std::f64::consts::{FRAC_PI_2, TAU};
 
-use marlu::{c64, constants::VEL_C, rayon, AzEl, Jones};
-use rayon::prelude::*;
+use marlu::{constants::VEL_C, rayon, AzEl, Jones};
+use num_complex::Complex;
+use num_traits::Float;
+use rayon::{prelude::*, ThreadPoolBuildError};
 
-use crate::constants::{DELAY_STEP, MWA_DPL_SEP};
+use crate::{
+    constants::{DELAY_STEP, MWA_DPL_SEP},
+    parallelism::{Parallelism, ThreadControl},
+};
 
 #[cfg(any(feature = "cuda", feature = "hip"))]
 use ndarray::prelude::*;
@@ -46,6 +51,30 @@ impl AnalyticType {
             AnalyticType::Rts => 0.30,
         }
     }
+
+    /// Get the latitude to use in beam calculations of this analytic beam type
+    /// when one is optional; see [`AnalyticBeam::get_latitude`].
+    pub(crate) fn get_latitude(self, latitude_rad: Option<f64>) -> Result<f64, AnalyticBeamError> {
+        match (latitude_rad, self) {
+            (Some(l), _) => Ok(l),
+            (None, AnalyticType::MwaPb) => Ok(0.0),
+            (None, AnalyticType::Rts) => Err(AnalyticBeamError::MissingLatitude),
+        }
+    }
+
+    /// Arrange beam-response Jones matrices of this analytic beam type so that
+    /// the N-S dipole is first (IAU order; `iau_order` is `true`) or the E-W
+    /// dipole is first (as for FEE beam responses without IAU order). mwa_pb-
+    /// style responses have the E-W dipole first, whereas RTS-style responses
+    /// have the N-S dipole first, so the rows of one or the other are swapped.
+    pub fn set_jones_order<T: Float>(self, iau_order: bool, jones: &mut [Jones<T>]) {
+        let is_iau_order = matches!(self, AnalyticType::Rts);
+        if iau_order != is_iau_order {
+            for j in jones {
+                *j = Jones::from([j[2], j[3], j[0], j[1]]);
+            }
+        }
+    }
 }
 
 /// The main struct to be used for calculating analytic pointings.
@@ -64,6 +93,9 @@ pub struct AnalyticBeam {
     /// the only exception is the CRAM tile, which has 8 bowties per row, for a
     /// total of 64 bowties.
     pub(crate) bowties_per_row: u8,
+
+    /// The threads used for calculations.
+    pub(crate) threads: ThreadControl,
 }
 
 impl Default for AnalyticBeam {
@@ -73,6 +105,7 @@ impl Default for AnalyticBeam {
             dipole_height: beam_type.get_default_dipole_height(),
             beam_type,
             bowties_per_row: 4,
+            threads: ThreadControl::default(),
         }
     }
 }
@@ -92,6 +125,7 @@ impl AnalyticBeam {
             dipole_height: beam_type.get_default_dipole_height(),
             beam_type,
             bowties_per_row: 4,
+            threads: ThreadControl::default(),
         }
     }
 
@@ -115,9 +149,62 @@ impl AnalyticBeam {
             dipole_height: dipole_height_metres,
             beam_type,
             bowties_per_row,
+            threads: ThreadControl::default(),
         }
     }
 
+    /// Get the analytic beam code that this beam emulates.
+    pub fn get_beam_type(&self) -> AnalyticType {
+        self.beam_type
+    }
+
+    /// Get the height of the MWA dipoles used by this beam [metres].
+    pub fn get_dipole_height(&self) -> f64 {
+        self.dipole_height
+    }
+
+    /// Get the number of bowties in a row of a tile used by this beam.
+    pub fn get_bowties_per_row(&self) -> u8 {
+        self.bowties_per_row
+    }
+
+    /// The analytic beam doesn't cache anything, so this does nothing. It
+    /// exists so that the analytic and FEE beams have the same interface (see
+    /// [`crate::fee::FEEBeam::empty_cache`]).
+    pub fn empty_cache(&self) {}
+
+    /// Get the latitude to use in beam calculations when one is optional, as
+    /// it is in the C and Python interfaces. RTS-style responses need the
+    /// latitude, whereas mwa_pb-style responses don't use it.
+    pub(crate) fn get_latitude(&self, latitude_rad: Option<f64>) -> Result<f64, AnalyticBeamError> {
+        self.beam_type.get_latitude(latitude_rad)
+    }
+
+    /// Arrange beam-response Jones matrices from this beam so that the N-S
+    /// dipole is first (IAU order; `iau_order` is `true`) or the E-W dipole is
+    /// first (as for FEE beam responses without IAU order); see
+    /// [`AnalyticType::set_jones_order`].
+    pub fn set_jones_order<T: Float>(&self, iau_order: bool, jones: &mut [Jones<T>]) {
+        self.beam_type.set_jones_order(iau_order, jones)
+    }
+
+    /// Set the threads used to calculate beam responses on the CPU; see
+    /// [`Parallelism`]. By default, rayon's global thread pool is used. An
+    /// error is returned if a new thread pool can't be made.
+    pub fn set_parallelism(
+        &mut self,
+        parallelism: Parallelism,
+    ) -> Result<(), ThreadPoolBuildError> {
+        self.threads = ThreadControl::new(parallelism)?;
+        Ok(())
+    }
+
+    /// Get the threads used to calculate beam responses on the CPU; see
+    /// [`AnalyticBeam::set_parallelism`].
+    pub fn get_parallelism(&self) -> &Parallelism {
+        self.threads.get()
+    }
+
     /// Calculate the beam-response Jones matrix for a given direction, pointing
     /// and latitude.
     ///
@@ -184,49 +271,17 @@ impl AnalyticBeam {
         if za_rad > FRAC_PI_2 {
             return Err(AnalyticBeamError::BelowHorizon { za: za_rad });
         }
-        let num_bowties = usize::from(self.bowties_per_row * self.bowties_per_row);
-        if delays.len() != num_bowties {
-            return Err(AnalyticBeamError::IncorrectDelaysLength {
-                got: delays.len(),
-                expected: num_bowties,
-            });
-        }
-        if amps.len() != num_bowties && amps.len() != num_bowties * 2 {
-            return Err(AnalyticBeamError::IncorrectAmpsLength {
-                got: amps.len(),
-                expected1: num_bowties,
-                expected2: num_bowties * 2,
-            });
-        }
-
-        let amps = fix_amps(amps, delays);
-        let (amps, delays) = if matches!(self.beam_type, AnalyticType::Rts) {
-            reorder_to_rts(&amps, delays)
-        } else {
-            (amps.to_vec(), delay_ints_to_floats(delays))
-        };
-
-        let lambda_m = VEL_C / freq_hz as f64;
-        let (s_lat, c_lat) = latitude_rad.sin_cos();
-        let jones = self.calc_jones_inner(
-            az_rad,
-            za_rad,
-            lambda_m,
-            latitude_rad,
-            s_lat,
-            c_lat,
-            &delays,
-            &amps,
-            norm_to_zenith,
-        );
-        Ok(jones)
+        let prepared = self.prepare(freq_hz, delays, amps, latitude_rad, norm_to_zenith)?;
+        let mut jones = [Jones::default()];
+        prepared.calc_jones_block(&[az_rad], &[za_rad], &mut jones);
+        Ok(jones[0])
     }
 
     /// Calculate the beam-response Jones matrices for many directions
     /// given a pointing and latitude. This is basically a wrapper around
     /// `calc_jones` that efficiently calculates the Jones matrices in
-    /// parallel. The number of parallel threads used can be controlled by
-    /// setting `RAYON_NUM_THREADS`.
+    /// parallel. The threads used can be controlled with
+    /// [`AnalyticBeam::set_parallelism`].
     ///
     /// `delays` and `amps` apply to each dipole in an MWA tile in the M&C
     /// order; see
@@ -297,59 +352,29 @@ impl AnalyticBeam {
                 return Err(AnalyticBeamError::BelowHorizon { za });
             }
         }
-        let num_bowties = usize::from(self.bowties_per_row * self.bowties_per_row);
-        if delays.len() != num_bowties {
-            return Err(AnalyticBeamError::IncorrectDelaysLength {
-                got: delays.len(),
-                expected: num_bowties,
-            });
-        }
-        if amps.len() != num_bowties && amps.len() != num_bowties * 2 {
-            return Err(AnalyticBeamError::IncorrectAmpsLength {
-                got: amps.len(),
-                expected1: num_bowties,
-                expected2: num_bowties * 2,
-            });
-        }
-
-        let amps = fix_amps(amps, delays);
-        let (amps, delays) = if matches!(self.beam_type, AnalyticType::Rts) {
-            reorder_to_rts(&amps, delays)
-        } else {
-            (amps.to_vec(), delay_ints_to_floats(delays))
-        };
-
-        let lambda_m = VEL_C / freq_hz as f64;
-        let (s_lat, c_lat) = latitude_rad.sin_cos();
-        azels
-            .par_iter()
-            .zip(results.par_iter_mut())
-            .try_for_each(|(&azel, result)| {
-                if azel.za() > FRAC_PI_2 {
-                    return Err(AnalyticBeamError::BelowHorizon { za: azel.za() });
-                }
-
-                let j = self.calc_jones_inner(
-                    azel.az,
-                    azel.za(),
-                    lambda_m,
-                    latitude_rad,
-                    s_lat,
-                    c_lat,
-                    &delays,
-                    &amps,
-                    norm_to_zenith,
-                );
-                *result = j;
-
-                Ok(())
-            })
+        let prepared = self.prepare(freq_hz, delays, amps, latitude_rad, norm_to_zenith)?;
+        self.threads.install(|| {
+            azels
+                .par_chunks(LANES)
+                .zip(results.par_chunks_mut(LANES))
+                .for_each(|(azels, results)| {
+                    let mut az_rad = [0.0; LANES];
+                    let mut za_rad = [0.0; LANES];
+                    for ((az, za), azel) in az_rad.iter_mut().zip(za_rad.iter_mut()).zip(azels) {
+                        *az = azel.az;
+                        *za = azel.za();
+                    }
+                    let n = azels.len();
+                    prepared.calc_jones_block(&az_rad[..n], &za_rad[..n], results);
+                })
+        });
+        Ok(())
     }
 
     /// Calculate the beam-response Jones matrices for many directions given a
     /// pointing. This is basically a wrapper around `calc_jones` that
-    /// efficiently calculates the Jones matrices in parallel. The number of
-    /// parallel threads used can be controlled by setting `RAYON_NUM_THREADS`.
+    /// efficiently calculates the Jones matrices in parallel. The threads used
+    /// can be controlled with [`AnalyticBeam::set_parallelism`].
     ///
     /// `delays` and `amps` apply to each dipole in an MWA tile in the M&C
     /// order; see
@@ -375,53 +400,18 @@ impl AnalyticBeam {
         latitude_rad: f64,
         norm_to_zenith: bool,
     ) -> Result<Vec<Jones<f64>>, AnalyticBeamError> {
-        for &za in za_rad {
-            if za > FRAC_PI_2 {
-                return Err(AnalyticBeamError::BelowHorizon { za });
-            }
-        }
-        let num_bowties = usize::from(self.bowties_per_row * self.bowties_per_row);
-        if delays.len() != num_bowties {
-            return Err(AnalyticBeamError::IncorrectDelaysLength {
-                got: delays.len(),
-                expected: num_bowties,
-            });
-        }
-        if amps.len() != num_bowties && amps.len() != num_bowties * 2 {
-            return Err(AnalyticBeamError::IncorrectAmpsLength {
-                got: amps.len(),
-                expected1: num_bowties,
-                expected2: num_bowties * 2,
-            });
-        }
-
-        let amps = fix_amps(amps, delays);
-        let (amps, delays) = if matches!(self.beam_type, AnalyticType::Rts) {
-            reorder_to_rts(&amps, delays)
-        } else {
-            (amps.to_vec(), delay_ints_to_floats(delays))
-        };
-
-        let lambda_m = VEL_C / freq_hz as f64;
-        let (s_lat, c_lat) = latitude_rad.sin_cos();
-        let out = az_rad
-            .par_iter()
-            .zip(za_rad.par_iter())
-            .map(|(&az, &za)| {
-                self.calc_jones_inner(
-                    az,
-                    za,
-                    lambda_m,
-                    latitude_rad,
-                    s_lat,
-                    c_lat,
-                    &delays,
-                    &amps,
-                    norm_to_zenith,
-                )
-            })
-            .collect();
-        Ok(out)
+        let mut results = vec![Jones::default(); az_rad.len().min(za_rad.len())];
+        self.calc_jones_array_pair_inner(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            latitude_rad,
+            norm_to_zenith,
+            &mut results,
+        )?;
+        Ok(results)
     }
 
     /// Calculate the Jones matrices for many directions given a pointing. This
@@ -452,11 +442,121 @@ impl AnalyticBeam {
         norm_to_zenith: bool,
         results: &mut [Jones<f64>],
     ) -> Result<(), AnalyticBeamError> {
+        self.calc_jones_array_pair_generic(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            latitude_rad,
+            norm_to_zenith,
+            results,
+        )
+    }
+
+    /// Calculate the beam-response Jones matrices for many directions given a
+    /// pointing and latitude, with single precision. This is the same as
+    /// `calc_jones_array_pair`, except that the directions and results are
+    /// `f32`, which is faster and needs half the memory. Given the same
+    /// directions, each element of the difference from
+    /// `calc_jones_array_pair` has a magnitude no more than 1e-5 (the results
+    /// are no bigger than 1).
+    #[allow(clippy::too_many_arguments)]
+    pub fn calc_jones_array_pair_f32(
+        &self,
+        az_rad: &[f32],
+        za_rad: &[f32],
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        latitude_rad: f64,
+        norm_to_zenith: bool,
+    ) -> Result<Vec<Jones<f32>>, AnalyticBeamError> {
+        let mut results = vec![Jones::default(); az_rad.len()];
+        self.calc_jones_array_pair_inner_f32(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            latitude_rad,
+            norm_to_zenith,
+            &mut results,
+        )?;
+        Ok(results)
+    }
+
+    /// Calculate the beam-response Jones matrices for many directions given a
+    /// pointing and latitude, with single precision. This is the same as
+    /// `calc_jones_array_pair_f32` but uses pre-allocated memory.
+    #[allow(clippy::too_many_arguments)]
+    pub fn calc_jones_array_pair_inner_f32(
+        &self,
+        az_rad: &[f32],
+        za_rad: &[f32],
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        latitude_rad: f64,
+        norm_to_zenith: bool,
+        results: &mut [Jones<f32>],
+    ) -> Result<(), AnalyticBeamError> {
+        self.calc_jones_array_pair_generic(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            latitude_rad,
+            norm_to_zenith,
+            results,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn calc_jones_array_pair_generic<T: Float + Send + Sync>(
+        &self,
+        az_rad: &[T],
+        za_rad: &[T],
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        latitude_rad: f64,
+        norm_to_zenith: bool,
+        results: &mut [Jones<T>],
+    ) -> Result<(), AnalyticBeamError> {
+        let horizon = T::from(FRAC_PI_2).unwrap();
+        let below_horizon = |za: T| AnalyticBeamError::BelowHorizon {
+            za: za.to_f64().unwrap_or(f64::NAN),
+        };
         for &za in za_rad {
-            if za > FRAC_PI_2 {
-                return Err(AnalyticBeamError::BelowHorizon { za });
+            if za > horizon {
+                return Err(below_horizon(za));
             }
         }
+        let prepared = self.prepare(freq_hz, delays, amps, latitude_rad, norm_to_zenith)?;
+        self.threads.install(|| {
+            az_rad
+                .par_chunks(LANES)
+                .zip(za_rad.par_chunks(LANES))
+                .zip(results.par_chunks_mut(LANES))
+                .for_each(|((az_rad, za_rad), results)| {
+                    prepared.calc_jones_block(az_rad, za_rad, results)
+                })
+        });
+        Ok(())
+    }
+
+    /// Check `delays` and `amps` and get the quantities needed to calculate
+    /// beam responses that don't depend on the direction.
+    fn prepare<T: Float>(
+        &self,
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        latitude_rad: f64,
+        norm_to_zenith: bool,
+    ) -> Result<PreparedPointing<T>, AnalyticBeamError> {
         let num_bowties = usize::from(self.bowties_per_row * self.bowties_per_row);
         if delays.len() != num_bowties {
             return Err(AnalyticBeamError::IncorrectDelaysLength {
@@ -479,141 +579,43 @@ impl AnalyticBeam {
             (amps.to_vec(), delay_ints_to_floats(delays))
         };
 
+        // The code here was derived with the help of primary_beam.py in
+        // mwa_pb, commit 8619797, and Jack's WODEN. The phase of a bowtie is
+        // multiplier * (dip_e * proj_e + dip_n * proj_n - delay), where the
+        // sign of the multiplier depends on the beam type. (The RTS also has a
+        // dip_z * proj_z term, but dip_z is always 0.)
         let lambda_m = VEL_C / freq_hz as f64;
-        let (s_lat, c_lat) = latitude_rad.sin_cos();
-        az_rad
-            .par_iter()
-            .zip(za_rad.par_iter())
-            .zip(results.par_iter_mut())
-            .try_for_each(|((&az, &za), result)| {
-                if za > FRAC_PI_2 {
-                    return Err(AnalyticBeamError::BelowHorizon { za });
-                }
-
-                let j = self.calc_jones_inner(
-                    az,
-                    za,
-                    lambda_m,
-                    latitude_rad,
-                    s_lat,
-                    c_lat,
-                    &delays,
-                    &amps,
-                    norm_to_zenith,
-                );
-                *result = j;
-
-                Ok(())
-            })
-    }
-
-    /// Helper function.
-    // The code here was derived with the help of primary_beam.py in mwa_pb,
-    // commit 8619797, and Jack's WODEN.
-    #[allow(clippy::too_many_arguments)]
-    fn calc_jones_inner(
-        &self,
-        az_rad: f64,
-        za_rad: f64,
-        lambda_m: f64,
-        latitude_rad: f64,
-        sin_latitude: f64,
-        cos_latitude: f64,
-        delays: &[f64],
-        amps: &[f64],
-        norm_to_zenith: bool,
-    ) -> Jones<f64> {
-        // The following logic could probably be significantly cleaned up, but
-        // I'm out of time.
-
-        let (s_az, c_az) = az_rad.sin_cos();
-        let (s_za, c_za) = za_rad.sin_cos();
-
-        let mut jones = match self.beam_type {
-            AnalyticType::MwaPb => Jones::from([
-                c64::new(c_za * s_az, 0.0),
-                c64::new(c_az, 0.0),
-                c64::new(c_za * c_az, 0.0),
-                c64::new(-s_az, 0.0),
-            ]),
-            AnalyticType::Rts => {
-                let hadec = AzEl::from_radians(az_rad, FRAC_PI_2 - za_rad).to_hadec(latitude_rad);
-                let (s_ha, c_ha) = hadec.ha.sin_cos();
-                let (s_dec, c_dec) = hadec.dec.sin_cos();
-
-                Jones::from([
-                    c64::new(cos_latitude * c_dec + sin_latitude * s_dec * c_ha, 0.0),
-                    c64::new(-sin_latitude * s_ha, 0.0),
-                    c64::new(s_dec * s_ha, 0.0),
-                    c64::new(c_ha, 0.0),
-                ])
-            }
+        let multiplier = match self.beam_type {
+            AnalyticType::MwaPb => TAU / lambda_m,
+            AnalyticType::Rts => -TAU / lambda_m,
         };
+        let cast = |v: f64| T::from(v).unwrap();
+        let weights = amps
+            .iter()
+            .zip(delays.iter())
+            .map(|(&amp, &delay)| {
+                let (s_phase, c_phase) = (-multiplier * delay).sin_cos();
+                Complex::new(cast(amp * c_phase), cast(amp * s_phase))
+            })
+            .collect();
 
-        let proj_e = s_za * s_az;
-        let proj_n = s_za * c_az;
-        // The RTS code uses proj_z as below, but dip_z is always set to 0.0, so
-        // we don't actually need proj_z. lmao
-        // let proj_z = c_za;
-
-        let multiplier = -TAU / lambda_m;
-
-        // Loop over each dipole.
-        let mut array_factor = c64::new(0.0, 0.0);
-        for (k, (&delay, &amp)) in delays.iter().zip(amps.iter()).enumerate() {
-            let col = k % usize::from(self.bowties_per_row);
-            let row = k / usize::from(self.bowties_per_row);
-            let (dip_e, dip_n) = match self.beam_type {
-                AnalyticType::MwaPb => (
-                    (col as f64 - 1.5) * MWA_DPL_SEP,
-                    (row as f64 - 1.5) * MWA_DPL_SEP,
-                ),
-                AnalyticType::Rts => (
-                    (row as f64 - 1.5) * MWA_DPL_SEP,
-                    (col as f64 - 1.5) * MWA_DPL_SEP,
-                ),
-            };
-            // let dip_z = 0.0;
-
-            let phase = match self.beam_type {
-                AnalyticType::MwaPb => {
-                    -multiplier
-                        * (dip_e * proj_e
-                         + dip_n * proj_n
-                         // + dip_z * proj_z
-                         - delay)
-                }
-                AnalyticType::Rts => {
-                    multiplier
-                        * (dip_e * proj_e
-                         + dip_n * proj_n
-                         // + dip_z * proj_z
-                         - delay)
-                }
-            };
-            let (s_phase, c_phase) = phase.sin_cos();
-            array_factor += amp * c64::new(c_phase, s_phase);
-        }
-
-        let mut ground_plane = 2.0 * (TAU * self.dipole_height / lambda_m * c_za).sin()
-            / usize::from(self.bowties_per_row).pow(2) as f64;
+        let height_phase = TAU * self.dipole_height / lambda_m;
+        let mut ground_plane_scale = 2.0 / num_bowties as f64;
         if norm_to_zenith {
-            ground_plane /= 2.0 * (TAU * self.dipole_height / lambda_m).sin();
-        }
-
-        jones[0] *= ground_plane * array_factor;
-        jones[1] *= ground_plane * array_factor;
-        jones[2] *= ground_plane * array_factor;
-        jones[3] *= ground_plane * array_factor;
-
-        // The RTS deliberately sets the imaginary parts to 0.
-        if matches!(self.beam_type, AnalyticType::Rts) {
-            for j in jones.iter_mut() {
-                *j = c64::new(j.re, 0.0);
-            }
+            ground_plane_scale /= 2.0 * height_phase.sin();
         }
 
-        jones
+        let (s_lat, c_lat) = latitude_rad.sin_cos();
+        Ok(PreparedPointing {
+            beam_type: self.beam_type,
+            bowties_per_row: usize::from(self.bowties_per_row),
+            weights,
+            phase_per_bowtie: cast(multiplier * MWA_DPL_SEP),
+            height_phase: cast(height_phase),
+            ground_plane_scale: cast(ground_plane_scale),
+            sin_latitude: cast(s_lat),
+            cos_latitude: cast(c_lat),
+        })
     }
 
     /// Prepare a compute-capable GPU device for beam-response computations
@@ -645,6 +647,160 @@ impl AnalyticBeam {
     }
 }
 
+/// The number of directions that are calculated together by
+/// [`PreparedPointing::calc_jones_block`]. The per-bowtie loops are over these
+/// directions, so that the compiler can vectorise them.
+const LANES: usize = 8;
+
+/// The most bowties that can be in a row of a tile (see
+/// [`AnalyticBeam::new_custom`]).
+const MAX_BOWTIES_PER_ROW: usize = 16;
+
+/// The quantities needed to calculate beam responses that depend on the
+/// pointing, frequency and latitude, but not on the direction. Made with
+/// [`AnalyticBeam::prepare`].
+struct PreparedPointing<T> {
+    beam_type: AnalyticType,
+    bowties_per_row: usize,
+    /// For each bowtie (a row of the tile at a time), its amp multiplied by the
+    /// phase factor of its delay.
+    weights: Vec<Complex<T>>,
+    /// Multiplying this by the projection of a direction along a row or column
+    /// of bowties gives the phase difference between neighbouring bowties.
+    phase_per_bowtie: T,
+    /// 2 pi multiplied by the dipole height in wavelengths.
+    height_phase: T,
+    /// The ground-plane response is this multiplied by sin(height_phase *
+    /// cos(za)). This includes the division by the number of bowties and the
+    /// normalisation to zenith.
+    ground_plane_scale: T,
+    sin_latitude: T,
+    cos_latitude: T,
+}
+
+impl<T: Float> PreparedPointing<T> {
+    /// Calculate the beam responses for up to [`LANES`] directions.
+    fn calc_jones_block(&self, az_rad: &[T], za_rad: &[T], results: &mut [Jones<T>]) {
+        // Most tiles have 4 bowties per row; the smaller arrays are noticeably
+        // quicker to set up when there are few directions.
+        if self.bowties_per_row <= 4 {
+            self.calc_jones_block_sized::<4>(az_rad, za_rad, results)
+        } else {
+            self.calc_jones_block_sized::<MAX_BOWTIES_PER_ROW>(az_rad, za_rad, results)
+        }
+    }
+
+    /// [`PreparedPointing::calc_jones_block`] for tiles with no more than
+    /// `N` bowties per row.
+    fn calc_jones_block_sized<const N: usize>(
+        &self,
+        az_rad: &[T],
+        za_rad: &[T],
+        results: &mut [Jones<T>],
+    ) {
+        let n = results.len().min(az_rad.len()).min(za_rad.len());
+        debug_assert!(n <= LANES);
+        let zero = T::zero();
+        let czero = Complex::new(zero, zero);
+
+        // Unused lanes have no factors, and point at zenith.
+        let mut s_az = [zero; LANES];
+        let mut c_az = [T::one(); LANES];
+        let mut s_za = [zero; LANES];
+        let mut c_za = [T::one(); LANES];
+        for l in 0..n {
+            (s_az[l], c_az[l]) = az_rad[l].sin_cos();
+            (s_za[l], c_za[l]) = za_rad[l].sin_cos();
+        }
+
+        // The bowties are on a regular grid, so the phase factor of a bowtie
+        // is the product of the phase factors of its column and row.
+        let bowties_per_row = self.bowties_per_row;
+        let mut col_factors = [[czero; LANES]; N];
+        let mut row_factors = [[czero; LANES]; N];
+        for l in 0..n {
+            let proj_e = s_za[l] * s_az[l];
+            let proj_n = s_za[l] * c_az[l];
+            let (col_proj, row_proj) = match self.beam_type {
+                AnalyticType::MwaPb => (proj_e, proj_n),
+                AnalyticType::Rts => (proj_n, proj_e),
+            };
+            for (proj, factors) in [(col_proj, &mut col_factors), (row_proj, &mut row_factors)] {
+                // The first bowtie of a row or column is 1.5 separations from
+                // the centre of the tile (even with 8 bowties per row, as in
+                // the RTS and mwa_pb).
+                let (s, c) = (proj * self.phase_per_bowtie / T::from(2.0).unwrap()).sin_cos();
+                let half_step = Complex::new(c, s);
+                let step = half_step * half_step;
+                let mut factor = half_step.conj() * half_step.conj() * half_step.conj();
+                for factors in factors.iter_mut().take(bowties_per_row) {
+                    factors[l] = factor;
+                    factor = factor * step;
+                }
+            }
+        }
+
+        let mut array_factor = [czero; LANES];
+        for (weights, row_factors) in self
+            .weights
+            .chunks_exact(bowties_per_row)
+            .zip(row_factors.iter())
+        {
+            let mut row_sum = [czero; LANES];
+            for (&weight, col_factors) in weights.iter().zip(col_factors.iter()) {
+                for (sum, &factor) in row_sum.iter_mut().zip(col_factors.iter()) {
+                    *sum = *sum + factor * weight;
+                }
+            }
+            for ((af, &factor), &sum) in array_factor
+                .iter_mut()
+                .zip(row_factors.iter())
+                .zip(row_sum.iter())
+            {
+                *af = *af + factor * sum;
+            }
+        }
+
+        for l in 0..n {
+            let ground_plane = self.ground_plane_scale * (self.height_phase * c_za[l]).sin();
+            let response = array_factor[l] * ground_plane;
+            results[l] = match self.beam_type {
+                AnalyticType::MwaPb => Jones::from([
+                    response * (c_za[l] * s_az[l]),
+                    response * c_az[l],
+                    response * (c_za[l] * c_az[l]),
+                    response * -s_az[l],
+                ]),
+                AnalyticType::Rts => {
+                    // Convert the direction to an hour angle and declination
+                    // (as in ERFA's eraAe2hd).
+                    let (s_lat, c_lat) = (self.sin_latitude, self.cos_latitude);
+                    let x = -c_az[l] * s_za[l] * s_lat + c_za[l] * c_lat;
+                    let y = -s_az[l] * s_za[l];
+                    let z = c_az[l] * s_za[l] * c_lat + c_za[l] * s_lat;
+                    let r = (x * x + y * y).sqrt();
+                    let (s_ha, c_ha) = if r > zero {
+                        (y / r, x / r)
+                    } else {
+                        (zero, T::one())
+                    };
+                    let norm = (r * r + z * z).sqrt();
+                    let (s_dec, c_dec) = (z / norm, r / norm);
+
+                    // The RTS deliberately sets the imaginary parts to 0.
+                    let real = |v: T| Complex::new(v * response.re, zero);
+                    Jones::from([
+                        real(c_lat * c_dec + s_lat * s_dec * c_ha),
+                        real(-s_lat * s_ha),
+                        real(s_dec * s_ha),
+                        real(c_ha),
+                    ])
+                }
+            };
+        }
+    }
+}
+
 /// Ensure that any delays of 32 have an amplitude (dipole gain) of 0. The
 /// results are bad otherwise! Also potentially halve the number of amps (e.g.
 /// if 32 are given for a 16-bowtie tile, yield 16); we use the smaller of the
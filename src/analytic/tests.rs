@@ -366,6 +366,129 @@ fn rts_single_matches_array() {
     assert_abs_diff_eq!(result, result_a);
 }
 
+#[test]
+fn f32_matches_f64() {
+    let delays = [0, 2, 4, 6, 0, 1, 2, 3, 10, 12, 14, 16, 0, 4, 8, 12];
+    let mut amps = [1.0; 16];
+    amps[5] = 0.0;
+    // Use directions that are exactly representable in f32, so that only the
+    // calculation precision differs.
+    let (az, za): (Vec<f32>, Vec<f32>) = (0..200)
+        .map(|i| {
+            let i = i as f32;
+            ((i * 0.0314) % std::f32::consts::TAU, (i * 0.0078).min(1.5))
+        })
+        .unzip();
+    let az_f64: Vec<f64> = az.iter().map(|&a| a as f64).collect();
+    let za_f64: Vec<f64> = za.iter().map(|&z| z as f64).collect();
+
+    for beam in [AnalyticBeam::new(), AnalyticBeam::new_rts()] {
+        for norm_to_zenith in [false, true] {
+            for freq_hz in [51_200_000, 180_000_000, 327_680_000] {
+                let result = beam
+                    .calc_jones_array_pair(
+                        &az_f64,
+                        &za_f64,
+                        freq_hz,
+                        &delays,
+                        &amps,
+                        MWA_LAT_RAD,
+                        norm_to_zenith,
+                    )
+                    .unwrap();
+                let result_f32 = beam
+                    .calc_jones_array_pair_f32(
+                        &az,
+                        &za,
+                        freq_hz,
+                        &delays,
+                        &amps,
+                        MWA_LAT_RAD,
+                        norm_to_zenith,
+                    )
+                    .unwrap();
+                for (j, j_f32) in result.iter().zip(result_f32.iter()) {
+                    for (e, e_f32) in j.iter().zip(j_f32.iter()) {
+                        let diff = (e - Complex::new(e_f32.re as f64, e_f32.im as f64)).norm();
+                        assert!(diff < 1e-5, "{e} {e_f32} {diff}");
+                    }
+                }
+            }
+        }
+    }
+
+    let result = AnalyticBeam::new().calc_jones_array_pair_f32(
+        &[0.0],
+        &[1.6],
+        180_000_000,
+        &delays,
+        &amps,
+        MWA_LAT_RAD,
+        false,
+    );
+    assert!(matches!(
+        result,
+        Err(AnalyticBeamError::BelowHorizon { .. })
+    ));
+}
+
+#[test]
+fn parallelism_doesnt_change_results() {
+    let delays = [0, 2, 4, 6, 0, 1, 2, 3, 10, 12, 14, 16, 0, 4, 8, 12];
+    let amps = [1.0; 16];
+    let (az, za): (Vec<f64>, Vec<f64>) = (0..500)
+        .map(|i| (i as f64 * 0.0314, (i as f64 * 0.003).min(FRAC_PI_2)))
+        .unzip();
+    let azels: Vec<AzEl> = az
+        .iter()
+        .zip(za.iter())
+        .map(|(&az, &za)| AzEl::from_radians(az, FRAC_PI_2 - za))
+        .collect();
+
+    let mut beam = AnalyticBeam::new_rts();
+    let expected = beam
+        .calc_jones_array_pair(&az, &za, 180_000_000, &delays, &amps, MWA_LAT_RAD, true)
+        .unwrap();
+    let pool = std::sync::Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap(),
+    );
+    for parallelism in [
+        Parallelism::Serial,
+        Parallelism::Threads(3),
+        Parallelism::Pool(pool),
+        Parallelism::Global,
+    ] {
+        beam.set_parallelism(parallelism).unwrap();
+        let result = beam
+            .calc_jones_array_pair(&az, &za, 180_000_000, &delays, &amps, MWA_LAT_RAD, true)
+            .unwrap();
+        assert_eq!(result, expected);
+        let result = beam
+            .calc_jones_array(&azels, 180_000_000, &delays, &amps, MWA_LAT_RAD, true)
+            .unwrap();
+        for (j, j_expected) in result.iter().zip(expected.iter()) {
+            assert_abs_diff_eq!(j, j_expected, epsilon = 1e-12);
+        }
+
+        let result = beam.calc_jones_array_pair(
+            &[0.0],
+            &[1.6],
+            180_000_000,
+            &delays,
+            &amps,
+            MWA_LAT_RAD,
+            true,
+        );
+        assert!(matches!(
+            result,
+            Err(AnalyticBeamError::BelowHorizon { .. })
+        ));
+    }
+}
+
 #[test]
 fn test_fix_amps_1() {
     let amps = fix_amps(&[1.0; 16], &[0; 16]);
@@ -441,3 +564,125 @@ fn test_cram() {
     );
     result.unwrap();
 }
+
+/// The beam response of a direction, summing over every bowtie as in the
+/// RTS and mwa_pb.
+fn per_bowtie_reference(
+    beam: &AnalyticBeam,
+    az_rad: f64,
+    za_rad: f64,
+    freq_hz: u32,
+    delays: &[u32],
+    amps: &[f64],
+    norm_to_zenith: bool,
+) -> Jones<f64> {
+    let amps = fix_amps(amps, delays);
+    let (amps, delays) = match beam.beam_type {
+        AnalyticType::MwaPb => (amps.clone(), delay_ints_to_floats(delays)),
+        AnalyticType::Rts => reorder_to_rts(&amps, delays),
+    };
+    let lambda_m = VEL_C / freq_hz as f64;
+    let bowties_per_row = usize::from(beam.bowties_per_row);
+    let (s_az, c_az) = az_rad.sin_cos();
+    let (s_za, c_za) = za_rad.sin_cos();
+    let (proj_e, proj_n) = (s_za * s_az, s_za * c_az);
+
+    let mut array_factor = Complex::new(0.0, 0.0);
+    for (k, (&delay, &amp)) in delays.iter().zip(amps.iter()).enumerate() {
+        let col = (k % bowties_per_row) as f64 - 1.5;
+        let row = (k / bowties_per_row) as f64 - 1.5;
+        let phase = match beam.beam_type {
+            AnalyticType::MwaPb => {
+                TAU / lambda_m * (col * MWA_DPL_SEP * proj_e + row * MWA_DPL_SEP * proj_n - delay)
+            }
+            AnalyticType::Rts => {
+                -TAU / lambda_m * (row * MWA_DPL_SEP * proj_e + col * MWA_DPL_SEP * proj_n - delay)
+            }
+        };
+        array_factor += Complex::new(phase.cos(), phase.sin()) * amp;
+    }
+    let height_phase = TAU * beam.dipole_height / lambda_m;
+    let mut ground_plane = 2.0 * (height_phase * c_za).sin() / (bowties_per_row.pow(2) as f64);
+    if norm_to_zenith {
+        ground_plane /= 2.0 * height_phase.sin();
+    }
+    let response = array_factor * ground_plane;
+
+    match beam.beam_type {
+        AnalyticType::MwaPb => Jones::from([
+            response * (c_za * s_az),
+            response * c_az,
+            response * (c_za * c_az),
+            response * -s_az,
+        ]),
+        AnalyticType::Rts => {
+            let hadec = AzEl::from_radians(az_rad, FRAC_PI_2 - za_rad).to_hadec(MWA_LAT_RAD);
+            let (s_ha, c_ha) = hadec.ha.sin_cos();
+            let (s_dec, c_dec) = hadec.dec.sin_cos();
+            let (s_lat, c_lat) = MWA_LAT_RAD.sin_cos();
+            let real = |v: f64| Complex::new(v * response.re, 0.0);
+            Jones::from([
+                real(c_lat * c_dec + s_lat * s_dec * c_ha),
+                real(-s_lat * s_ha),
+                real(s_dec * s_ha),
+                real(c_ha),
+            ])
+        }
+    }
+}
+
+#[test]
+fn prepared_pointing_matches_per_bowtie_sum() {
+    // Include zenith, the horizon and near the south celestial pole (where the
+    // hour angle is undefined), and a number of directions that isn't a
+    // multiple of LANES.
+    let mut az = vec![0.0, 0.0, PI, 1.0];
+    let mut za = vec![0.0, FRAC_PI_2, FRAC_PI_2 + MWA_LAT_RAD - 1e-3, 0.3];
+    for i in 0..17 {
+        az.push(i as f64 * 0.37);
+        za.push(i as f64 * 0.09);
+    }
+
+    for (beam_type, bowties_per_row) in [
+        (AnalyticType::MwaPb, 4),
+        (AnalyticType::Rts, 4),
+        (AnalyticType::MwaPb, 8),
+        (AnalyticType::Rts, 8),
+    ] {
+        let beam = AnalyticBeam::new_custom(
+            beam_type,
+            beam_type.get_default_dipole_height(),
+            bowties_per_row,
+        );
+        let num_bowties = usize::from(bowties_per_row).pow(2);
+        let delays: Vec<u32> = (0..num_bowties as u32).map(|i| (i * 3) % 32).collect();
+        let amps: Vec<f64> = (0..num_bowties).map(|i| 1.0 - i as f64 * 0.01).collect();
+
+        for norm_to_zenith in [false, true] {
+            let results = beam
+                .calc_jones_array_pair(
+                    &az,
+                    &za,
+                    150_000_000,
+                    &delays,
+                    &amps,
+                    MWA_LAT_RAD,
+                    norm_to_zenith,
+                )
+                .unwrap();
+            assert_eq!(results.len(), az.len());
+            for ((&az, &za), result) in az.iter().zip(za.iter()).zip(results) {
+                let expected = per_bowtie_reference(
+                    &beam,
+                    az,
+                    za,
+                    150_000_000,
+                    &delays,
+                    &amps,
+                    norm_to_zenith,
+                );
+                assert_abs_diff_eq!(result, expected, epsilon = 1e-10);
+            }
+        }
+    }
+}
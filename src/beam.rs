@@ -0,0 +1,273 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A common interface to the FEE and analytic beams, for code (e.g. beam-image
+//! generation) that should work with either.
+
+use rayon::ThreadPoolBuildError;
+use thiserror::Error;
+
+use crate::{
+    analytic::{AnalyticBeam, AnalyticBeamError, AnalyticType},
+    fee::{apply_parallactic_correction, parallactic_rotation, FEEBeam, FEEBeamError},
+    Jones, Parallelism,
+};
+
+/// Either an FEE beam or an analytic beam.
+// There are only ever a few of these, so the size difference doesn't matter.
+#[allow(clippy::large_enum_variant)]
+pub enum Beam {
+    Fee(FEEBeam),
+    Analytic(AnalyticBeam),
+}
+
+impl From<FEEBeam> for Beam {
+    fn from(beam: FEEBeam) -> Self {
+        Beam::Fee(beam)
+    }
+}
+
+impl From<AnalyticBeam> for Beam {
+    fn from(beam: AnalyticBeam) -> Self {
+        Beam::Analytic(beam)
+    }
+}
+
+/// The tile configuration and calculation options used when evaluating a
+/// [`Beam`].
+#[derive(Debug, Clone, Copy)]
+pub struct BeamParams<'a> {
+    /// The 16 dipole delays of the tile, in the M&C order.
+    pub delays: &'a [u32],
+
+    /// The 16 or 32 dipole gains of the tile, in the M&C order.
+    pub amps: &'a [f64],
+
+    /// Normalise the beam responses to zenith.
+    pub norm_to_zenith: bool,
+
+    /// Apply the parallactic-angle correction to beam responses.
+    pub parallactic: bool,
+
+    /// Arrange beam Jones matrices in the IAU order. This only applies when
+    /// `parallactic` is `true`; otherwise the E-W dipole is first.
+    pub iau_order: bool,
+}
+
+impl Beam {
+    /// Calculate beam responses for many directions. `latitude_rad` is always
+    /// used by RTS-style analytic beams, but is otherwise only used for the
+    /// parallactic-angle correction if `params.parallactic` is `true`. The
+    /// Jones matrices of either beam are arranged in the same way, so their
+    /// elements refer to the same dipoles.
+    pub fn calc_jones_array_pair(
+        &self,
+        az_rad: &[f64],
+        za_rad: &[f64],
+        freq_hz: u32,
+        params: &BeamParams,
+        latitude_rad: f64,
+    ) -> Result<Vec<Jones<f64>>, BeamError> {
+        let jones = match self {
+            Beam::Fee(beam) => beam.calc_jones_array_pair(
+                az_rad,
+                za_rad,
+                freq_hz,
+                params.delays,
+                params.amps,
+                params.norm_to_zenith,
+                params.parallactic.then_some(latitude_rad),
+                params.iau_order,
+            )?,
+            Beam::Analytic(beam) => {
+                let mut jones = beam.calc_jones_array_pair(
+                    az_rad,
+                    za_rad,
+                    freq_hz,
+                    params.delays,
+                    params.amps,
+                    latitude_rad,
+                    params.norm_to_zenith,
+                )?;
+                // mwa_pb-style responses are like FEE responses without the
+                // parallactic-angle correction, whereas RTS-style responses
+                // have the correction and are in IAU order.
+                let is_rts = matches!(beam.get_beam_type(), AnalyticType::Rts);
+                if !(is_rts && params.parallactic && params.iau_order) {
+                    for ((&az, &za), j) in az_rad.iter().zip(za_rad).zip(jones.iter_mut()) {
+                        if is_rts {
+                            remove_parallactic_correction(az, za, latitude_rad, j);
+                        }
+                        if params.parallactic {
+                            apply_parallactic_correction(az, za, latitude_rad, params.iau_order, j);
+                        }
+                    }
+                }
+                jones
+            }
+        };
+        Ok(jones)
+    }
+
+    /// Get the frequency [Hz] that beam responses are actually calculated at
+    /// when `freq_hz` is requested. FEE beams use the closest frequency
+    /// defined in the beam file; analytic beams use any frequency.
+    pub fn find_closest_freq(&self, freq_hz: u32) -> u32 {
+        match self {
+            Beam::Fee(beam) => beam.find_closest_freq(freq_hz),
+            Beam::Analytic(_) => freq_hz,
+        }
+    }
+
+    /// Set the threads used to calculate beam responses on the CPU; see
+    /// [`FEEBeam::set_parallelism`] and [`AnalyticBeam::set_parallelism`].
+    pub fn set_parallelism(
+        &mut self,
+        parallelism: Parallelism,
+    ) -> Result<(), ThreadPoolBuildError> {
+        match self {
+            Beam::Fee(beam) => beam.set_parallelism(parallelism),
+            Beam::Analytic(beam) => beam.set_parallelism(parallelism),
+        }
+    }
+
+    /// Get the threads used to calculate beam responses on the CPU.
+    pub fn get_parallelism(&self) -> &Parallelism {
+        match self {
+            Beam::Fee(beam) => beam.get_parallelism(),
+            Beam::Analytic(beam) => beam.get_parallelism(),
+        }
+    }
+
+    /// Run `op` so that any parallel work inside it uses the beam's threads.
+    pub(crate) fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match self {
+            Beam::Fee(beam) => beam.threads.install(op),
+            Beam::Analytic(beam) => beam.threads.install(op),
+        }
+    }
+}
+
+/// Undo the parallactic-angle correction of an RTS-style analytic beam response
+/// (which is in IAU order), so that it's arranged like an mwa_pb-style response
+/// (see [`apply_parallactic_correction`]).
+fn remove_parallactic_correction(
+    az_rad: f64,
+    za_rad: f64,
+    latitude_rad: f64,
+    jones: &mut Jones<f64>,
+) {
+    let (s_rot, c_rot) = parallactic_rotation(az_rad, za_rad, latitude_rad);
+    *jones = Jones::from([
+        jones[2] * -c_rot + jones[3] * -s_rot,
+        jones[2] * s_rot + jones[3] * -c_rot,
+        jones[0] * -c_rot + jones[1] * -s_rot,
+        jones[0] * s_rot + jones[1] * -c_rot,
+    ]);
+}
+
+#[derive(Error, Debug)]
+pub enum BeamError {
+    #[error(transparent)]
+    Fee(#[from] FEEBeamError),
+
+    #[error(transparent)]
+    Analytic(#[from] AnalyticBeamError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_3};
+
+    use approx::assert_abs_diff_eq;
+    use marlu::constants::MWA_LAT_RAD;
+    use serial_test::serial;
+
+    use super::*;
+
+    /// Towards the north, the E-W dipole responds more than the N-S dipole;
+    /// towards the east, it's the other way round.
+    const AZ: [f64; 2] = [0.0, FRAC_PI_2];
+    const ZA: [f64; 2] = [FRAC_PI_3; 2];
+
+    fn params(parallactic: bool, iau_order: bool) -> BeamParams<'static> {
+        BeamParams {
+            delays: &[0; 16],
+            amps: &[1.0; 16],
+            norm_to_zenith: true,
+            parallactic,
+            iau_order,
+        }
+    }
+
+    /// Check that the first row of the Jones matrices belongs to the E-W
+    /// dipole, unless they're in IAU order.
+    fn check_rows(jones: &[Jones<f64>], params: &BeamParams) {
+        let ns_first = params.parallactic && params.iau_order;
+        for (i, j) in jones.iter().enumerate() {
+            let first = j[0].norm_sqr() + j[1].norm_sqr();
+            let second = j[2].norm_sqr() + j[3].norm_sqr();
+            let towards_north = i == 0;
+            assert_eq!(first > second, towards_north != ns_first);
+        }
+    }
+
+    #[test]
+    fn test_analytic_jones_are_arranged_like_fee_jones() {
+        let mwa_pb = Beam::from(AnalyticBeam::new_custom(AnalyticType::MwaPb, 0.3, 4));
+        let rts = Beam::from(AnalyticBeam::new_rts());
+        for parallactic in [false, true] {
+            for iau_order in [false, true] {
+                let params = params(parallactic, iau_order);
+                let mwa_pb = mwa_pb
+                    .calc_jones_array_pair(&AZ, &ZA, 150_000_000, &params, MWA_LAT_RAD)
+                    .unwrap();
+                let rts = rts
+                    .calc_jones_array_pair(&AZ, &ZA, 150_000_000, &params, MWA_LAT_RAD)
+                    .unwrap();
+                check_rows(&mwa_pb, &params);
+                check_rows(&rts, &params);
+
+                // The beams only differ by their array factors, which are the
+                // same for every element.
+                for (a, r) in mwa_pb.iter().zip(rts.iter()) {
+                    for i in 1..4 {
+                        assert_abs_diff_eq!(a[i] * r[0], r[i] * a[0], epsilon = 1e-10);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_fee_and_analytic_closest_freqs() {
+        let fee = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+        let expected = fee.find_closest_freq(150_100_000);
+        assert_ne!(expected, 150_100_000);
+        assert_eq!(Beam::from(fee).find_closest_freq(150_100_000), expected);
+        let analytic = Beam::from(AnalyticBeam::new());
+        assert_eq!(analytic.find_closest_freq(150_100_000), 150_100_000);
+    }
+
+    #[test]
+    #[serial]
+    fn test_fee_and_analytic_jones_are_arranged_alike() {
+        let fee = Beam::from(FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap());
+        let mwa_pb = Beam::from(AnalyticBeam::new());
+        for parallactic in [false, true] {
+            for iau_order in [false, true] {
+                let params = params(parallactic, iau_order);
+                let fee = fee
+                    .calc_jones_array_pair(&AZ, &ZA, 150_000_000, &params, MWA_LAT_RAD)
+                    .unwrap();
+                let mwa_pb = mwa_pb
+                    .calc_jones_array_pair(&AZ, &ZA, 150_000_000, &params, MWA_LAT_RAD)
+                    .unwrap();
+                check_rows(&fee, &params);
+                check_rows(&mwa_pb, &params);
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Beam-related arguments shared by subcommands.
+
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use mwa_hyperbeam::{
+    analytic::{AnalyticBeam, AnalyticType},
+    beam::{Beam, BeamParams},
+    fee::FEEBeam,
+    fits::FitsHeader,
+};
+
+use crate::{error::CliError, gridpoint::read_gridpoint_delays};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum BeamType {
+    /// The Fully Embedded Element beam.
+    Fee,
+    /// The analytic beam, emulating mwa_pb.
+    MwaPb,
+    /// The analytic beam, emulating the RTS.
+    Rts,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct BeamArgs {
+    /// The beam model to use.
+    #[arg(long, value_enum, default_value_t = BeamType::Fee)]
+    pub(crate) beam: BeamType,
+
+    /// The path to the FEE beam HDF5 file. If not given, MWA_BEAM_FILE is
+    /// used, then the directories given by --beam-dir, then the XDG data
+    /// directories (e.g. ~/.local/share/mwa_hyperbeam).
+    #[arg(long)]
+    pub(crate) beam_file: Option<PathBuf>,
+
+    /// A directory to look for the FEE beam file in, if --beam-file isn't
+    /// given. Can be given multiple times.
+    #[arg(long)]
+    pub(crate) beam_dir: Vec<PathBuf>,
+
+    /// The height of the dipoles for the analytic beam [metres]. The default
+    /// depends on the analytic beam type.
+    #[arg(long)]
+    pub(crate) dipole_height: Option<f64>,
+
+    /// The 16 dipole delays of the tile, in the M&C order.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0"
+    )]
+    pub(crate) delays: Vec<u32>,
+
+    /// Use the delays of this MWA sweet-spot gridpoint instead of --delays.
+    /// The gridpoint is looked up in the table given by --sweetspot-file (or
+    /// MWA_SWEETSPOT_FILE); hyperbeam doesn't come with this table.
+    #[arg(long, conflicts_with = "delays")]
+    pub(crate) gridpoint: Option<u32>,
+
+    /// A CSV file of MWA sweet-spot gridpoints, used with --gridpoint. Each
+    /// row has the gridpoint number in its first column and the 16 dipole
+    /// delays in its last 16 columns; other columns (e.g. the gridpoint's
+    /// azimuth and elevation) may come in between. A header line and lines
+    /// starting with '#' are ignored. The gridpoints are defined by the MWA
+    /// observatory; a table can be made from the sweet-spot list distributed
+    /// with mwa_pb. If not given, MWA_SWEETSPOT_FILE is used.
+    #[arg(long)]
+    pub(crate) sweetspot_file: Option<PathBuf>,
+
+    /// The 16 or 32 dipole gains of the tile, in the M&C order. If 32 are
+    /// given, the first 16 are for X dipoles and the next 16 are for Y.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1"
+    )]
+    pub(crate) amps: Vec<f64>,
+
+    /// Normalise the beam responses to zenith.
+    #[arg(long)]
+    pub(crate) norm_to_zenith: bool,
+
+    /// Don't apply the parallactic-angle correction to beam responses; FEE
+    /// beam results then match the original specification of the FEE beam
+    /// code, and analytic beam results are in the basis of mwa_pb.
+    #[arg(long)]
+    pub(crate) no_parallactic: bool,
+
+    /// Arrange beam Jones matrices in the IAU order [NS-NS NS-EW EW-NS
+    /// EW-EW]. Only applies when the parallactic-angle correction is applied.
+    #[arg(long)]
+    pub(crate) iau_order: bool,
+}
+
+impl BeamArgs {
+    /// If a gridpoint was given, replace the delays with those of the
+    /// gridpoint.
+    pub(crate) fn resolve_gridpoint(&mut self) -> Result<(), CliError> {
+        let Some(gridpoint) = self.gridpoint else {
+            return Ok(());
+        };
+        let file = match &self.sweetspot_file {
+            Some(f) => f.clone(),
+            None => std::env::var_os("MWA_SWEETSPOT_FILE")
+                .map(PathBuf::from)
+                .ok_or(CliError::NoSweetSpotFile)?,
+        };
+        self.delays = read_gridpoint_delays(&file, gridpoint)?;
+        Ok(())
+    }
+
+    /// Create the beam object specified by these arguments.
+    pub(crate) fn create_beam(&self) -> Result<Beam, CliError> {
+        let analytic_type = match self.beam {
+            BeamType::Fee => {
+                let beam = match &self.beam_file {
+                    Some(f) => FEEBeam::new(f)?,
+                    None => FEEBeam::new_from_search(&self.beam_dir)?,
+                };
+                return Ok(Beam::Fee(beam));
+            }
+            BeamType::MwaPb => AnalyticType::MwaPb,
+            BeamType::Rts => AnalyticType::Rts,
+        };
+        Ok(Beam::Analytic(AnalyticBeam::new_custom(
+            analytic_type,
+            self.dipole_height
+                .unwrap_or_else(|| analytic_type.get_default_dipole_height()),
+            4,
+        )))
+    }
+
+    /// The tile configuration and calculation options given by these
+    /// arguments.
+    pub(crate) fn params(&self) -> BeamParams<'_> {
+        BeamParams {
+            delays: &self.delays,
+            amps: &self.amps,
+            norm_to_zenith: self.norm_to_zenith,
+            parallactic: !self.no_parallactic,
+            iau_order: self.iau_order,
+        }
+    }
+
+    /// FITS header cards describing the beam. For the FEE beam, the checksum
+    /// of the beam file is included.
+    pub(crate) fn fits_header(&self, beam: &Beam) -> Result<FitsHeader, CliError> {
+        let mut header = FitsHeader::new();
+        header.push(
+            "BEAM",
+            format!("{:?}", self.beam).to_uppercase(),
+            "beam model",
+        );
+        let delays = self
+            .delays
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        header.push("DELAYS", delays, "");
+        if let Some(gridpoint) = self.gridpoint {
+            header.push("GRIDNUM", i64::from(gridpoint), "sweet-spot gridpoint");
+        }
+        if let Beam::Fee(fee) = beam {
+            let id = fee.identity()?;
+            header.push("BEAMSHA", id.sha256.as_str(), "SHA-256 of the beam file");
+        }
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        beam: BeamArgs,
+    }
+
+    #[test]
+    fn test_gridpoint_delays() {
+        let path = std::env::temp_dir().join(format!(
+            "hyperbeam_beam_sweetspots_{}.csv",
+            std::process::id()
+        ));
+        std::fs::write(&path, "3,90,80,0,1,2,3,0,1,2,3,0,1,2,3,0,1,2,3\n").unwrap();
+
+        let mut args = TestArgs::try_parse_from([
+            "test",
+            "--gridpoint",
+            "3",
+            "--sweetspot-file",
+            path.to_str().unwrap(),
+        ])
+        .unwrap()
+        .beam;
+        args.resolve_gridpoint().unwrap();
+        assert_eq!(
+            args.delays,
+            [0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3]
+        );
+        assert_eq!(args.params().delays, args.delays.as_slice());
+        std::fs::remove_file(&path).unwrap();
+
+        // Delays and a gridpoint can't both be given.
+        let result = TestArgs::try_parse_from([
+            "test",
+            "--gridpoint",
+            "3",
+            "--delays",
+            "0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0",
+        ]);
+        assert!(result.is_err());
+
+        // Without a gridpoint, the given delays are untouched.
+        let mut args =
+            TestArgs::try_parse_from(["test", "--delays", "1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1"])
+                .unwrap()
+                .beam;
+        args.resolve_gridpoint().unwrap();
+        assert_eq!(args.delays, [1; 16]);
+    }
+}
@@ -0,0 +1,288 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `calc` subcommand: calculate beam responses for directions read from a
+//! file.
+
+use std::{f64::consts::FRAC_PI_2, path::PathBuf};
+
+use clap::{Args, ValueEnum};
+use marlu::RADec;
+
+use crate::{
+    beam::BeamArgs,
+    error::CliError,
+    io::{read_coord_pairs, write_results, Quantity, Results},
+    obs::ObsArgs,
+};
+
+/// The coordinate system of the input directions.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CoordType {
+    /// Azimuth and zenith angle.
+    AzZa,
+    /// Azimuth and elevation.
+    AzEl,
+    /// Right ascension and declination. A time (or LST) is needed to convert
+    /// these to azimuth and zenith angle.
+    RaDec,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CalcArgs {
+    /// A file of directions (.csv or .npy). Each row has two coordinates; see
+    /// --coords.
+    #[arg(short, long)]
+    directions: PathBuf,
+
+    /// The coordinate system of the directions.
+    #[arg(long, value_enum, default_value_t = CoordType::AzZa)]
+    coords: CoordType,
+
+    /// The direction coordinates are in radians, not degrees.
+    #[arg(long)]
+    radians: bool,
+
+    /// The frequencies to use [Hz]. The FEE beam uses the closest frequencies
+    /// defined in the beam file, and these are the frequencies written out.
+    #[arg(short, long, value_delimiter = ',', num_args = 1.., required = true)]
+    freqs_hz: Vec<f64>,
+
+    #[command(flatten)]
+    obs: ObsArgs,
+
+    #[command(flatten)]
+    beam: BeamArgs,
+
+    /// What to write out.
+    #[arg(short, long, value_enum, default_value_t = Quantity::Jones)]
+    quantity: Quantity,
+
+    /// The output file (.csv, .npy or .json). If not given, CSV is written to
+    /// stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl CalcArgs {
+    pub(crate) fn run(mut self) -> Result<(), CliError> {
+        self.beam.resolve_gridpoint()?;
+        let latitude_rad = self.obs.latitude_rad();
+        let (az_rad, za_rad) = self.get_azza(latitude_rad)?;
+
+        let beam = self.beam.create_beam()?;
+        // Report the frequencies that the beam responses are really for.
+        let freqs_hz: Vec<u32> = self
+            .freqs_hz
+            .iter()
+            .map(|f| beam.find_closest_freq(f.round() as u32))
+            .collect();
+        let jones = freqs_hz
+            .iter()
+            .map(|&freq| {
+                beam.calc_jones_array_pair(
+                    &az_rad,
+                    &za_rad,
+                    freq,
+                    &self.beam.params(),
+                    latitude_rad,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        write_results(
+            &Results {
+                freqs_hz: &freqs_hz,
+                az_rad: &az_rad,
+                za_rad: &za_rad,
+                jones: &jones,
+                quantity: self.quantity,
+            },
+            self.output.as_ref(),
+        )
+    }
+
+    /// Read the directions and convert them to azimuths and zenith angles
+    /// [radians].
+    fn get_azza(&self, latitude_rad: f64) -> Result<(Vec<f64>, Vec<f64>), CliError> {
+        let to_rad = |v: f64| if self.radians { v } else { v.to_radians() };
+        let pairs = read_coord_pairs(&self.directions)?;
+
+        let lst_rad = match self.coords {
+            CoordType::RaDec => Some(self.obs.observer()?.lst_rad),
+            CoordType::AzZa | CoordType::AzEl => None,
+        };
+        Ok(pairs
+            .into_iter()
+            .map(|(a, b)| {
+                let (a, b) = (to_rad(a), to_rad(b));
+                match (self.coords, lst_rad) {
+                    (CoordType::AzZa, _) => (a, b),
+                    (CoordType::AzEl, _) => (a, FRAC_PI_2 - b),
+                    (CoordType::RaDec, Some(lst_rad)) => {
+                        let azel = RADec::from_radians(a, b)
+                            .to_hadec(lst_rad)
+                            .to_azel(latitude_rad);
+                        (azel.az, azel.za())
+                    }
+                    (CoordType::RaDec, None) => unreachable!("LST is always set for RA/Dec"),
+                }
+            })
+            .unzip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use clap::Parser;
+    use marlu::constants::MWA_LAT_RAD;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        calc: CalcArgs,
+    }
+
+    /// Parse `calc` arguments with the given directions written to a CSV file,
+    /// and convert the directions to az/za.
+    fn get_azza(
+        name: &str,
+        directions: &str,
+        args: &[&str],
+    ) -> Result<(Vec<f64>, Vec<f64>), CliError> {
+        let path =
+            std::env::temp_dir().join(format!("hyperbeam_calc_{name}_{}.csv", std::process::id()));
+        std::fs::write(&path, directions).unwrap();
+        let calc = TestArgs::parse_from(
+            ["test", "-d", path.to_str().unwrap(), "-f", "150e6"]
+                .into_iter()
+                .chain(args.iter().copied()),
+        )
+        .calc;
+        let result = calc.get_azza(calc.obs.latitude_rad());
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_azza_directions() {
+        let (az, za) = get_azza("azza", "az,za\n10,20\n30,40\n", &[]).unwrap();
+        assert_abs_diff_eq!(
+            az.as_slice(),
+            [10_f64.to_radians(), 30_f64.to_radians()].as_slice()
+        );
+        assert_abs_diff_eq!(
+            za.as_slice(),
+            [20_f64.to_radians(), 40_f64.to_radians()].as_slice()
+        );
+
+        let (az, za) = get_azza("azza_rad", "0.1,0.2\n", &["--radians"]).unwrap();
+        assert_abs_diff_eq!(az.as_slice(), [0.1].as_slice());
+        assert_abs_diff_eq!(za.as_slice(), [0.2].as_slice());
+    }
+
+    #[test]
+    fn test_azel_directions() {
+        let (az, za) =
+            get_azza("azel", "# comment\n10,90\n200,30\n", &["--coords", "az-el"]).unwrap();
+        assert_abs_diff_eq!(
+            az.as_slice(),
+            [10_f64.to_radians(), 200_f64.to_radians()].as_slice()
+        );
+        assert_abs_diff_eq!(
+            za.as_slice(),
+            [0.0, 60_f64.to_radians()].as_slice(),
+            epsilon = 1e-15
+        );
+    }
+
+    #[test]
+    fn test_radec_directions_with_lst() {
+        // A source at the LST and with a declination equal to the latitude is
+        // at zenith. A source on the meridian south of zenith has an azimuth
+        // of 180 degrees.
+        let (_, za) = get_azza(
+            "radec_lst",
+            &format!("60,{}\n", MWA_LAT_RAD.to_degrees()),
+            &["--coords", "ra-dec", "--lst-deg", "60"],
+        )
+        .unwrap();
+        assert_abs_diff_eq!(za[0], 0.0, epsilon = 1e-10);
+
+        let (az, za) = get_azza(
+            "radec_lst_south",
+            &format!("60,{}\n", MWA_LAT_RAD.to_degrees() - 20.0),
+            &["--coords", "ra-dec", "--lst-deg", "60"],
+        )
+        .unwrap();
+        assert_abs_diff_eq!(az[0], 180_f64.to_radians(), epsilon = 1e-10);
+        assert_abs_diff_eq!(za[0], 20_f64.to_radians(), epsilon = 1e-10);
+    }
+
+    #[test]
+    fn test_radec_directions_with_time() {
+        // The same time as a string and as GPS seconds must give the same
+        // directions.
+        let dirs = "0,-27\n100,-60\n";
+        let gps_seconds = marlu::hifitime::Epoch::from_gregorian_utc_at_noon(2024, 1, 1)
+            .to_gpst_seconds()
+            .to_string();
+        let utc = get_azza(
+            "radec_utc",
+            dirs,
+            &["--coords", "ra-dec", "--time", "2024-01-01T12:00:00 UTC"],
+        )
+        .unwrap();
+        let gps = get_azza(
+            "radec_gps",
+            dirs,
+            &["--coords", "ra-dec", "--time", &gps_seconds],
+        )
+        .unwrap();
+        assert_abs_diff_eq!(utc.0.as_slice(), gps.0.as_slice(), epsilon = 1e-8);
+        assert_abs_diff_eq!(utc.1.as_slice(), gps.1.as_slice(), epsilon = 1e-8);
+
+        // The directions must agree with those from the equivalent LST.
+        let lst_deg = TestArgs::parse_from([
+            "test",
+            "-d",
+            "unused.csv",
+            "-f",
+            "150e6",
+            "--time",
+            &gps_seconds,
+        ])
+        .calc
+        .obs
+        .observer()
+        .unwrap()
+        .lst_rad
+        .to_degrees()
+        .to_string();
+        let lst = get_azza(
+            "radec_lst_equiv",
+            dirs,
+            &["--coords", "ra-dec", "--lst-deg", &lst_deg],
+        )
+        .unwrap();
+        assert_abs_diff_eq!(utc.0.as_slice(), lst.0.as_slice(), epsilon = 1e-8);
+        assert_abs_diff_eq!(utc.1.as_slice(), lst.1.as_slice(), epsilon = 1e-8);
+    }
+
+    #[test]
+    fn test_radec_directions_need_time() {
+        let result = get_azza("radec_no_time", "0,-27\n", &["--coords", "ra-dec"]);
+        assert!(matches!(result, Err(CliError::NoTime)));
+
+        let result = get_azza(
+            "radec_bad_time",
+            "0,-27\n",
+            &["--coords", "ra-dec", "--time", "yesterday"],
+        );
+        assert!(matches!(result, Err(CliError::BadTime(_))));
+    }
+}
@@ -0,0 +1,77 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Errors associated with the `hyperbeam` command-line tool.
+
+use std::path::PathBuf;
+
+use mwa_hyperbeam::{
+    analytic::AnalyticBeamError,
+    beam::BeamError,
+    fee::{FEEBeamError, InitFEEBeamError},
+    healpix::HealpixError,
+    image::ImageError,
+    npy::NpyError,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum CliError {
+    #[error("Couldn't parse line {line} of '{file}': {msg}")]
+    CsvParse {
+        file: PathBuf,
+        line: usize,
+        msg: String,
+    },
+
+    #[error(
+        "Expected the .npy array in '{file}' to have shape (N, 2), but it has shape {shape:?}"
+    )]
+    NpyShape { file: PathBuf, shape: Vec<usize> },
+
+    #[error("Unrecognised file extension for '{0}'; expected one of .csv, .npy or .json")]
+    UnknownExtension(PathBuf),
+
+    #[error("RA/Dec directions need either --time or --lst-deg to be specified")]
+    NoTime,
+
+    #[error("Couldn't parse '{0}' as a UTC time or GPS seconds")]
+    BadTime(String),
+
+    #[error("--gridpoint needs a sweet-spot table; give one with --sweetspot-file or MWA_SWEETSPOT_FILE")]
+    NoSweetSpotFile,
+
+    #[error("Gridpoint {gridpoint} isn't in the sweet-spot table '{file}'")]
+    UnknownGridpoint { gridpoint: u32, file: PathBuf },
+
+    #[error("Either --freq-range-hz or --freqs-hz must be given")]
+    NoSubsetFreqs,
+
+    #[error(transparent)]
+    InitFee(#[from] InitFEEBeamError),
+
+    #[error(transparent)]
+    Fee(#[from] FEEBeamError),
+
+    #[error(transparent)]
+    Analytic(#[from] AnalyticBeamError),
+
+    #[error(transparent)]
+    Beam(#[from] BeamError),
+
+    #[error(transparent)]
+    Image(#[from] ImageError),
+
+    #[error(transparent)]
+    Healpix(#[from] HealpixError),
+
+    #[error(transparent)]
+    Npy(#[from] NpyError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
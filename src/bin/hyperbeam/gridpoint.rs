@@ -0,0 +1,112 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Looking up the dipole delays of MWA "sweet-spot" gridpoints.
+//!
+//! The MWA usually points at one of a fixed set of directions (gridpoints),
+//! each with its own set of 16 dipole delays. The table of gridpoints is
+//! maintained by the observatory (e.g. it is distributed with mwa_pb), so
+//! rather than keeping a copy here that could go stale, it's read from a CSV
+//! file. Each row has the gridpoint number in its first column and the 16
+//! delays in its last 16 columns; any columns in between (e.g. the azimuth
+//! and elevation of the gridpoint) are ignored. A header line and comment
+//! lines (starting with '#') are allowed.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::error::CliError;
+
+/// Get the delays of a gridpoint from a sweet-spot table file.
+pub(crate) fn read_gridpoint_delays(path: &Path, gridpoint: u32) -> Result<Vec<u32>, CliError> {
+    let f = BufReader::new(File::open(path)?);
+    let mut first_content_line = true;
+    for (i_line, line) in f.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let is_first = std::mem::take(&mut first_content_line);
+
+        let cols: Vec<&str> = line.split(',').map(|c| c.trim()).collect();
+        let parse_err = |msg: String| CliError::CsvParse {
+            file: path.to_path_buf(),
+            line: i_line + 1,
+            msg,
+        };
+        let num = match cols[0].parse::<u32>() {
+            Ok(n) => n,
+            // Allow a header line before any data.
+            Err(_) if is_first => continue,
+            Err(_) => {
+                return Err(parse_err(format!(
+                    "couldn't parse '{}' as a gridpoint number",
+                    cols[0]
+                )))
+            }
+        };
+        if num != gridpoint {
+            continue;
+        }
+        if cols.len() < 17 {
+            return Err(parse_err(format!(
+                "expected a gridpoint number and 16 delays, but found {} columns",
+                cols.len()
+            )));
+        }
+        return cols[cols.len() - 16..]
+            .iter()
+            .map(|d| {
+                d.parse::<u32>()
+                    .map_err(|_| parse_err(format!("couldn't parse '{d}' as a delay")))
+            })
+            .collect();
+    }
+
+    Err(CliError::UnknownGridpoint {
+        gridpoint,
+        file: path.to_path_buf(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_gridpoint_delays() {
+        let path =
+            std::env::temp_dir().join(format!("hyperbeam_sweetspots_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "# A test table\n\
+             gridpoint,az_deg,el_deg,d0,d1,d2,d3,d4,d5,d6,d7,d8,d9,d10,d11,d12,d13,d14,d15\n\
+             0,0,90,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0\n\
+             7,90,80,0,1,2,3,0,1,2,3,0,1,2,3,0,1,2,3\n\
+             8,0,80,3,3,3,3,2,2,2,2,1,1,1,1,0,0,0,0\n",
+        )
+        .unwrap();
+
+        let delays = read_gridpoint_delays(&path, 7).unwrap();
+        assert_eq!(delays, [0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3]);
+        let delays = read_gridpoint_delays(&path, 0).unwrap();
+        assert_eq!(delays, [0; 16]);
+        let result = read_gridpoint_delays(&path, 9);
+        assert!(matches!(
+            result,
+            Err(CliError::UnknownGridpoint { gridpoint: 9, .. })
+        ));
+
+        // Rows without the gridpoint's delays are rejected.
+        std::fs::write(&path, "1,0,90,0,0,0\n").unwrap();
+        let result = read_gridpoint_delays(&path, 1);
+        assert!(matches!(result, Err(CliError::CsvParse { line: 1, .. })));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
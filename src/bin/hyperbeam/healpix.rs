@@ -0,0 +1,100 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `healpix` subcommand: write all-sky HEALPix maps of the beam.
+
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use mwa_hyperbeam::{
+    healpix::{make_healpix_map, HealpixFrame, Ordering},
+    image::Observer,
+};
+
+use crate::{beam::BeamArgs, error::CliError, image::ProductArg, obs::ObsArgs};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OrderingArg {
+    Ring,
+    Nested,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum FrameArg {
+    /// Azimuth and zenith angle.
+    Horizon,
+    /// RA and Dec; a time (or LST) is needed.
+    Equatorial,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct HealpixArgs {
+    /// The HEALPix resolution parameter. Must be a power of 2.
+    #[arg(long, default_value_t = 64)]
+    nside: u32,
+
+    /// The pixel ordering.
+    #[arg(long, value_enum, default_value_t = OrderingArg::Ring)]
+    ordering: OrderingArg,
+
+    /// The coordinate system of the map.
+    #[arg(long, value_enum, default_value_t = FrameArg::Horizon)]
+    frame: FrameArg,
+
+    /// The frequencies of the maps [Hz].
+    #[arg(short, long, value_delimiter = ',', num_args = 1.., required = true)]
+    freqs_hz: Vec<f64>,
+
+    #[command(flatten)]
+    obs: ObsArgs,
+
+    #[command(flatten)]
+    beam: BeamArgs,
+
+    /// What the maps contain.
+    #[arg(long, value_enum, default_value_t = ProductArg::Power)]
+    product: ProductArg,
+
+    /// The output FITS file.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+impl HealpixArgs {
+    pub(crate) fn run(mut self) -> Result<(), CliError> {
+        self.beam.resolve_gridpoint()?;
+        let (frame, observer) = match self.frame {
+            // Horizon maps don't depend on the LST.
+            FrameArg::Horizon => (
+                HealpixFrame::Horizon,
+                Observer {
+                    lst_rad: 0.0,
+                    latitude_rad: self.obs.latitude_rad(),
+                    epoch: None,
+                },
+            ),
+            FrameArg::Equatorial => (HealpixFrame::Equatorial, self.obs.observer()?),
+        };
+        let ordering = match self.ordering {
+            OrderingArg::Ring => Ordering::Ring,
+            OrderingArg::Nested => Ordering::Nested,
+        };
+        let freqs_hz: Vec<u32> = self.freqs_hz.iter().map(|f| f.round() as u32).collect();
+
+        let beam = self.beam.create_beam()?;
+        let mut map = make_healpix_map(
+            &beam,
+            &self.beam.params(),
+            self.nside,
+            ordering,
+            frame,
+            &freqs_hz,
+            observer,
+            self.product.into(),
+        )?;
+        map.header = self.beam.fits_header(&beam)?;
+        map.write_fits(&self.output)?;
+        Ok(())
+    }
+}
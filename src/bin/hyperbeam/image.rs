@@ -0,0 +1,118 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `image` subcommand: write FITS images of the beam, e.g. for
+//! primary-beam correction.
+
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use mwa_hyperbeam::image::{make_beam_image, ImageProduct, ImageWcs, Projection};
+
+use crate::{beam::BeamArgs, error::CliError, obs::ObsArgs};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ProjectionArg {
+    /// Slant orthographic.
+    Sin,
+    /// Zenithal equal area.
+    Zea,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum ProductArg {
+    /// XX, YY, Re(XY) and Im(XY) instrumental power.
+    Power,
+    /// Instrumental Stokes I, Q, U and V.
+    Stokes,
+    /// The real and imaginary parts of each Jones matrix element.
+    Jones,
+}
+
+impl From<ProductArg> for ImageProduct {
+    fn from(p: ProductArg) -> Self {
+        match p {
+            ProductArg::Power => ImageProduct::Power,
+            ProductArg::Stokes => ImageProduct::Stokes,
+            ProductArg::Jones => ImageProduct::Jones,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ImageArgs {
+    /// The RA of the image centre [degrees].
+    #[arg(long, allow_negative_numbers = true)]
+    ra_deg: f64,
+
+    /// The Dec of the image centre [degrees].
+    #[arg(long, allow_negative_numbers = true)]
+    dec_deg: f64,
+
+    /// The size of each pixel [degrees].
+    #[arg(long)]
+    pixel_scale_deg: f64,
+
+    /// The number of pixels along the RA axis.
+    #[arg(long)]
+    width: usize,
+
+    /// The number of pixels along the Dec axis. Defaults to the width.
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// The projection of the image.
+    #[arg(long, value_enum, default_value_t = ProjectionArg::Sin)]
+    projection: ProjectionArg,
+
+    /// The frequencies of the image channels [Hz]. These must be evenly
+    /// spaced.
+    #[arg(short, long, value_delimiter = ',', num_args = 1.., required = true)]
+    freqs_hz: Vec<f64>,
+
+    #[command(flatten)]
+    obs: ObsArgs,
+
+    #[command(flatten)]
+    beam: BeamArgs,
+
+    /// What the image planes contain.
+    #[arg(long, value_enum, default_value_t = ProductArg::Power)]
+    product: ProductArg,
+
+    /// The output FITS file.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+impl ImageArgs {
+    pub(crate) fn run(mut self) -> Result<(), CliError> {
+        self.beam.resolve_gridpoint()?;
+        let wcs = ImageWcs {
+            projection: match self.projection {
+                ProjectionArg::Sin => Projection::Sin,
+                ProjectionArg::Zea => Projection::Zea,
+            },
+            ra_deg: self.ra_deg,
+            dec_deg: self.dec_deg,
+            pixel_scale_deg: self.pixel_scale_deg,
+            width: self.width,
+            height: self.height.unwrap_or(self.width),
+        };
+        let freqs_hz: Vec<u32> = self.freqs_hz.iter().map(|f| f.round() as u32).collect();
+
+        let beam = self.beam.create_beam()?;
+        let mut image = make_beam_image(
+            &beam,
+            &self.beam.params(),
+            wcs,
+            &freqs_hz,
+            self.obs.observer()?,
+            self.product.into(),
+        )?;
+        image.header = self.beam.fits_header(&beam)?;
+        image.write_fits(&self.output)?;
+        Ok(())
+    }
+}
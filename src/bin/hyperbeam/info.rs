@@ -0,0 +1,49 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `info` subcommand: describe an FEE beam file.
+
+use std::path::PathBuf;
+
+use clap::Args;
+use mwa_hyperbeam::fee::{beam_file_search_paths, FEEBeam};
+
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub(crate) struct InfoArgs {
+    /// The path to the FEE beam HDF5 file to describe. If not given,
+    /// MWA_BEAM_FILE is used, then the directories given by --beam-dir, then
+    /// the XDG data directories (e.g. ~/.local/share/mwa_hyperbeam).
+    #[arg(long)]
+    beam_file: Option<PathBuf>,
+
+    /// A directory to look for the FEE beam file in, if --beam-file isn't
+    /// given. Can be given multiple times.
+    #[arg(long)]
+    beam_dir: Vec<PathBuf>,
+
+    /// Print the paths where the FEE beam file is looked for, rather than
+    /// describing it.
+    #[arg(long)]
+    search_paths: bool,
+}
+
+impl InfoArgs {
+    pub(crate) fn run(self) -> Result<(), CliError> {
+        if self.search_paths {
+            for path in beam_file_search_paths(&self.beam_dir) {
+                println!("{}", path.display());
+            }
+            return Ok(());
+        }
+
+        let beam = match &self.beam_file {
+            Some(f) => FEEBeam::new(f)?,
+            None => FEEBeam::new_from_search(&self.beam_dir)?,
+        };
+        println!("{}", beam.metadata()?.to_json());
+        Ok(())
+    }
+}
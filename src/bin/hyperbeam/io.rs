@@ -0,0 +1,481 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Reading directions and writing beam responses.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use mwa_hyperbeam::{npy, Jones};
+use num_complex::Complex64 as c64;
+
+use crate::error::CliError;
+
+/// The supported file formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FileFormat {
+    Csv,
+    Npy,
+    Json,
+}
+
+impl FileFormat {
+    /// Determine the format of a file from its extension.
+    pub(crate) fn from_path(path: &Path) -> Result<FileFormat, CliError> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("csv") | Some("txt") => Ok(FileFormat::Csv),
+            Some("npy") => Ok(FileFormat::Npy),
+            Some("json") => Ok(FileFormat::Json),
+            _ => Err(CliError::UnknownExtension(path.to_path_buf())),
+        }
+    }
+}
+
+/// Read pairs of coordinates from a CSV or `.npy` file. CSV files may have a
+/// header line and comment lines (starting with '#'); only the first two
+/// columns are used. `.npy` files must contain a 2D array with two columns.
+pub(crate) fn read_coord_pairs(path: &Path) -> Result<Vec<(f64, f64)>, CliError> {
+    match FileFormat::from_path(path)? {
+        FileFormat::Csv => read_csv_pairs(path),
+        FileFormat::Npy => {
+            let mut f = BufReader::new(File::open(path)?);
+            let (shape, data) = npy::read_npy_f64(&mut f)?;
+            match shape.as_slice() {
+                [_, 2] => Ok(data.chunks_exact(2).map(|c| (c[0], c[1])).collect()),
+                _ => Err(CliError::NpyShape {
+                    file: path.to_path_buf(),
+                    shape,
+                }),
+            }
+        }
+        FileFormat::Json => Err(CliError::UnknownExtension(path.to_path_buf())),
+    }
+}
+
+fn read_csv_pairs(path: &Path) -> Result<Vec<(f64, f64)>, CliError> {
+    let f = BufReader::new(File::open(path)?);
+    let mut pairs = vec![];
+    let mut first_content_line = true;
+    for (i_line, line) in f.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let is_first = std::mem::take(&mut first_content_line);
+
+        let mut cols = line.split(',').map(|c| c.trim());
+        let (c1, c2) = (cols.next().unwrap_or(""), cols.next().unwrap_or(""));
+        match (c1.parse(), c2.parse()) {
+            (Ok(a), Ok(b)) => pairs.push((a, b)),
+            // Allow a header line before any data.
+            _ if is_first => (),
+            _ => {
+                return Err(CliError::CsvParse {
+                    file: path.to_path_buf(),
+                    line: i_line + 1,
+                    msg: format!("couldn't parse '{c1}' and '{c2}' as numbers"),
+                })
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// The quantity derived from beam-response Jones matrices that gets written
+/// out.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum Quantity {
+    /// The complex Jones matrix [j00, j01, j10, j11].
+    Jones,
+    /// The real 4x4 Mueller matrix mapping Stokes (I, Q, U, V) to
+    /// instrumental Stokes. This is only meaningful when the Jones matrices
+    /// are in the IAU order.
+    Mueller,
+    /// The instrumental power of each polarisation for an unpolarised source
+    /// [XX, YY].
+    Power,
+}
+
+impl Quantity {
+    /// The names of the real-valued columns of this quantity.
+    pub(crate) fn columns(self) -> Vec<String> {
+        match self {
+            Quantity::Jones => ["j00", "j01", "j10", "j11"]
+                .iter()
+                .flat_map(|j| [format!("{j}_re"), format!("{j}_im")])
+                .collect(),
+            Quantity::Mueller => (0..4)
+                .flat_map(|r| (0..4).map(move |c| format!("m{r}{c}")))
+                .collect(),
+            Quantity::Power => vec!["xx".to_string(), "yy".to_string()],
+        }
+    }
+
+    /// Get the real values of this quantity from a Jones matrix.
+    pub(crate) fn values(self, j: Jones<f64>) -> Vec<f64> {
+        match self {
+            Quantity::Jones => j.to_float_array().to_vec(),
+            Quantity::Mueller => jones_to_mueller(j).into_iter().flatten().collect(),
+            Quantity::Power => {
+                vec![
+                    j[0].norm_sqr() + j[1].norm_sqr(),
+                    j[2].norm_sqr() + j[3].norm_sqr(),
+                ]
+            }
+        }
+    }
+}
+
+/// Convert a beam-response Jones matrix to a Mueller matrix, i.e. `M = S (J ⊗
+/// J*) S^-1`, where `S` converts linear-feed coherencies [XX XY YX YY] to
+/// Stokes [I Q U V].
+pub(crate) fn jones_to_mueller(j: Jones<f64>) -> [[f64; 4]; 4] {
+    let i = c64::i();
+    let one = c64::new(1.0, 0.0);
+    let zero = c64::default();
+    let s = [
+        [one, zero, zero, one],
+        [one, zero, zero, -one],
+        [zero, one, one, zero],
+        [zero, -i, i, zero],
+    ];
+    let s_inv = [
+        [one * 0.5, one * 0.5, zero, zero],
+        [zero, zero, one * 0.5, i * 0.5],
+        [zero, zero, one * 0.5, -i * 0.5],
+        [one * 0.5, -one * 0.5, zero, zero],
+    ];
+
+    // The Kronecker product J ⊗ J*.
+    let mut kron = [[zero; 4]; 4];
+    for (r, row) in kron.iter_mut().enumerate() {
+        for (c, k) in row.iter_mut().enumerate() {
+            *k = j[(r / 2) * 2 + c / 2] * j[(r % 2) * 2 + c % 2].conj();
+        }
+    }
+
+    let matmul = |a: &[[c64; 4]; 4], b: &[[c64; 4]; 4]| {
+        let mut out = [[zero; 4]; 4];
+        for (r, row) in out.iter_mut().enumerate() {
+            for (c, o) in row.iter_mut().enumerate() {
+                *o = (0..4).map(|k| a[r][k] * b[k][c]).sum();
+            }
+        }
+        out
+    };
+    let m = matmul(&matmul(&s, &kron), &s_inv);
+    m.map(|row| row.map(|c| c.re))
+}
+
+/// Beam responses to be written out, arranged by frequency then direction.
+pub(crate) struct Results<'a> {
+    pub(crate) freqs_hz: &'a [u32],
+    pub(crate) az_rad: &'a [f64],
+    pub(crate) za_rad: &'a [f64],
+    /// One vector of Jones matrices per frequency.
+    pub(crate) jones: &'a [Vec<Jones<f64>>],
+    pub(crate) quantity: Quantity,
+}
+
+/// Write results to the given file, or to stdout as CSV if no file is given.
+pub(crate) fn write_results(results: &Results, output: Option<&PathBuf>) -> Result<(), CliError> {
+    let Some(output) = output else {
+        let stdout = std::io::stdout();
+        return write_csv(results, &mut stdout.lock());
+    };
+
+    let format = FileFormat::from_path(output)?;
+    let mut f = BufWriter::new(File::create(output)?);
+    match format {
+        FileFormat::Csv => write_csv(results, &mut f)?,
+        FileFormat::Npy => write_npy(results, &mut f)?,
+        FileFormat::Json => write_json(results, &mut f)?,
+    }
+    f.flush()?;
+    Ok(())
+}
+
+fn write_csv<W: Write>(results: &Results, w: &mut W) -> Result<(), CliError> {
+    writeln!(
+        w,
+        "freq_hz,az_rad,za_rad,{}",
+        results.quantity.columns().join(",")
+    )?;
+    for (&freq, jones) in results.freqs_hz.iter().zip(results.jones) {
+        for ((&az, &za), &j) in results.az_rad.iter().zip(results.za_rad).zip(jones) {
+            write!(w, "{freq},{az},{za}")?;
+            for v in results.quantity.values(j) {
+                write!(w, ",{v:e}")?;
+            }
+            writeln!(w)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_npy<W: Write>(results: &Results, w: &mut W) -> Result<(), CliError> {
+    let num_freqs = results.freqs_hz.len();
+    let num_dirs = results.az_rad.len();
+    match results.quantity {
+        // Jones matrices are written as complex numbers, like the Python
+        // interface.
+        Quantity::Jones => {
+            let data: Vec<c64> = results
+                .jones
+                .iter()
+                .flatten()
+                .flat_map(|j| j.to_complex_array())
+                .collect();
+            npy::write_npy(w, &[num_freqs, num_dirs, 4], &data)?;
+        }
+        Quantity::Mueller => {
+            let data: Vec<f64> = results
+                .jones
+                .iter()
+                .flatten()
+                .flat_map(|&j| results.quantity.values(j))
+                .collect();
+            npy::write_npy(w, &[num_freqs, num_dirs, 4, 4], &data)?;
+        }
+        Quantity::Power => {
+            let data: Vec<f64> = results
+                .jones
+                .iter()
+                .flatten()
+                .flat_map(|&j| results.quantity.values(j))
+                .collect();
+            npy::write_npy(w, &[num_freqs, num_dirs, 2], &data)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_json<W: Write>(results: &Results, w: &mut W) -> Result<(), CliError> {
+    let values: Vec<Vec<Vec<f64>>> = results
+        .jones
+        .iter()
+        .map(|jones| jones.iter().map(|&j| results.quantity.values(j)).collect())
+        .collect();
+    let json = serde_json::json!({
+        "quantity": format!("{:?}", results.quantity).to_lowercase(),
+        "freqs_hz": results.freqs_hz,
+        "az_rad": results.az_rad,
+        "za_rad": results.za_rad,
+        "columns": results.quantity.columns(),
+        "values": values,
+    });
+    serde_json::to_writer_pretty(&mut *w, &json)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_mueller_identity() {
+        let m = jones_to_mueller(Jones::identity());
+        for (r, row) in m.iter().enumerate() {
+            for (c, &v) in row.iter().enumerate() {
+                assert_abs_diff_eq!(v, if r == c { 1.0 } else { 0.0 }, epsilon = 1e-15);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mueller_unpolarised_power() {
+        // For an unpolarised source, the instrumental Stokes I is the
+        // average of the XX and YY powers.
+        let j = Jones::from([
+            c64::new(0.5, 0.1),
+            c64::new(0.02, -0.01),
+            c64::new(-0.03, 0.0),
+            c64::new(0.4, -0.2),
+        ]);
+        let m = jones_to_mueller(j);
+        let p = Quantity::Power.values(j);
+        assert_abs_diff_eq!(m[0][0], (p[0] + p[1]) / 2.0, epsilon = 1e-15);
+        assert_abs_diff_eq!(m[1][0], (p[0] - p[1]) / 2.0, epsilon = 1e-15);
+    }
+
+    fn test_jones() -> Vec<Vec<Jones<f64>>> {
+        (0..2)
+            .map(|i_freq| {
+                (0..3)
+                    .map(|i_dir| {
+                        let f = (1 + i_freq * 3 + i_dir) as f64;
+                        Jones::from([
+                            c64::new(0.5 * f, 0.1),
+                            c64::new(0.02, -0.01 * f),
+                            c64::new(-0.03 * f, 0.0),
+                            c64::new(0.4, -0.2 * f),
+                        ])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn test_results(jones: &[Vec<Jones<f64>>], quantity: Quantity) -> Results<'_> {
+        Results {
+            freqs_hz: &[150_000_000, 200_000_000],
+            az_rad: &[0.0, 0.5, 1.0],
+            za_rad: &[0.1, 0.2, 0.3],
+            jones,
+            quantity,
+        }
+    }
+
+    /// The expected values of a quantity, arranged by frequency then direction.
+    fn expected_values(jones: &[Vec<Jones<f64>>], quantity: Quantity) -> Vec<f64> {
+        jones
+            .iter()
+            .flatten()
+            .flat_map(|&j| quantity.values(j))
+            .collect()
+    }
+
+    #[test]
+    fn test_file_format_from_path() {
+        assert_eq!(
+            FileFormat::from_path(Path::new("a.csv")).unwrap(),
+            FileFormat::Csv
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("a.TXT")).unwrap(),
+            FileFormat::Csv
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("a.npy")).unwrap(),
+            FileFormat::Npy
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("a.json")).unwrap(),
+            FileFormat::Json
+        );
+        assert!(matches!(
+            FileFormat::from_path(Path::new("a.fits")),
+            Err(CliError::UnknownExtension(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let jones = test_jones();
+        for quantity in [Quantity::Jones, Quantity::Mueller, Quantity::Power] {
+            let results = test_results(&jones, quantity);
+            let mut out = vec![];
+            write_csv(&results, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            let mut lines = out.lines();
+
+            let header = lines.next().unwrap();
+            let columns = quantity.columns();
+            assert_eq!(
+                header,
+                format!("freq_hz,az_rad,za_rad,{}", columns.join(","))
+            );
+
+            let rows: Vec<Vec<f64>> = lines
+                .map(|l| l.split(',').map(|v| v.parse().unwrap()).collect())
+                .collect();
+            assert_eq!(rows.len(), 6);
+            for (i, row) in rows.iter().enumerate() {
+                let (i_freq, i_dir) = (i / 3, i % 3);
+                assert_eq!(row.len(), 3 + columns.len());
+                assert_eq!(row[0], f64::from(results.freqs_hz[i_freq]));
+                assert_eq!(row[1], results.az_rad[i_dir]);
+                assert_eq!(row[2], results.za_rad[i_dir]);
+            }
+            let values: Vec<f64> = rows.iter().flat_map(|r| r[3..].to_vec()).collect();
+            assert_abs_diff_eq!(
+                values.as_slice(),
+                expected_values(&jones, quantity).as_slice(),
+                epsilon = 1e-15
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_npy() {
+        let jones = test_jones();
+
+        // Jones matrices are written as complex numbers.
+        let mut out = vec![];
+        write_npy(&test_results(&jones, Quantity::Jones), &mut out).unwrap();
+        let header_len = usize::from(u16::from_le_bytes([out[8], out[9]]));
+        let header = String::from_utf8_lossy(&out[10..10 + header_len]);
+        assert!(header.contains("'descr': '<c16'"), "{header}");
+        assert!(header.contains("'shape': (2, 3, 4)"), "{header}");
+        let values: Vec<f64> = out[10 + header_len..]
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, expected_values(&jones, Quantity::Jones).as_slice());
+
+        for (quantity, shape) in [
+            (Quantity::Mueller, vec![2, 3, 4, 4]),
+            (Quantity::Power, vec![2, 3, 2]),
+        ] {
+            let mut out = vec![];
+            write_npy(&test_results(&jones, quantity), &mut out).unwrap();
+            let (read_shape, values) = npy::read_npy_f64(&mut out.as_slice()).unwrap();
+            assert_eq!(read_shape, shape);
+            assert_eq!(values, expected_values(&jones, quantity));
+        }
+    }
+
+    #[test]
+    fn test_write_json() {
+        let jones = test_jones();
+        for quantity in [Quantity::Jones, Quantity::Mueller, Quantity::Power] {
+            let results = test_results(&jones, quantity);
+            let mut out = vec![];
+            write_json(&results, &mut out).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+            assert_eq!(
+                json["quantity"],
+                format!("{quantity:?}").to_lowercase().as_str()
+            );
+            assert_eq!(json["freqs_hz"], serde_json::json!(results.freqs_hz));
+            assert_eq!(json["az_rad"], serde_json::json!(results.az_rad));
+            assert_eq!(json["za_rad"], serde_json::json!(results.za_rad));
+            assert_eq!(json["columns"], serde_json::json!(quantity.columns()));
+
+            let values = json["values"].as_array().unwrap();
+            assert_eq!(values.len(), 2);
+            let values: Vec<f64> = values
+                .iter()
+                .flat_map(|per_freq| {
+                    let per_freq = per_freq.as_array().unwrap();
+                    assert_eq!(per_freq.len(), 3);
+                    per_freq.iter().flat_map(|per_dir| {
+                        let per_dir = per_dir.as_array().unwrap();
+                        assert_eq!(per_dir.len(), quantity.columns().len());
+                        per_dir.iter().map(|v| v.as_f64().unwrap())
+                    })
+                })
+                .collect();
+            assert_abs_diff_eq!(
+                values.as_slice(),
+                expected_values(&jones, quantity).as_slice(),
+                epsilon = 1e-15
+            );
+        }
+    }
+}
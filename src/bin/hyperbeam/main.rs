@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `hyperbeam` command-line tool. This allows people to query the beam
+//! without writing any code.
+
+mod beam;
+mod calc;
+mod error;
+mod gridpoint;
+mod healpix;
+mod image;
+mod info;
+mod io;
+mod obs;
+mod subset;
+
+use clap::{Parser, Subcommand};
+
+use error::CliError;
+
+#[derive(Parser)]
+#[command(author, version, about, propagate_version = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Calculate beam responses for directions read from a file.
+    Calc(calc::CalcArgs),
+
+    /// Write FITS images of the beam on a sky pixel grid.
+    Image(image::ImageArgs),
+
+    /// Write all-sky HEALPix maps of the beam.
+    Healpix(healpix::HealpixArgs),
+
+    /// Write a smaller FEE beam file with only some frequencies.
+    Subset(subset::SubsetArgs),
+
+    /// Describe an FEE beam file (checksum, frequencies, modes and
+    /// attributes) as JSON.
+    Info(info::InfoArgs),
+}
+
+fn main() {
+    let args = Args::parse();
+    let result: Result<(), CliError> = match args.command {
+        Command::Calc(args) => args.run(),
+        Command::Image(args) => args.run(),
+        Command::Healpix(args) => args.run(),
+        Command::Subset(args) => args.run(),
+        Command::Info(args) => args.run(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,69 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Observation-related arguments (time and telescope location) shared by
+//! subcommands.
+
+use std::str::FromStr;
+
+use clap::Args;
+use marlu::{
+    constants::{MWA_LAT_DEG, MWA_LONG_DEG},
+    hifitime::Epoch,
+};
+use mwa_hyperbeam::image::Observer;
+
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub(crate) struct ObsArgs {
+    /// The UTC time of the observation, either as a string (e.g.
+    /// "2024-01-01T12:00:00 UTC") or GPS seconds.
+    #[arg(long)]
+    time: Option<String>,
+
+    /// The local sidereal time of the observation [degrees]. Used instead of
+    /// --time.
+    #[arg(long, conflicts_with = "time")]
+    lst_deg: Option<f64>,
+
+    /// The longitude of the telescope [degrees]. Only used to get the LST from
+    /// --time.
+    #[arg(long, allow_negative_numbers = true, default_value_t = MWA_LONG_DEG)]
+    longitude_deg: f64,
+
+    /// The latitude of the telescope [degrees]. This is used to convert RA/Dec
+    /// directions, by the RTS-style analytic beam, and for the
+    /// parallactic-angle correction.
+    #[arg(long, allow_negative_numbers = true, default_value_t = MWA_LAT_DEG)]
+    latitude_deg: f64,
+}
+
+impl ObsArgs {
+    pub(crate) fn latitude_rad(&self) -> f64 {
+        self.latitude_deg.to_radians()
+    }
+
+    /// Get the observer from these arguments. This fails if neither a time
+    /// nor an LST was given.
+    pub(crate) fn observer(&self) -> Result<Observer, CliError> {
+        if let Some(lst_deg) = self.lst_deg {
+            return Ok(Observer {
+                lst_rad: lst_deg.to_radians(),
+                latitude_rad: self.latitude_rad(),
+                epoch: None,
+            });
+        }
+        let time = self.time.as_deref().ok_or(CliError::NoTime)?;
+        let epoch = match time.parse::<f64>() {
+            Ok(gps) => Epoch::from_gpst_seconds(gps),
+            Err(_) => Epoch::from_str(time).map_err(|_| CliError::BadTime(time.to_string()))?,
+        };
+        Ok(Observer::from_epoch(
+            epoch,
+            self.longitude_deg.to_radians(),
+            self.latitude_rad(),
+        ))
+    }
+}
@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The `subset` subcommand: write a smaller FEE beam file containing only
+//! some frequencies (and optionally fewer modes).
+
+use std::path::PathBuf;
+
+use clap::Args;
+use mwa_hyperbeam::fee::FEEBeam;
+
+use crate::error::CliError;
+
+#[derive(Args, Debug)]
+pub(crate) struct SubsetArgs {
+    /// The path to the FEE beam HDF5 file to read. If not given,
+    /// MWA_BEAM_FILE is used, then the directories given by --beam-dir, then
+    /// the XDG data directories (e.g. ~/.local/share/mwa_hyperbeam).
+    #[arg(long)]
+    beam_file: Option<PathBuf>,
+
+    /// A directory to look for the FEE beam file in, if --beam-file isn't
+    /// given. Can be given multiple times.
+    #[arg(long)]
+    beam_dir: Vec<PathBuf>,
+
+    /// The lowest and highest frequencies that will be used with the new file
+    /// [Hz]. All of the file's frequencies that are closest to a frequency in
+    /// this range are kept.
+    #[arg(long, value_delimiter = ',', num_args = 2, conflicts_with = "freqs_hz")]
+    freq_range_hz: Option<Vec<f64>>,
+
+    /// The frequencies to keep [Hz]. Each is rounded to the closest frequency
+    /// in the file.
+    #[arg(short, long, value_delimiter = ',', num_args = 1..)]
+    freqs_hz: Option<Vec<f64>>,
+
+    /// Only keep modes with N up to this value. This makes the file smaller,
+    /// but reduces the accuracy of the beam.
+    #[arg(long)]
+    max_n: Option<u8>,
+
+    /// The new beam file.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+impl SubsetArgs {
+    pub(crate) fn run(self) -> Result<(), CliError> {
+        let beam = match &self.beam_file {
+            Some(f) => FEEBeam::new(f)?,
+            None => FEEBeam::new_from_search(&self.beam_dir)?,
+        };
+        let freqs_hz = match (&self.freq_range_hz, &self.freqs_hz) {
+            (Some(range), _) => {
+                beam.get_freqs_for_range(range[0].round() as u32, range[1].round() as u32)
+            }
+            (None, Some(freqs)) => freqs
+                .iter()
+                .map(|&f| beam.find_closest_freq(f.round() as u32))
+                .collect(),
+            (None, None) => return Err(CliError::NoSubsetFreqs),
+        };
+
+        beam.write_subset(&self.output, &freqs_hz, self.max_n)?;
+        eprintln!(
+            "Wrote {} frequencies ({} to {} Hz) to '{}'",
+            freqs_hz.len(),
+            freqs_hz.iter().min().unwrap(),
+            freqs_hz.iter().max().unwrap(),
+            self.output.display()
+        );
+        Ok(())
+    }
+}
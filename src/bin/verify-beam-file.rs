@@ -2,39 +2,89 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-//! This program calculates the "m_accum" and "n_accum" for all frequencies
-//! specified in the given HDF5 file to verify the file's data. hyperbeam used
-//! to have runtime checks for this, but it's much easier on the computer to not
-//! check something that likely never happens.
+//! This program thoroughly checks the data in FEE beam HDF5 files; see
+//! `FEEBeam::validate` for the checks. hyperbeam used to have runtime checks
+//! for some of this, but it's much easier on the computer to not check
+//! something that likely never happens.
+//!
+//! A JSON report is written to stdout and a summary to stderr. The exit code
+//! is non-zero if any file has a problem.
+//!
+//! Usage: verify-beam-file [--max-jump <value>] <beam file>...
 
-use mwa_hyperbeam::fee::{FEEBeam, InitFEEBeamError};
+use mwa_hyperbeam::{fee::FEEBeam, json::json_string};
+
+/// The default largest allowed change in the magnitudes of zenith-normalised
+/// Jones matrix elements between adjacent frequencies.
+const DEFAULT_MAX_JUMP: f64 = 0.1;
 
 fn main() {
-    // Test each input file.
-    for beam_file in std::env::args().skip(1) {
-        // If this threw an error, it was during initialisation.
-        if let Err(e) = test_file(&beam_file) {
-            println!("File '{}' failed to create an FEEBeam: {}", &beam_file, e);
+    let mut max_jump = DEFAULT_MAX_JUMP;
+    let mut beam_files = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-jump" => match args.next().and_then(|v| v.parse().ok()) {
+                Some(v) => max_jump = v,
+                None => {
+                    eprintln!("--max-jump needs a number");
+                    std::process::exit(2);
+                }
+            },
+            "-h" | "--help" => {
+                eprintln!("Usage: verify-beam-file [--max-jump <value>] <beam file>...");
+                return;
+            }
+            _ => beam_files.push(arg),
         }
     }
-}
+    if beam_files.is_empty() {
+        eprintln!("Usage: verify-beam-file [--max-jump <value>] <beam file>...");
+        std::process::exit(2);
+    }
 
-fn test_file(beam_file: &str) -> Result<(), InitFEEBeamError> {
-    println!("Testing file '{beam_file}'");
-    let beam = FEEBeam::new(beam_file)?;
-    // It does not matter what the direction, delays or amps are, the m_accum
-    // and n_accum that we're interested in testing depend only on the
-    // frequency. (Frequency determined the number of dipole coeffs, which
-    // determines which m_accum and n_accum values are used.)
-    for &file_freq in beam.get_freqs() {
-        println!("Testing freq {file_freq}");
-        // If this blows up, we know there's a problem...
-        beam.calc_jones_pair(
-            0.0, 0.0, file_freq, &[0; 16], &[1.0; 16], false, None, false,
-        )
-        .unwrap();
+    // Test each input file.
+    let mut all_passed = true;
+    let mut reports = vec![];
+    for beam_file in &beam_files {
+        eprintln!("Testing file '{beam_file}'");
+        let file_json = json_string(beam_file);
+        match FEEBeam::new(beam_file) {
+            Ok(beam) => {
+                let report = beam.validate(max_jump);
+                for f in &report.freqs {
+                    for p in &f.problems {
+                        eprintln!("  {} Hz: {p}", f.freq_hz);
+                    }
+                }
+                if report.passed() {
+                    eprintln!("File '{beam_file}' is all good!");
+                } else {
+                    eprintln!("File '{beam_file}' has problems");
+                    all_passed = false;
+                }
+                reports.push(format!(
+                    "{{\"file\": {file_json}, \"error\": null, \"report\": {}}}",
+                    report.to_json()
+                ));
+            }
+            // If this threw an error, it was during initialisation.
+            Err(e) => {
+                eprintln!("File '{beam_file}' failed to create an FEEBeam: {e}");
+                all_passed = false;
+                reports.push(format!(
+                    "{{\"file\": {file_json}, \"error\": {}, \"report\": null}}",
+                    json_string(&e.to_string())
+                ));
+            }
+        }
     }
 
-    println!("File '{beam_file}' is all good!");
-    Ok(())
+    println!(
+        "{{\"passed\": {all_passed}, \"files\": [{}]}}",
+        reports.join(", ")
+    );
+    if !all_passed {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Information on how hyperbeam was built, so that callers can check which
+//! library they're using at runtime.
+
+use std::os::raw::c_char;
+
+/// Information on how this copy of hyperbeam was built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// The version of hyperbeam, e.g. "0.10.1".
+    pub version: &'static str,
+
+    /// The Cargo features that hyperbeam was built with, separated by commas
+    /// (e.g. "cuda,python"). Only the features that change what hyperbeam can
+    /// do are included: cli, cuda, gpu-single, hdf5-static, hip and python.
+    /// See [`BuildInfo::features`].
+    pub features_str: &'static str,
+
+    /// The GPU backend ("cuda" or "hip"), if hyperbeam was built with GPU
+    /// support.
+    pub gpu_backend: Option<&'static str>,
+
+    /// The size of a GPU float in bytes (4 if built with the "gpu-single"
+    /// feature, otherwise 8), if hyperbeam was built with GPU support.
+    pub gpu_float_size: Option<usize>,
+}
+
+impl BuildInfo {
+    /// The Cargo features that hyperbeam was built with.
+    pub fn features(&self) -> impl Iterator<Item = &'static str> {
+        self.features_str.split(',').filter(|f| !f.is_empty())
+    }
+
+    /// Was hyperbeam built with GPU support?
+    pub fn has_gpu(&self) -> bool {
+        self.gpu_backend.is_some()
+    }
+}
+
+/// Get information on how this copy of hyperbeam was built.
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        features_str: env!("HYPERBEAM_FEATURES"),
+        gpu_backend: GPU_BACKEND,
+        #[cfg(any(feature = "cuda", feature = "hip"))]
+        gpu_float_size: Some(std::mem::size_of::<crate::GpuFloat>()),
+        #[cfg(not(any(feature = "cuda", feature = "hip")))]
+        gpu_float_size: None,
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "cuda")] {
+        const GPU_BACKEND: Option<&str> = Some("cuda");
+    } else if #[cfg(feature = "hip")] {
+        const GPU_BACKEND: Option<&str> = Some("hip");
+    } else {
+        const GPU_BACKEND: Option<&str> = None;
+    }
+}
+
+/// Get the version of hyperbeam, e.g. "0.10.1". The string is owned by
+/// hyperbeam and must not be freed.
+#[no_mangle]
+pub extern "C" fn hb_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Get the Cargo features that hyperbeam was built with, separated by commas
+/// (e.g. "cuda,hdf5-static"). The string is owned by hyperbeam and must not
+/// be freed.
+#[no_mangle]
+pub extern "C" fn hb_build_features() -> *const c_char {
+    concat!(env!("HYPERBEAM_FEATURES"), "\0").as_ptr().cast()
+}
+
+/// Get whether hyperbeam was built with GPU support (1) or not (0).
+#[no_mangle]
+pub extern "C" fn hb_has_gpu() -> u8 {
+    GPU_BACKEND.is_some().into()
+}
+
+/// Get the GPU backend that hyperbeam was built with; "cuda", "hip", or
+/// "none" if hyperbeam was built without GPU support. The string is owned by
+/// hyperbeam and must not be freed.
+#[no_mangle]
+pub extern "C" fn hb_gpu_backend() -> *const c_char {
+    let backend: &'static str = match GPU_BACKEND {
+        Some("cuda") => "cuda\0",
+        Some(_) => "hip\0",
+        None => "none\0",
+    };
+    backend.as_ptr().cast()
+}
+
+/// Get the size of the floats used by hyperbeam's GPU code in bytes; 4 if
+/// hyperbeam was built with the "gpu-single" feature, otherwise 8. If
+/// hyperbeam was built without GPU support, this is 0.
+#[no_mangle]
+pub extern "C" fn hb_gpu_float_size() -> usize {
+    build_info().gpu_float_size.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        // Other features (e.g. "default" and "all-static") aren't reported.
+        let expected: Vec<&str> = [
+            ("cli", cfg!(feature = "cli")),
+            ("cuda", cfg!(feature = "cuda")),
+            ("gpu-single", cfg!(feature = "gpu-single")),
+            ("hdf5-static", cfg!(feature = "hdf5-static")),
+            ("hip", cfg!(feature = "hip")),
+            ("python", cfg!(feature = "python")),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| feature)
+        .collect();
+        assert_eq!(info.features().collect::<Vec<_>>(), expected);
+        assert_eq!(info.has_gpu(), cfg!(any(feature = "cuda", feature = "hip")));
+        assert_eq!(info.has_gpu(), info.gpu_float_size.is_some());
+
+        unsafe {
+            assert_eq!(CStr::from_ptr(hb_version()).to_str().unwrap(), info.version);
+            assert_eq!(
+                CStr::from_ptr(hb_build_features()).to_str().unwrap(),
+                info.features_str
+            );
+            assert_eq!(
+                CStr::from_ptr(hb_gpu_backend()).to_str().unwrap(),
+                info.gpu_backend.unwrap_or("none")
+            );
+        }
+        assert_eq!(hb_has_gpu() == 1, info.has_gpu());
+        assert_eq!(hb_gpu_float_size(), info.gpu_float_size.unwrap_or(0));
+    }
+}
@@ -33,9 +33,28 @@ pub enum InitFEEBeamError {
     #[error("Problem with the MWA_BEAM_FILE variable: {0}")]
     MwaBeamFileVarError(#[from] std::env::VarError),
 
+    #[error("No FEE beam files were given")]
+    NoBeamFiles,
+
+    /// The modes of two beam files don't agree.
+    #[error("The modes of beam file '{other}' aren't compatible with those of '{first}'")]
+    IncompatibleModes { first: String, other: String },
+
+    /// A frequency is defined in more than one beam file.
+    #[error("Frequency {freq_hz} Hz is defined in more than one beam file: {}", .files.join(", "))]
+    OverlappingFreqs { freq_hz: u32, files: Vec<String> },
+
+    /// No FEE beam file was found in any of the searched locations.
+    #[error("Couldn't find an FEE beam file; looked for {}", .0.iter().map(|p| format!("'{}'", p.display())).collect::<Vec<_>>().join(", "))]
+    BeamFileNotFound(Vec<std::path::PathBuf>),
+
     #[error("Unexpected array shape when reading HDF5 dataset 'modes': expected 3 rows")]
     ModesShape,
 
+    /// An error associated with reading a beam file into memory.
+    #[error("Couldn't read the beam file: {0}")]
+    Io(#[from] std::io::Error),
+
     /// An error associated with the hdf5_metno crate.
     #[error("HDF5 error: {0}")]
     Hdf5(#[from] hdf5_metno::Error),
@@ -63,9 +82,34 @@ pub enum FEEBeamError {
     #[error("The number of delays wasn't 16 (got {0}); these must either correspond to bowties in the M&C order")]
     IncorrectDelaysLength(usize),
 
+    #[error("Dipole {0} doesn't exist; dipoles are numbered 0 to 15 in the M&C order")]
+    InvalidDipole(usize),
+
+    #[error("Got {delays} rows of delays but {amps} rows of amps; these must be the same")]
+    TileCountMismatch { delays: usize, amps: usize },
+
+    #[error("Got {az} azimuths but {za} zenith angles; these must be the same")]
+    DirectionCountMismatch { az: usize, za: usize },
+
     #[error("The number of delays wasn't 16 (got {rows} tiles with {num_delays} each); each tile's 16 delays these must correspond to bowties in the M&C order")]
     IncorrectDelaysArrayColLength { rows: usize, num_delays: usize },
 
+    #[error("No frequencies were selected for the new beam file")]
+    SubsetNoFreqs,
+
+    #[error("Frequency {0} Hz is not defined in the beam file")]
+    SubsetFreqNotInFile(u32),
+
+    #[error("No modes would be left in the new beam file")]
+    SubsetNoModes,
+
+    #[error("The beam is made from {0} files, so it can't be a single file")]
+    MultipleFiles(usize),
+
+    /// An error associated with reading the beam file to identify it.
+    #[error("Couldn't read the beam file: {0}")]
+    Io(#[from] std::io::Error),
+
     /// An error associated with the hdf5_metno crate.
     #[error("HDF5 error: {0}")]
     Hdf5Error(#[from] hdf5_metno::Error),
@@ -13,7 +13,13 @@ use std::{ffi::CStr, os::raw::c_char, panic, slice};
 use rayon::iter::Either;
 
 use super::FEEBeam;
-use crate::ffi::{ffi_error, update_last_error};
+use crate::{
+    ffi::{
+        ffi_error, hb_error_code::*, hb_error_details, update_last_error, update_last_error_from,
+        update_last_error_with,
+    },
+    Parallelism,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(any(feature = "cuda", feature = "hip"))] {
@@ -34,10 +40,11 @@ cfg_if::cfg_if! {
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[no_mangle]
 pub unsafe extern "C" fn new_fee_beam(
@@ -45,23 +52,75 @@ pub unsafe extern "C" fn new_fee_beam(
     fee_beam: *mut *mut FEEBeam,
 ) -> i32 {
     panic::set_hook(Box::new(|pi| {
-        update_last_error(panic_message::panic_info_message(pi).to_string());
+        update_last_error(HB_PANIC, panic_message::panic_info_message(pi).to_string());
     }));
 
     let result = panic::catch_unwind(|| {
         let path = match CStr::from_ptr(hdf5_file).to_str() {
             Ok(p) => p,
             Err(e) => {
-                update_last_error(e.to_string());
-                return Either::Left(2);
+                return Either::Left(update_last_error_from(&e));
             }
         };
         match FEEBeam::new(path) {
             Ok(b) => Either::Right(b),
-            Err(e) => {
-                update_last_error(e.to_string());
-                Either::Left(1)
-            }
+            Err(e) => Either::Left(update_last_error_from(&e)),
+        }
+    });
+
+    let _ = panic::take_hook();
+
+    match result {
+        Ok(Either::Right(b)) => {
+            *fee_beam = Box::into_raw(Box::new(b));
+            0
+        }
+        Ok(Either::Left(e)) => e,
+        // For panics, the FFI error message is already updated.
+        Err(_) => -1,
+    }
+}
+
+/// Create a new MWA FEE beam from the contents of an MWA FEE beam file held in
+/// memory. The contents are copied, so the buffer can be freed after this
+/// function returns.
+///
+/// # Arguments
+///
+/// * `buffer` - a pointer to the contents of the MWA FEE beam file.
+/// * `buffer_len` - the number of bytes in `buffer`.
+/// * `fee_beam` - a double pointer to the `FEEBeam` struct which is set by this
+///   function. This struct must be freed by calling `free_fee_beam`.
+///
+/// # Returns
+///
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
+///
+#[no_mangle]
+pub unsafe extern "C" fn new_fee_beam_from_buffer(
+    buffer: *const u8,
+    buffer_len: usize,
+    fee_beam: *mut *mut FEEBeam,
+) -> i32 {
+    panic::set_hook(Box::new(|pi| {
+        update_last_error(HB_PANIC, panic_message::panic_info_message(pi).to_string());
+    }));
+
+    let result = panic::catch_unwind(|| {
+        if buffer.is_null() {
+            return Either::Left(update_last_error(
+                HB_ERROR_INVALID_ARGUMENT,
+                "The beam file buffer was null".to_string(),
+            ));
+        }
+        let bytes = std::slice::from_raw_parts(buffer, buffer_len);
+        match FEEBeam::from_bytes(bytes) {
+            Ok(b) => Either::Right(b),
+            Err(e) => Either::Left(update_last_error_from(&e)),
         }
     });
 
@@ -88,23 +147,21 @@ pub unsafe extern "C" fn new_fee_beam(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[no_mangle]
 pub unsafe extern "C" fn new_fee_beam_from_env(fee_beam: *mut *mut FEEBeam) -> i32 {
     panic::set_hook(Box::new(|pi| {
-        update_last_error(panic_message::panic_info_message(pi).to_string());
+        update_last_error(HB_PANIC, panic_message::panic_info_message(pi).to_string());
     }));
 
     let result = panic::catch_unwind(|| match FEEBeam::new_from_env() {
         Ok(b) => Either::Right(b),
-        Err(e) => {
-            update_last_error(e.to_string());
-            Either::Left(1)
-        }
+        Err(e) => Either::Left(update_last_error_from(&e)),
     });
 
     let _ = panic::take_hook();
@@ -166,10 +223,11 @@ pub unsafe extern "C" fn new_fee_beam_from_env(fee_beam: *mut *mut FEEBeam) -> i
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[no_mangle]
 pub unsafe extern "C" fn fee_calc_jones(
@@ -188,16 +246,24 @@ pub unsafe extern "C" fn fee_calc_jones(
     match num_amps {
         16 | 32 => (),
         _ => {
-            update_last_error("A value other than 16 or 32 was used for num_amps".to_string());
-            return 1;
+            return update_last_error_with(
+                hb_error_details::lengths(
+                    HB_ERROR_INVALID_AMPS_LENGTH,
+                    num_amps as usize,
+                    &[16, 32],
+                ),
+                "A value other than 16 or 32 was used for num_amps".to_string(),
+            );
         }
     };
     let norm_bool = match norm_to_zenith {
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
         }
     };
     let latitude_rad = latitude_rad.as_ref().copied();
@@ -205,8 +271,10 @@ pub unsafe extern "C" fn fee_calc_jones(
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for iau_order".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
@@ -232,10 +300,7 @@ pub unsafe extern "C" fn fee_calc_jones(
             ]);
             0
         }
-        Err(e) => {
-            update_last_error(e.to_string());
-            1
-        }
+        Err(e) => update_last_error_from(&e),
     }
 }
 
@@ -285,10 +350,11 @@ pub unsafe extern "C" fn fee_calc_jones(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[no_mangle]
 pub unsafe extern "C" fn fee_calc_jones_array(
@@ -308,16 +374,24 @@ pub unsafe extern "C" fn fee_calc_jones_array(
     match num_amps {
         16 | 32 => (),
         _ => {
-            update_last_error("A value other than 16 or 32 was used for num_amps".to_string());
-            return 1;
+            return update_last_error_with(
+                hb_error_details::lengths(
+                    HB_ERROR_INVALID_AMPS_LENGTH,
+                    num_amps as usize,
+                    &[16, 32],
+                ),
+                "A value other than 16 or 32 was used for num_amps".to_string(),
+            );
         }
     };
     let norm_bool = match norm_to_zenith {
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
         }
     };
     let latitude_rad = latitude_rad.as_ref().copied();
@@ -325,8 +399,10 @@ pub unsafe extern "C" fn fee_calc_jones_array(
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for iau_order".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
@@ -389,6 +465,45 @@ pub unsafe extern "C" fn fee_closest_freq(fee_beam: *mut FEEBeam, freq: u32) ->
     beam.find_closest_freq(freq)
 }
 
+/// Empty the cached dipole coefficients and normalisation Jones matrices of an
+/// `FEEBeam` to recover memory.
+///
+/// # Arguments
+///
+/// * `fee_beam` - the pointer to the `FEEBeam` struct.
+///
+#[no_mangle]
+pub unsafe extern "C" fn fee_empty_cache(fee_beam: *mut FEEBeam) {
+    let beam = &*fee_beam;
+    beam.empty_cache();
+}
+
+/// Set the number of threads used by an `FEEBeam` to calculate beam responses
+/// on the CPU. By default, all beam objects share a global pool of threads
+/// whose size can be set with the `RAYON_NUM_THREADS` environment variable.
+///
+/// # Arguments
+///
+/// * `fee_beam` - the pointer to the `FEEBeam` struct.
+/// * `num_threads` - 0 to use the global pool of threads, 1 to do all
+///   calculations on a single thread, or any other number to give the beam its
+///   own pool with that many threads.
+///
+/// # Returns
+///
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
+///
+#[no_mangle]
+pub unsafe extern "C" fn fee_set_num_threads(fee_beam: *mut FEEBeam, num_threads: u32) -> i32 {
+    let beam = &mut *fee_beam;
+    ffi_error!(beam.set_parallelism(Parallelism::from_num_threads(num_threads as usize)));
+    0
+}
+
 /// Free the memory associated with an `FEEBeam`.
 ///
 /// # Arguments
@@ -426,10 +541,11 @@ pub unsafe extern "C" fn free_fee_beam(fee_beam: *mut FEEBeam) {
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -447,16 +563,24 @@ pub unsafe extern "C" fn new_gpu_fee_beam(
     match num_amps {
         16 | 32 => (),
         _ => {
-            update_last_error("A value other than 16 or 32 was used for num_amps".to_string());
-            return 1;
+            return update_last_error_with(
+                hb_error_details::lengths(
+                    HB_ERROR_INVALID_AMPS_LENGTH,
+                    num_amps as usize,
+                    &[16, 32],
+                ),
+                "A value other than 16 or 32 was used for num_amps".to_string(),
+            );
         }
     };
     let norm_bool = match norm_to_zenith {
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for norm_to_zenith".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for norm_to_zenith".to_string(),
+            );
         }
     };
 
@@ -499,10 +623,11 @@ pub unsafe extern "C" fn new_gpu_fee_beam(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -519,8 +644,10 @@ pub unsafe extern "C" fn fee_calc_jones_gpu(
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for iau_order".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
@@ -569,10 +696,11 @@ pub unsafe extern "C" fn fee_calc_jones_gpu(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -589,8 +717,10 @@ pub unsafe extern "C" fn fee_calc_jones_gpu_device(
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for iau_order".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
@@ -642,10 +772,11 @@ pub unsafe extern "C" fn fee_calc_jones_gpu_device(
 ///
 /// # Returns
 ///
-/// * An exit code integer. If this is non-zero then an error occurred; the
-///   details can be obtained by (1) getting the length of the error string by
-///   calling `hb_last_error_length` and (2) calling `hb_last_error_message`
-///   with a string buffer with a length at least equal to the error length.
+/// * An exit code integer (see `hb_error_code`). If this is non-zero then an
+///   error occurred; the details can be obtained by (1) getting the length of
+///   the error string by calling `hb_last_error_length` and (2) calling
+///   `hb_last_error_message` with a string buffer with a length at least equal
+///   to the error length.
 ///
 #[cfg(any(feature = "cuda", feature = "hip"))]
 #[no_mangle]
@@ -662,8 +793,10 @@ pub unsafe extern "C" fn fee_calc_jones_gpu_device_inner(
         0 => false,
         1 => true,
         _ => {
-            update_last_error("A value other than 0 or 1 was used for iau_order".to_string());
-            return 1;
+            return update_last_error(
+                HB_ERROR_INVALID_BOOL,
+                "A value other than 0 or 1 was used for iau_order".to_string(),
+            );
         }
     };
 
@@ -11,7 +11,7 @@ use ndarray::prelude::*;
 use serial_test::serial;
 
 use super::*;
-use crate::ffi::{hb_last_error_length, hb_last_error_message};
+use crate::ffi::{hb_error_code, hb_last_error_code, hb_last_error_length, hb_last_error_message};
 
 #[cfg(any(feature = "cuda", feature = "hip"))]
 use marlu::Jones;
@@ -42,6 +42,22 @@ fn test_ffi_fee_new_from_env() {
     };
 }
 
+#[test]
+#[serial]
+fn test_ffi_fee_new_from_buffer() {
+    let bytes = std::fs::read("mwa_full_embedded_element_pattern.h5").unwrap();
+    unsafe {
+        let mut beam = null_mut();
+        let result = new_fee_beam_from_buffer(bytes.as_ptr(), bytes.len(), &mut beam);
+        assert_eq!(result, 0);
+
+        free_fee_beam(beam);
+
+        let result = new_fee_beam_from_buffer(null(), 0, &mut beam);
+        assert_eq!(result, hb_error_code::HB_ERROR_INVALID_ARGUMENT as i32);
+    };
+}
+
 #[test]
 #[serial]
 fn test_calc_jones_via_ffi() {
@@ -404,7 +420,11 @@ fn test_error_file_doesnt_exist() {
     unsafe {
         let mut beam = null_mut();
         let result = new_fee_beam(file_ptr, &mut beam);
-        assert_ne!(result, 0);
+        assert_eq!(result, hb_error_code::HB_ERROR_BEAM_FILE_MISSING as i32);
+        assert_eq!(
+            hb_last_error_code(),
+            hb_error_code::HB_ERROR_BEAM_FILE_MISSING
+        );
         drop(CString::from_raw(file_ptr));
 
         let err_len = hb_last_error_length();
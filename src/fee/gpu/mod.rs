@@ -177,7 +177,8 @@ impl FEEBeamGpu {
                 drop(fee_beam.get_modes(freq, &delays, &full_amps)?);
 
                 let fee_freq = fee_beam.find_closest_freq(freq);
-                let hash = CacheKey::new(fee_freq, &delays, &full_amps);
+                let hash =
+                    CacheKey::new(fee_freq, &delays, &full_amps, fee_beam.truncation.is_some());
                 if !unique_hashes.contains(&(hash, fee_freq)) {
                     unique_hashes.push((hash, fee_freq));
                 }
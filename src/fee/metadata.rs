@@ -0,0 +1,278 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Finding FEE beam files, identifying them and inspecting what they contain.
+//!
+//! Outputs and caches can record a beam file's SHA-256 checksum so that it's
+//! clear which beam model produced them.
+
+use std::{
+    fmt::Write as _,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use hdf5_metno::types::{TypeDescriptor, VarLenAscii, VarLenUnicode};
+use sha2::{Digest, Sha256};
+
+use super::{FEEBeam, FEEBeamError, InitFEEBeamError};
+use crate::json::json_string;
+
+/// The name of the FEE beam file distributed by the MWA.
+pub const BEAM_FILE_NAME: &str = "mwa_full_embedded_element_pattern.h5";
+
+/// Get the paths where an FEE beam file is looked for, in order:
+///
+/// 1. The `MWA_BEAM_FILE` environment variable, if it's set;
+/// 2. [`BEAM_FILE_NAME`] in each of `search_dirs`;
+/// 3. `mwa_hyperbeam/`[`BEAM_FILE_NAME`] in `$XDG_DATA_HOME` (default
+///    `~/.local/share`) and each of `$XDG_DATA_DIRS` (default
+///    `/usr/local/share:/usr/share`).
+pub fn beam_file_search_paths<P: AsRef<Path>>(search_dirs: &[P]) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if let Some(f) = std::env::var_os("MWA_BEAM_FILE") {
+        paths.push(PathBuf::from(f));
+    }
+    paths.extend(search_dirs.iter().map(|d| d.as_ref().join(BEAM_FILE_NAME)));
+
+    // Empty XDG variables are treated as unset, as the spec requires.
+    let env_dir = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty());
+    let data_home = env_dir("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env_dir("HOME").map(|h| PathBuf::from(h).join(".local/share")));
+    let data_dirs: Vec<PathBuf> = match env_dir("XDG_DATA_DIRS") {
+        Some(dirs) => std::env::split_paths(&dirs).collect(),
+        None => vec!["/usr/local/share".into(), "/usr/share".into()],
+    };
+    paths.extend(
+        data_home
+            .into_iter()
+            .chain(data_dirs)
+            .map(|d| d.join("mwa_hyperbeam").join(BEAM_FILE_NAME)),
+    );
+    paths
+}
+
+/// Find an FEE beam file; the first path from [`beam_file_search_paths`] that
+/// exists is returned.
+pub fn find_beam_file<P: AsRef<Path>>(search_dirs: &[P]) -> Result<PathBuf, InitFEEBeamError> {
+    let paths = beam_file_search_paths(search_dirs);
+    match paths.iter().find(|p| p.is_file()) {
+        Some(p) => Ok(p.clone()),
+        None => Err(InitFEEBeamError::BeamFileNotFound(paths)),
+    }
+}
+
+/// What identifies an FEE beam file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeamFileIdentity {
+    /// The SHA-256 checksum of the file, as lowercase hex.
+    pub sha256: String,
+
+    /// The size of the file [bytes].
+    pub size_bytes: u64,
+
+    /// The (absolute) path to the file, unless it was read from memory or the
+    /// beam was made from several files.
+    pub path: Option<PathBuf>,
+}
+
+/// What an FEE beam file contains at a single frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreqMetadata {
+    pub freq_hz: u32,
+
+    /// The number of coefficients in this frequency's datasets (taken from the
+    /// first dipole).
+    pub num_coeffs: usize,
+
+    /// The largest N of this frequency's modes.
+    pub n_max: u8,
+}
+
+/// The results of [`FEEBeam::metadata`].
+#[derive(Debug, Clone)]
+pub struct BeamFileMetadata {
+    pub identity: BeamFileIdentity,
+
+    /// The number of modes in the file's `modes` dataset.
+    pub num_modes: usize,
+
+    pub freqs: Vec<FreqMetadata>,
+
+    /// The attributes of the file's root group, with their values formatted
+    /// as strings.
+    pub attributes: Vec<(String, String)>,
+}
+
+impl BeamFileMetadata {
+    /// Get the metadata as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let id = &self.identity;
+        write!(
+            json,
+            "{{\"sha256\": {}, \"size_bytes\": {}, \"path\": {}, \"num_modes\": {}, \"freqs\": [",
+            json_string(&id.sha256),
+            id.size_bytes,
+            id.path
+                .as_ref()
+                .map(|p| json_string(&p.display().to_string()))
+                .unwrap_or_else(|| "null".to_string()),
+            self.num_modes,
+        )
+        .unwrap();
+        let freqs: Vec<String> = self
+            .freqs
+            .iter()
+            .map(|f| {
+                format!(
+                    "{{\"freq_hz\": {}, \"num_coeffs\": {}, \"n_max\": {}}}",
+                    f.freq_hz, f.num_coeffs, f.n_max
+                )
+            })
+            .collect();
+        json.push_str(&freqs.join(", "));
+        json.push_str("], \"attributes\": {");
+        let attributes: Vec<String> = self
+            .attributes
+            .iter()
+            .map(|(k, v)| format!("{}: {}", json_string(k), json_string(v)))
+            .collect();
+        json.push_str(&attributes.join(", "));
+        json.push_str("}}");
+        json
+    }
+}
+
+impl FEEBeam {
+    /// Create a new [`FEEBeam`] struct from the first FEE beam file found by
+    /// [`find_beam_file`].
+    pub fn new_from_search<P: AsRef<Path>>(search_dirs: &[P]) -> Result<Self, InitFEEBeamError> {
+        Self::new(find_beam_file(search_dirs)?)
+    }
+
+    /// Get the identity of this beam's HDF5 file. The checksum is calculated
+    /// the first time this is called (which involves reading the whole file)
+    /// and cached thereafter. If the beam was made from several files, the
+    /// checksum and size are those of the files concatenated in the order
+    /// that they were given.
+    pub fn identity(&self) -> Result<&BeamFileIdentity, FEEBeamError> {
+        if let Some(id) = self.identity.get() {
+            return Ok(id);
+        }
+
+        let mut hasher = Sha256::new();
+        let size_bytes = if self.file_paths.is_empty() {
+            let bytes = self.to_bytes()?;
+            hasher.update(&bytes);
+            bytes.len() as u64
+        } else {
+            let mut buf = vec![0; 1 << 20];
+            let mut size = 0;
+            for path in &self.file_paths {
+                let mut file = std::fs::File::open(path)?;
+                loop {
+                    let n = file.read(&mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                    size += n as u64;
+                }
+            }
+            size
+        };
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .fold(String::with_capacity(64), |mut s, b| {
+                write!(s, "{b:02x}").unwrap();
+                s
+            });
+        let id = BeamFileIdentity {
+            sha256,
+            size_bytes,
+            path: self.get_file_path().map(Path::to_path_buf),
+        };
+        // Another thread may have got here first; both results are the same.
+        Ok(self.identity.get_or_init(|| id))
+    }
+
+    /// Get a description of this beam's HDF5 file: its identity (see
+    /// [`FEEBeam::identity`]), the number of coefficients and the largest N at
+    /// each frequency, and the attributes of the file's root group (the first
+    /// file's, if the beam was made from several files). Only the shapes of
+    /// the datasets are read.
+    pub fn metadata(&self) -> Result<BeamFileMetadata, FEEBeamError> {
+        let identity = self.identity()?.clone();
+
+        let mut freqs = Vec::with_capacity(self.freqs.len());
+        for &freq_hz in &self.freqs {
+            let key = format!("X1_{freq_hz}");
+            let shape = self.hdf5_file(freq_hz).dataset(&key)?.shape();
+            let num_coeffs = match shape.as_slice() {
+                [2, n] => *n,
+                _ => return Err(FEEBeamError::DatasetShape { key, exp: 2 }),
+            };
+            // This matches how N is found when calculating coefficients; only
+            // the S=1 modes are considered.
+            let n_max = (0..num_coeffs.min(self.modes.dim().1))
+                .filter(|&i| self.modes[[0, i]] <= 1)
+                .map(|i| self.modes[[2, i]])
+                .max()
+                .unwrap_or(0);
+            freqs.push(FreqMetadata {
+                freq_hz,
+                num_coeffs,
+                n_max: n_max.max(0) as u8,
+            });
+        }
+
+        let h5 = self.hdf5_files[0].lock().unwrap();
+        let mut attributes = vec![];
+        for name in h5.attr_names()? {
+            let value = format_attribute(&h5.attr(&name)?)?;
+            attributes.push((name, value));
+        }
+
+        Ok(BeamFileMetadata {
+            identity,
+            num_modes: self.modes.dim().1,
+            freqs,
+            attributes,
+        })
+    }
+}
+
+/// Format the value of an HDF5 attribute as a string. Arrays are formatted
+/// like `[1, 2, 3]`. Types that can't be read are described by their type.
+fn format_attribute(attr: &hdf5_metno::Attribute) -> Result<String, FEEBeamError> {
+    fn join<T: ToString>(values: Vec<T>, scalar: bool) -> String {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        if scalar {
+            values.join(", ")
+        } else {
+            format!("[{}]", values.join(", "))
+        }
+    }
+
+    let scalar = attr.is_scalar();
+    let descriptor = attr.dtype()?.to_descriptor()?;
+    let value = match descriptor {
+        TypeDescriptor::Integer(_) => attr.read_raw::<i64>().map(|v| join(v, scalar)),
+        TypeDescriptor::Unsigned(_) => attr.read_raw::<u64>().map(|v| join(v, scalar)),
+        TypeDescriptor::Float(_) => attr.read_raw::<f64>().map(|v| join(v, scalar)),
+        TypeDescriptor::Boolean => attr.read_raw::<bool>().map(|v| join(v, scalar)),
+        TypeDescriptor::VarLenUnicode => attr.read_raw::<VarLenUnicode>().map(|v| join(v, scalar)),
+        TypeDescriptor::VarLenAscii | TypeDescriptor::FixedAscii(_) => {
+            attr.read_raw::<VarLenAscii>().map(|v| join(v, scalar))
+        }
+        TypeDescriptor::FixedUnicode(_) => {
+            attr.read_raw::<VarLenUnicode>().map(|v| join(v, scalar))
+        }
+        _ => return Ok(format!("<{descriptor}>")),
+    };
+    Ok(value.unwrap_or_else(|_| format!("<{descriptor}>")))
+}
@@ -9,48 +9,81 @@ mod error;
 mod ffi;
 #[cfg(any(feature = "cuda", feature = "hip"))]
 mod gpu;
+mod metadata;
+mod multi;
+mod subset;
+mod superposition;
 #[cfg(test)]
 mod tests;
+mod truncation;
 mod types;
+mod validate;
 
 pub use error::{FEEBeamError, InitFEEBeamError};
+pub use metadata::{
+    beam_file_search_paths, find_beam_file, BeamFileIdentity, BeamFileMetadata, FreqMetadata,
+    BEAM_FILE_NAME,
+};
+pub use multi::{FreqOverlap, FreqPrecedence};
+pub use superposition::ElementFields;
+use truncation::truncate_coeffs;
+pub use truncation::{ModeTruncation, TruncationReport};
 use types::*;
+pub use validate::{FreqValidation, ValidationProblem, ValidationReport};
 
 #[cfg(any(feature = "cuda", feature = "hip"))]
 pub use gpu::FEEBeamGpu;
 
 use std::{
     f64::consts::{FRAC_PI_2, TAU},
-    sync::Mutex,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, OnceLock},
 };
 
 use marlu::{AzEl, Jones};
 use ndarray::prelude::*;
-use num_complex::Complex64 as c64;
+use num_complex::{Complex, Complex64 as c64};
+use num_traits::{Float, NumAssign};
 use parking_lot::{MappedRwLockReadGuard, RwLockReadGuard};
-use rayon::prelude::*;
+use rayon::{prelude::*, ThreadPoolBuildError};
 
+pub use crate::types::Pol;
 use crate::{
     constants::*,
-    factorial::FACTORIAL,
-    legendre::p1sin,
-    types::{CacheKey, Pol},
+    legendre::{num_modes, p1sin_into, LegendreTable, NormalisedLegendre},
+    parallelism::{Parallelism, ThreadControl},
+    types::CacheKey,
 };
 
 /// The main struct to be used for calculating Jones matrices.
 #[allow(clippy::upper_case_acronyms)]
 pub struct FEEBeam {
-    /// The [`hdf5_metno::File`] struct associated with the opened HDF5 file. It is
+    /// The [`hdf5_metno::File`] structs associated with the opened HDF5 files.
+    /// There is usually only one, but a beam can be made from several files
+    /// covering different frequencies (see [`FEEBeam::new_multi`]). Each is
     /// behind a [`Mutex`] to prevent parallel usage of the file.
-    hdf5_file: Mutex<hdf5_metno::File>,
-    /// An ascendingly-sorted vector of frequencies available in the HDF5 file.
-    /// Not allowed to be empty.
+    hdf5_files: Vec<Mutex<hdf5_metno::File>>,
+    /// The paths to the HDF5 files, unless the beam was read from memory.
+    file_paths: Vec<PathBuf>,
+    /// The identity of the HDF5 files; this is only determined when asked for.
+    identity: OnceLock<BeamFileIdentity>,
+    /// An ascendingly-sorted vector of frequencies available in the HDF5
+    /// files. Not allowed to be empty.
     freqs: Vec<u32>,
+    /// For each frequency in `freqs`, the index into `hdf5_files` of the file
+    /// that it is read from.
+    freq_files: Vec<usize>,
+    /// Frequencies that are defined in more than one of the HDF5 files.
+    freq_overlaps: Vec<FreqOverlap>,
     /// Values used in calculating coefficients for X and Y.
     /// Row 0: Type
     /// Row 1: M
     /// Row 2: N
     modes: Array2<i8>,
+    /// How modes are dropped from the coefficients, if at all.
+    truncation: Option<ModeTruncation>,
+    /// The threads used for calculations.
+    pub(crate) threads: ThreadControl,
     /// A cache of X and Y coefficients.
     coeff_cache: CoeffCache,
     /// A cache of normalisation Jones matrices.
@@ -59,18 +92,72 @@ pub struct FEEBeam {
 
 impl FEEBeam {
     /// Given the path to an FEE beam file, create a new [`FEEBeam`] struct.
-    pub fn new<T: AsRef<std::path::Path>>(file: T) -> Result<Self, InitFEEBeamError> {
-        // so that libhdf5 doesn't print errors to stdout
+    pub fn new<T: AsRef<Path>>(file: T) -> Result<Self, InitFEEBeamError> {
+        Self::new_multi(&[file], FreqPrecedence::Error)
+    }
+
+    /// Create a new [`FEEBeam`] struct from the contents of an FEE beam file
+    /// held in memory (e.g. fetched from object storage or embedded in a
+    /// program). The bytes are copied into an HDF5 "file image", so nothing is
+    /// written to disk.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InitFEEBeamError> {
+        use hdf5_metno_sys::{
+            h5f::{H5Fopen, H5F_ACC_RDONLY},
+            h5p::H5Pset_file_image,
+        };
+
         hdf5_metno::silence_errors(true);
 
-        // If the file doesn't exist, hdf5_metno::File::open will handle it, but the
-        // error message is horrendous.
-        if !file.as_ref().exists() {
-            return Err(InitFEEBeamError::BeamFileDoesntExist(
-                file.as_ref().display().to_string(),
-            ));
-        }
-        let h5 = hdf5_metno::File::open(file)?;
+        // The "core" driver keeps the file in memory; with a file image set,
+        // nothing is read from or written to disk.
+        let fapl = hdf5_metno::plist::FileAccess::build()
+            .core_options(1 << 16, false)
+            .finish()?;
+        let h5 = hdf5_metno::sync::sync(|| unsafe {
+            // HDF5 copies the buffer, so it doesn't need to outlive the file.
+            if H5Pset_file_image(fapl.id(), bytes.as_ptr() as *mut _, bytes.len()) < 0 {
+                return Err(hdf5_metno::Error::from("couldn't set the HDF5 file image"));
+            }
+            // The name of the file is only used for error messages.
+            let name = b"in-memory FEE beam\0";
+            let id = H5Fopen(name.as_ptr().cast(), H5F_ACC_RDONLY, fapl.id());
+            hdf5_metno::from_id::<hdf5_metno::File>(id)
+        })?;
+        Self::from_hdf5_file(h5)
+    }
+
+    /// Create a new [`FEEBeam`] struct from anything that can be read, e.g. a
+    /// compressed stream. The whole FEE beam file is read into memory; see
+    /// [`FEEBeam::from_bytes`].
+    pub fn from_reader<R: std::io::Read>(mut reader: R) -> Result<Self, InitFEEBeamError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Create a new [`FEEBeam`] struct from an open FEE beam HDF5 file.
+    fn from_hdf5_file(h5: hdf5_metno::File) -> Result<Self, InitFEEBeamError> {
+        let (freqs, modes) = Self::read_freqs_and_modes(&h5)?;
+        Ok(Self {
+            hdf5_files: vec![Mutex::new(h5)],
+            file_paths: vec![],
+            identity: OnceLock::new(),
+            freq_files: vec![0; freqs.len()],
+            freq_overlaps: vec![],
+            freqs,
+            modes,
+            truncation: None,
+            threads: ThreadControl::default(),
+            coeff_cache: CoeffCache::default(),
+            norm_cache: NormCache::default(),
+        })
+    }
+
+    /// Get the (ascendingly sorted) frequencies and the modes of an FEE beam
+    /// HDF5 file, checking that they look sensible.
+    fn read_freqs_and_modes(
+        h5: &hdf5_metno::File,
+    ) -> Result<(Vec<u32>, Array2<i8>), InitFEEBeamError> {
         // We want all of the available frequencies and the biggest antenna index.
         let mut freqs: Vec<u32> = vec![];
         let mut biggest_dip_index: Option<u8> = None;
@@ -137,13 +224,7 @@ impl FEEBeam {
             }
         };
 
-        Ok(Self {
-            hdf5_file: Mutex::new(h5),
-            freqs,
-            modes,
-            coeff_cache: CoeffCache::default(),
-            norm_cache: NormCache::default(),
-        })
+        Ok((freqs, modes))
     }
 
     /// Create a new [`FEEBeam`] struct from the `MWA_BEAM_FILE` environment
@@ -155,8 +236,50 @@ impl FEEBeam {
         }
     }
 
-    /// Get the frequencies defined in the HDF5 file that was used to create
-    /// this [`FEEBeam`]. They are ascendingly sorted.
+    /// Get the (absolute) path to the HDF5 file that was used to create this
+    /// [`FEEBeam`]. This is `None` if the beam was read from memory or made
+    /// from several files (see [`FEEBeam::get_file_paths`]).
+    pub fn get_file_path(&self) -> Option<&Path> {
+        match self.file_paths.as_slice() {
+            [path] => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Get the (absolute) paths to the HDF5 files that were used to create
+    /// this [`FEEBeam`], in the order that they were given. This is empty if
+    /// the beam was read from memory.
+    pub fn get_file_paths(&self) -> &[PathBuf] {
+        &self.file_paths
+    }
+
+    /// Get the contents of the HDF5 file that was used to create this
+    /// [`FEEBeam`]. These bytes can be given to [`FEEBeam::from_bytes`]. This
+    /// isn't possible if the beam was made from several files.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, FEEBeamError> {
+        use hdf5_metno_sys::h5f::H5Fget_file_image;
+
+        if self.hdf5_files.len() > 1 {
+            return Err(FEEBeamError::MultipleFiles(self.hdf5_files.len()));
+        }
+        let h5 = self.hdf5_files[0].lock().unwrap();
+        let bytes = hdf5_metno::sync::sync(|| unsafe {
+            // The first call gets the size of the image.
+            let size = H5Fget_file_image(h5.id(), std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(hdf5_metno::Error::from("couldn't get the HDF5 file image"));
+            }
+            let mut bytes = vec![0_u8; size as usize];
+            if H5Fget_file_image(h5.id(), bytes.as_mut_ptr() as *mut _, bytes.len()) < 0 {
+                return Err(hdf5_metno::Error::from("couldn't get the HDF5 file image"));
+            }
+            Ok(bytes)
+        })?;
+        Ok(bytes)
+    }
+
+    /// Get the frequencies defined in the HDF5 file(s) that were used to
+    /// create this [`FEEBeam`]. They are ascendingly sorted.
     pub fn get_freqs(&self) -> &[u32] {
         &self.freqs
     }
@@ -186,11 +309,28 @@ impl FEEBeam {
         best_freq.expect("self.freqs is not allowed to be empty so this can't fail")
     }
 
-    /// Given a key, get a dataset from the HDF5 file.
+    /// Get the HDF5 file that a frequency's datasets are read from. The
+    /// frequency should be one of `self.freqs`; otherwise, the first file is
+    /// used.
+    fn hdf5_file(&self, freq_hz: u32) -> MutexGuard<'_, hdf5_metno::File> {
+        let i_file = self
+            .freqs
+            .binary_search(&freq_hz)
+            .map(|i| self.freq_files[i])
+            .unwrap_or(0);
+        self.hdf5_files[i_file].lock().unwrap()
+    }
+
+    /// Given a key, get a dataset from the HDF5 file that holds it.
     ///
     /// This function is expected to only receive keys like X16_51200000
     fn get_dataset(&self, key: &str) -> Result<Array2<f64>, FEEBeamError> {
-        let h5 = self.hdf5_file.lock().unwrap();
+        // The frequency follows the underscore.
+        let freq_hz = key
+            .rsplit_once('_')
+            .and_then(|(_, f)| f.parse().ok())
+            .unwrap_or_default();
+        let h5 = self.hdf5_file(freq_hz);
         let h5_data = h5.dataset(key)?.read_raw()?;
         // The aforementioned expected keys are 2D arrays with two rows. If 2
         // doesn't divide evenly into the data length, then something is wrong.
@@ -214,17 +354,40 @@ impl FEEBeam {
     /// to them.
     ///
     /// Note that specified frequencies are "rounded" to frequencies that are
-    /// defined the HDF5 file.
+    /// defined the HDF5 file. If mode truncation is enabled (see
+    /// [`FEEBeam::set_mode_truncation`]), the coefficients are truncated.
     fn get_modes(
         &self,
         desired_freq_hz: u32,
         delays: &[u32; 16],
         amps: &[f64; 32],
+    ) -> Result<MappedRwLockReadGuard<'_, BowtieCoefficients>, FEEBeamError> {
+        self.get_modes_with(desired_freq_hz, delays, amps, self.truncation)
+    }
+
+    /// The same as `get_modes`, but the coefficients are never truncated.
+    /// Superposition needs this, because the modes that truncation keeps
+    /// depend on the whole tile configuration, not on any one element.
+    fn get_full_modes(
+        &self,
+        desired_freq_hz: u32,
+        delays: &[u32; 16],
+        amps: &[f64; 32],
+    ) -> Result<MappedRwLockReadGuard<'_, BowtieCoefficients>, FEEBeamError> {
+        self.get_modes_with(desired_freq_hz, delays, amps, None)
+    }
+
+    fn get_modes_with(
+        &self,
+        desired_freq_hz: u32,
+        delays: &[u32; 16],
+        amps: &[f64; 32],
+        truncation: Option<ModeTruncation>,
     ) -> Result<MappedRwLockReadGuard<'_, BowtieCoefficients>, FEEBeamError> {
         let fee_freq = self.find_closest_freq(desired_freq_hz);
 
         // Are the input settings already cached? Hash them to check.
-        let hash = CacheKey::new(fee_freq, delays, amps);
+        let hash = CacheKey::new(fee_freq, delays, amps, truncation.is_some());
 
         // If the cache for this hash is already populated, we can return the reference.
         {
@@ -235,7 +398,7 @@ impl FEEBeam {
         }
 
         // If we hit this part of the code, we need to populate the cache.
-        let m = self.calc_modes(fee_freq, delays, amps)?;
+        let m = self.calc_modes(fee_freq, delays, amps, truncation)?;
         {
             let mut locked_cache = self.coeff_cache.write();
             locked_cache.insert(hash, m);
@@ -278,12 +441,13 @@ impl FEEBeam {
 
     /// Given the input parameters, calculate and return the X and Y
     /// coefficients ("modes"). As this function is relatively expensive, it
-    /// should only be called by `Self::get_modes` to cache the outputs.
+    /// should only be called by `Self::get_modes_with` to cache the outputs.
     fn calc_modes(
         &self,
         freq: u32,
         delays: &[u32; 16],
         amps: &[f64; 32],
+        truncation: Option<ModeTruncation>,
     ) -> Result<BowtieCoefficients, FEEBeamError> {
         let mut x = self.calc_mode(freq, delays, amps, Pol::X)?;
         let mut y = self.calc_mode(freq, delays, amps, Pol::Y)?;
@@ -298,7 +462,17 @@ impl FEEBeam {
             dipole_coeffs.m_signs.shrink_to_fit();
         }
 
-        Ok(BowtieCoefficients { x, y })
+        let coeffs = BowtieCoefficients {
+            x,
+            y,
+            truncation: None,
+        };
+        Ok(match truncation {
+            Some(truncation) => self
+                .threads
+                .install(|| truncate_coeffs(coeffs, truncation, freq)),
+            None => coeffs,
+        })
     }
 
     /// Given the input parameters, calculate and return the coefficients for a
@@ -435,14 +609,14 @@ impl FEEBeam {
             });
         }
 
-        Ok(DipoleCoefficients {
+        Ok(DipoleCoefficients::new(
             q1_accum,
             q2_accum,
             m_accum,
             n_accum,
             m_signs,
-            n_max: n_max.try_into().expect("n_max is always positive"),
-        })
+            n_max.try_into().expect("n_max is always positive"),
+        ))
     }
 
     /// Calculate the beam-response Jones matrix for a given direction and
@@ -544,8 +718,8 @@ impl FEEBeam {
 
     /// Calculate the beam-response Jones matrices for many directions given a
     /// pointing. This is basically a wrapper around `calc_jones` that
-    /// efficiently calculates the Jones matrices in parallel. The number of
-    /// parallel threads used can be controlled by setting `RAYON_NUM_THREADS`.
+    /// efficiently calculates the Jones matrices in parallel. The threads used
+    /// can be controlled with [`FEEBeam::set_parallelism`].
     ///
     /// `delays` and `amps` apply to each dipole in an MWA tile in the M&C
     /// order; see
@@ -617,25 +791,43 @@ impl FEEBeam {
         // Populate the coefficients cache if it isn't already populated.
         let coeffs = self.get_modes(freq_hz, delays, &full_amps)?;
 
-        azels
-            .par_iter()
-            .zip(results.par_iter_mut())
-            .for_each(|(&azel, result)| {
-                let az = azel.az;
-                let za = azel.za();
-                let mut jones = calc_jones_direct(az, za, &coeffs, norm_jones);
-                if let Some(latitude_rad) = latitude_rad {
-                    apply_parallactic_correction(az, za, latitude_rad, iau_order, &mut jones);
-                }
-                *result = jones;
-            });
+        let n_max = coeffs.x.n_max.max(coeffs.y.n_max);
+        self.threads.install(|| {
+            let legendre = LegendreTable::new(n_max, azels.iter().map(|azel| azel.za()));
+            azels
+                .par_chunks(LANES)
+                .zip(results.par_chunks_mut(LANES))
+                .for_each_init(
+                    || Workspace::new(n_max),
+                    |workspace, (azels, results)| {
+                        let mut az = [0.0; LANES];
+                        let mut za = [0.0; LANES];
+                        for ((az, za), azel) in az.iter_mut().zip(za.iter_mut()).zip(azels) {
+                            *az = azel.az;
+                            *za = azel.za();
+                        }
+                        let n = azels.len();
+                        calc_jones_direct_chunk(
+                            &az[..n],
+                            &za[..n],
+                            &coeffs,
+                            norm_jones,
+                            latitude_rad,
+                            iau_order,
+                            legendre.as_ref(),
+                            workspace,
+                            results,
+                        );
+                    },
+                );
+        });
         Ok(())
     }
 
     /// Calculate the Jones matrices for many directions given a pointing. This
     /// is basically a wrapper around `calc_jones` that efficiently calculates
-    /// the Jones matrices in parallel. The number of parallel threads used can
-    /// be controlled by setting `RAYON_NUM_THREADS`.
+    /// the Jones matrices in parallel. The threads used can be controlled with
+    /// [`FEEBeam::set_parallelism`].
     ///
     /// `delays` and `amps` apply to each dipole in an MWA tile in the M&C
     /// order; see
@@ -691,6 +883,141 @@ impl FEEBeam {
         latitude_rad: Option<f64>,
         iau_order: bool,
         results: &mut [Jones<f64>],
+    ) -> Result<(), FEEBeamError> {
+        self.calc_jones_array_pair_with_legendre(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            norm_to_zenith,
+            latitude_rad,
+            iau_order,
+            None,
+            results,
+        )
+    }
+
+    /// The same as `calc_jones_array_pair_inner`, but Legendre values are
+    /// taken from `legendre` (made with [`FEEBeam::legendre_table`]), e.g. so
+    /// that they're shared by many tiles and frequencies. If `legendre` is
+    /// `None`, a table is made for these directions if it's worthwhile.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn calc_jones_array_pair_with_legendre(
+        &self,
+        az_rad: &[f64],
+        za_rad: &[f64],
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        norm_to_zenith: bool,
+        latitude_rad: Option<f64>,
+        iau_order: bool,
+        legendre: Option<&LegendreTable>,
+        results: &mut [Jones<f64>],
+    ) -> Result<(), FEEBeamError> {
+        self.calc_jones_array_pair_generic(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            norm_to_zenith,
+            latitude_rad,
+            iau_order,
+            legendre,
+            results,
+        )
+    }
+
+    /// Calculate the Jones matrices for many directions given a pointing, with
+    /// single precision. This is the same as `calc_jones_array_pair`, except
+    /// that the directions and results are `f32`; the calculations are faster
+    /// and the results need half the memory.
+    ///
+    /// Legendre values and the per-mode sums are calculated with `f32`. Given
+    /// the same directions, each element of the difference from
+    /// `calc_jones_array_pair` has a magnitude no more than 1e-4 times the
+    /// largest magnitude of that element over the sky (i.e. 1e-4 when
+    /// normalised to zenith). Angles in `f32` are only precise to about 1e-7
+    /// of their size, so azimuths should be within [0, 2pi).
+    ///
+    /// `delays` and `amps` apply to each dipole in an MWA tile in the M&C
+    /// order; see
+    /// <https://wiki.mwatelescope.org/pages/viewpage.action?pageId=48005139>.
+    /// `delays` *must* have 16 elements, whereas `amps` can have 16 or 32
+    /// elements; if 16 are given, then these map 1:1 with dipoles, otherwise
+    /// the first 16 are for X dipole elements, and the next 16 are for Y.
+    #[allow(clippy::too_many_arguments)]
+    pub fn calc_jones_array_pair_f32(
+        &self,
+        az_rad: &[f32],
+        za_rad: &[f32],
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        norm_to_zenith: bool,
+        latitude_rad: Option<f64>,
+        iau_order: bool,
+    ) -> Result<Vec<Jones<f32>>, FEEBeamError> {
+        let mut results = vec![Jones::default(); az_rad.len()];
+        self.calc_jones_array_pair_inner_f32(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            norm_to_zenith,
+            latitude_rad,
+            iau_order,
+            &mut results,
+        )?;
+        Ok(results)
+    }
+
+    /// Calculate the Jones matrices for many directions given a pointing, with
+    /// single precision. This is the same as `calc_jones_array_pair_f32` but
+    /// uses pre-allocated memory.
+    #[allow(clippy::too_many_arguments)]
+    pub fn calc_jones_array_pair_inner_f32(
+        &self,
+        az_rad: &[f32],
+        za_rad: &[f32],
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        norm_to_zenith: bool,
+        latitude_rad: Option<f64>,
+        iau_order: bool,
+        results: &mut [Jones<f32>],
+    ) -> Result<(), FEEBeamError> {
+        self.calc_jones_array_pair_generic(
+            az_rad,
+            za_rad,
+            freq_hz,
+            delays,
+            amps,
+            norm_to_zenith,
+            latitude_rad,
+            iau_order,
+            None,
+            results,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn calc_jones_array_pair_generic<T: CpuFloat>(
+        &self,
+        az_rad: &[T],
+        za_rad: &[T],
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+        norm_to_zenith: bool,
+        latitude_rad: Option<f64>,
+        iau_order: bool,
+        legendre: Option<&LegendreTable>,
+        results: &mut [Jones<T>],
     ) -> Result<(), FEEBeamError> {
         if delays.len() != 16 {
             return Err(FEEBeamError::IncorrectDelaysLength(delays.len()));
@@ -710,20 +1037,148 @@ impl FEEBeam {
         // Populate the coefficients cache if it isn't already populated.
         let coeffs = self.get_modes(freq_hz, delays, &full_amps)?;
 
-        az_rad
-            .par_iter()
-            .zip(za_rad.par_iter())
-            .zip(results.par_iter_mut())
-            .for_each(|((&az, &za), result)| {
-                let mut jones = calc_jones_direct(az, za, &coeffs, norm_jones);
-                if let Some(latitude_rad) = latitude_rad {
-                    apply_parallactic_correction(az, za, latitude_rad, iau_order, &mut jones);
+        let n_max = coeffs.x.n_max.max(coeffs.y.n_max);
+        self.threads.install(|| {
+            let own_legendre;
+            let legendre = match legendre {
+                Some(legendre) => Some(legendre),
+                None => {
+                    own_legendre = LegendreTable::new(
+                        n_max,
+                        za_rad.iter().map(|za| za.to_f64().unwrap_or_default()),
+                    );
+                    own_legendre.as_ref()
                 }
-                *result = jones;
-            });
+            };
+            az_rad
+                .par_chunks(LANES)
+                .zip(za_rad.par_chunks(LANES))
+                .zip(results.par_chunks_mut(LANES))
+                .for_each_init(
+                    || Workspace::new(n_max),
+                    |workspace, ((az, za), results)| {
+                        calc_jones_direct_chunk(
+                            az,
+                            za,
+                            &coeffs,
+                            norm_jones,
+                            latitude_rad,
+                            iau_order,
+                            legendre,
+                            workspace,
+                            results,
+                        );
+                    },
+                );
+        });
         Ok(())
     }
 
+    /// Calculate the far field of a single embedded element (one polarisation
+    /// of one dipole) in a given direction. Unlike the Jones matrices of
+    /// `calc_jones`, this is not summed over the dipoles of the tile; the
+    /// element is excited with a unit voltage and every other element is
+    /// terminated. The result is not normalised, and mode truncation (see
+    /// [`FEEBeam::set_mode_truncation`]) isn't applied.
+    ///
+    /// `dipole` is the index of the dipole in the M&C order (0 to 15; see
+    /// <https://wiki.mwatelescope.org/pages/viewpage.action?pageId=48005139>).
+    /// The returned values are the complex theta and phi components of the
+    /// field, in the same convention as the columns of the (un-normalised,
+    /// uncorrected) Jones matrices of `calc_jones`, i.e. the beam response of a
+    /// tile is the sum of these patterns weighted by the dipoles' complex
+    /// excitations.
+    ///
+    /// As with `calc_jones`, the frequency is "rounded" to the closest one
+    /// defined in the HDF5 file.
+    pub fn calc_element_pattern(
+        &self,
+        dipole: usize,
+        pol: Pol,
+        az_rad: f64,
+        za_rad: f64,
+        freq_hz: u32,
+    ) -> Result<[c64; 2], FEEBeamError> {
+        let coeffs = self.get_element_modes(dipole, pol, freq_hz)?;
+        let jones = calc_jones_direct(az_rad, za_rad, &coeffs, None);
+        Ok(element_pattern_from_jones(pol, jones))
+    }
+
+    /// Calculate the far field of a single embedded element for many
+    /// directions. This is the same as `calc_element_pattern`, but the
+    /// directions are calculated in parallel. `az_rad` and `za_rad` must have
+    /// the same length.
+    pub fn calc_element_pattern_array(
+        &self,
+        dipole: usize,
+        pol: Pol,
+        az_rad: &[f64],
+        za_rad: &[f64],
+        freq_hz: u32,
+    ) -> Result<Vec<[c64; 2]>, FEEBeamError> {
+        check_direction_counts(az_rad, za_rad)?;
+        let coeffs = self.get_element_modes(dipole, pol, freq_hz)?;
+        let n_max = coeffs.x.n_max.max(coeffs.y.n_max);
+        let mut jones = vec![Jones::default(); az_rad.len()];
+        self.threads.install(|| {
+            let legendre = LegendreTable::new(n_max, za_rad.iter().copied());
+            az_rad
+                .par_chunks(LANES)
+                .zip(za_rad.par_chunks(LANES))
+                .zip(jones.par_chunks_mut(LANES))
+                .for_each_init(
+                    || Workspace::new(n_max),
+                    |workspace, ((az, za), results)| {
+                        calc_jones_direct_chunk(
+                            az,
+                            za,
+                            &coeffs,
+                            None,
+                            None,
+                            false,
+                            legendre.as_ref(),
+                            workspace,
+                            results,
+                        );
+                    },
+                );
+        });
+        Ok(jones
+            .into_iter()
+            .map(|jones| element_pattern_from_jones(pol, jones))
+            .collect())
+    }
+
+    /// Get the coefficients of a tile in which only one element is excited
+    /// (with a unit voltage); see `calc_element_pattern`. These are never
+    /// truncated.
+    fn get_element_modes(
+        &self,
+        dipole: usize,
+        pol: Pol,
+        freq_hz: u32,
+    ) -> Result<MappedRwLockReadGuard<'_, BowtieCoefficients>, FEEBeamError> {
+        if dipole >= 16 {
+            return Err(FEEBeamError::InvalidDipole(dipole));
+        }
+        let mut amps = [0.0; 32];
+        match pol {
+            Pol::X => amps[dipole] = 1.0,
+            Pol::Y => amps[16 + dipole] = 1.0,
+        }
+        self.get_full_modes(freq_hz, &[0; 16], &amps)
+    }
+
+    /// Make a table of Legendre values for the zenith angles `za_rad` that can
+    /// be used at any of this beam's frequencies (see
+    /// [`FEEBeam::calc_jones_array_pair_with_legendre`]). `None` is returned if
+    /// a table isn't worthwhile.
+    #[cfg(feature = "python")]
+    pub(crate) fn legendre_table(&self, za_rad: &[f64]) -> Option<LegendreTable> {
+        let n_max = self.modes.row(2).iter().copied().max().unwrap_or(0).max(0) as u8;
+        LegendreTable::new(n_max, za_rad.iter().copied())
+    }
+
     /// Empty the cached dipole coefficients and normalisation Jones matrices to
     /// recover memory.
     pub fn empty_cache(&self) {
@@ -731,6 +1186,23 @@ impl FEEBeam {
         self.norm_cache.write().clear();
     }
 
+    /// Set the threads used to calculate beam responses on the CPU; see
+    /// [`Parallelism`]. By default, rayon's global thread pool is used. An
+    /// error is returned if a new thread pool can't be made.
+    pub fn set_parallelism(
+        &mut self,
+        parallelism: Parallelism,
+    ) -> Result<(), ThreadPoolBuildError> {
+        self.threads = ThreadControl::new(parallelism)?;
+        Ok(())
+    }
+
+    /// Get the threads used to calculate beam responses on the CPU; see
+    /// [`FEEBeam::set_parallelism`].
+    pub fn get_parallelism(&self) -> &Parallelism {
+        self.threads.get()
+    }
+
     /// Prepare a compute-capable GPU device for beam-response computations
     /// given the frequencies, delays and amps to be used. The resulting object
     /// takes directions and computes the beam responses on the device.
@@ -762,84 +1234,351 @@ impl FEEBeam {
     }
 }
 
-/// Calculate the Jones matrix components given a pointing and coefficients
-/// associated with a single dipole polarisation.
-fn calc_sigmas(
-    phi: f64,
-    theta: f64,
+/// Check that there are as many azimuths as zenith angles.
+fn check_direction_counts(az_rad: &[f64], za_rad: &[f64]) -> Result<(), FEEBeamError> {
+    if az_rad.len() != za_rad.len() {
+        return Err(FEEBeamError::DirectionCountMismatch {
+            az: az_rad.len(),
+            za: za_rad.len(),
+        });
+    }
+    Ok(())
+}
+
+/// The number of directions that are calculated together by
+/// [`calc_jones_direct_block`]. The per-mode loops are over these directions,
+/// so that the compiler can vectorise them.
+const LANES: usize = 8;
+
+/// The float types that Jones matrices can be calculated with on the CPU.
+///
+/// Phases and the parallactic-angle correction are always calculated with
+/// `f64`; the per-mode loops (which dominate the cost) use `Self`. Associated
+/// Legendre values can't be represented by `f32` at large N, so for `f32`,
+/// the per-mode normalisations (apart from their signs) are applied to the
+/// Legendre values rather than the coefficients (see [`NormalisedLegendre`]).
+trait CpuFloat: Float + NumAssign + From<i8> + Default + Send + Sync {
+    /// Whether the Legendre values need to be normalised.
+    const NORMALISE_LEGENDRE: bool;
+
+    fn from_f64(v: f64) -> Self;
+
+    /// Get the per-mode normalisations and the Q1 and Q2 coefficients
+    /// (multiplied by j^N) to be used with this float type.
+    fn coeffs(dipole: &DipoleCoefficients) -> (&[Self], &[Complex<Self>], &[Complex<Self>]);
+}
+
+impl CpuFloat for f64 {
+    const NORMALISE_LEGENDRE: bool = false;
+
+    fn from_f64(v: f64) -> f64 {
+        v
+    }
+
+    fn coeffs(dipole: &DipoleCoefficients) -> (&[f64], &[c64], &[c64]) {
+        (&dipole.norms, &dipole.q1_jn, &dipole.q2_jn)
+    }
+}
+
+impl CpuFloat for f32 {
+    const NORMALISE_LEGENDRE: bool = true;
+
+    fn from_f64(v: f64) -> f32 {
+        v as f32
+    }
+
+    fn coeffs(dipole: &DipoleCoefficients) -> (&[f32], &[Complex<f32>], &[Complex<f32>]) {
+        let coeffs = dipole.f32_coeffs();
+        (&coeffs.signs, &coeffs.q1_jn, &coeffs.q2_jn)
+    }
+}
+
+/// Calculate the Jones matrix components of `L` directions given coefficients
+/// associated with a single dipole polarisation. `u` are the cosines of the
+/// zenith angles. For each mode, `p1sin` and `p1` have the associated Legendre
+/// values of every direction, and `cos_mphi` and `sin_mphi` have `cos(|M|
+/// phi)` and `sin(|M| phi)` for every |M| (up to `n_max`), where phi are FEKO
+/// azimuths.
+fn calc_sigmas<T: CpuFloat, const L: usize>(
     coeffs: &DipoleCoefficients,
-    p1sin_arr: &[f64],
-    p1_arr: &[f64],
-) -> (c64, c64) {
-    let u = theta.cos();
-    let mut sigma_p = c64::default();
-    let mut sigma_t = c64::default();
-    // Use an iterator for maximum performance.
-    coeffs
+    u: &[T; L],
+    p1sin: &[[T; L]],
+    p1: &[[T; L]],
+    cos_mphi: &[[T; L]],
+    sin_mphi: &[[T; L]],
+) -> ([Complex<T>; L], [Complex<T>; L]) {
+    let (norms, q1_jn, q2_jn) = T::coeffs(coeffs);
+    let mut sigma_t_re = [T::zero(); L];
+    let mut sigma_t_im = [T::zero(); L];
+    let mut sigma_p_re = [T::zero(); L];
+    let mut sigma_p_im = [T::zero(); L];
+    for (((((&m, &norm), q1), q2), p1sin), p1) in coeffs
         .m_accum
         .iter()
-        .zip(coeffs.n_accum.iter())
-        .zip(coeffs.m_signs.iter())
-        .zip(coeffs.q1_accum.iter())
-        .zip(coeffs.q2_accum.iter())
-        .zip(p1sin_arr.iter())
-        .zip(p1_arr.iter())
-        .for_each(|((((((m, n), sign), q1), q2), p1sin), p1)| {
-            let mf = *m as f64;
-            let nf = *n as f64;
-            let signf = *sign as f64;
-
-            unsafe {
-                let c_mn = ((0.5 * (2 * n + 1) as f64)
-                // TODO: Is using get_unchecked going to help here?
-                * FACTORIAL[(n - m.abs()) as usize]
-                    / FACTORIAL[(n + m.abs()) as usize])
-                    .sqrt();
-                let ejm_phi = c64::cis(mf * phi);
-                let phi_comp = (ejm_phi * c_mn) / (nf * (nf + 1.0)).sqrt() * signf;
-                let j_power_n = J_POWER_TABLE.get_unchecked((*n % 4) as usize);
-                let e_theta_mn = j_power_n * ((p1sin * (mf.abs() * q2 * u - mf * q1)) + q2 * p1);
-                let j_power_np1 = J_POWER_TABLE.get_unchecked(((*n + 1) % 4) as usize);
-                let e_phi_mn = j_power_np1 * ((p1sin * (mf * q2 - mf.abs() * q1 * u)) - q1 * p1);
-                sigma_p += phi_comp * e_phi_mn;
-                sigma_t += phi_comp * e_theta_mn;
+        .zip(norms.iter())
+        .zip(q1_jn.iter())
+        .zip(q2_jn.iter())
+        .zip(p1sin.iter())
+        .zip(p1.iter())
+    {
+        let mf = <T as From<i8>>::from(m);
+        let m_abs = <T as From<i8>>::from(m.abs());
+        // e^{-j|M|phi} is the conjugate of e^{j|M|phi}.
+        let m_sign = if m < 0 { -T::one() } else { T::one() };
+        let cos_mphi = &cos_mphi[usize::from(m.unsigned_abs())];
+        let sin_mphi = &sin_mphi[usize::from(m.unsigned_abs())];
+        for l in 0..L {
+            let a = p1sin[l] * m_abs * u[l];
+            let b = p1sin[l] * mf;
+            // Q1 and Q2 already include j^N.
+            let t_re = a * q2.re - b * q1.re + q2.re * p1[l];
+            let t_im = a * q2.im - b * q1.im + q2.im * p1[l];
+            let p_re = b * q2.re - a * q1.re - q1.re * p1[l];
+            let p_im = b * q2.im - a * q1.im - q1.im * p1[l];
+            let e_re = norm * cos_mphi[l];
+            let e_im = norm * m_sign * sin_mphi[l];
+            sigma_t_re[l] += e_re * t_re - e_im * t_im;
+            sigma_t_im[l] += e_re * t_im + e_im * t_re;
+            sigma_p_re[l] += e_re * p_re - e_im * p_im;
+            sigma_p_im[l] += e_re * p_im + e_im * p_re;
+        }
+    }
+
+    // The phi components need j^(N+1), i.e. another factor of j. The C++ code
+    // currently doesn't distinguish between the polarisations.
+    let sigma_t = std::array::from_fn(|l| Complex::new(sigma_t_re[l], sigma_t_im[l]));
+    let sigma_p = std::array::from_fn(|l| -Complex::new(-sigma_p_im[l], sigma_p_re[l]));
+    (sigma_t, sigma_p)
+}
+
+/// Buffers used by [`calc_jones_direct_block`], so that they needn't be
+/// allocated for every block of directions.
+struct Workspace<T: CpuFloat, const L: usize> {
+    p1sin: Vec<[T; L]>,
+    p1: Vec<[T; L]>,
+    cos_mphi: Vec<[T; L]>,
+    sin_mphi: Vec<[T; L]>,
+    /// Legendre values for a single direction.
+    p1sin_dir: Vec<f64>,
+    p1_dir: Vec<f64>,
+    /// How to calculate normalised Legendre values (if they're needed; see
+    /// [`CpuFloat`]).
+    normalised_legendre: Option<NormalisedLegendre<T>>,
+}
+
+impl<T: CpuFloat, const L: usize> Workspace<T, L> {
+    /// Make a workspace for coefficients with N up to `n_max`.
+    fn new(n_max: u8) -> Workspace<T, L> {
+        let num_modes = num_modes(n_max);
+        Workspace {
+            p1sin: vec![[T::zero(); L]; num_modes],
+            p1: vec![[T::zero(); L]; num_modes],
+            cos_mphi: vec![[T::one(); L]; usize::from(n_max) + 1],
+            sin_mphi: vec![[T::zero(); L]; usize::from(n_max) + 1],
+            p1sin_dir: vec![0.0; num_modes],
+            p1_dir: vec![0.0; num_modes],
+            normalised_legendre: T::NORMALISE_LEGENDRE.then(|| NormalisedLegendre::new(n_max)),
+        }
+    }
+}
+
+/// Calculate the Jones matrix components of `L` directions for both dipole
+/// polarisations. `phi_rad` are FEKO azimuths (East through North). Legendre
+/// values are taken from `legendre` if it has them.
+fn calc_sigmas_block<T: CpuFloat, const L: usize>(
+    phi_rad: &[f64; L],
+    za_rad: &[f64; L],
+    coeffs: &BowtieCoefficients,
+    legendre: Option<&LegendreTable>,
+    workspace: &mut Workspace<T, L>,
+) -> [[Complex<T>; 4]; L] {
+    let n_max = coeffs.x.n_max.max(coeffs.y.n_max);
+    let u = fill_workspace(phi_rad, za_rad, n_max, legendre, workspace);
+    calc_sigmas_filled(coeffs, &u, workspace)
+}
+
+/// Calculate the Jones matrix components of the `L` directions that
+/// `workspace` was filled for (see [`fill_workspace`]). `u` are the cosines of
+/// the zenith angles.
+fn calc_sigmas_filled<T: CpuFloat, const L: usize>(
+    coeffs: &BowtieCoefficients,
+    u: &[T; L],
+    workspace: &Workspace<T, L>,
+) -> [[Complex<T>; 4]; L] {
+    let Workspace {
+        p1sin,
+        p1,
+        cos_mphi,
+        sin_mphi,
+        ..
+    } = workspace;
+    let (x_t, x_p) = calc_sigmas(&coeffs.x, u, p1sin, p1, cos_mphi, sin_mphi);
+    let (y_t, y_p) = calc_sigmas(&coeffs.y, u, p1sin, p1, cos_mphi, sin_mphi);
+    std::array::from_fn(|l| [x_t[l], x_p[l], y_t[l], y_p[l]])
+}
+
+/// Fill `workspace` with the Legendre values and e^{jM phi} of `L` directions
+/// for coefficients with N up to `n_max`, and return the cosines of the zenith
+/// angles.
+fn fill_workspace<T: CpuFloat, const L: usize>(
+    phi_rad: &[f64; L],
+    za_rad: &[f64; L],
+    n_max: u8,
+    legendre: Option<&LegendreTable>,
+    workspace: &mut Workspace<T, L>,
+) -> [T; L] {
+    let num_modes = num_modes(n_max);
+    let Workspace {
+        p1sin: p1sin_arr,
+        p1: p1_arr,
+        cos_mphi,
+        sin_mphi,
+        p1sin_dir,
+        p1_dir,
+        normalised_legendre,
+    } = workspace;
+    let (p1sin_arr, p1_arr) = (&mut p1sin_arr[..num_modes], &mut p1_arr[..num_modes]);
+    if let Some(normalised_legendre) = normalised_legendre {
+        normalised_legendre.fill(za_rad, p1sin_arr, p1_arr);
+    } else {
+        for (l, &za) in za_rad.iter().enumerate() {
+            // Consecutive directions often share zenith angles (e.g. along the
+            // rows of a grid).
+            if l > 0 && za.to_bits() == za_rad[l - 1].to_bits() {
+                for v in p1sin_arr.iter_mut().chain(p1_arr.iter_mut()) {
+                    v[l] = v[l - 1];
+                }
+                continue;
             }
-        });
+            let (p1sin_dir, p1_dir) = match legendre.and_then(|t| t.get(n_max, za)) {
+                Some(values) => values,
+                None => {
+                    p1sin_into(n_max, za, p1sin_dir, p1_dir);
+                    (&p1sin_dir[..num_modes], &p1_dir[..num_modes])
+                }
+            };
+            for ((out_sin, out), (&v_sin, &v)) in p1sin_arr
+                .iter_mut()
+                .zip(p1_arr.iter_mut())
+                .zip(p1sin_dir.iter().zip(p1_dir))
+            {
+                out_sin[l] = T::from_f64(v_sin);
+                out[l] = T::from_f64(v);
+            }
+        }
+    }
+    let u = za_rad.map(|za| T::from_f64(za.cos()));
+
+    // e^{jM phi} for each |M| by recurrence, rather than calling cis for every
+    // mode.
+    let sin_cos_phi = phi_rad.map(f64::sin_cos);
+    let mut cos_prev = [1.0; L];
+    let mut sin_prev = [0.0; L];
+    for m in 1..=usize::from(n_max) {
+        for (l, &(sin_phi, cos_phi)) in sin_cos_phi.iter().enumerate() {
+            let (c, s) = (cos_prev[l], sin_prev[l]);
+            cos_prev[l] = c * cos_phi - s * sin_phi;
+            sin_prev[l] = s * cos_phi + c * sin_phi;
+            cos_mphi[m][l] = T::from_f64(cos_prev[l]);
+            sin_mphi[m][l] = T::from_f64(sin_prev[l]);
+        }
+    }
+
+    u
+}
 
-    // The C++ code currently doesn't distinguish between the polarisations.
-    (sigma_t, -sigma_p)
+/// Actually calculate Jones matrices for `L` directions. All other "calc"
+/// functions use this function.
+fn calc_jones_direct_block<T: CpuFloat, const L: usize>(
+    az_rad: &[f64; L],
+    za_rad: &[f64; L],
+    coeffs: &BowtieCoefficients,
+    norm_matrix: Option<Jones<f64>>,
+    legendre: Option<&LegendreTable>,
+    workspace: &mut Workspace<T, L>,
+) -> [Jones<T>; L] {
+    // Convert azimuth to FEKO phi (East through North).
+    let phi_rad = az_rad.map(|az| FRAC_PI_2 - az);
+    let norm_matrix =
+        norm_matrix.map(|norm| norm.map(|n| Complex::new(T::from_f64(n.re), T::from_f64(n.im))));
+    calc_sigmas_block(&phi_rad, za_rad, coeffs, legendre, workspace).map(|mut jones| {
+        if let Some(norm) = norm_matrix {
+            jones.iter_mut().zip(norm.iter()).for_each(|(j, n)| *j /= n);
+        }
+        Jones::from(jones)
+    })
 }
 
-/// Actually calculate a Jones matrix. All other "calc" functions use this
-/// function.
+/// Calculate a Jones matrix for a single direction.
 fn calc_jones_direct(
     az_rad: f64,
     za_rad: f64,
     coeffs: &BowtieCoefficients,
     norm_matrix: Option<Jones<f64>>,
 ) -> Jones<f64> {
-    // Convert azimuth to FEKO phi (East through North).
-    let phi_rad = FRAC_PI_2 - az_rad;
-    let (p1sin_arr, p1_arr) = p1sin(coeffs.x.n_max.max(coeffs.y.n_max), za_rad);
+    let mut workspace = Workspace::new(coeffs.x.n_max.max(coeffs.y.n_max));
+    let [jones] = calc_jones_direct_block(
+        &[az_rad],
+        &[za_rad],
+        coeffs,
+        norm_matrix,
+        None,
+        &mut workspace,
+    );
+    jones
+}
 
-    let (j00, j01) = calc_sigmas(phi_rad, za_rad, &coeffs.x, &p1sin_arr, &p1_arr);
-    let (j10, j11) = calc_sigmas(phi_rad, za_rad, &coeffs.y, &p1sin_arr, &p1_arr);
-    let mut jones = [j00, j01, j10, j11];
-    if let Some(norm) = norm_matrix {
-        jones.iter_mut().zip(norm.iter()).for_each(|(j, n)| *j /= n);
+/// Calculate Jones matrices for any number of directions, [`LANES`] at a
+/// time, optionally applying the parallactic-angle correction.
+#[allow(clippy::too_many_arguments)]
+fn calc_jones_direct_chunk<T: CpuFloat>(
+    az_rad: &[T],
+    za_rad: &[T],
+    coeffs: &BowtieCoefficients,
+    norm_matrix: Option<Jones<f64>>,
+    latitude_rad: Option<f64>,
+    iau_order: bool,
+    legendre: Option<&LegendreTable>,
+    workspace: &mut Workspace<T, LANES>,
+    results: &mut [Jones<T>],
+) {
+    for ((az_rad, za_rad), results) in az_rad
+        .chunks(LANES)
+        .zip(za_rad.chunks(LANES))
+        .zip(results.chunks_mut(LANES))
+    {
+        // Unused lanes point at zenith.
+        let mut az = [0.0; LANES];
+        let mut za = [0.0; LANES];
+        for ((az, za), (&az_in, &za_in)) in az
+            .iter_mut()
+            .zip(za.iter_mut())
+            .zip(az_rad.iter().zip(za_rad))
+        {
+            *az = az_in.to_f64().unwrap_or_default();
+            *za = za_in.to_f64().unwrap_or_default();
+        }
+        let jones = calc_jones_direct_block(&az, &za, coeffs, norm_matrix, legendre, workspace);
+        for (((&az, &za), mut jones), result) in
+            az.iter().zip(za.iter()).zip(jones).zip(results.iter_mut())
+        {
+            if let Some(latitude_rad) = latitude_rad {
+                apply_parallactic_correction(az, za, latitude_rad, iau_order, &mut jones);
+            }
+            *result = jones;
+        }
     }
-    Jones::from(jones)
 }
 
 fn calc_zenith_norm_jones(coeffs: &BowtieCoefficients) -> Jones<f64> {
     // Azimuth angles at which Jones components are maximum.
     let max_phi = [0.0, -FRAC_PI_2, FRAC_PI_2, 0.0];
-    let (p1sin_arr, p1_arr) = p1sin(coeffs.x.n_max.max(coeffs.y.n_max), 0.0);
-
-    let (j00, _) = calc_sigmas(max_phi[0], 0.0, &coeffs.x, &p1sin_arr, &p1_arr);
-    let (_, j01) = calc_sigmas(max_phi[1], 0.0, &coeffs.x, &p1sin_arr, &p1_arr);
-    let (j10, _) = calc_sigmas(max_phi[2], 0.0, &coeffs.y, &p1sin_arr, &p1_arr);
-    let (_, j11) = calc_sigmas(max_phi[3], 0.0, &coeffs.y, &p1sin_arr, &p1_arr);
+    let mut workspace = Workspace::<f64, 4>::new(coeffs.x.n_max.max(coeffs.y.n_max));
+    let sigmas = calc_sigmas_block(&max_phi, &[0.0; 4], coeffs, None, &mut workspace);
+    let j00 = sigmas[0][0];
+    let j01 = sigmas[1][1];
+    let j10 = sigmas[2][2];
+    let j11 = sigmas[3][3];
     // C++ uses abs(c) here, where abs is the magnitude of the complex number
     // vector. The result of this function should be a complex Jones matrix,
     // but, confusingly, the returned "Jones matrix" is all real in the C++.
@@ -848,6 +1587,15 @@ fn calc_zenith_norm_jones(coeffs: &BowtieCoefficients) -> Jones<f64> {
     Jones::from([abs(j00), abs(j01), abs(j10), abs(j11)])
 }
 
+/// Get the theta and phi components of an element pattern from the Jones
+/// matrix of a tile in which only that element is excited.
+fn element_pattern_from_jones(pol: Pol, jones: Jones<f64>) -> [c64; 2] {
+    match pol {
+        Pol::X => [jones[0], jones[1]],
+        Pol::Y => [jones[2], jones[3]],
+    }
+}
+
 /// Ensure that any delays of 32 have an amplitude (dipole gain) of 0. The
 /// results are bad otherwise! Also ensure that we have 32 dipole gains (amps)
 /// here.
@@ -874,18 +1622,30 @@ fn fix_amps(amps: &[f64], delays: &[u32; 16]) -> [f64; 32] {
 ///
 /// See for how/why this is done:
 /// <https://github.com/MWATelescope/mwa_hyperbeam/blob/main/fee_pols.pdf>
-fn apply_parallactic_correction(
+pub(crate) fn apply_parallactic_correction<T: Float>(
     az_rad: f64,
     za_rad: f64,
     latitude_rad: f64,
     iau_order: bool,
-    jones: &mut Jones<f64>,
+    jones: &mut Jones<T>,
 ) {
-    // Get the parallactic-angle and find its sine and cosine.
-    let para_angle = AzEl::from_radians(az_rad, FRAC_PI_2 - za_rad)
+    let rotation = parallactic_rotation(az_rad, za_rad, latitude_rad);
+    rotate_jones(rotation, iau_order, jones);
+}
+
+/// Get the sine and cosine of the parallactic angle of a direction.
+pub(crate) fn parallactic_rotation(az_rad: f64, za_rad: f64, latitude_rad: f64) -> (f64, f64) {
+    AzEl::from_radians(az_rad, FRAC_PI_2 - za_rad)
         .to_hadec(latitude_rad)
-        .get_parallactic_angle(latitude_rad);
-    let (s_rot, c_rot) = para_angle.sin_cos();
+        .get_parallactic_angle(latitude_rad)
+        .sin_cos()
+}
+
+/// Apply a parallactic-angle correction (see
+/// [`apply_parallactic_correction`]) given the sine and cosine of the angle.
+fn rotate_jones<T: Float>((s_rot, c_rot): (f64, f64), iau_order: bool, jones: &mut Jones<T>) {
+    let s_rot = T::from(s_rot).unwrap();
+    let c_rot = T::from(c_rot).unwrap();
     *jones = if iau_order {
         Jones::from([
             jones[2] * -c_rot + jones[3] * s_rot,
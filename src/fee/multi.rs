@@ -0,0 +1,194 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Making an FEE beam from several HDF5 files, each covering some
+//! frequencies.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+
+use ndarray::prelude::*;
+
+use super::{CoeffCache, FEEBeam, InitFEEBeamError, NormCache};
+use crate::parallelism::ThreadControl;
+
+/// What to do when a frequency is defined in more than one of the files given
+/// to [`FEEBeam::new_multi`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FreqPrecedence {
+    /// Return an error.
+    #[default]
+    Error,
+
+    /// Use the datasets of the first file (in the order given) that defines
+    /// the frequency.
+    First,
+
+    /// Use the datasets of the last file (in the order given) that defines
+    /// the frequency.
+    Last,
+}
+
+impl FreqPrecedence {
+    /// The name of this rule, as accepted by [`FreqPrecedence::from_str`].
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FreqPrecedence::Error => "error",
+            FreqPrecedence::First => "first",
+            FreqPrecedence::Last => "last",
+        }
+    }
+}
+
+impl FromStr for FreqPrecedence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(FreqPrecedence::Error),
+            "first" => Ok(FreqPrecedence::First),
+            "last" => Ok(FreqPrecedence::Last),
+            _ => Err(format!(
+                "Unrecognised frequency precedence '{s}'; expected one of 'error', 'first' or 'last'"
+            )),
+        }
+    }
+}
+
+/// A frequency that is defined in more than one of the files given to
+/// [`FEEBeam::new_multi`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FreqOverlap {
+    pub freq_hz: u32,
+
+    /// The file whose datasets are used for this frequency.
+    pub used: PathBuf,
+
+    /// The other files that define this frequency.
+    pub ignored: Vec<PathBuf>,
+}
+
+impl FEEBeam {
+    /// Create a new [`FEEBeam`] struct from several FEE beam files, e.g. a
+    /// simulation delivered as one file per band. The frequencies of all files
+    /// are available; `precedence` determines what happens when more than one
+    /// file defines a frequency (the overlaps can be inspected with
+    /// [`FEEBeam::get_freq_overlaps`]).
+    ///
+    /// The `modes` datasets of the files must be compatible, i.e. each must be
+    /// the same as the start of the longest one, because each frequency's
+    /// coefficients correspond to the first modes.
+    pub fn new_multi<T: AsRef<Path>>(
+        files: &[T],
+        precedence: FreqPrecedence,
+    ) -> Result<Self, InitFEEBeamError> {
+        // so that libhdf5 doesn't print errors to stdout
+        hdf5_metno::silence_errors(true);
+
+        if files.is_empty() {
+            return Err(InitFEEBeamError::NoBeamFiles);
+        }
+
+        let mut hdf5_files = Vec::with_capacity(files.len());
+        let mut file_paths: Vec<PathBuf> = Vec::with_capacity(files.len());
+        // Which files define each frequency, in the order that they're given.
+        let mut freq_map: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        let mut modes: Option<Array2<i8>> = None;
+        for (i_file, file) in files.iter().enumerate() {
+            let file = file.as_ref();
+            // If the file doesn't exist, hdf5_metno::File::open will handle
+            // it, but the error message is horrendous.
+            if !file.exists() {
+                return Err(InitFEEBeamError::BeamFileDoesntExist(
+                    file.display().to_string(),
+                ));
+            }
+            let h5 = hdf5_metno::File::open(file)?;
+            let (file_freqs, file_modes) = Self::read_freqs_and_modes(&h5)?;
+            let path = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+
+            modes = Some(match modes {
+                None => file_modes,
+                Some(modes) => {
+                    let (shorter, longer) = if file_modes.len_of(Axis(1)) > modes.len_of(Axis(1)) {
+                        (modes, file_modes)
+                    } else {
+                        (file_modes, modes)
+                    };
+                    if longer.slice(s![.., ..shorter.len_of(Axis(1))]) != shorter {
+                        return Err(InitFEEBeamError::IncompatibleModes {
+                            first: file_paths[0].display().to_string(),
+                            other: path.display().to_string(),
+                        });
+                    }
+                    longer
+                }
+            });
+
+            for freq_hz in file_freqs {
+                freq_map.entry(freq_hz).or_default().push(i_file);
+            }
+            hdf5_files.push(Mutex::new(h5));
+            file_paths.push(path);
+        }
+
+        let mut freqs = Vec::with_capacity(freq_map.len());
+        let mut freq_files = Vec::with_capacity(freq_map.len());
+        let mut freq_overlaps = vec![];
+        for (freq_hz, i_files) in freq_map {
+            let i_used = match (i_files.as_slice(), precedence) {
+                ([i], _) => *i,
+                (_, FreqPrecedence::Error) => {
+                    return Err(InitFEEBeamError::OverlappingFreqs {
+                        freq_hz,
+                        files: i_files
+                            .iter()
+                            .map(|&i| file_paths[i].display().to_string())
+                            .collect(),
+                    })
+                }
+                (_, FreqPrecedence::First) => i_files[0],
+                (_, FreqPrecedence::Last) => i_files[i_files.len() - 1],
+            };
+            if i_files.len() > 1 {
+                freq_overlaps.push(FreqOverlap {
+                    freq_hz,
+                    used: file_paths[i_used].clone(),
+                    ignored: i_files
+                        .iter()
+                        .filter(|&&i| i != i_used)
+                        .map(|&i| file_paths[i].clone())
+                        .collect(),
+                });
+            }
+            freqs.push(freq_hz);
+            freq_files.push(i_used);
+        }
+
+        Ok(Self {
+            hdf5_files,
+            file_paths,
+            identity: OnceLock::new(),
+            freqs,
+            freq_files,
+            freq_overlaps,
+            modes: modes.expect("at least one file was read"),
+            truncation: None,
+            threads: ThreadControl::default(),
+            coeff_cache: CoeffCache::default(),
+            norm_cache: NormCache::default(),
+        })
+    }
+
+    /// Get the frequencies that are defined in more than one of the files used
+    /// to create this [`FEEBeam`], and which file is used for each. This is
+    /// empty unless the beam was made with [`FEEBeam::new_multi`].
+    pub fn get_freq_overlaps(&self) -> &[FreqOverlap] {
+        &self.freq_overlaps
+    }
+}
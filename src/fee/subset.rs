@@ -0,0 +1,144 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Writing smaller FEE beam HDF5 files, containing only some of the
+//! frequencies (and optionally fewer modes) of an existing file.
+
+use std::{path::Path, str::FromStr};
+
+use hdf5_metno::types::VarLenUnicode;
+use ndarray::prelude::*;
+
+use super::{FEEBeam, FEEBeamError, Pol};
+
+/// The number of dipoles on an MWA tile.
+const NUM_DIPOLES: usize = 16;
+
+impl FEEBeam {
+    /// Get the frequencies in the HDF5 file that are needed to calculate beam
+    /// responses between `min_freq_hz` and `max_freq_hz` (inclusive), i.e. all
+    /// of the file's frequencies that are the closest to any frequency in the
+    /// range. This is a good selection for [`FEEBeam::write_subset`].
+    pub fn get_freqs_for_range(&self, min_freq_hz: u32, max_freq_hz: u32) -> Vec<u32> {
+        let (min_freq_hz, max_freq_hz) = if min_freq_hz <= max_freq_hz {
+            (min_freq_hz, max_freq_hz)
+        } else {
+            (max_freq_hz, min_freq_hz)
+        };
+        let lowest = self.find_closest_freq(min_freq_hz);
+        let highest = self.find_closest_freq(max_freq_hz);
+        self.freqs
+            .iter()
+            .copied()
+            .filter(|&f| f >= lowest && f <= highest)
+            .collect()
+    }
+
+    /// Write a new FEE beam HDF5 file containing only the given frequencies
+    /// (which must be defined in this beam's file). If `max_n` is given, only
+    /// the modes with `N <= max_n` are kept, which reduces the file's size
+    /// (and the accuracy of the beam, particularly at high frequencies).
+    ///
+    /// The new file uses the same dataset names (`X{dipole}_{freq}`,
+    /// `Y{dipole}_{freq}` and `modes`) as the original, so it can be used with
+    /// [`FEEBeam::new`]. Attributes recording the source file and the
+    /// selection are added to the file's root group.
+    pub fn write_subset<P: AsRef<Path>>(
+        &self,
+        path: P,
+        freqs_hz: &[u32],
+        max_n: Option<u8>,
+    ) -> Result<(), FEEBeamError> {
+        if freqs_hz.is_empty() {
+            return Err(FEEBeamError::SubsetNoFreqs);
+        }
+        if let Some(&f) = freqs_hz.iter().find(|f| !self.freqs.contains(f)) {
+            return Err(FEEBeamError::SubsetFreqNotInFile(f));
+        }
+        let mut freqs_hz = freqs_hz.to_vec();
+        freqs_hz.sort_unstable();
+        freqs_hz.dedup();
+
+        // The mode indices that are kept. The datasets' coefficients
+        // correspond to the first columns of `modes`, so filtering both the
+        // same way keeps them consistent.
+        let kept_modes: Vec<usize> = self
+            .modes
+            .row(2)
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| max_n.map(|max_n| n <= max_n as i8).unwrap_or(true))
+            .map(|(i, _)| i)
+            .collect();
+        if kept_modes.is_empty() {
+            return Err(FEEBeamError::SubsetNoModes);
+        }
+        let modes = self.modes.select(Axis(1), &kept_modes);
+
+        let source = self
+            .hdf5_files
+            .iter()
+            .map(|h5| h5.lock().unwrap().filename())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let out = hdf5_metno::File::create(path)?;
+        out.new_dataset_builder()
+            .with_data(&modes.as_standard_layout())
+            .create("modes")?;
+
+        for &freq_hz in &freqs_hz {
+            for pol in [Pol::X, Pol::Y] {
+                for dipole in 1..=NUM_DIPOLES {
+                    let key = format!("{pol}{dipole}_{freq_hz}");
+                    let data = self.get_dataset(&key)?;
+                    let columns: Vec<usize> = kept_modes
+                        .iter()
+                        .copied()
+                        .take_while(|&i| i < data.len_of(Axis(1)))
+                        .collect();
+                    // The S=1 and S=2 coefficients are used in pairs.
+                    let num_s1 = columns.iter().filter(|&&i| self.modes[[0, i]] <= 1).count();
+                    if num_s1 * 2 != columns.len() {
+                        return Err(FEEBeamError::S1S2CountMismatch {
+                            expected: columns.len() / 2,
+                            got: num_s1,
+                        });
+                    }
+                    let data = data.select(Axis(1), &columns);
+                    out.new_dataset_builder()
+                        .with_data(&data.as_standard_layout())
+                        .create(key.as_str())?;
+                }
+            }
+        }
+
+        // Provenance.
+        let write_str = |name: &str, value: &str| -> Result<(), FEEBeamError> {
+            // The value can't contain nul bytes, as it comes from a path or
+            // from us.
+            let value = VarLenUnicode::from_str(value).expect("no nul bytes");
+            out.new_attr::<VarLenUnicode>()
+                .shape(())
+                .create(name)?
+                .write_scalar(&value)?;
+            Ok(())
+        };
+        write_str("hyperbeam_source_file", &source)?;
+        write_str(
+            "hyperbeam_version",
+            &format!("mwa_hyperbeam {}", env!("CARGO_PKG_VERSION")),
+        )?;
+        out.new_attr_builder()
+            .with_data(&freqs_hz)
+            .create("hyperbeam_selected_freqs_hz")?;
+        if let Some(max_n) = max_n {
+            out.new_attr_builder()
+                .with_data(&[max_n])
+                .create("hyperbeam_max_n")?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+}
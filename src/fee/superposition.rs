@@ -0,0 +1,389 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Calculating beam responses for many tile configurations by linear
+//! superposition.
+//!
+//! The FEE beam is linear in the dipole excitations, so the beam response of a
+//! tile is a weighted sum of the fields of its 32 embedded elements. The fields
+//! are calculated once for a frequency and a set of directions (which is about
+//! as expensive as 16 tile configurations), and then the Jones matrices of any
+//! tile are found with a cheap complex weighted sum.
+
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+use marlu::Jones;
+use ndarray::prelude::*;
+use num_complex::Complex64 as c64;
+use rayon::prelude::*;
+
+use super::{
+    calc_sigmas_filled, calc_zenith_norm_jones, check_direction_counts, fill_workspace, fix_amps,
+    parallactic_rotation, rotate_jones, BowtieCoefficients, FEEBeam, FEEBeamError, Pol, Workspace,
+    LANES,
+};
+use crate::{
+    constants::DELAY_STEP, legendre::LegendreTable, parallelism::ThreadControl, types::CacheKey,
+};
+
+/// The number of dipoles on an MWA tile.
+const NUM_DIPOLES: usize = 16;
+
+/// The number of directions of a tile's Jones matrices that are calculated
+/// by each parallel task.
+const DIRECTIONS_PER_TASK: usize = 256;
+
+/// The fields of all 32 embedded elements of an MWA tile, for a frequency and a
+/// set of directions. Made with [`FEEBeam::calc_element_fields`].
+///
+/// These use 1 KiB of memory per direction; to use less memory, make them for
+/// fewer directions at a time (see [`FEEBeam::calc_jones_tiles`]).
+pub struct ElementFields {
+    /// The frequency in the beam file that the fields are for.
+    freq_hz: u32,
+    /// For each element of the Jones matrices, the dipole polarisation that
+    /// contributes to it.
+    pols: [Pol; 4],
+    /// For each direction and dipole, the contributions to each element of the
+    /// Jones matrix (normalised and corrected, if requested).
+    fields: Vec<[[c64; 4]; NUM_DIPOLES]>,
+    /// The threads used for calculations, taken from the beam.
+    threads: ThreadControl,
+}
+
+impl FEEBeam {
+    /// Calculate the fields of all 32 embedded elements of an MWA tile for the
+    /// given directions, so that beam responses for many tile configurations
+    /// can be found cheaply with [`ElementFields::calc_jones`] and
+    /// [`ElementFields::calc_jones_tiles`]. The results match those of
+    /// [`FEEBeam::calc_jones_array_pair`] with the same `norm_to_zenith`,
+    /// `latitude_rad` and `iau_order` to floating-point precision.
+    ///
+    /// Mode truncation (see [`FEEBeam::set_mode_truncation`]) is not applied
+    /// to the element fields, because the modes that it keeps depend on the
+    /// whole tile configuration. With truncation enabled, the results instead
+    /// match those of a beam without truncation.
+    ///
+    /// As with `calc_jones`, the frequency is "rounded" to the closest one
+    /// defined in the HDF5 file. `az_rad` and `za_rad` must have the same
+    /// length.
+    pub fn calc_element_fields(
+        &self,
+        az_rad: &[f64],
+        za_rad: &[f64],
+        freq_hz: u32,
+        norm_to_zenith: bool,
+        latitude_rad: Option<f64>,
+        iau_order: bool,
+    ) -> Result<ElementFields, FEEBeamError> {
+        check_direction_counts(az_rad, za_rad)?;
+        let fee_freq = self.find_closest_freq(freq_hz);
+        // Element coefficients are never truncated, so neither are the
+        // coefficients used for normalisation.
+        let norm_jones = match (norm_to_zenith, self.truncation) {
+            (false, _) => None,
+            (true, None) => Some(self.get_norm_jones(fee_freq)?),
+            (true, Some(_)) => Some(calc_zenith_norm_jones(
+                &*self.get_full_modes(fee_freq, &[0; 16], &[1.0; 32])?,
+            )),
+        };
+
+        // Each dipole's X and Y elements are excited on their own.
+        let element_amps: Vec<[f64; 32]> = (0..NUM_DIPOLES)
+            .map(|dipole| {
+                let mut amps = [0.0; 32];
+                amps[dipole] = 1.0;
+                amps[NUM_DIPOLES + dipole] = 1.0;
+                amps
+            })
+            .collect();
+        let keys: Vec<CacheKey> = element_amps
+            .iter()
+            .map(|amps| CacheKey::new(fee_freq, &[0; 16], amps, false))
+            .collect();
+        // Populate the cache, then hold it for all of the dipoles at once.
+        // Someone else may empty the cache in between, so check.
+        let cache = loop {
+            for amps in &element_amps {
+                drop(self.get_full_modes(fee_freq, &[0; 16], amps)?);
+            }
+            let cache = self.coeff_cache.read();
+            if keys.iter().all(|key| cache.contains_key(key)) {
+                break cache;
+            }
+        };
+        let coeffs: Vec<&BowtieCoefficients> = keys.iter().map(|key| &cache[key]).collect();
+
+        let n_max = coeffs
+            .iter()
+            .map(|c| c.x.n_max.max(c.y.n_max))
+            .max()
+            .unwrap_or(0);
+        let mut fields = vec![[[c64::default(); 4]; NUM_DIPOLES]; az_rad.len()];
+        self.threads.install(|| {
+            let legendre = LegendreTable::new(n_max, za_rad.iter().copied());
+            az_rad
+                .par_chunks(LANES)
+                .zip(za_rad.par_chunks(LANES))
+                .zip(fields.par_chunks_mut(LANES))
+                .for_each_init(
+                    || Workspace::<f64, LANES>::new(n_max),
+                    |workspace, ((az_rad, za_rad), fields)| {
+                        // Unused lanes point at zenith.
+                        let mut az = [0.0; LANES];
+                        let mut za = [0.0; LANES];
+                        az[..az_rad.len()].copy_from_slice(az_rad);
+                        za[..za_rad.len()].copy_from_slice(za_rad);
+                        let phi = az.map(|az| FRAC_PI_2 - az);
+                        let rotations = latitude_rad.map(|lat| {
+                            std::array::from_fn::<_, LANES, _>(|l| {
+                                parallactic_rotation(az[l], za[l], lat)
+                            })
+                        });
+                        let u = fill_workspace(&phi, &za, n_max, legendre.as_ref(), workspace);
+                        for (dipole, coeffs) in coeffs.iter().enumerate() {
+                            let sigmas = calc_sigmas_filled(coeffs, &u, workspace);
+                            for (l, (mut jones, field)) in
+                                sigmas.into_iter().zip(fields.iter_mut()).enumerate()
+                            {
+                                if let Some(norm) = norm_jones {
+                                    jones.iter_mut().zip(norm.iter()).for_each(|(j, n)| *j /= n);
+                                }
+                                let mut jones = Jones::from(jones);
+                                if let Some(rotations) = rotations {
+                                    rotate_jones(rotations[l], iau_order, &mut jones);
+                                }
+                                field[dipole] = [jones[0], jones[1], jones[2], jones[3]];
+                            }
+                        }
+                    },
+                );
+        });
+
+        let pols = if latitude_rad.is_some() && iau_order {
+            [Pol::Y, Pol::Y, Pol::X, Pol::X]
+        } else {
+            [Pol::X, Pol::X, Pol::Y, Pol::Y]
+        };
+        Ok(ElementFields {
+            freq_hz: fee_freq,
+            pols,
+            fields,
+            threads: self.threads.clone(),
+        })
+    }
+
+    /// Calculate the beam-response Jones matrices of many tile configurations
+    /// for many directions, by linear superposition (see
+    /// [`FEEBeam::calc_element_fields`]). This is much faster than calling
+    /// `calc_jones_array_pair` for every tile when there are more than a few
+    /// tens of distinct tile configurations.
+    ///
+    /// `delays_array` and `amps_array` must have the same number of rows; these
+    /// correspond to tile configurations. `delays_array` must have 16 elements
+    /// per row, but `amps_array` can have 16 or 32 elements per row (see
+    /// [`FEEBeam::calc_jones`] for an explanation). `az_rad` and `za_rad` must
+    /// have the same length. The result has a row for each tile and a column
+    /// for each direction.
+    ///
+    /// The element fields are calculated for at most `max_directions`
+    /// directions at a time, which limits the extra memory used to 1 KiB per
+    /// direction.
+    #[allow(clippy::too_many_arguments)]
+    pub fn calc_jones_tiles(
+        &self,
+        az_rad: &[f64],
+        za_rad: &[f64],
+        freq_hz: u32,
+        delays_array: ArrayView2<u32>,
+        amps_array: ArrayView2<f64>,
+        norm_to_zenith: bool,
+        latitude_rad: Option<f64>,
+        iau_order: bool,
+        max_directions: usize,
+    ) -> Result<Array2<Jones<f64>>, FEEBeamError> {
+        check_direction_counts(az_rad, za_rad)?;
+        let weights = tile_weights(self.find_closest_freq(freq_hz), delays_array, amps_array)?;
+        let num_dirs = az_rad.len();
+        let mut results = Array2::default((weights.len(), num_dirs));
+        for (i_chunk, (az_rad, za_rad)) in az_rad
+            .chunks(max_directions.max(1))
+            .zip(za_rad.chunks(max_directions.max(1)))
+            .enumerate()
+        {
+            let fields = self.calc_element_fields(
+                az_rad,
+                za_rad,
+                freq_hz,
+                norm_to_zenith,
+                latitude_rad,
+                iau_order,
+            )?;
+            let jones = fields.calc_jones_weighted(&weights);
+            let start = i_chunk * max_directions.max(1);
+            results
+                .slice_mut(s![.., start..start + az_rad.len()])
+                .assign(&jones);
+        }
+        Ok(results)
+    }
+}
+
+impl ElementFields {
+    /// Get the frequency in the beam file that these fields are for.
+    pub fn get_freq(&self) -> u32 {
+        self.freq_hz
+    }
+
+    /// Get the number of directions that these fields are for.
+    pub fn num_directions(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Calculate the beam-response Jones matrices of a tile for all of the
+    /// directions.
+    ///
+    /// `delays` and `amps` apply to each dipole in an MWA tile in the M&C
+    /// order; see
+    /// <https://wiki.mwatelescope.org/pages/viewpage.action?pageId=48005139>.
+    /// `delays` *must* have 16 elements, whereas `amps` can have 16 or 32
+    /// elements; if 16 are given, then these map 1:1 with dipoles, otherwise
+    /// the first 16 are for X dipole elements, and the next 16 are for Y.
+    pub fn calc_jones(
+        &self,
+        delays: &[u32],
+        amps: &[f64],
+    ) -> Result<Vec<Jones<f64>>, FEEBeamError> {
+        let mut results = vec![Jones::default(); self.fields.len()];
+        self.calc_jones_inner(delays, amps, &mut results)?;
+        Ok(results)
+    }
+
+    /// Calculate the beam-response Jones matrices of a tile for all of the
+    /// directions. This is the same as `calc_jones` but uses pre-allocated
+    /// memory.
+    pub fn calc_jones_inner(
+        &self,
+        delays: &[u32],
+        amps: &[f64],
+        results: &mut [Jones<f64>],
+    ) -> Result<(), FEEBeamError> {
+        if delays.len() != 16 {
+            return Err(FEEBeamError::IncorrectDelaysLength(delays.len()));
+        }
+        let delays: &[u32; 16] = delays.try_into().unwrap();
+        if !(amps.len() == 16 || amps.len() == 32) {
+            return Err(FEEBeamError::IncorrectAmpsLength(amps.len()));
+        }
+        let weights = excitations(self.freq_hz, delays, &fix_amps(amps, delays));
+        self.threads.install(|| {
+            results
+                .par_chunks_mut(DIRECTIONS_PER_TASK)
+                .zip(self.fields.par_chunks(DIRECTIONS_PER_TASK))
+                .for_each(|(results, fields)| self.superpose(&weights, fields, results));
+        });
+        Ok(())
+    }
+
+    /// Calculate the beam-response Jones matrices of many tile configurations
+    /// for all of the directions. See [`FEEBeam::calc_jones_tiles`] for the
+    /// meaning of `delays_array` and `amps_array`. The result has a row for
+    /// each tile and a column for each direction.
+    pub fn calc_jones_tiles(
+        &self,
+        delays_array: ArrayView2<u32>,
+        amps_array: ArrayView2<f64>,
+    ) -> Result<Array2<Jones<f64>>, FEEBeamError> {
+        let weights = tile_weights(self.freq_hz, delays_array, amps_array)?;
+        Ok(self.calc_jones_weighted(&weights))
+    }
+
+    /// Calculate the Jones matrices of tiles with the given element
+    /// excitations.
+    fn calc_jones_weighted(&self, weights: &[[[c64; NUM_DIPOLES]; 2]]) -> Array2<Jones<f64>> {
+        let num_dirs = self.fields.len();
+        let mut results = vec![Jones::default(); weights.len() * num_dirs];
+        self.threads.install(|| {
+            results
+                .par_chunks_mut(num_dirs.max(1))
+                .zip(weights.par_iter())
+                .for_each(|(results, weights)| {
+                    results
+                        .par_chunks_mut(DIRECTIONS_PER_TASK)
+                        .zip(self.fields.par_chunks(DIRECTIONS_PER_TASK))
+                        .for_each(|(results, fields)| self.superpose(weights, fields, results));
+                });
+        });
+        Array2::from_shape_vec((weights.len(), num_dirs), results).expect("has the right length")
+    }
+
+    /// Sum the element fields of some directions with a tile's element
+    /// excitations.
+    fn superpose(
+        &self,
+        weights: &[[c64; NUM_DIPOLES]; 2],
+        fields: &[[[c64; 4]; NUM_DIPOLES]],
+        results: &mut [Jones<f64>],
+    ) {
+        let weights = self.pols.map(|pol| match pol {
+            Pol::X => &weights[0],
+            Pol::Y => &weights[1],
+        });
+        for (result, field) in results.iter_mut().zip(fields) {
+            let mut jones = [c64::default(); 4];
+            for (k, jones) in jones.iter_mut().enumerate() {
+                for (field, &weight) in field.iter().zip(weights[k].iter()) {
+                    *jones += field[k] * weight;
+                }
+            }
+            *result = Jones::from(jones);
+        }
+    }
+}
+
+/// Get the complex excitations of the X and Y elements of a tile, in the same
+/// way as the beam coefficients are calculated.
+fn excitations(freq_hz: u32, delays: &[u32; 16], amps: &[f64; 32]) -> [[c64; NUM_DIPOLES]; 2] {
+    std::array::from_fn(|i_pol| {
+        std::array::from_fn(|dipole| {
+            let phase = TAU * freq_hz as f64 * (-(delays[dipole] as f64)) * DELAY_STEP;
+            amps[i_pol * NUM_DIPOLES + dipole] * c64::cis(phase)
+        })
+    })
+}
+
+/// Get the complex excitations of the elements of many tiles.
+fn tile_weights(
+    freq_hz: u32,
+    delays_array: ArrayView2<u32>,
+    amps_array: ArrayView2<f64>,
+) -> Result<Vec<[[c64; NUM_DIPOLES]; 2]>, FEEBeamError> {
+    if delays_array.len_of(Axis(1)) != 16 {
+        return Err(FEEBeamError::IncorrectDelaysArrayColLength {
+            rows: delays_array.len_of(Axis(0)),
+            num_delays: delays_array.len_of(Axis(1)),
+        });
+    }
+    if !(amps_array.len_of(Axis(1)) == 16 || amps_array.len_of(Axis(1)) == 32) {
+        return Err(FEEBeamError::IncorrectAmpsLength(
+            amps_array.len_of(Axis(1)),
+        ));
+    }
+    if delays_array.len_of(Axis(0)) != amps_array.len_of(Axis(0)) {
+        return Err(FEEBeamError::TileCountMismatch {
+            delays: delays_array.len_of(Axis(0)),
+            amps: amps_array.len_of(Axis(0)),
+        });
+    }
+
+    Ok(delays_array
+        .outer_iter()
+        .zip(amps_array.outer_iter())
+        .map(|(delays, amps)| {
+            let delays: [u32; 16] = std::array::from_fn(|i| delays[i]);
+            let amps = amps.to_vec();
+            excitations(freq_hz, &delays, &fix_amps(&amps, &delays))
+        })
+        .collect())
+}
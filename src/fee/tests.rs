@@ -887,6 +887,934 @@ fn test_get_freqs() {
     assert!(!beam.get_freqs().is_empty());
 }
 
+#[test]
+#[serial]
+fn test_validate() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    // Populate the caches, so that we can check that validation leaves them
+    // alone.
+    beam.calc_jones_pair(
+        0.0,
+        0.1,
+        150_000_000,
+        &[0; 16],
+        &[1.0; 16],
+        true,
+        None,
+        false,
+    )
+    .unwrap();
+    let report = beam.validate(0.1);
+    for f in &report.freqs {
+        assert!(f.problems.is_empty(), "{}: {:?}", f.freq_hz, f.problems);
+        assert!(f.zenith_norm.is_some());
+    }
+    assert!(report.passed());
+    assert_eq!(report.freqs.len(), beam.get_freqs().len());
+    assert_eq!(report.num_modes, beam.modes.dim().1);
+    // Validation has no side effects on the caches.
+    assert_eq!(beam.coeff_cache.read().len(), 1);
+    assert_eq!(beam.norm_cache.read().len(), 1);
+}
+
+#[test]
+fn test_validation_report_json() {
+    let report = ValidationReport {
+        num_modes: 10,
+        max_jump: 0.1,
+        freqs: vec![
+            FreqValidation {
+                freq_hz: 100,
+                num_coeffs: Some(4),
+                zenith_norm: Some(Jones::identity()),
+                problems: vec![],
+            },
+            FreqValidation {
+                freq_hz: 200,
+                num_coeffs: None,
+                zenith_norm: None,
+                problems: vec![ValidationProblem::MissingDataset {
+                    key: "X1_\"200\"".to_string(),
+                }],
+            },
+        ],
+    };
+    assert!(!report.passed());
+    assert_eq!(
+        report.to_json(),
+        concat!(
+            r#"{"passed": false, "num_modes": 10, "max_jump": 1e-1, "freqs": ["#,
+            r#"{"freq_hz": 100, "passed": true, "num_coeffs": 4, "zenith_norm": [1e0, 0e0, 0e0, 0e0, 0e0, 0e0, 1e0, 0e0], "problems": []}, "#,
+            r#"{"freq_hz": 200, "passed": false, "num_coeffs": null, "zenith_norm": null, "problems": [{"kind": "missing_dataset", "message": "Dataset 'X1_\"200\"' is missing"}]}"#,
+            "]}"
+        )
+    );
+}
+
+#[test]
+#[serial]
+fn test_get_freqs_for_range() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let freqs = beam.get_freqs_for_range(51840001, 53000000);
+    assert_eq!(freqs, [52480000]);
+    let freqs = beam.get_freqs_for_range(53000000, 51840000);
+    assert_eq!(freqs, [51200000, 52480000]);
+}
+
+#[test]
+#[serial]
+fn test_write_subset() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let path = std::env::temp_dir().join(format!("hyperbeam_subset_{}.h5", std::process::id()));
+    let freqs = beam.get_freqs_for_range(167000000, 170000000);
+    beam.write_subset(&path, &freqs, None).unwrap();
+
+    let subset = FEEBeam::new(&path).unwrap();
+    assert_eq!(subset.get_freqs(), freqs);
+    assert_eq!(subset.modes, beam.modes);
+    let delays = [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0];
+    for freq in [167000000, 170000000] {
+        let expected = beam
+            .calc_jones_pair(0.3, 0.6, freq, &delays, &[1.0; 16], true, None, false)
+            .unwrap();
+        let result = subset
+            .calc_jones_pair(0.3, 0.6, freq, &delays, &[1.0; 16], true, None, false)
+            .unwrap();
+        assert_abs_diff_eq!(expected, result);
+    }
+    assert!(subset.validate(0.1).passed());
+    drop(subset);
+
+    // Truncated modes still give a usable beam.
+    beam.write_subset(&path, &freqs, Some(10)).unwrap();
+    let subset = FEEBeam::new(&path).unwrap();
+    assert!(subset.modes.row(2).iter().all(|&n| n <= 10));
+    assert!(subset.modes.dim().1 < beam.modes.dim().1);
+    let result = subset
+        .calc_jones_pair(0.3, 0.6, 167000000, &delays, &[1.0; 16], true, None, false)
+        .unwrap();
+    assert!(result.iter().all(|j| j.is_finite()));
+    drop(subset);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(
+        beam.write_subset(&path, &[1], None),
+        Err(FEEBeamError::SubsetFreqNotInFile(1))
+    ));
+}
+
+#[test]
+#[serial]
+fn test_validate_finds_problems() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let path = std::env::temp_dir().join(format!("hyperbeam_invalid_{}.h5", std::process::id()));
+    let freqs = beam.get_freqs_for_range(167000000, 170000000);
+    assert_eq!(freqs.len(), 2);
+    beam.write_subset(&path, &freqs, None).unwrap();
+
+    // Remove a dataset at the first frequency and put a NaN in a dataset at
+    // the second.
+    {
+        let h5 = hdf5_metno::File::open_rw(&path).unwrap();
+        h5.unlink(&format!("Y3_{}", freqs[0])).unwrap();
+        let key = format!("X2_{}", freqs[1]);
+        let mut data = h5.dataset(&key).unwrap().read_2d::<f64>().unwrap();
+        data[(1, 4)] = f64::NAN;
+        h5.unlink(&key).unwrap();
+        h5.new_dataset_builder()
+            .with_data(&data)
+            .create(key.as_str())
+            .unwrap();
+    }
+    let report = FEEBeam::new(&path).unwrap().validate(0.1);
+    assert!(!report.passed());
+    assert_eq!(report.freqs.len(), 2);
+    assert!(matches!(
+        report.freqs[0].problems.as_slice(),
+        [ValidationProblem::MissingDataset { key }] if *key == format!("Y3_{}", freqs[0])
+    ));
+    assert!(matches!(
+        report.freqs[1].problems.as_slice(),
+        [ValidationProblem::NonFiniteCoeffs { key, count: 1 }] if *key == format!("X2_{}", freqs[1])
+    ));
+    // Problems stop the beam responses from being calculated.
+    assert!(report.freqs.iter().all(|f| f.zenith_norm.is_none()));
+    assert!(report.to_json().contains("\"kind\": \"missing_dataset\""));
+
+    // Make an S=1 mode an S=2 mode, so every dataset has too few S=1 modes.
+    beam.write_subset(&path, &freqs, None).unwrap();
+    {
+        let h5 = hdf5_metno::File::open_rw(&path).unwrap();
+        let mut modes = h5.dataset("modes").unwrap().read_2d::<i8>().unwrap();
+        let i = modes.row(0).iter().position(|&t| t <= 1).unwrap();
+        modes[(0, i)] = 2;
+        h5.unlink("modes").unwrap();
+        h5.new_dataset_builder()
+            .with_data(&modes)
+            .create("modes")
+            .unwrap();
+    }
+    let report = FEEBeam::new(&path).unwrap().validate(0.1);
+    assert!(!report.passed());
+    for f in &report.freqs {
+        assert_eq!(f.problems.len(), 32, "{:?}", f.problems);
+        for problem in &f.problems {
+            match problem {
+                ValidationProblem::S1S2CountMismatch {
+                    num_s1,
+                    num_s2,
+                    num_coeffs,
+                    ..
+                } => {
+                    assert_eq!(num_s1 + 1, num_coeffs / 2);
+                    assert_eq!(num_s2 - 1, num_coeffs / 2);
+                }
+                p => panic!("unexpected problem {p:?}"),
+            }
+        }
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[serial]
+fn test_from_bytes() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    assert!(beam.get_file_path().unwrap().is_absolute());
+    let bytes = std::fs::read("mwa_full_embedded_element_pattern.h5").unwrap();
+    let in_memory = FEEBeam::from_reader(bytes.as_slice()).unwrap();
+    assert!(in_memory.get_file_path().is_none());
+    assert_eq!(in_memory.get_freqs(), beam.get_freqs());
+    assert_eq!(in_memory.modes, beam.modes);
+
+    let delays = [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0];
+    let expected = beam
+        .calc_jones_pair(0.3, 0.6, 167000000, &delays, &[1.0; 16], true, None, false)
+        .unwrap();
+    let result = in_memory
+        .calc_jones_pair(0.3, 0.6, 167000000, &delays, &[1.0; 16], true, None, false)
+        .unwrap();
+    assert_abs_diff_eq!(expected, result);
+
+    // The file image can be used to make the beam again.
+    let again = FEEBeam::from_bytes(&in_memory.to_bytes().unwrap()).unwrap();
+    assert_eq!(again.get_freqs(), beam.get_freqs());
+
+    assert!(FEEBeam::from_bytes(b"not an HDF5 file").is_err());
+}
+
+#[test]
+#[serial]
+fn test_new_multi() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let dir = std::env::temp_dir();
+    let low = dir.join(format!("hyperbeam_multi_low_{}.h5", std::process::id()));
+    let high = dir.join(format!("hyperbeam_multi_high_{}.h5", std::process::id()));
+    let low_freqs = beam.get_freqs_for_range(100000000, 150000000);
+    let high_freqs = beam.get_freqs_for_range(150000000, 200000000);
+    // The frequency closest to 150 MHz is in both files.
+    let overlap = beam.find_closest_freq(150000000);
+    beam.write_subset(&low, &low_freqs, None).unwrap();
+    beam.write_subset(&high, &high_freqs, None).unwrap();
+
+    match FEEBeam::new_multi(&[&low, &high], FreqPrecedence::Error) {
+        Err(InitFEEBeamError::OverlappingFreqs { freq_hz, files }) => {
+            assert_eq!(freq_hz, overlap);
+            assert_eq!(files.len(), 2);
+        }
+        _ => panic!("expected an overlapping frequency error"),
+    }
+
+    let delays = [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0];
+    for precedence in [FreqPrecedence::First, FreqPrecedence::Last] {
+        let multi = FEEBeam::new_multi(&[&low, &high], precedence).unwrap();
+        let mut expected_freqs = low_freqs.clone();
+        expected_freqs.extend(high_freqs.iter().filter(|&&f| f != overlap));
+        assert_eq!(multi.get_freqs(), expected_freqs);
+        assert_eq!(multi.get_file_paths().len(), 2);
+        assert!(multi.get_file_path().is_none());
+        assert!(multi.to_bytes().is_err());
+
+        let overlaps = multi.get_freq_overlaps();
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].freq_hz, overlap);
+        let (used, ignored) = match precedence {
+            FreqPrecedence::First => (&low, &high),
+            _ => (&high, &low),
+        };
+        assert_eq!(overlaps[0].used, std::fs::canonicalize(used).unwrap());
+        assert_eq!(
+            overlaps[0].ignored,
+            [std::fs::canonicalize(ignored).unwrap()]
+        );
+
+        // Datasets are read from the right files.
+        for freq in [120000000, overlap, 180000000] {
+            let expected = beam
+                .calc_jones_pair(0.3, 0.6, freq, &delays, &[1.0; 16], true, None, false)
+                .unwrap();
+            let result = multi
+                .calc_jones_pair(0.3, 0.6, freq, &delays, &[1.0; 16], true, None, false)
+                .unwrap();
+            assert_abs_diff_eq!(expected, result);
+        }
+    }
+
+    std::fs::remove_file(&low).unwrap();
+    std::fs::remove_file(&high).unwrap();
+
+    assert!(matches!(
+        FEEBeam::new_multi::<&str>(&[], FreqPrecedence::First),
+        Err(InitFEEBeamError::NoBeamFiles)
+    ));
+}
+
+/// Make coefficients for all modes up to `n_max` that don't come from a beam
+/// file, so that the kernels can be tested without HDF5.
+fn synthetic_coeffs(n_max: u8, seed: u64) -> DipoleCoefficients {
+    // A small LCG is enough to get varied values.
+    let mut state = seed;
+    let mut next = || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    };
+    let (mut q1, mut q2, mut m_accum, mut n_accum, mut m_signs) =
+        (vec![], vec![], vec![], vec![], vec![]);
+    for n in 1..=n_max as i8 {
+        for m in -n..=n {
+            q1.push(c64::new(next(), next()));
+            q2.push(c64::new(next(), next()));
+            m_accum.push(m);
+            n_accum.push(n);
+            m_signs.push(if m > 0 && m % 2 == 1 { -1 } else { 1 });
+        }
+    }
+    DipoleCoefficients::new(q1, q2, m_accum, n_accum, m_signs, n_max)
+}
+
+/// The per-direction calculation that was used before the per-mode values
+/// were precomputed.
+fn calc_sigmas_reference(phi: f64, theta: f64, coeffs: &DipoleCoefficients) -> (c64, c64) {
+    let (p1sin_arr, p1_arr) = crate::legendre::p1sin(coeffs.n_max, theta);
+    let u = theta.cos();
+    let mut sigma_p = c64::default();
+    let mut sigma_t = c64::default();
+    for i in 0..coeffs.m_accum.len() {
+        let (m, n) = (coeffs.m_accum[i], coeffs.n_accum[i]);
+        let (mf, nf) = (f64::from(m), f64::from(n));
+        let (q1, q2) = (coeffs.q1_accum[i], coeffs.q2_accum[i]);
+        let (p1sin, p1) = (p1sin_arr[i], p1_arr[i]);
+        let c_mn =
+            (0.5 * f64::from(2 * n + 1) * crate::factorial::FACTORIAL[(n - m.abs()) as usize]
+                / crate::factorial::FACTORIAL[(n + m.abs()) as usize])
+                .sqrt();
+        let phi_comp =
+            c64::cis(mf * phi) * c_mn / (nf * (nf + 1.0)).sqrt() * f64::from(coeffs.m_signs[i]);
+        let e_theta_mn =
+            J_POWER_TABLE[(n % 4) as usize] * ((p1sin * (mf.abs() * q2 * u - mf * q1)) + q2 * p1);
+        let e_phi_mn = J_POWER_TABLE[((n + 1) % 4) as usize]
+            * ((p1sin * (mf * q2 - mf.abs() * q1 * u)) - q1 * p1);
+        sigma_p += phi_comp * e_phi_mn;
+        sigma_t += phi_comp * e_theta_mn;
+    }
+    (sigma_t, -sigma_p)
+}
+
+#[test]
+fn test_calc_jones_matches_reference() {
+    let coeffs = BowtieCoefficients {
+        x: synthetic_coeffs(16, 1),
+        y: synthetic_coeffs(16, 2),
+        truncation: None,
+    };
+    // 11 directions; this isn't a multiple of the number of lanes, so padding
+    // is exercised. Zenith and the horizon are included.
+    let az: Vec<f64> = (0..11).map(|i| i as f64 * 0.61 - 3.0).collect();
+    let mut za: Vec<f64> = (0..11).map(|i| i as f64 * 0.15).collect();
+    za[10] = FRAC_PI_2;
+
+    let mut results = vec![Jones::default(); az.len()];
+    let mut workspace = Workspace::new(16);
+    calc_jones_direct_chunk(
+        &az,
+        &za,
+        &coeffs,
+        None,
+        None,
+        false,
+        None,
+        &mut workspace,
+        &mut results,
+    );
+    // Repeated zenith angles, with Legendre values from a table and from
+    // the previous lane.
+    let za_repeated: Vec<f64> = za.iter().map(|&za| za.min(0.6)).collect();
+    let legendre = LegendreTable::new(16, za_repeated.iter().copied()).unwrap();
+    let mut results_repeated = vec![Jones::default(); az.len()];
+    calc_jones_direct_chunk(
+        &az,
+        &za_repeated,
+        &coeffs,
+        None,
+        None,
+        false,
+        Some(&legendre),
+        &mut workspace,
+        &mut results_repeated,
+    );
+    for ((&az, &za), result) in az.iter().zip(za_repeated.iter()).zip(results_repeated) {
+        let phi = FRAC_PI_2 - az;
+        let (j00, j01) = calc_sigmas_reference(phi, za, &coeffs.x);
+        let (j10, j11) = calc_sigmas_reference(phi, za, &coeffs.y);
+        let expected = Jones::from([j00, j01, j10, j11]);
+        assert_abs_diff_eq!(result, expected, epsilon = 1e-12);
+    }
+
+    for ((&az, &za), result) in az.iter().zip(za.iter()).zip(results) {
+        let phi = FRAC_PI_2 - az;
+        let (j00, j01) = calc_sigmas_reference(phi, za, &coeffs.x);
+        let (j10, j11) = calc_sigmas_reference(phi, za, &coeffs.y);
+        let expected = Jones::from([j00, j01, j10, j11]);
+        assert_abs_diff_eq!(result, expected, epsilon = 1e-12);
+        assert_abs_diff_eq!(
+            calc_jones_direct(az, za, &coeffs, None),
+            expected,
+            epsilon = 1e-12
+        );
+    }
+
+    let norm = calc_zenith_norm_jones(&coeffs);
+    let expected = [
+        calc_sigmas_reference(0.0, 0.0, &coeffs.x).0,
+        calc_sigmas_reference(-FRAC_PI_2, 0.0, &coeffs.x).1,
+        calc_sigmas_reference(FRAC_PI_2, 0.0, &coeffs.y).0,
+        calc_sigmas_reference(0.0, 0.0, &coeffs.y).1,
+    ]
+    .map(|c| c64::new(c.norm(), 0.0));
+    assert_abs_diff_eq!(norm, Jones::from(expected), epsilon = 1e-12);
+}
+
+#[test]
+fn test_calc_jones_f32_accuracy() {
+    for n_max in [8, 24, 40] {
+        let coeffs = BowtieCoefficients {
+            x: synthetic_coeffs(n_max, 3),
+            y: synthetic_coeffs(n_max, 4),
+            truncation: None,
+        };
+        let n = 2000;
+        let az_f32: Vec<f32> = (0..n).map(|i| (i as f32 * 0.0137) % TAU as f32).collect();
+        let za_f32: Vec<f32> = (0..n)
+            .map(|i| i as f32 * FRAC_PI_2 as f32 / n as f32)
+            .collect();
+        // Use the same directions with f64.
+        let az: Vec<f64> = az_f32.iter().map(|&a| f64::from(a)).collect();
+        let za: Vec<f64> = za_f32.iter().map(|&z| f64::from(z)).collect();
+        let mut results = vec![Jones::<f64>::default(); n];
+        let mut results_f32 = vec![Jones::<f32>::default(); n];
+        calc_jones_direct_chunk(
+            &az,
+            &za,
+            &coeffs,
+            None,
+            Some(MWA_LAT_RAD),
+            true,
+            None,
+            &mut Workspace::new(n_max),
+            &mut results,
+        );
+        calc_jones_direct_chunk(
+            &az_f32,
+            &za_f32,
+            &coeffs,
+            None,
+            Some(MWA_LAT_RAD),
+            true,
+            None,
+            &mut Workspace::new(n_max),
+            &mut results_f32,
+        );
+
+        // The documented bound, relative to the largest magnitude of each
+        // element.
+        for i in 0..4 {
+            let max = results.iter().map(|j| j[i].norm()).fold(0.0, f64::max);
+            let max_diff = results
+                .iter()
+                .zip(results_f32.iter())
+                .map(|(j, j32)| (j[i] - Jones::<f64>::from(j32)[i]).norm())
+                .fold(0.0, f64::max);
+            assert!(
+                max_diff <= 1e-4 * max,
+                "n_max {n_max}, element {i}: difference {max_diff:e}, max {max:e}"
+            );
+        }
+    }
+}
+
+/// Coefficients whose power falls with N, like those of the real beam.
+fn decaying_coeffs(n_max: u8, seed: u64) -> DipoleCoefficients {
+    let c = synthetic_coeffs(n_max, seed);
+    let decay = |q: &[c64]| -> Vec<c64> {
+        q.iter()
+            .zip(&c.n_accum)
+            .map(|(q, &n)| q * (-f64::from(n) / 1.5).exp())
+            .collect()
+    };
+    DipoleCoefficients::new(
+        decay(&c.q1_accum),
+        decay(&c.q2_accum),
+        c.m_accum.clone(),
+        c.n_accum.clone(),
+        c.m_signs.clone(),
+        n_max,
+    )
+}
+
+#[test]
+fn test_mode_truncation() {
+    let n_max = 20;
+    let coeffs = || BowtieCoefficients {
+        x: decaying_coeffs(n_max, 5),
+        y: decaying_coeffs(n_max, 6),
+        truncation: None,
+    };
+    // Directions that aren't on the grid used to estimate errors.
+    let n = 3000;
+    let az: Vec<f64> = (0..n).map(|i| (i as f64 * 0.0123) % TAU).collect();
+    let za: Vec<f64> = (0..n).map(|i| (i as f64 * 0.000523) % FRAC_PI_2).collect();
+    let calc = |coeffs: &BowtieCoefficients| -> Vec<Jones<f64>> {
+        let mut workspace = Workspace::new(n_max);
+        let mut results = vec![Jones::default(); n];
+        calc_jones_direct_chunk(
+            &az,
+            &za,
+            coeffs,
+            None,
+            None,
+            false,
+            None,
+            &mut workspace,
+            &mut results,
+        );
+        results
+    };
+    let full = calc(&coeffs());
+    let peak = full
+        .iter()
+        .flat_map(|j| j.iter())
+        .map(|j| j.norm())
+        .fold(0.0, f64::max);
+
+    for target in [1e-2, 1e-4] {
+        let truncated = truncate_coeffs(coeffs(), ModeTruncation::MaxError(target), 150000000);
+        let report = truncated.truncation.unwrap();
+        assert_eq!(report.freq_hz, 150000000);
+        assert_eq!(report.n_max, n_max);
+        assert!(report.truncated_n_max < n_max);
+        assert_eq!(truncated.x.n_max, report.truncated_n_max);
+        assert_eq!(
+            report.truncated_num_modes,
+            2 * crate::legendre::num_modes(report.truncated_n_max)
+        );
+        assert!(report.estimated_speed_up() > 1.0);
+        assert!(report.estimated_max_error <= target);
+        // Dropping another degree would be too inaccurate.
+        let worse = truncate_coeffs(
+            coeffs(),
+            ModeTruncation::MaxError(report.estimated_max_error),
+            150000000,
+        );
+        assert_eq!(
+            worse.truncation.unwrap().truncated_n_max,
+            report.truncated_n_max
+        );
+
+        let results = calc(&truncated);
+        let max_diff = full
+            .iter()
+            .zip(results.iter())
+            .flat_map(|(a, b)| a.iter().zip(b.iter()).map(|(a, b)| (a - b).norm()))
+            .fold(0.0, f64::max);
+        // The estimate is from a grid of directions, so allow some slack.
+        assert!(
+            max_diff / peak <= 2.0 * target,
+            "target {target:e}, error {:e}",
+            max_diff / peak
+        );
+    }
+
+    // Dropping a tiny fraction of the power drops some degrees.
+    let truncated = truncate_coeffs(coeffs(), ModeTruncation::Power(1e-10), 150000000);
+    let report = truncated.truncation.unwrap();
+    assert!(report.truncated_n_max < n_max);
+    assert!(report.estimated_max_error < 1e-3);
+    // Dropping nothing keeps everything.
+    let truncated = truncate_coeffs(coeffs(), ModeTruncation::Power(0.0), 150000000);
+    let report = truncated.truncation.unwrap();
+    assert_eq!(report.truncated_n_max, n_max);
+    assert_abs_diff_eq!(report.estimated_max_error, 0.0);
+}
+
+#[test]
+#[serial]
+fn test_calc_jones_array_f32() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let az_f32: Vec<f32> = (0..1000)
+        .map(|i| (i as f32 * 0.0137) % TAU as f32)
+        .collect();
+    let za_f32: Vec<f32> = (0..1000).map(|i| i as f32 * 0.0015).collect();
+    let az: Vec<f64> = az_f32.iter().map(|&a| f64::from(a)).collect();
+    let za: Vec<f64> = za_f32.iter().map(|&z| f64::from(z)).collect();
+    let delays = [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0];
+    for freq_hz in [51200000, 180000000, 327680000] {
+        let result = beam
+            .calc_jones_array_pair(
+                &az,
+                &za,
+                freq_hz,
+                &delays,
+                &[1.0; 16],
+                true,
+                Some(MWA_LAT_RAD),
+                false,
+            )
+            .unwrap();
+        let result_f32 = beam
+            .calc_jones_array_pair_f32(
+                &az_f32,
+                &za_f32,
+                freq_hz,
+                &delays,
+                &[1.0; 16],
+                true,
+                Some(MWA_LAT_RAD),
+                false,
+            )
+            .unwrap();
+        for (j, j32) in result.iter().zip(result_f32.iter()) {
+            assert_abs_diff_eq!(*j, Jones::<f64>::from(j32), epsilon = 1e-4);
+        }
+    }
+}
+
+#[test]
+fn test_freq_precedence_from_str() {
+    for p in [
+        FreqPrecedence::Error,
+        FreqPrecedence::First,
+        FreqPrecedence::Last,
+    ] {
+        assert_eq!(p.as_str().parse::<FreqPrecedence>(), Ok(p));
+    }
+    assert_eq!("LAST".parse::<FreqPrecedence>(), Ok(FreqPrecedence::Last));
+    assert!("middle".parse::<FreqPrecedence>().is_err());
+}
+
+#[test]
+#[serial]
+fn test_metadata() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let metadata = beam.metadata().unwrap();
+    let id = &metadata.identity;
+    assert_eq!(id.sha256.len(), 64);
+    assert!(id.sha256.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(
+        id.size_bytes,
+        std::fs::metadata("mwa_full_embedded_element_pattern.h5")
+            .unwrap()
+            .len()
+    );
+    assert_eq!(id.path.as_deref(), beam.get_file_path());
+    assert_eq!(metadata.num_modes, beam.modes.dim().1);
+    assert_eq!(metadata.freqs.len(), beam.get_freqs().len());
+    for f in &metadata.freqs {
+        assert!(f.num_coeffs <= metadata.num_modes);
+        assert_eq!(f.num_coeffs % 2, 0);
+        assert!(f.n_max > 0);
+    }
+    // Higher frequencies need more modes.
+    assert!(metadata.freqs.last().unwrap().n_max >= metadata.freqs[0].n_max);
+
+    // The checksum doesn't depend on how the beam was opened.
+    let bytes = std::fs::read("mwa_full_embedded_element_pattern.h5").unwrap();
+    let in_memory = FEEBeam::from_bytes(&bytes).unwrap();
+    let in_memory_id = in_memory.identity().unwrap();
+    assert_eq!(in_memory_id.sha256, id.sha256);
+    assert!(in_memory_id.path.is_none());
+
+    assert!(metadata
+        .to_json()
+        .starts_with(&format!("{{\"sha256\": \"{}\"", id.sha256)));
+}
+
+#[test]
+#[serial]
+fn test_find_beam_file() {
+    let dir = std::env::temp_dir().join(format!("hyperbeam_search_{}", std::process::id()));
+    let data_home = dir.join("data_home");
+    let search_dir = dir.join("search");
+    std::fs::create_dir_all(data_home.join("mwa_hyperbeam")).unwrap();
+    std::fs::create_dir_all(&search_dir).unwrap();
+    let old_vars: Vec<_> = ["MWA_BEAM_FILE", "XDG_DATA_HOME", "XDG_DATA_DIRS"]
+        .into_iter()
+        .map(|v| (v, std::env::var_os(v)))
+        .collect();
+    unsafe {
+        std::env::remove_var("MWA_BEAM_FILE");
+        std::env::set_var("XDG_DATA_HOME", &data_home);
+        std::env::set_var("XDG_DATA_DIRS", dir.join("nothing_here"));
+    }
+
+    let paths = beam_file_search_paths(&[&search_dir]);
+    assert_eq!(
+        paths,
+        vec![
+            search_dir.join(BEAM_FILE_NAME),
+            data_home.join("mwa_hyperbeam").join(BEAM_FILE_NAME),
+            dir.join("nothing_here/mwa_hyperbeam").join(BEAM_FILE_NAME),
+        ]
+    );
+    match find_beam_file(&[&search_dir]) {
+        Err(InitFEEBeamError::BeamFileNotFound(searched)) => assert_eq!(searched, paths),
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    // Files found earlier in the search take precedence.
+    let xdg_file = data_home.join("mwa_hyperbeam").join(BEAM_FILE_NAME);
+    std::fs::write(&xdg_file, b"").unwrap();
+    assert_eq!(find_beam_file(&[&search_dir]).unwrap(), xdg_file);
+    let dir_file = search_dir.join(BEAM_FILE_NAME);
+    std::fs::write(&dir_file, b"").unwrap();
+    assert_eq!(find_beam_file(&[&search_dir]).unwrap(), dir_file);
+    let env_file = dir.join("env.h5");
+    std::fs::write(&env_file, b"").unwrap();
+    unsafe { std::env::set_var("MWA_BEAM_FILE", &env_file) };
+    assert_eq!(find_beam_file(&[&search_dir]).unwrap(), env_file);
+
+    for (var, value) in old_vars {
+        match value {
+            Some(v) => unsafe { std::env::set_var(var, v) },
+            None => unsafe { std::env::remove_var(var) },
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[serial]
+fn test_element_patterns_sum_to_tile() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let freq_hz = 180000000;
+    let delays = [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0];
+    let mut amps = [1.0; 32];
+    amps[4] = 0.5;
+    amps[21] = 0.0;
+    let az = [0.0, 0.4, 1.3, 2.9, 4.5];
+    let za = [0.0, 0.1, 0.5, 0.9, 1.4];
+    let tile = beam
+        .calc_jones_array_pair(&az, &za, freq_hz, &delays, &amps, false, None, false)
+        .unwrap();
+
+    let mut sums = vec![[[c64::default(); 2]; 2]; az.len()];
+    for (i_pol, pol) in [Pol::X, Pol::Y].into_iter().enumerate() {
+        for (dipole, &delay) in delays.iter().enumerate() {
+            let v = amps[16 * i_pol + dipole]
+                * c64::cis(TAU * freq_hz as f64 * -(delay as f64) * DELAY_STEP);
+            let patterns = beam
+                .calc_element_pattern_array(dipole, pol, &az, &za, freq_hz)
+                .unwrap();
+            for (i_dir, (sum, pattern)) in sums.iter_mut().zip(patterns).enumerate() {
+                let single = beam
+                    .calc_element_pattern(dipole, pol, az[i_dir], za[i_dir], freq_hz)
+                    .unwrap();
+                assert_abs_diff_eq!(single[0], pattern[0], epsilon = 1e-12);
+                assert_abs_diff_eq!(single[1], pattern[1], epsilon = 1e-12);
+                sum[i_pol][0] += pattern[0] * v;
+                sum[i_pol][1] += pattern[1] * v;
+            }
+        }
+    }
+
+    for (jones, sum) in tile.iter().zip(sums) {
+        let expected = Jones::from([sum[0][0], sum[0][1], sum[1][0], sum[1][1]]);
+        assert_abs_diff_eq!(*jones, expected, epsilon = 1e-10);
+    }
+}
+
+#[test]
+#[serial]
+fn test_superposition_matches_calc_jones_array() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let freq_hz = 180000000;
+    let az: Vec<f64> = (0..37).map(|i| 0.17 * i as f64).collect();
+    let za: Vec<f64> = (0..37).map(|i| 0.04 * i as f64).collect();
+    let delays = array![
+        [0; 16],
+        [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0],
+        [0, 32, 4, 6, 0, 1, 2, 3, 10, 12, 14, 16, 0, 4, 8, 12],
+    ];
+    let mut amps = Array2::ones((3, 32));
+    amps[(1, 4)] = 0.5;
+    amps[(2, 20)] = 0.0;
+
+    for (norm_to_zenith, latitude_rad, iau_order) in [
+        (false, None, false),
+        (true, None, false),
+        (true, Some(MWA_LAT_RAD), false),
+        (true, Some(MWA_LAT_RAD), true),
+    ] {
+        let results = beam
+            .calc_jones_tiles(
+                &az,
+                &za,
+                freq_hz,
+                delays.view(),
+                amps.view(),
+                norm_to_zenith,
+                latitude_rad,
+                iau_order,
+                10,
+            )
+            .unwrap();
+        let fields = beam
+            .calc_element_fields(&az, &za, freq_hz, norm_to_zenith, latitude_rad, iau_order)
+            .unwrap();
+        assert_eq!(fields.num_directions(), az.len());
+        for ((tile_delays, tile_amps), tile_results) in delays
+            .outer_iter()
+            .zip(amps.outer_iter())
+            .zip(results.outer_iter())
+        {
+            let tile_delays = tile_delays.as_slice().unwrap();
+            let tile_amps = tile_amps.as_slice().unwrap();
+            let expected = beam
+                .calc_jones_array_pair(
+                    &az,
+                    &za,
+                    freq_hz,
+                    tile_delays,
+                    tile_amps,
+                    norm_to_zenith,
+                    latitude_rad,
+                    iau_order,
+                )
+                .unwrap();
+            let single = fields.calc_jones(tile_delays, tile_amps).unwrap();
+            for ((result, single), expected) in tile_results.iter().zip(single).zip(expected) {
+                assert_abs_diff_eq!(*result, expected, epsilon = 1e-12);
+                assert_abs_diff_eq!(single, expected, epsilon = 1e-12);
+            }
+        }
+    }
+
+    let result = beam.calc_jones_tiles(
+        &az,
+        &za,
+        freq_hz,
+        delays.view(),
+        amps.slice(s![..2, ..]),
+        false,
+        None,
+        false,
+        10,
+    );
+    assert!(matches!(
+        result,
+        Err(FEEBeamError::TileCountMismatch { delays: 3, amps: 2 })
+    ));
+}
+
+#[test]
+#[serial]
+fn test_superposition_with_mode_truncation() {
+    let full_beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let mut beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let target = 1e-3;
+    beam.set_mode_truncation(Some(ModeTruncation::MaxError(target)));
+    let freq_hz = 180000000;
+    let az: Vec<f64> = (0..37).map(|i| 0.17 * i as f64).collect();
+    let za: Vec<f64> = (0..37).map(|i| 0.04 * i as f64).collect();
+    let delays = array![[0; 16], [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0]];
+    let amps = Array2::ones((2, 32));
+
+    // Element fields ignore truncation, so superposition matches a beam
+    // without truncation.
+    let results = beam
+        .calc_jones_tiles(
+            &az,
+            &za,
+            freq_hz,
+            delays.view(),
+            amps.view(),
+            true,
+            Some(MWA_LAT_RAD),
+            true,
+            10,
+        )
+        .unwrap();
+    for ((tile_delays, tile_amps), tile_results) in delays
+        .outer_iter()
+        .zip(amps.outer_iter())
+        .zip(results.outer_iter())
+    {
+        let tile_delays = tile_delays.as_slice().unwrap();
+        let tile_amps = tile_amps.as_slice().unwrap();
+        let report = beam
+            .get_truncation_report(freq_hz, tile_delays, tile_amps)
+            .unwrap();
+        assert!(report.truncated_n_max < report.n_max);
+
+        let full = full_beam
+            .calc_jones_array_pair(
+                &az,
+                &za,
+                freq_hz,
+                tile_delays,
+                tile_amps,
+                true,
+                Some(MWA_LAT_RAD),
+                true,
+            )
+            .unwrap();
+        let truncated = beam
+            .calc_jones_array_pair(
+                &az,
+                &za,
+                freq_hz,
+                tile_delays,
+                tile_amps,
+                true,
+                Some(MWA_LAT_RAD),
+                true,
+            )
+            .unwrap();
+        let mut max_diff: f64 = 0.0;
+        for ((result, full), truncated) in tile_results.iter().zip(full).zip(truncated) {
+            assert_abs_diff_eq!(*result, full, epsilon = 1e-12);
+            for (a, b) in result.iter().zip(truncated.iter()) {
+                max_diff = max_diff.max((a - b).norm());
+            }
+        }
+        // The truncated beam responses are still close.
+        assert!(max_diff > 0.0);
+        assert!(max_diff <= 2.0 * target, "{max_diff:e}");
+    }
+
+    // Element patterns ignore truncation too.
+    let pattern = beam
+        .calc_element_pattern(5, Pol::Y, 0.3, 0.4, freq_hz)
+        .unwrap();
+    let expected = full_beam
+        .calc_element_pattern(5, Pol::Y, 0.3, 0.4, freq_hz)
+        .unwrap();
+    assert_abs_diff_eq!(pattern[0], expected[0], epsilon = 1e-12);
+    assert_abs_diff_eq!(pattern[1], expected[1], epsilon = 1e-12);
+}
+
 // Tests for coverage follow.
 
 #[test]
@@ -950,3 +1878,90 @@ fn test_error_env_file_doesnt_exist() {
         _ => unreachable!(),
     }
 }
+
+#[test]
+#[serial]
+fn test_error_invalid_dipole() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let result = beam.calc_element_pattern(16, Pol::X, 0.0, 0.0, 51200000);
+    assert!(matches!(result, Err(FEEBeamError::InvalidDipole(16))));
+}
+
+#[test]
+#[serial]
+fn test_error_direction_count_mismatch() {
+    let beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let az = [0.1, 0.2, 0.3];
+    let za = [0.1, 0.2];
+    let result = beam.calc_element_pattern_array(0, Pol::X, &az, &za, 51200000);
+    assert!(matches!(
+        result,
+        Err(FEEBeamError::DirectionCountMismatch { az: 3, za: 2 })
+    ));
+
+    let result = beam.calc_jones_tiles(
+        &az,
+        &za,
+        51200000,
+        Array2::zeros((1, 16)).view(),
+        Array2::ones((1, 16)).view(),
+        false,
+        None,
+        false,
+        10,
+    );
+    assert!(matches!(
+        result,
+        Err(FEEBeamError::DirectionCountMismatch { az: 3, za: 2 })
+    ));
+
+    let result = beam.calc_element_fields(&az[..1], &za, 51200000, false, None, false);
+    assert!(matches!(
+        result,
+        Err(FEEBeamError::DirectionCountMismatch { az: 1, za: 2 })
+    ));
+}
+
+#[test]
+#[serial]
+fn test_parallelism_doesnt_change_results() {
+    let mut beam = FEEBeam::new("mwa_full_embedded_element_pattern.h5").unwrap();
+    let freq_hz = 180000000;
+    let delays = [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0];
+    let amps = [1.0; 16];
+    let az: Vec<f64> = (0..300).map(|i| 0.021 * i as f64).collect();
+    let za: Vec<f64> = (0..300).map(|i| 0.005 * i as f64).collect();
+    let expected = beam
+        .calc_jones_array_pair(
+            &az,
+            &za,
+            freq_hz,
+            &delays,
+            &amps,
+            true,
+            Some(MWA_LAT_RAD),
+            true,
+        )
+        .unwrap();
+
+    for parallelism in [
+        Parallelism::Serial,
+        Parallelism::Threads(3),
+        Parallelism::Global,
+    ] {
+        beam.set_parallelism(parallelism).unwrap();
+        let results = beam
+            .calc_jones_array_pair(
+                &az,
+                &za,
+                freq_hz,
+                &delays,
+                &amps,
+                true,
+                Some(MWA_LAT_RAD),
+                true,
+            )
+            .unwrap();
+        assert_eq!(results, expected);
+    }
+}
@@ -0,0 +1,285 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Dropping the highest-order spherical-harmonic modes of FEE beam
+//! coefficients, trading accuracy for speed.
+//!
+//! The cost of calculating a beam response is proportional to the number of
+//! modes, which is about N_max^2. Most of the power of the beam is in the modes
+//! with low N, so the modes with the highest N can often be dropped without
+//! changing the beam responses much. Only whole degrees (all modes with the
+//! same N) are dropped.
+
+use std::f64::consts::TAU;
+
+use marlu::Jones;
+use rayon::prelude::*;
+
+use super::{
+    calc_jones_direct_chunk, fix_amps, BowtieCoefficients, DipoleCoefficients, FEEBeam,
+    FEEBeamError, Workspace, LANES,
+};
+use crate::legendre::LegendreTable;
+
+/// How the modes of FEE beam coefficients are truncated. See
+/// [`FEEBeam::set_mode_truncation`].
+///
+/// Only whole degrees are dropped, starting from the highest N; individual
+/// modes with little power are kept if any mode with a bigger N is kept, and
+/// modes are never dropped from the middle of the range of N.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeTruncation {
+    /// Drop the modes with the highest N as long as their combined power
+    /// (|Q1|^2 + |Q2|^2) is no more than this fraction of the total power of
+    /// the coefficients.
+    Power(f64),
+
+    /// Drop the modes with the highest N as long as the estimated maximum
+    /// error of any Jones matrix element, relative to the biggest Jones matrix
+    /// element of the beam, is no more than this.
+    MaxError(f64),
+}
+
+/// The effect of truncating a set of FEE beam coefficients. See
+/// [`FEEBeam::get_truncation_report`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TruncationReport {
+    /// The frequency in the beam file that the coefficients are for \[Hz\].
+    pub freq_hz: u32,
+    /// The biggest N of the coefficients before truncation.
+    pub n_max: u8,
+    /// The biggest N of the coefficients after truncation.
+    pub truncated_n_max: u8,
+    /// The number of modes (for both polarisations) before truncation.
+    pub num_modes: usize,
+    /// The number of modes (for both polarisations) after truncation.
+    pub truncated_num_modes: usize,
+    /// The maximum error of the Jones matrix elements caused by the
+    /// truncation, relative to the biggest Jones matrix element of the beam.
+    /// This is found on a grid of directions above the horizon (2 degrees in
+    /// zenith angle by 4 degrees in azimuth), so it's an estimate.
+    pub estimated_max_error: f64,
+}
+
+impl TruncationReport {
+    /// The estimated speed-up of beam-response calculations due to the
+    /// truncation, i.e. the ratio of the numbers of modes.
+    pub fn estimated_speed_up(&self) -> f64 {
+        self.num_modes as f64 / self.truncated_num_modes.max(1) as f64
+    }
+}
+
+impl FEEBeam {
+    /// Drop the modes with the highest N from the beam coefficients of every
+    /// frequency according to `truncation`, which makes beam responses faster
+    /// to calculate at the expense of accuracy. `None` uses all of the modes
+    /// (the default). This applies to both CPU and GPU calculations, and
+    /// empties the caches of coefficients.
+    ///
+    /// The modes that are kept depend on the frequency, delays and amps; use
+    /// [`FEEBeam::get_truncation_report`] to see what was done. Element
+    /// patterns and superposition (e.g. [`FEEBeam::calc_element_pattern`] and
+    /// [`FEEBeam::calc_jones_tiles`]) always use all of the modes.
+    /// Preparing each set of coefficients is slower, because the error of the
+    /// truncation is estimated by calculating thousands of beam responses.
+    pub fn set_mode_truncation(&mut self, truncation: Option<ModeTruncation>) {
+        self.truncation = truncation;
+        self.empty_cache();
+    }
+
+    /// Get how modes are truncated; see [`FEEBeam::set_mode_truncation`].
+    pub fn get_mode_truncation(&self) -> Option<ModeTruncation> {
+        self.truncation
+    }
+
+    /// Get the effect of mode truncation (see
+    /// [`FEEBeam::set_mode_truncation`]) on the coefficients for a frequency,
+    /// delays and amps. If truncation is not enabled, the report shows that no
+    /// modes were dropped.
+    ///
+    /// See [`FEEBeam::calc_jones`] for the meaning of `delays` and `amps`.
+    pub fn get_truncation_report(
+        &self,
+        freq_hz: u32,
+        delays: &[u32],
+        amps: &[f64],
+    ) -> Result<TruncationReport, FEEBeamError> {
+        if delays.len() != 16 {
+            return Err(FEEBeamError::IncorrectDelaysLength(delays.len()));
+        }
+        let delays: &[u32; 16] = delays.try_into().unwrap();
+        if !(amps.len() == 16 || amps.len() == 32) {
+            return Err(FEEBeamError::IncorrectAmpsLength(amps.len()));
+        }
+        let full_amps = fix_amps(amps, delays);
+        let fee_freq = self.find_closest_freq(freq_hz);
+        let coeffs = self.get_modes(fee_freq, delays, &full_amps)?;
+        Ok(coeffs.truncation.unwrap_or_else(|| {
+            let n_max = coeffs.x.n_max.max(coeffs.y.n_max);
+            let num_modes = coeffs.x.m_accum.len() + coeffs.y.m_accum.len();
+            TruncationReport {
+                freq_hz: fee_freq,
+                n_max,
+                truncated_n_max: n_max,
+                num_modes,
+                truncated_num_modes: num_modes,
+                estimated_max_error: 0.0,
+            }
+        }))
+    }
+}
+
+impl DipoleCoefficients {
+    /// Get these coefficients without the modes with N bigger than `n_max`.
+    fn truncated(&self, n_max: u8) -> DipoleCoefficients {
+        // Modes are in order of N.
+        let n_max = i8::try_from(n_max).unwrap_or(i8::MAX);
+        let num_kept = self.n_accum.iter().take_while(|&&n| n <= n_max).count();
+        DipoleCoefficients::new(
+            self.q1_accum[..num_kept].to_vec(),
+            self.q2_accum[..num_kept].to_vec(),
+            self.m_accum[..num_kept].to_vec(),
+            self.n_accum[..num_kept].to_vec(),
+            self.m_signs[..num_kept].to_vec(),
+            self.n_accum[..num_kept]
+                .iter()
+                .copied()
+                .max()
+                .unwrap_or(0)
+                .try_into()
+                .expect("N is always positive"),
+        )
+    }
+}
+
+impl BowtieCoefficients {
+    /// Get these coefficients without the modes with N bigger than `n_max`.
+    fn truncated(&self, n_max: u8) -> BowtieCoefficients {
+        BowtieCoefficients {
+            x: self.x.truncated(n_max),
+            y: self.y.truncated(n_max),
+            truncation: None,
+        }
+    }
+}
+
+/// Truncate the modes of `coeffs` (for the beam-file frequency `freq_hz`)
+/// according to `truncation`. The result includes a [`TruncationReport`].
+pub(super) fn truncate_coeffs(
+    coeffs: BowtieCoefficients,
+    truncation: ModeTruncation,
+    freq_hz: u32,
+) -> BowtieCoefficients {
+    let n_max = coeffs.x.n_max.max(coeffs.y.n_max);
+    let (az, za) = error_grid();
+    let legendre = LegendreTable::new(n_max, za.iter().copied());
+    let full_jones = grid_jones(&coeffs, &az, &za, legendre.as_ref());
+    let peak = full_jones
+        .iter()
+        .flat_map(|j| j.iter())
+        .map(|j| j.norm())
+        .fold(0.0, f64::max);
+    let max_error = |truncated: &BowtieCoefficients| {
+        let jones = grid_jones(truncated, &az, &za, legendre.as_ref());
+        let max_diff = full_jones
+            .iter()
+            .flat_map(|j| j.iter())
+            .zip(jones.iter().flat_map(|j| j.iter()))
+            .map(|(a, b)| (a - b).norm())
+            .fold(0.0, f64::max);
+        if peak > 0.0 {
+            max_diff / peak
+        } else {
+            0.0
+        }
+    };
+
+    let (truncated_n_max, truncated) = match truncation {
+        ModeTruncation::Power(fraction) => {
+            // The power of each degree.
+            let mut power = vec![0.0; usize::from(n_max) + 1];
+            for c in [&coeffs.x, &coeffs.y] {
+                for ((&n, q1), q2) in c.n_accum.iter().zip(&c.q1_accum).zip(&c.q2_accum) {
+                    power[n as usize] += q1.norm_sqr() + q2.norm_sqr();
+                }
+            }
+            let total: f64 = power.iter().sum();
+            let mut dropped = 0.0;
+            let mut truncated_n_max = n_max;
+            while truncated_n_max > 1 {
+                dropped += power[usize::from(truncated_n_max)];
+                if dropped > fraction * total {
+                    break;
+                }
+                truncated_n_max -= 1;
+            }
+            (truncated_n_max, coeffs.truncated(truncated_n_max))
+        }
+
+        ModeTruncation::MaxError(target) => {
+            // Find the smallest N_max that is accurate enough, assuming that
+            // the error gets smaller as N_max gets bigger.
+            let (mut low, mut high) = (1, n_max);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if max_error(&coeffs.truncated(mid)) <= target {
+                    high = mid;
+                } else {
+                    low = mid + 1;
+                }
+            }
+            (low, coeffs.truncated(low))
+        }
+    };
+
+    let report = TruncationReport {
+        freq_hz,
+        n_max,
+        truncated_n_max,
+        num_modes: coeffs.x.m_accum.len() + coeffs.y.m_accum.len(),
+        truncated_num_modes: truncated.x.m_accum.len() + truncated.y.m_accum.len(),
+        estimated_max_error: max_error(&truncated),
+    };
+    BowtieCoefficients {
+        truncation: Some(report),
+        ..truncated
+    }
+}
+
+/// The directions used to estimate the errors of truncation.
+fn error_grid() -> (Vec<f64>, Vec<f64>) {
+    let mut az = vec![];
+    let mut za = vec![];
+    for i_za in 0..=45 {
+        for i_az in 0..90 {
+            az.push(TAU * i_az as f64 / 90.0);
+            za.push((2.0 * i_za as f64).to_radians());
+        }
+    }
+    (az, za)
+}
+
+/// Calculate un-normalised Jones matrices for many directions.
+fn grid_jones(
+    coeffs: &BowtieCoefficients,
+    az_rad: &[f64],
+    za_rad: &[f64],
+    legendre: Option<&LegendreTable>,
+) -> Vec<Jones<f64>> {
+    let n_max = coeffs.x.n_max.max(coeffs.y.n_max);
+    let mut results = vec![Jones::default(); az_rad.len()];
+    az_rad
+        .par_chunks(LANES)
+        .zip(za_rad.par_chunks(LANES))
+        .zip(results.par_chunks_mut(LANES))
+        .for_each_init(
+            || Workspace::new(n_max),
+            |workspace, ((az, za), results)| {
+                calc_jones_direct_chunk(
+                    az, za, coeffs, None, None, false, legendre, workspace, results,
+                );
+            },
+        );
+    results
+}
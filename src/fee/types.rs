@@ -4,13 +4,14 @@
 
 //! Helper types for the FEE beam.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::OnceLock};
 
 use marlu::Jones;
-use num_complex::Complex64 as c64;
+use num_complex::{Complex, Complex64 as c64};
 use parking_lot::RwLock;
 
-use crate::types::CacheKey;
+use super::TruncationReport;
+use crate::{constants::J_POWER_TABLE, factorial::FACTORIAL, types::CacheKey};
 
 /// Coefficients for the X or Y dipole on an MWA bowtie. When combined with an
 /// (az, za) direction, this is everything that's needed to calculate a beam
@@ -25,11 +26,97 @@ pub(super) struct DipoleCoefficients {
     pub(super) m_signs: Vec<i8>,
     /// The biggest N coefficient.
     pub(super) n_max: u8,
+    /// For each mode, the factors that don't depend on direction, i.e.
+    /// `sign(M) * sqrt(0.5 (2N+1) (N-|M|)! / (N+|M|)!) / sqrt(N (N+1))`.
+    pub(super) norms: Vec<f64>,
+    /// For each mode, Q1 multiplied by j^N.
+    pub(super) q1_jn: Vec<c64>,
+    /// For each mode, Q2 multiplied by j^N.
+    pub(super) q2_jn: Vec<c64>,
+    /// The per-mode values for calculations with `f32`. These are only made
+    /// when first needed; see [`DipoleCoefficients::f32_coeffs`].
+    f32_coeffs: OnceLock<DipoleCoefficientsF32>,
+}
+
+/// The per-mode values of [`DipoleCoefficients`] used for calculations with
+/// `f32`.
+pub(super) struct DipoleCoefficientsF32 {
+    /// For each mode, the sign of the normalisation (the rest of the
+    /// normalisation is applied to Legendre values).
+    pub(super) signs: Vec<f32>,
+    /// `q1_jn` as `f32`.
+    pub(super) q1_jn: Vec<Complex<f32>>,
+    /// `q2_jn` as `f32`.
+    pub(super) q2_jn: Vec<Complex<f32>>,
+}
+
+impl DipoleCoefficients {
+    /// Make new [`DipoleCoefficients`], calculating the per-mode values that
+    /// are used for every direction.
+    pub(super) fn new(
+        q1_accum: Vec<c64>,
+        q2_accum: Vec<c64>,
+        m_accum: Vec<i8>,
+        n_accum: Vec<i8>,
+        m_signs: Vec<i8>,
+        n_max: u8,
+    ) -> DipoleCoefficients {
+        let num_modes = m_accum.len();
+        let mut norms = Vec::with_capacity(num_modes);
+        let mut q1_jn = Vec::with_capacity(num_modes);
+        let mut q2_jn = Vec::with_capacity(num_modes);
+        for ((((&m, &n), &sign), &q1), &q2) in m_accum
+            .iter()
+            .zip(n_accum.iter())
+            .zip(m_signs.iter())
+            .zip(q1_accum.iter())
+            .zip(q2_accum.iter())
+        {
+            let m_abs = usize::from(m.unsigned_abs());
+            let n_u = usize::try_from(n).expect("N is always positive");
+            let nf = f64::from(n);
+            let c_mn = (0.5 * (2 * n_u + 1) as f64 * FACTORIAL[n_u - m_abs]
+                / FACTORIAL[n_u + m_abs])
+                .sqrt();
+            norms.push(f64::from(sign) * c_mn / (nf * (nf + 1.0)).sqrt());
+            let j_power_n = J_POWER_TABLE[n_u % 4];
+            q1_jn.push(j_power_n * q1);
+            q2_jn.push(j_power_n * q2);
+        }
+
+        DipoleCoefficients {
+            q1_accum,
+            q2_accum,
+            m_accum,
+            n_accum,
+            m_signs,
+            n_max,
+            norms,
+            q1_jn,
+            q2_jn,
+            f32_coeffs: OnceLock::new(),
+        }
+    }
+
+    /// Get the per-mode values for calculations with `f32`, making them if
+    /// this is the first time they're needed.
+    pub(super) fn f32_coeffs(&self) -> &DipoleCoefficientsF32 {
+        self.f32_coeffs.get_or_init(|| {
+            let to_f32 = |c: &c64| Complex::new(c.re as f32, c.im as f32);
+            DipoleCoefficientsF32 {
+                signs: self.m_signs.iter().map(|&s| f32::from(s)).collect(),
+                q1_jn: self.q1_jn.iter().map(to_f32).collect(),
+                q2_jn: self.q2_jn.iter().map(to_f32).collect(),
+            }
+        })
+    }
 }
 
 pub(super) struct BowtieCoefficients {
     pub(super) x: DipoleCoefficients,
     pub(super) y: DipoleCoefficients,
+    /// If modes were dropped from the coefficients, what was done.
+    pub(super) truncation: Option<TruncationReport>,
 }
 
 /// [`CoeffCache`] is just a [`RwLock`] around a [`HashMap`]. This allows
@@ -0,0 +1,437 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Thorough validation of FEE beam HDF5 files.
+//!
+//! This is what `verify-beam-file` runs. Checking everything is much slower
+//! than opening the file, so none of this happens at runtime.
+
+use std::fmt::Write;
+
+use marlu::Jones;
+
+use super::{calc_jones_direct_chunk, calc_zenith_norm_jones, FEEBeam, Pol, Workspace};
+use crate::json::{json_f64, json_string};
+
+/// The number of dipoles on an MWA tile.
+const NUM_DIPOLES: usize = 16;
+
+/// The directions [radians] used to compare beam responses between adjacent
+/// frequencies; zenith angles up to 80 degrees every 10 degrees, at 8
+/// azimuths.
+fn continuity_directions() -> (Vec<f64>, Vec<f64>) {
+    (0..8)
+        .flat_map(|i_az| {
+            (0..=8).map(move |i_za| {
+                (
+                    (i_az as f64 * 45.0).to_radians(),
+                    (i_za as f64 * 10.0).to_radians(),
+                )
+            })
+        })
+        .unzip()
+}
+
+/// A problem found with an FEE beam file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationProblem {
+    /// A dataset for a dipole and frequency doesn't exist.
+    MissingDataset { key: String },
+
+    /// A dataset couldn't be read.
+    Hdf5 { key: String, msg: String },
+
+    /// A dataset isn't a 2D array with 2 rows.
+    DatasetShape { key: String, shape: Vec<usize> },
+
+    /// A dataset has more coefficients than there are modes.
+    TooManyCoeffs {
+        key: String,
+        num_coeffs: usize,
+        num_modes: usize,
+    },
+
+    /// Not all of a frequency's datasets have the same number of
+    /// coefficients.
+    InconsistentCoeffCounts {
+        key: String,
+        num_coeffs: usize,
+        expected: usize,
+    },
+
+    /// The numbers of S=1 and S=2 modes for a dataset aren't both half of its
+    /// coefficients.
+    S1S2CountMismatch {
+        key: String,
+        num_s1: usize,
+        num_s2: usize,
+        num_coeffs: usize,
+    },
+
+    /// A dataset contains NaN or infinite values.
+    NonFiniteCoeffs { key: String, count: usize },
+
+    /// The zenith normalisation Jones matrix isn't finite or is singular.
+    BadZenithNorm,
+
+    /// Beam responses couldn't be calculated.
+    Calc { msg: String },
+
+    /// The magnitudes of zenith-normalised beam responses differ too much from
+    /// those of the previous frequency.
+    Discontinuity {
+        prev_freq_hz: u32,
+        max_diff: f64,
+        max_allowed: f64,
+    },
+}
+
+impl ValidationProblem {
+    /// A short, machine-readable name for the kind of problem.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ValidationProblem::MissingDataset { .. } => "missing_dataset",
+            ValidationProblem::Hdf5 { .. } => "hdf5",
+            ValidationProblem::DatasetShape { .. } => "dataset_shape",
+            ValidationProblem::TooManyCoeffs { .. } => "too_many_coeffs",
+            ValidationProblem::InconsistentCoeffCounts { .. } => "inconsistent_coeff_counts",
+            ValidationProblem::S1S2CountMismatch { .. } => "s1_s2_count_mismatch",
+            ValidationProblem::NonFiniteCoeffs { .. } => "non_finite_coeffs",
+            ValidationProblem::BadZenithNorm => "bad_zenith_norm",
+            ValidationProblem::Calc { .. } => "calc",
+            ValidationProblem::Discontinuity { .. } => "discontinuity",
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationProblem::MissingDataset { key } => write!(f, "Dataset '{key}' is missing"),
+            ValidationProblem::Hdf5 { key, msg } => {
+                write!(f, "Couldn't read dataset '{key}': {msg}")
+            }
+            ValidationProblem::DatasetShape { key, shape } => write!(
+                f,
+                "Dataset '{key}' has shape {shape:?}, but it should have 2 rows"
+            ),
+            ValidationProblem::TooManyCoeffs {
+                key,
+                num_coeffs,
+                num_modes,
+            } => write!(
+                f,
+                "Dataset '{key}' has {num_coeffs} coefficients, but there are only {num_modes} modes"
+            ),
+            ValidationProblem::InconsistentCoeffCounts {
+                key,
+                num_coeffs,
+                expected,
+            } => write!(
+                f,
+                "Dataset '{key}' has {num_coeffs} coefficients, but other datasets at this frequency have {expected}"
+            ),
+            ValidationProblem::S1S2CountMismatch {
+                key,
+                num_s1,
+                num_s2,
+                num_coeffs,
+            } => write!(
+                f,
+                "Dataset '{key}' has {num_s1} S=1 and {num_s2} S=2 modes, but both should be half of its {num_coeffs} coefficients"
+            ),
+            ValidationProblem::NonFiniteCoeffs { key, count } => {
+                write!(f, "Dataset '{key}' has {count} NaN or infinite values")
+            }
+            ValidationProblem::BadZenithNorm => {
+                write!(f, "The zenith normalisation Jones matrix is not finite or is singular")
+            }
+            ValidationProblem::Calc { msg } => {
+                write!(f, "Couldn't calculate beam responses: {msg}")
+            }
+            ValidationProblem::Discontinuity {
+                prev_freq_hz,
+                max_diff,
+                max_allowed,
+            } => write!(
+                f,
+                "Beam responses differ from those at {prev_freq_hz} Hz by up to {max_diff} (more than {max_allowed})"
+            ),
+        }
+    }
+}
+
+/// The validation results for a single frequency.
+#[derive(Debug, Clone)]
+pub struct FreqValidation {
+    pub freq_hz: u32,
+
+    /// The number of coefficients in this frequency's datasets (taken from the
+    /// first dipole).
+    pub num_coeffs: Option<usize>,
+
+    /// The zenith normalisation Jones matrix, if it could be calculated.
+    pub zenith_norm: Option<Jones<f64>>,
+
+    pub problems: Vec<ValidationProblem>,
+}
+
+/// The results of [`FEEBeam::validate`].
+#[derive(Debug, Clone)]
+pub struct ValidationReport {
+    /// The number of modes in the file's `modes` dataset.
+    pub num_modes: usize,
+
+    /// The largest allowed difference in the magnitudes of zenith-normalised
+    /// Jones matrix elements between adjacent frequencies.
+    pub max_jump: f64,
+
+    pub freqs: Vec<FreqValidation>,
+}
+
+impl ValidationReport {
+    /// Did every check pass?
+    pub fn passed(&self) -> bool {
+        self.freqs.iter().all(|f| f.problems.is_empty())
+    }
+
+    /// Get the report as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"passed\": {}, \"num_modes\": {}, \"max_jump\": {}, \"freqs\": [",
+            self.passed(),
+            self.num_modes,
+            json_f64(self.max_jump)
+        )
+        .unwrap();
+        for (i, f) in self.freqs.iter().enumerate() {
+            if i > 0 {
+                json.push_str(", ");
+            }
+            write!(
+                json,
+                "{{\"freq_hz\": {}, \"passed\": {}, \"num_coeffs\": {}, \"zenith_norm\": ",
+                f.freq_hz,
+                f.problems.is_empty(),
+                f.num_coeffs
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| "null".to_string()),
+            )
+            .unwrap();
+            match f.zenith_norm {
+                Some(j) => {
+                    let values: Vec<String> =
+                        j.to_float_array().into_iter().map(json_f64).collect();
+                    write!(json, "[{}]", values.join(", ")).unwrap();
+                }
+                None => json.push_str("null"),
+            }
+            json.push_str(", \"problems\": [");
+            let problems: Vec<String> = f
+                .problems
+                .iter()
+                .map(|p| {
+                    format!(
+                        "{{\"kind\": {}, \"message\": {}}}",
+                        json_string(p.kind()),
+                        json_string(&p.to_string())
+                    )
+                })
+                .collect();
+            json.push_str(&problems.join(", "));
+            json.push_str("]}");
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl FEEBeam {
+    /// Check everything in the HDF5 file. For each frequency, all 32 X and Y
+    /// datasets must exist, be 2D arrays with 2 rows, have the same number of
+    /// coefficients (no more than there are modes) with equal numbers of S=1
+    /// and S=2 modes, and contain no NaN or infinite values. The zenith
+    /// normalisation must be finite, and the magnitudes of zenith-normalised
+    /// beam responses (zero delays, no parallactic-angle correction) must not
+    /// change by more than `max_jump` from the previous frequency.
+    ///
+    /// This is expensive; every dataset is read and beam responses are
+    /// calculated at every frequency. The beam's caches aren't used, so they
+    /// are unchanged afterwards.
+    pub fn validate(&self, max_jump: f64) -> ValidationReport {
+        let num_modes = self.modes.dim().1;
+        let (az_rad, za_rad) = continuity_directions();
+        let mut freqs = Vec::with_capacity(self.freqs.len());
+        let mut prev: Option<(u32, Vec<Jones<f64>>)> = None;
+
+        for &freq_hz in &self.freqs {
+            let mut problems = vec![];
+            let num_coeffs = self.validate_datasets(freq_hz, &mut problems);
+
+            // Only attempt calculations if the datasets are fine; otherwise
+            // we could panic. The coefficients are calculated here rather
+            // than taken from the cache, so that validating doesn't change
+            // the beam.
+            let mut zenith_norm = None;
+            let mut responses = None;
+            if problems.is_empty() {
+                match self.calc_modes(freq_hz, &[0; 16], &[1.0; 32], self.truncation) {
+                    Ok(coeffs) => {
+                        let norm = calc_zenith_norm_jones(&coeffs);
+                        let norm_ok = norm.iter().all(|j| j.is_finite())
+                            && norm.iter().any(|j| j.norm_sqr() > 0.0);
+                        if norm_ok {
+                            let mut workspace = Workspace::new(coeffs.x.n_max.max(coeffs.y.n_max));
+                            let mut r = vec![Jones::default(); az_rad.len()];
+                            calc_jones_direct_chunk(
+                                &az_rad,
+                                &za_rad,
+                                &coeffs,
+                                Some(norm),
+                                None,
+                                false,
+                                None,
+                                &mut workspace,
+                                &mut r,
+                            );
+                            responses = Some(r);
+                        } else {
+                            problems.push(ValidationProblem::BadZenithNorm);
+                        }
+                        zenith_norm = Some(norm);
+                    }
+                    Err(e) => problems.push(ValidationProblem::Calc { msg: e.to_string() }),
+                }
+            }
+
+            if let (Some((prev_freq_hz, prev_responses)), Some(responses)) = (&prev, &responses) {
+                let max_diff = prev_responses
+                    .iter()
+                    .zip(responses.iter())
+                    .flat_map(|(p, r)| {
+                        p.iter()
+                            .zip(r.iter())
+                            .map(|(p, r)| (p.norm() - r.norm()).abs())
+                    })
+                    .fold(0.0, f64::max);
+                if max_diff.is_nan() || max_diff > max_jump {
+                    problems.push(ValidationProblem::Discontinuity {
+                        prev_freq_hz: *prev_freq_hz,
+                        max_diff,
+                        max_allowed: max_jump,
+                    });
+                }
+            }
+            // Compare the next frequency against the last one that worked.
+            if let Some(responses) = responses {
+                prev = Some((freq_hz, responses));
+            }
+
+            freqs.push(FreqValidation {
+                freq_hz,
+                num_coeffs,
+                zenith_norm,
+                problems,
+            });
+        }
+
+        ValidationReport {
+            num_modes,
+            max_jump,
+            freqs,
+        }
+    }
+
+    /// Check the X and Y datasets of all dipoles at a frequency, returning the
+    /// number of coefficients of the first dataset.
+    fn validate_datasets(
+        &self,
+        freq_hz: u32,
+        problems: &mut Vec<ValidationProblem>,
+    ) -> Option<usize> {
+        let num_modes = self.modes.dim().1;
+        let mut expected_num_coeffs = None;
+
+        for pol in [Pol::X, Pol::Y] {
+            for dipole in 1..=NUM_DIPOLES {
+                let key = format!("{pol}{dipole}_{freq_hz}");
+                let data = {
+                    let h5 = self.hdf5_file(freq_hz);
+                    if !h5.link_exists(&key) {
+                        problems.push(ValidationProblem::MissingDataset { key });
+                        continue;
+                    }
+                    h5.dataset(&key)
+                        .and_then(|d| Ok((d.shape(), d.read_raw::<f64>()?)))
+                };
+                let (shape, data) = match data {
+                    Ok(d) => d,
+                    Err(e) => {
+                        problems.push(ValidationProblem::Hdf5 {
+                            key,
+                            msg: e.to_string(),
+                        });
+                        continue;
+                    }
+                };
+
+                let num_coeffs = match shape.as_slice() {
+                    [2, n] => *n,
+                    _ => {
+                        problems.push(ValidationProblem::DatasetShape { key, shape });
+                        continue;
+                    }
+                };
+                match expected_num_coeffs {
+                    None => expected_num_coeffs = Some(num_coeffs),
+                    Some(expected) if expected != num_coeffs => {
+                        problems.push(ValidationProblem::InconsistentCoeffCounts {
+                            key: key.clone(),
+                            num_coeffs,
+                            expected,
+                        })
+                    }
+                    Some(_) => (),
+                }
+
+                if num_coeffs > num_modes {
+                    problems.push(ValidationProblem::TooManyCoeffs {
+                        key: key.clone(),
+                        num_coeffs,
+                        num_modes,
+                    });
+                } else {
+                    let num_s1 = self
+                        .modes
+                        .row(0)
+                        .iter()
+                        .take(num_coeffs)
+                        .filter(|&&t| t <= 1)
+                        .count();
+                    let num_s2 = num_coeffs - num_s1;
+                    if num_s1 != num_s2 || num_s1 != num_coeffs / 2 {
+                        problems.push(ValidationProblem::S1S2CountMismatch {
+                            key: key.clone(),
+                            num_s1,
+                            num_s2,
+                            num_coeffs,
+                        });
+                    }
+                }
+
+                let num_non_finite = data.iter().filter(|v| !v.is_finite()).count();
+                if num_non_finite > 0 {
+                    problems.push(ValidationProblem::NonFiniteCoeffs {
+                        key,
+                        count: num_non_finite,
+                    });
+                }
+            }
+        }
+
+        expected_num_coeffs
+    }
+}
@@ -13,15 +13,120 @@ use std::{
     slice,
 };
 
+use crate::{
+    analytic::AnalyticBeamError,
+    fee::{FEEBeamError, InitFEEBeamError},
+};
+
+/// The codes returned by hyperbeam's C functions; 0 indicates success. The
+/// value of each code is part of hyperbeam's ABI and will never change. New
+/// codes may be added in future releases, so callers should be prepared to
+/// handle codes they don't know about.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum hb_error_code {
+    /// Rust code panicked. This is a bug in hyperbeam.
+    HB_PANIC = -1,
+    /// No error occurred.
+    HB_SUCCESS = 0,
+    /// An error without a more specific code.
+    HB_ERROR_OTHER = 1,
+    /// A string argument isn't valid UTF-8.
+    HB_ERROR_INVALID_STRING = 2,
+    /// A boolean argument was neither 0 nor 1.
+    HB_ERROR_INVALID_BOOL = 3,
+    /// The wrong number of dipole/bowtie amps was given.
+    HB_ERROR_INVALID_AMPS_LENGTH = 4,
+    /// The wrong number of dipole/bowtie delays was given.
+    HB_ERROR_INVALID_DELAYS_LENGTH = 5,
+    /// A direction is below the horizon.
+    HB_ERROR_BELOW_HORIZON = 6,
+    /// The beam file doesn't exist (or wasn't specified).
+    HB_ERROR_BEAM_FILE_MISSING = 7,
+    /// The contents of the beam file aren't what was expected.
+    HB_ERROR_INVALID_BEAM_FILE = 8,
+    /// An error from the HDF5 library.
+    HB_ERROR_HDF5 = 9,
+    /// An error from the GPU.
+    #[cfg_attr(not(any(feature = "cuda", feature = "hip")), allow(dead_code))]
+    HB_ERROR_GPU = 10,
+    /// Another argument is invalid (e.g. a negative count or a null pointer).
+    HB_ERROR_INVALID_ARGUMENT = 11,
+}
+
+/// Details on the most recent error, as filled by `hb_last_error_details`.
+/// Fields that don't apply to an error are zero (or NaN for floats).
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug)]
+pub struct hb_error_details {
+    /// The code of the error.
+    pub code: hb_error_code,
+    /// For length errors (e.g. amps or delays), the length that was given.
+    pub got: u64,
+    /// For length errors, the expected length.
+    pub expected: u64,
+    /// For length errors where two lengths are allowed (e.g. 16 or 32 amps),
+    /// the other allowed length.
+    pub expected_alt: u64,
+    /// For below-horizon errors, the offending zenith angle [radians].
+    pub za_rad: f64,
+}
+
+impl hb_error_details {
+    pub(crate) fn new(code: hb_error_code) -> Self {
+        Self {
+            code,
+            got: 0,
+            expected: 0,
+            expected_alt: 0,
+            za_rad: f64::NAN,
+        }
+    }
+
+    pub(crate) fn lengths(code: hb_error_code, got: usize, expected: &[usize]) -> Self {
+        Self {
+            got: got as u64,
+            expected: expected.first().copied().unwrap_or(0) as u64,
+            expected_alt: expected.get(1).copied().unwrap_or(0) as u64,
+            ..Self::new(code)
+        }
+    }
+}
+
+/// Errors that can be described by an [`hb_error_code`] (and details) over the
+/// FFI.
+pub(crate) trait FfiError: std::fmt::Display {
+    fn details(&self) -> hb_error_details;
+}
+
 thread_local! {
     static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+    static LAST_ERROR_DETAILS: RefCell<Option<hb_error_details>> = const { RefCell::new(None) };
 }
 
 /// Update the most recent error, clearing whatever may have been there before.
-pub(crate) fn update_last_error(err: String) {
+/// The error's code is returned, so that it can be passed back to the caller.
+pub(crate) fn update_last_error(code: hb_error_code, err: String) -> i32 {
+    update_last_error_with(hb_error_details::new(code), err)
+}
+
+/// The same as [`update_last_error`], but with details of the error.
+pub(crate) fn update_last_error_with(details: hb_error_details, err: String) -> i32 {
     LAST_ERROR.with(|prev| {
         *prev.borrow_mut() = Some(err);
     });
+    LAST_ERROR_DETAILS.with(|prev| {
+        *prev.borrow_mut() = Some(details);
+    });
+    details.code as i32
+}
+
+/// The same as [`update_last_error`], but using a Rust error's code, details
+/// and message.
+pub(crate) fn update_last_error_from<E: FfiError>(err: &E) -> i32 {
+    update_last_error_with(err.details(), err.to_string())
 }
 
 /// Retrieve the most recent error, clearing it in the process.
@@ -39,19 +144,160 @@ pub extern "C" fn hb_last_error_length() -> c_int {
     })
 }
 
+/// Get the code of the most recent error (`HB_SUCCESS` if there hasn't been
+/// one). This is the same value that was returned by the function that
+/// failed. Unlike the error message, the code isn't cleared when it's read.
+#[no_mangle]
+pub extern "C" fn hb_last_error_code() -> hb_error_code {
+    LAST_ERROR_DETAILS.with(|prev| match *prev.borrow() {
+        Some(details) => details.code,
+        None => hb_error_code::HB_SUCCESS,
+    })
+}
+
+/// Write details on the most recent error into `details`. If there hasn't
+/// been an error, the code in `details` is `HB_SUCCESS`. Unlike the error
+/// message, the details aren't cleared when they're read.
+///
+/// This returns `0`, or `-1` if `details` is a null pointer.
+#[no_mangle]
+pub unsafe extern "C" fn hb_last_error_details(details: *mut hb_error_details) -> c_int {
+    let Some(details) = details.as_mut() else {
+        return -1;
+    };
+    *details = LAST_ERROR_DETAILS
+        .with(|prev| *prev.borrow())
+        .unwrap_or_else(|| hb_error_details::new(hb_error_code::HB_SUCCESS));
+    0
+}
+
 macro_rules! ffi_error {
     ($result:expr) => {{
         match $result {
             Ok(r) => r,
             Err(e) => {
-                update_last_error(e.to_string());
-                return 1;
+                return $crate::ffi::update_last_error_from(&e);
             }
         }
     }};
 }
 pub(crate) use ffi_error;
 
+impl FfiError for std::str::Utf8Error {
+    fn details(&self) -> hb_error_details {
+        hb_error_details::new(hb_error_code::HB_ERROR_INVALID_STRING)
+    }
+}
+
+impl FfiError for InitFEEBeamError {
+    fn details(&self) -> hb_error_details {
+        use hb_error_code::*;
+        match self {
+            InitFEEBeamError::BeamFileDoesntExist(_)
+            | InitFEEBeamError::MwaBeamFileVarError(_)
+            | InitFEEBeamError::BeamFileNotFound(_) => {
+                hb_error_details::new(HB_ERROR_BEAM_FILE_MISSING)
+            }
+            InitFEEBeamError::DipoleCountMismatch { expected, got } => hb_error_details::lengths(
+                HB_ERROR_INVALID_BEAM_FILE,
+                usize::from(*got),
+                &[usize::from(*expected)],
+            ),
+            InitFEEBeamError::MissingDipole
+            | InitFEEBeamError::NoDipoles
+            | InitFEEBeamError::NoFreqs
+            | InitFEEBeamError::Parse(_)
+            | InitFEEBeamError::ModesShape
+            | InitFEEBeamError::IncompatibleModes { .. }
+            | InitFEEBeamError::OverlappingFreqs { .. } => {
+                hb_error_details::new(HB_ERROR_INVALID_BEAM_FILE)
+            }
+            InitFEEBeamError::NoBeamFiles => hb_error_details::new(HB_ERROR_INVALID_ARGUMENT),
+            InitFEEBeamError::Io(_) => hb_error_details::new(HB_ERROR_OTHER),
+            InitFEEBeamError::Hdf5(_) => hb_error_details::new(HB_ERROR_HDF5),
+        }
+    }
+}
+
+impl FfiError for FEEBeamError {
+    fn details(&self) -> hb_error_details {
+        use hb_error_code::*;
+        match self {
+            FEEBeamError::S1S2CountMismatch { expected, got }
+            | FEEBeamError::CoeffCountMismatch { expected, got, .. } => {
+                hb_error_details::lengths(HB_ERROR_INVALID_BEAM_FILE, *got, &[*expected])
+            }
+            FEEBeamError::DatasetShape { .. } => hb_error_details::new(HB_ERROR_INVALID_BEAM_FILE),
+            FEEBeamError::IncorrectAmpsLength(got) => {
+                hb_error_details::lengths(HB_ERROR_INVALID_AMPS_LENGTH, *got, &[16, 32])
+            }
+            FEEBeamError::IncorrectDelaysLength(got)
+            | FEEBeamError::IncorrectDelaysArrayColLength {
+                num_delays: got, ..
+            } => hb_error_details::lengths(HB_ERROR_INVALID_DELAYS_LENGTH, *got, &[16]),
+            FEEBeamError::InvalidDipole(_)
+            | FEEBeamError::TileCountMismatch { .. }
+            | FEEBeamError::DirectionCountMismatch { .. } => {
+                hb_error_details::new(HB_ERROR_INVALID_ARGUMENT)
+            }
+            FEEBeamError::SubsetNoFreqs
+            | FEEBeamError::SubsetFreqNotInFile(_)
+            | FEEBeamError::SubsetNoModes
+            | FEEBeamError::MultipleFiles(_)
+            | FEEBeamError::Io(_) => hb_error_details::new(HB_ERROR_OTHER),
+            FEEBeamError::Hdf5Error(_) => hb_error_details::new(HB_ERROR_HDF5),
+            #[cfg(any(feature = "cuda", feature = "hip"))]
+            FEEBeamError::Gpu(_) => hb_error_details::new(HB_ERROR_GPU),
+        }
+    }
+}
+
+impl FfiError for AnalyticBeamError {
+    fn details(&self) -> hb_error_details {
+        use hb_error_code::*;
+        match self {
+            AnalyticBeamError::IncorrectAmpsLength {
+                got,
+                expected1,
+                expected2,
+            } => hb_error_details::lengths(
+                HB_ERROR_INVALID_AMPS_LENGTH,
+                *got,
+                &[*expected1, *expected2],
+            ),
+            AnalyticBeamError::IncorrectDelaysLength { got, expected } => {
+                hb_error_details::lengths(HB_ERROR_INVALID_DELAYS_LENGTH, *got, &[*expected])
+            }
+            #[cfg(any(feature = "cuda", feature = "hip"))]
+            AnalyticBeamError::IncorrectDelaysArrayColLength {
+                num_delays: got,
+                expected,
+                ..
+            } => hb_error_details::lengths(HB_ERROR_INVALID_DELAYS_LENGTH, *got, &[*expected]),
+            AnalyticBeamError::BelowHorizon { za } => hb_error_details {
+                za_rad: *za,
+                ..hb_error_details::new(HB_ERROR_BELOW_HORIZON)
+            },
+            AnalyticBeamError::MissingLatitude => hb_error_details::new(HB_ERROR_INVALID_ARGUMENT),
+            #[cfg(any(feature = "cuda", feature = "hip"))]
+            AnalyticBeamError::Gpu(_) => hb_error_details::new(HB_ERROR_GPU),
+        }
+    }
+}
+
+impl FfiError for rayon::ThreadPoolBuildError {
+    fn details(&self) -> hb_error_details {
+        hb_error_details::new(hb_error_code::HB_ERROR_OTHER)
+    }
+}
+
+#[cfg(any(feature = "cuda", feature = "hip"))]
+impl FfiError for crate::gpu::GpuError {
+    fn details(&self) -> hb_error_details {
+        hb_error_details::new(hb_error_code::HB_ERROR_GPU)
+    }
+}
+
 /// Write the most recent error message into a caller-provided buffer as a UTF-8
 /// string, returning the number of bytes written.
 ///
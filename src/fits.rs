@@ -0,0 +1,323 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Minimal writing of FITS files.
+//!
+//! Only what hyperbeam needs is supported: a primary HDU containing a
+//! single-precision float image, or an empty primary HDU followed by a binary
+//! table of single-precision float columns. See the FITS standard
+//! (<https://fits.gsfc.nasa.gov/fits_standard.html>) for details.
+
+use std::io::Write;
+
+/// FITS files are made of blocks of this many bytes.
+const BLOCK_SIZE: usize = 2880;
+
+/// Each header "card" is this many bytes.
+const CARD_SIZE: usize = 80;
+
+/// A FITS header value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FitsValue {
+    Logical(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl FitsValue {
+    /// Format this value as it appears in a header card (columns 11-30 for
+    /// fixed-format values).
+    fn format(&self) -> String {
+        match self {
+            FitsValue::Logical(b) => format!("{:>20}", if *b { "T" } else { "F" }),
+            FitsValue::Int(i) => format!("{i:>20}"),
+            // 17 significant digits to round-trip f64s. The exponent
+            // character must be uppercase.
+            FitsValue::Float(f) if f.is_finite() => {
+                format!("{:>20}", format!("{f:.16E}"))
+            }
+            // Non-finite numbers aren't allowed; NaN is the closest thing to
+            // "undefined".
+            FitsValue::Float(_) => format!("{:>20}", "NAN"),
+            FitsValue::String(s) => {
+                // Quotes are escaped by doubling them, and strings are padded
+                // to at least 8 characters.
+                format!("'{:<8}'", s.replace('\'', "''"))
+            }
+        }
+    }
+}
+
+impl From<bool> for FitsValue {
+    fn from(b: bool) -> Self {
+        FitsValue::Logical(b)
+    }
+}
+
+impl From<i64> for FitsValue {
+    fn from(i: i64) -> Self {
+        FitsValue::Int(i)
+    }
+}
+
+impl From<i32> for FitsValue {
+    fn from(i: i32) -> Self {
+        FitsValue::Int(i.into())
+    }
+}
+
+impl From<usize> for FitsValue {
+    fn from(i: usize) -> Self {
+        FitsValue::Int(i as i64)
+    }
+}
+
+impl From<f64> for FitsValue {
+    fn from(f: f64) -> Self {
+        FitsValue::Float(f)
+    }
+}
+
+impl From<&str> for FitsValue {
+    fn from(s: &str) -> Self {
+        FitsValue::String(s.to_string())
+    }
+}
+
+impl From<String> for FitsValue {
+    fn from(s: String) -> Self {
+        FitsValue::String(s)
+    }
+}
+
+/// The header of a FITS HDU, as a list of 80-character cards. The mandatory
+/// keywords (e.g. `SIMPLE`, `BITPIX`, `NAXISn`) and `END` are added when the
+/// HDU is written.
+#[derive(Debug, Default, Clone)]
+pub struct FitsHeader {
+    cards: Vec<String>,
+}
+
+impl FitsHeader {
+    pub fn new() -> FitsHeader {
+        FitsHeader::default()
+    }
+
+    /// Add a keyword with a value and (possibly empty) comment. Keywords
+    /// longer than 8 characters are truncated, as are cards longer than 80
+    /// characters.
+    pub fn push<V: Into<FitsValue>>(&mut self, key: &str, value: V, comment: &str) {
+        let mut card = format!("{:<8}= {}", truncate(key, 8), value.into().format());
+        if !comment.is_empty() {
+            card.push_str(" / ");
+            card.push_str(comment);
+        }
+        self.cards.push(card);
+    }
+
+    /// Add an already-formatted card.
+    pub fn push_card(&mut self, card: &str) {
+        self.cards.push(card.to_string());
+    }
+
+    /// The (unpadded) cards of this header.
+    pub fn cards(&self) -> &[String] {
+        &self.cards
+    }
+
+    /// Add a `COMMENT` card.
+    pub fn push_comment(&mut self, comment: &str) {
+        self.cards.push(format!("COMMENT {comment}"));
+    }
+
+    /// Add a `HISTORY` card.
+    pub fn push_history(&mut self, history: &str) {
+        self.cards.push(format!("HISTORY {history}"));
+    }
+
+    /// Write all cards (with the `END` card) and pad the header to a whole
+    /// number of blocks.
+    fn write<W: Write>(&self, mandatory: &[String], writer: &mut W) -> std::io::Result<()> {
+        let mut num_bytes = 0;
+        for card in mandatory
+            .iter()
+            .chain(self.cards.iter())
+            .map(|c| c.as_str())
+            .chain(std::iter::once("END"))
+        {
+            writer.write_all(format!("{:<80}", truncate(card, CARD_SIZE)).as_bytes())?;
+            num_bytes += CARD_SIZE;
+        }
+        write_padding(writer, num_bytes, b' ')
+    }
+}
+
+/// Write a primary HDU containing an image of big-endian `f32`s
+/// (`BITPIX = -32`). `axes` are given in FITS order, i.e. the first axis
+/// varies fastest in `data`.
+pub fn write_primary_image<W: Write>(
+    writer: &mut W,
+    header: &FitsHeader,
+    axes: &[usize],
+    data: &[f32],
+) -> std::io::Result<()> {
+    assert_eq!(
+        axes.iter().product::<usize>(),
+        data.len(),
+        "The FITS image axes don't match the amount of data"
+    );
+
+    let mut mandatory = vec![
+        card("SIMPLE", true, "conforms to FITS standard"),
+        card("BITPIX", -32, "single-precision float"),
+        card("NAXIS", axes.len(), "number of axes"),
+    ];
+    for (i, &n) in axes.iter().enumerate() {
+        mandatory.push(card(&format!("NAXIS{}", i + 1), n, ""));
+    }
+    header.write(&mandatory, writer)?;
+
+    for &d in data {
+        writer.write_all(&d.to_be_bytes())?;
+    }
+    write_padding(writer, std::mem::size_of_val(data), 0)
+}
+
+/// Write a primary HDU without any data, so that extensions can follow.
+pub fn write_empty_primary<W: Write>(writer: &mut W, header: &FitsHeader) -> std::io::Result<()> {
+    let mandatory = [
+        card("SIMPLE", true, "conforms to FITS standard"),
+        card("BITPIX", 8, ""),
+        card("NAXIS", 0, "no image data"),
+        card("EXTEND", true, "extensions may be present"),
+    ];
+    header.write(&mandatory, writer)
+}
+
+/// Write a binary-table extension HDU with columns of big-endian `f32`s
+/// (`TFORM = 'E'`). Each column is given as a name and its values; all columns
+/// must have the same length.
+pub fn write_bintable<W: Write>(
+    writer: &mut W,
+    header: &FitsHeader,
+    columns: &[(&str, &[f32])],
+) -> std::io::Result<()> {
+    let num_rows = columns.first().map(|(_, c)| c.len()).unwrap_or(0);
+    assert!(
+        columns.iter().all(|(_, c)| c.len() == num_rows),
+        "All FITS table columns must have the same length"
+    );
+
+    let mut mandatory = vec![
+        card("XTENSION", "BINTABLE", "binary table extension"),
+        card("BITPIX", 8, ""),
+        card("NAXIS", 2, ""),
+        card("NAXIS1", 4 * columns.len(), "bytes per row"),
+        card("NAXIS2", num_rows, "number of rows"),
+        card("PCOUNT", 0, ""),
+        card("GCOUNT", 1, ""),
+        card("TFIELDS", columns.len(), "number of columns"),
+    ];
+    for (i, (name, _)) in columns.iter().enumerate() {
+        mandatory.push(card(&format!("TTYPE{}", i + 1), *name, ""));
+        mandatory.push(card(&format!("TFORM{}", i + 1), "E", ""));
+    }
+    header.write(&mandatory, writer)?;
+
+    let mut row = Vec::with_capacity(4 * columns.len());
+    for i_row in 0..num_rows {
+        row.clear();
+        for (_, c) in columns {
+            row.extend_from_slice(&c[i_row].to_be_bytes());
+        }
+        writer.write_all(&row)?;
+    }
+    write_padding(writer, 4 * columns.len() * num_rows, 0)
+}
+
+/// Format a single card.
+fn card<V: Into<FitsValue>>(key: &str, value: V, comment: &str) -> String {
+    let mut h = FitsHeader::new();
+    h.push(key, value.into(), comment);
+    h.cards.pop().unwrap()
+}
+
+/// Pad to a whole number of blocks, given the number of bytes already written.
+fn write_padding<W: Write>(writer: &mut W, num_bytes: usize, byte: u8) -> std::io::Result<()> {
+    let padding = (BLOCK_SIZE - num_bytes % BLOCK_SIZE) % BLOCK_SIZE;
+    writer.write_all(&vec![byte; padding])
+}
+
+fn truncate(s: &str, max_len: usize) -> &str {
+    match s.char_indices().nth(max_len) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_cards() {
+        let mut h = FitsHeader::new();
+        h.push("CTYPE1", "RA---SIN", "");
+        h.push("CDELT1", -0.5, "degrees");
+        h.push("OBJECT", "it's", "");
+        h.push("EXTEND", false, "");
+        assert_eq!(h.cards[0], "CTYPE1  = 'RA---SIN'");
+        assert_eq!(h.cards[1], "CDELT1  = -5.0000000000000000E-1 / degrees");
+        assert_eq!(h.cards[2], "OBJECT  = 'it''s   '");
+        assert_eq!(h.cards[3], format!("EXTEND  = {:>20}", "F"));
+    }
+
+    #[test]
+    fn test_image_layout() {
+        let mut h = FitsHeader::new();
+        h.push_comment("test");
+        let data = [1.0f32, 2.0, f32::NAN, 4.0, 5.0, 6.0];
+        let mut buf = vec![];
+        write_primary_image(&mut buf, &h, &[3, 2], &data).unwrap();
+
+        // One header block and one data block.
+        assert_eq!(buf.len(), 2 * BLOCK_SIZE);
+        let header = std::str::from_utf8(&buf[..BLOCK_SIZE]).unwrap();
+        assert!(header.starts_with(&format!("SIMPLE  = {:>20}", "T")));
+        assert!(header.contains(&format!("NAXIS2  = {:>20}", 2)));
+        assert!(header.contains("COMMENT test"));
+        // END must start on a card boundary.
+        let end = header.find("END ").unwrap();
+        assert_eq!(end % CARD_SIZE, 0);
+        assert!(header[end + 3..].bytes().all(|b| b == b' '));
+
+        let data_block = &buf[BLOCK_SIZE..];
+        assert_eq!(&data_block[..4], &1.0f32.to_be_bytes());
+        assert!(f32::from_be_bytes(data_block[8..12].try_into().unwrap()).is_nan());
+        assert!(data_block[24..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_bintable_layout() {
+        let mut buf = vec![];
+        write_empty_primary(&mut buf, &FitsHeader::new()).unwrap();
+        assert_eq!(buf.len(), BLOCK_SIZE);
+
+        let a = [1.0f32, 2.0, 3.0];
+        let b = [-1.0f32, -2.0, -3.0];
+        write_bintable(&mut buf, &FitsHeader::new(), &[("A", &a), ("B", &b)]).unwrap();
+        assert_eq!(buf.len(), 3 * BLOCK_SIZE);
+        let header = std::str::from_utf8(&buf[BLOCK_SIZE..2 * BLOCK_SIZE]).unwrap();
+        assert!(header.starts_with("XTENSION= 'BINTABLE'"));
+        assert!(header.contains("TTYPE2  = 'B       '"));
+        assert!(header.contains("TFORM1  = 'E       '"));
+
+        // Rows are interleaved.
+        let data = &buf[2 * BLOCK_SIZE..];
+        assert_eq!(&data[..4], &1.0f32.to_be_bytes());
+        assert_eq!(&data[4..8], &(-1.0f32).to_be_bytes());
+        assert_eq!(&data[8..12], &2.0f32.to_be_bytes());
+    }
+}
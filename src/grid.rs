@@ -0,0 +1,603 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Precomputed grids of beam responses, for fast interpolation at arbitrary
+//! directions.
+//!
+//! A [`BeamGrid`] holds Jones matrices on a regular grid of azimuths (covering
+//! 0 to 2 pi, with wrap-around) and zenith angles (from zenith to the horizon).
+//! Interpolating is much cheaper than evaluating a beam, which is useful when
+//! beam responses are needed for the same tile configuration and frequency
+//! many times (e.g. for moving sources over many timesteps).
+//!
+//! Near zenith, bicubic interpolation needs values "beyond" the pole; the
+//! direction (az, -za) is the same as (az + pi, za), but depending on the
+//! basis of the Jones matrices, their elements may change sign across the
+//! pole. The sign of each element is determined when the grid is made.
+//!
+//! Jones matrices in an equatorial basis (i.e. with the parallactic-angle
+//! correction) are discontinuous at the celestial pole, so interpolation is
+//! much less accurate near it.
+
+use std::{
+    f64::consts::{FRAC_PI_2, TAU},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use num_complex::Complex64 as c64;
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    beam::{Beam, BeamError, BeamParams},
+    Jones,
+};
+
+/// The magic bytes at the start of a serialised [`BeamGrid`].
+const MAGIC: &[u8; 8] = b"HBGRID\0\0";
+
+/// The version of the serialised format.
+const VERSION: u32 = 1;
+
+/// How to interpolate a [`BeamGrid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Bilinear interpolation of the 4 surrounding grid points.
+    Bilinear,
+
+    /// Bicubic (Catmull-Rom) interpolation of the 16 surrounding grid points.
+    /// This is more accurate than bilinear interpolation for the same grid.
+    Bicubic,
+}
+
+/// Jones matrices on a regular grid of azimuths and zenith angles for a
+/// single tile configuration and frequency. See the [module
+/// documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeamGrid {
+    /// The frequency that the beam responses are for \[Hz\].
+    freq_hz: u32,
+    /// The number of azimuths. The azimuths are `i * 2 pi / num_az`.
+    num_az: usize,
+    /// The number of zenith angles. The zenith angles are `i * (pi / 2) /
+    /// (num_za - 1)`.
+    num_za: usize,
+    /// The Jones matrices in order of zenith angle, then azimuth.
+    jones: Vec<[c64; 4]>,
+    /// For each Jones matrix element, the sign that relates its values at (az,
+    /// -za) and (az + pi, za).
+    pole_signs: [f64; 4],
+    /// The maximum error of bilinear interpolation, measured at the centre of
+    /// every grid cell.
+    max_error_bilinear: f64,
+    /// The maximum error of bicubic interpolation, measured at the centre of
+    /// every grid cell.
+    max_error_bicubic: f64,
+}
+
+impl BeamGrid {
+    /// Calculate a grid of beam responses with `num_az` azimuths (which must
+    /// be even and at least 4) and `num_za` zenith angles (at least 2). The
+    /// beam is also evaluated at the centre of every grid cell to measure the
+    /// interpolation errors, so this is about as expensive as calculating
+    /// twice as many beam responses as there are grid points.
+    ///
+    /// To make a grid from an [`crate::fee::FEEBeam`] or an
+    /// [`crate::analytic::AnalyticBeam`], convert it to a [`Beam`] with
+    /// `Beam::from`.
+    pub fn new(
+        beam: &Beam,
+        params: &BeamParams,
+        freq_hz: u32,
+        latitude_rad: f64,
+        num_az: usize,
+        num_za: usize,
+    ) -> Result<BeamGrid, BeamGridError> {
+        if num_az < 4 || num_az % 2 != 0 || num_za < 2 {
+            return Err(BeamGridError::BadShape { num_az, num_za });
+        }
+        let (az_step, za_step) = steps(num_az, num_za);
+
+        let (az, za): (Vec<f64>, Vec<f64>) = (0..num_za)
+            .flat_map(|i_za| (0..num_az).map(move |i_az| (i_az, i_za)))
+            .map(|(i_az, i_za)| (i_az as f64 * az_step, za_at(i_za, za_step, num_za)))
+            .unzip();
+        let jones = beam
+            .calc_jones_array_pair(&az, &za, freq_hz, params, latitude_rad)?
+            .into_iter()
+            .map(|j| [j[0], j[1], j[2], j[3]])
+            .collect::<Vec<_>>();
+
+        // Choose the sign of each element across the pole that makes the
+        // values through zenith smoothest.
+        let pole_signs = std::array::from_fn(|k| {
+            let roughness = |sign: f64| -> f64 {
+                (0..num_az)
+                    .map(|i_az| {
+                        let opposite = (i_az + num_az / 2) % num_az;
+                        (jones[num_az + opposite][k] * sign - jones[i_az][k] * 2.0
+                            + jones[num_az + i_az][k])
+                            .norm()
+                    })
+                    .sum()
+            };
+            if roughness(-1.0) < roughness(1.0) {
+                -1.0
+            } else {
+                1.0
+            }
+        });
+
+        let mut grid = BeamGrid {
+            freq_hz,
+            num_az,
+            num_za,
+            jones,
+            pole_signs,
+            max_error_bilinear: 0.0,
+            max_error_bicubic: 0.0,
+        };
+
+        // Measure the interpolation errors at the cell centres.
+        let (az, za): (Vec<f64>, Vec<f64>) = (0..num_za - 1)
+            .flat_map(|i_za| (0..num_az).map(move |i_az| (i_az, i_za)))
+            .map(|(i_az, i_za)| ((i_az as f64 + 0.5) * az_step, (i_za as f64 + 0.5) * za_step))
+            .unzip();
+        let exact = beam.calc_jones_array_pair(&az, &za, freq_hz, params, latitude_rad)?;
+        let max_error = |grid: &BeamGrid, method| {
+            beam.install(|| {
+                az.par_iter()
+                    .zip(za.par_iter())
+                    .zip(exact.par_iter())
+                    .map(|((&az, &za), exact)| {
+                        let j = grid.interpolate_unchecked(az, za, method);
+                        (0..4).map(|k| (j[k] - exact[k]).norm()).fold(0.0, f64::max)
+                    })
+                    .reduce(|| 0.0, f64::max)
+            })
+        };
+        grid.max_error_bilinear = max_error(&grid, Interpolation::Bilinear);
+        grid.max_error_bicubic = max_error(&grid, Interpolation::Bicubic);
+        Ok(grid)
+    }
+
+    /// Get the frequency that the beam responses are for \[Hz\].
+    pub fn get_freq(&self) -> u32 {
+        self.freq_hz
+    }
+
+    /// Get the number of azimuths of the grid.
+    pub fn get_num_az(&self) -> usize {
+        self.num_az
+    }
+
+    /// Get the number of zenith angles of the grid.
+    pub fn get_num_za(&self) -> usize {
+        self.num_za
+    }
+
+    /// Get the maximum error of any Jones matrix element when interpolating
+    /// with `method`. This was measured at the centre of every grid cell when
+    /// the grid was made.
+    pub fn get_max_error(&self, method: Interpolation) -> f64 {
+        match method {
+            Interpolation::Bilinear => self.max_error_bilinear,
+            Interpolation::Bicubic => self.max_error_bicubic,
+        }
+    }
+
+    /// Interpolate the grid to get the beam response in a direction. Any
+    /// azimuth can be used, but the zenith angle must be between 0 and pi/2.
+    pub fn interpolate(
+        &self,
+        az_rad: f64,
+        za_rad: f64,
+        method: Interpolation,
+    ) -> Result<Jones<f64>, BeamGridError> {
+        if !(0.0..=FRAC_PI_2).contains(&za_rad) || !az_rad.is_finite() {
+            return Err(BeamGridError::OutsideGrid { az_rad, za_rad });
+        }
+        Ok(self.interpolate_unchecked(az_rad, za_rad, method))
+    }
+
+    /// Interpolate the grid to get the beam responses for many directions in
+    /// parallel. See [`BeamGrid::interpolate`]. There must be as many azimuths
+    /// as zenith angles.
+    pub fn interpolate_array(
+        &self,
+        az_rad: &[f64],
+        za_rad: &[f64],
+        method: Interpolation,
+    ) -> Result<Vec<Jones<f64>>, BeamGridError> {
+        if az_rad.len() != za_rad.len() {
+            return Err(BeamGridError::DirectionCountMismatch {
+                az: az_rad.len(),
+                za: za_rad.len(),
+            });
+        }
+        az_rad
+            .par_iter()
+            .zip(za_rad.par_iter())
+            .map(|(&az, &za)| self.interpolate(az, za, method))
+            .collect()
+    }
+
+    /// Interpolate without checking the direction.
+    fn interpolate_unchecked(&self, az_rad: f64, za_rad: f64, method: Interpolation) -> Jones<f64> {
+        let (az_step, za_step) = steps(self.num_az, self.num_za);
+        let x = az_rad.rem_euclid(TAU) / az_step;
+        let i_az = x.floor();
+        let fx = x - i_az;
+        let i_az = i_az as isize;
+        let y = za_rad / za_step;
+        let i_za = (y.floor() as isize).clamp(0, self.num_za as isize - 2);
+        let fy = y - i_za as f64;
+
+        let mut j = [c64::default(); 4];
+        match method {
+            Interpolation::Bilinear => {
+                let wx = [1.0 - fx, fx];
+                let wy = [1.0 - fy, fy];
+                for (dy, wy) in wy.into_iter().enumerate() {
+                    for (dx, wx) in wx.into_iter().enumerate() {
+                        let v = self.get(i_za + dy as isize, i_az + dx as isize);
+                        for k in 0..4 {
+                            j[k] += v[k] * (wx * wy);
+                        }
+                    }
+                }
+            }
+
+            Interpolation::Bicubic => {
+                let wx = catmull_rom(fx);
+                let wy = catmull_rom(fy);
+                for (dy, wy) in wy.into_iter().enumerate() {
+                    for (dx, wx) in wx.into_iter().enumerate() {
+                        let v = self.get(i_za + dy as isize - 1, i_az + dx as isize - 1);
+                        for k in 0..4 {
+                            j[k] += v[k] * (wx * wy);
+                        }
+                    }
+                }
+            }
+        }
+        Jones::from(j)
+    }
+
+    /// Get the grid value at a zenith-angle index (which may be -1 or
+    /// `num_za`, i.e. just beyond the grid) and any azimuth index.
+    fn get(&self, i_za: isize, i_az: isize) -> [c64; 4] {
+        let num_az = self.num_az as isize;
+        let num_za = self.num_za as isize;
+        let node = |i_za: isize, i_az: isize| -> [c64; 4] {
+            self.jones[(i_za * num_az + i_az.rem_euclid(num_az)) as usize]
+        };
+        if i_za < 0 {
+            // Reflect across the pole.
+            let v = node(-i_za, i_az + num_az / 2);
+            std::array::from_fn(|k| v[k] * self.pole_signs[k])
+        } else if i_za >= num_za {
+            // Extrapolate linearly beyond the horizon.
+            let (a, b) = (node(num_za - 1, i_az), node(num_za - 2, i_az));
+            std::array::from_fn(|k| a[k] * 2.0 - b[k])
+        } else {
+            node(i_za, i_az)
+        }
+    }
+
+    /// Write the grid to a file. See [`BeamGrid::write_to`].
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), BeamGridError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the grid in hyperbeam's own binary format (all values are
+    /// little-endian): the magic bytes `HBGRID\0\0`, the format version (u32),
+    /// the frequency (u32), the numbers of azimuths and zenith angles (u64),
+    /// the pole signs (4 f64), the maximum bilinear and bicubic errors (f64),
+    /// and then the Jones matrices (8 f64 each; real and imaginary parts of
+    /// each element) in order of zenith angle, then azimuth.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), BeamGridError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&self.freq_hz.to_le_bytes())?;
+        writer.write_all(&(self.num_az as u64).to_le_bytes())?;
+        writer.write_all(&(self.num_za as u64).to_le_bytes())?;
+        for v in self
+            .pole_signs
+            .iter()
+            .chain([&self.max_error_bilinear, &self.max_error_bicubic])
+        {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        for j in &self.jones {
+            for c in j {
+                writer.write_all(&c.re.to_le_bytes())?;
+                writer.write_all(&c.im.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a grid from a file written by [`BeamGrid::write`].
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<BeamGrid, BeamGridError> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Read a grid written by [`BeamGrid::write_to`].
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<BeamGrid, BeamGridError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(BeamGridError::NotAGrid);
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(BeamGridError::UnsupportedVersion(version));
+        }
+        let freq_hz = read_u32(reader)?;
+        let num_az = usize::try_from(read_u64(reader)?).map_err(|_| BeamGridError::NotAGrid)?;
+        let num_za = usize::try_from(read_u64(reader)?).map_err(|_| BeamGridError::NotAGrid)?;
+        if num_az < 4 || num_az % 2 != 0 || num_za < 2 {
+            return Err(BeamGridError::BadShape { num_az, num_za });
+        }
+        let pole_signs = [
+            read_f64(reader)?,
+            read_f64(reader)?,
+            read_f64(reader)?,
+            read_f64(reader)?,
+        ];
+        let max_error_bilinear = read_f64(reader)?;
+        let max_error_bicubic = read_f64(reader)?;
+        let num_points = num_az
+            .checked_mul(num_za)
+            .ok_or(BeamGridError::BadShape { num_az, num_za })?;
+        let mut jones = Vec::new();
+        for _ in 0..num_points {
+            let mut j = [c64::default(); 4];
+            for c in &mut j {
+                *c = c64::new(read_f64(reader)?, read_f64(reader)?);
+            }
+            jones.push(j);
+        }
+        Ok(BeamGrid {
+            freq_hz,
+            num_az,
+            num_za,
+            jones,
+            pole_signs,
+            max_error_bilinear,
+            max_error_bicubic,
+        })
+    }
+}
+
+/// The azimuth and zenith-angle spacings of a grid.
+fn steps(num_az: usize, num_za: usize) -> (f64, f64) {
+    (TAU / num_az as f64, FRAC_PI_2 / (num_za - 1) as f64)
+}
+
+/// The zenith angle of a grid index. The last is exactly pi/2.
+fn za_at(i_za: usize, za_step: f64, num_za: usize) -> f64 {
+    if i_za == num_za - 1 {
+        FRAC_PI_2
+    } else {
+        i_za as f64 * za_step
+    }
+}
+
+/// The Catmull-Rom weights of the points at -1, 0, 1 and 2 for interpolating
+/// at `t` (between 0 and 1).
+fn catmull_rom(t: f64) -> [f64; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> std::io::Result<f64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[derive(Error, Debug)]
+pub enum BeamGridError {
+    #[error("A beam grid needs an even number of at least 4 azimuths and at least 2 zenith angles (got {num_az} and {num_za})")]
+    BadShape { num_az: usize, num_za: usize },
+
+    #[error("The direction (az {az_rad} rad, za {za_rad} rad) is not covered by the beam grid; the zenith angle must be between 0 and pi/2")]
+    OutsideGrid { az_rad: f64, za_rad: f64 },
+
+    #[error("Got {az} azimuths but {za} zenith angles; these must be the same")]
+    DirectionCountMismatch { az: usize, za: usize },
+
+    #[error("The data is not a beam grid")]
+    NotAGrid,
+
+    #[error("Beam grid format version {0} is not supported")]
+    UnsupportedVersion(u32),
+
+    #[error(transparent)]
+    Beam(#[from] BeamError),
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use marlu::constants::MWA_LAT_RAD;
+
+    use super::*;
+    use crate::analytic::AnalyticBeam;
+
+    const DELAYS: [u32; 16] = [3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0];
+
+    fn params(parallactic: bool) -> BeamParams<'static> {
+        BeamParams {
+            delays: &DELAYS,
+            amps: &[1.0; 16],
+            norm_to_zenith: true,
+            parallactic,
+            iau_order: true,
+        }
+    }
+
+    #[test]
+    fn test_grid_interpolation() {
+        for (beam, equatorial) in [
+            (AnalyticBeam::new(), false),
+            (AnalyticBeam::new_rts(), true),
+        ] {
+            let beam = Beam::from(beam);
+            let grid = BeamGrid::new(
+                &beam,
+                &params(equatorial),
+                150_000_000,
+                MWA_LAT_RAD,
+                360,
+                91,
+            )
+            .unwrap();
+            let bilinear = grid.get_max_error(Interpolation::Bilinear);
+            let bicubic = grid.get_max_error(Interpolation::Bicubic);
+            assert!(bicubic < bilinear, "{bicubic:e} {bilinear:e}");
+            // The equatorial basis is discontinuous at the south celestial
+            // pole.
+            if !equatorial {
+                assert!(bicubic < 1e-4, "{bicubic:e}");
+            }
+
+            // Directions off the grid, including near zenith, the horizon and
+            // azimuths that need wrapping.
+            let (az, za): (Vec<f64>, Vec<f64>) = (0..2000)
+                .map(|i| {
+                    let i = i as f64;
+                    (i * 0.0317 - 5.0, (i * 0.000791) % FRAC_PI_2)
+                })
+                .chain([
+                    (TAU - 0.001, 0.001),
+                    (-0.01, 0.02),
+                    (1.0, FRAC_PI_2),
+                    (2.0, 0.0),
+                ])
+                .unzip();
+            let exact = beam
+                .calc_jones_array_pair(&az, &za, 150_000_000, &params(equatorial), MWA_LAT_RAD)
+                .unwrap();
+            for method in [Interpolation::Bilinear, Interpolation::Bicubic] {
+                let interpolated = grid.interpolate_array(&az, &za, method).unwrap();
+                let max_diff = interpolated
+                    .iter()
+                    .zip(exact.iter())
+                    .flat_map(|(a, b)| (0..4).map(move |k| (a[k] - b[k]).norm()))
+                    .fold(0.0, f64::max);
+                // The cell centres aren't always where the error is biggest.
+                assert!(
+                    max_diff <= 2.0 * grid.get_max_error(method),
+                    "{method:?}: {max_diff:e} {:e}",
+                    grid.get_max_error(method)
+                );
+            }
+
+            // Grid points are reproduced.
+            let j = grid
+                .interpolate(
+                    2.0 * TAU / 360.0,
+                    3.0 * FRAC_PI_2 / 90.0,
+                    Interpolation::Bicubic,
+                )
+                .unwrap();
+            let expected = grid.jones[3 * 360 + 2];
+            for k in 0..4 {
+                assert_abs_diff_eq!(j[k], expected[k], epsilon = 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid_pole_signs() {
+        // Without the parallactic-angle correction, the beam's elements change
+        // sign through zenith; with it, they don't.
+        let grid = BeamGrid::new(
+            &Beam::from(AnalyticBeam::new()),
+            &params(false),
+            150_000_000,
+            MWA_LAT_RAD,
+            36,
+            10,
+        )
+        .unwrap();
+        assert_eq!(grid.pole_signs, [-1.0; 4]);
+        let grid = BeamGrid::new(
+            &Beam::from(AnalyticBeam::new_rts()),
+            &params(true),
+            150_000_000,
+            MWA_LAT_RAD,
+            36,
+            10,
+        )
+        .unwrap();
+        assert_eq!(grid.pole_signs, [1.0; 4]);
+    }
+
+    #[test]
+    fn test_grid_round_trip() {
+        let beam = Beam::from(AnalyticBeam::new());
+        let grid = BeamGrid::new(&beam, &params(false), 200_000_000, MWA_LAT_RAD, 36, 10).unwrap();
+        let mut bytes = vec![];
+        grid.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 8 + 4 + 4 + 16 + 48 + 36 * 10 * 64);
+        let read = BeamGrid::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, grid);
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            BeamGrid::read_from(&mut bytes.as_slice()),
+            Err(BeamGridError::NotAGrid)
+        ));
+    }
+
+    #[test]
+    fn test_grid_errors() {
+        let beam = Beam::from(AnalyticBeam::new());
+        assert!(matches!(
+            BeamGrid::new(&beam, &params(false), 200_000_000, MWA_LAT_RAD, 35, 10),
+            Err(BeamGridError::BadShape { .. })
+        ));
+        let grid = BeamGrid::new(&beam, &params(false), 200_000_000, MWA_LAT_RAD, 36, 10).unwrap();
+        for (az, za) in [(0.0, -0.1), (0.0, 1.6), (f64::NAN, 0.1), (0.0, f64::NAN)] {
+            assert!(matches!(
+                grid.interpolate(az, za, Interpolation::Bilinear),
+                Err(BeamGridError::OutsideGrid { .. })
+            ));
+        }
+        assert!(matches!(
+            grid.interpolate_array(&[0.0, 0.1, 0.2], &[0.1, 0.2], Interpolation::Bilinear),
+            Err(BeamGridError::DirectionCountMismatch { az: 3, za: 2 })
+        ));
+    }
+}
@@ -0,0 +1,535 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! All-sky maps of the beam on a HEALPix grid.
+//!
+//! Maps can be made in horizon coordinates (HEALPix colatitude is the zenith
+//! angle and longitude is the azimuth) or in equatorial coordinates for a
+//! given LST (colatitude is 90 degrees minus Dec and longitude is RA). See
+//! Górski et al. (2005) for details of the HEALPix scheme.
+
+use std::{
+    f64::consts::{FRAC_PI_2, PI},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use marlu::RADec;
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    beam::{Beam, BeamError, BeamParams},
+    fits::{write_bintable, write_empty_primary, FitsHeader},
+    image::{ImageProduct, Observer},
+};
+
+/// The value healpy uses for masked pixels.
+pub const UNSEEN: f32 = -1.6375e30;
+
+/// The ordering of HEALPix pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ordering {
+    Ring,
+    Nested,
+}
+
+impl Ordering {
+    fn as_str(self) -> &'static str {
+        match self {
+            Ordering::Ring => "RING",
+            Ordering::Nested => "NESTED",
+        }
+    }
+}
+
+/// The coordinate system of a HEALPix map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealpixFrame {
+    /// Azimuth and zenith angle; the map doesn't depend on the LST.
+    Horizon,
+
+    /// RA and Dec (of date) at the observer's LST.
+    Equatorial,
+}
+
+/// Get the number of pixels in a HEALPix map with this `nside`.
+pub fn nside_to_npix(nside: u32) -> usize {
+    12 * nside as usize * nside as usize
+}
+
+/// Get the colatitude (theta) and longitude (phi) [radians] of the centre of
+/// a HEALPix pixel. `nside` must be a power of 2 for the nested ordering, and
+/// `ipix` must be smaller than [`nside_to_npix`].
+pub fn pix2ang(nside: u32, ordering: Ordering, ipix: usize) -> (f64, f64) {
+    match ordering {
+        Ordering::Ring => pix2ang_ring(nside, ipix),
+        Ordering::Nested => pix2ang_ring(nside, nest2ring(nside, ipix)),
+    }
+}
+
+fn pix2ang_ring(nside: u32, ipix: usize) -> (f64, f64) {
+    let nside = nside as usize;
+    let npix = 12 * nside * nside;
+    let ncap = 2 * nside * (nside - 1);
+
+    let (z, phi) = if ipix < ncap {
+        // North polar cap.
+        let iring = isqrt(1 + 2 * ipix).div_ceil(2);
+        let iphi = ipix + 1 - 2 * iring * (iring - 1);
+        let z = 1.0 - (iring * iring) as f64 * 4.0 / npix as f64;
+        (z, (iphi as f64 - 0.5) * FRAC_PI_2 / iring as f64)
+    } else if ipix < npix - ncap {
+        // Equatorial region.
+        let ip = ipix - ncap;
+        let iring = ip / (4 * nside) + nside;
+        let iphi = ip % (4 * nside) + 1;
+        let fodd = if (iring + nside) % 2 == 1 { 1.0 } else { 0.5 };
+        let z = (2 * nside) as f64 - iring as f64;
+        let z = z * 2.0 / (3.0 * nside as f64);
+        (z, (iphi as f64 - fodd) * FRAC_PI_2 / nside as f64)
+    } else {
+        // South polar cap.
+        let ip = npix - ipix;
+        let iring = isqrt(2 * ip - 1).div_ceil(2);
+        let iphi = 4 * iring + 1 - (ip - 2 * iring * (iring - 1));
+        let z = -1.0 + (iring * iring) as f64 * 4.0 / npix as f64;
+        (z, (iphi as f64 - 0.5) * FRAC_PI_2 / iring as f64)
+    };
+    (z.acos(), phi)
+}
+
+/// Convert a nested pixel index to a ring pixel index.
+fn nest2ring(nside: u32, ipix: usize) -> usize {
+    // The ring number (in units of nside) of the southernmost corner of each
+    // base face, and the longitude (in units of pi/4) of that corner.
+    const JRLL: [usize; 12] = [2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+    const JPLL: [usize; 12] = [1, 3, 5, 7, 0, 2, 4, 6, 1, 3, 5, 7];
+
+    let nside = nside as usize;
+    let npface = nside * nside;
+    let npix = 12 * npface;
+    let ncap = 2 * nside * (nside - 1);
+    let nl4 = 4 * nside;
+
+    let face = ipix / npface;
+    let ipf = ipix % npface;
+    // The x and y bits are interleaved in the pixel index within the face.
+    let (ix, iy) = (compress_bits(ipf), compress_bits(ipf >> 1));
+
+    let jr = JRLL[face] * nside - ix - iy - 1;
+    let (nr, n_before, kshift) = if jr < nside {
+        (jr, 2 * jr * (jr - 1), 0)
+    } else if jr > 3 * nside {
+        let nr = nl4 - jr;
+        (nr, npix - 2 * (nr + 1) * nr, 0)
+    } else {
+        (nside, ncap + (jr - nside) * nl4, (jr - nside) & 1)
+    };
+
+    // (jpll * nr + ix - iy + 1 + kshift) / 2, in [1, nl4].
+    let mut jp = ((JPLL[face] * nr + ix + 1 + kshift) as isize - iy as isize) / 2;
+    if jp > nl4 as isize {
+        jp -= nl4 as isize;
+    } else if jp < 1 {
+        jp += nl4 as isize;
+    }
+    n_before + jp as usize - 1
+}
+
+/// The integer square root, rounded down.
+fn isqrt(v: usize) -> usize {
+    let mut r = (v as f64).sqrt() as usize;
+    // Correct any floating-point error for large values.
+    while r * r > v {
+        r -= 1;
+    }
+    while (r + 1) * (r + 1) <= v {
+        r += 1;
+    }
+    r
+}
+
+/// Take every second bit of `v` (starting with the least significant).
+fn compress_bits(v: usize) -> usize {
+    let mut out = 0;
+    let mut v = v;
+    let mut i = 0;
+    while v != 0 {
+        out |= (v & 1) << i;
+        v >>= 2;
+        i += 1;
+    }
+    out
+}
+
+/// All-sky beam maps for one or more frequencies.
+#[derive(Debug, Clone)]
+pub struct HealpixMap {
+    pub nside: u32,
+    pub ordering: Ordering,
+    pub frame: HealpixFrame,
+    pub product: ImageProduct,
+    pub observer: Observer,
+
+    /// The frequencies of the maps [Hz].
+    pub freqs_hz: Vec<u32>,
+
+    /// The map data, with dimensions (plane, frequency, pixel). Pixels below
+    /// the horizon are NaN.
+    pub data: Array3<f32>,
+
+    /// Extra cards to add to FITS headers, e.g. describing the beam.
+    pub header: FitsHeader,
+}
+
+/// Generate all-sky beam maps. In the equatorial frame, the observer's LST
+/// determines where the beam is on the sky; the latitude is always used.
+#[allow(clippy::too_many_arguments)]
+pub fn make_healpix_map(
+    beam: &Beam,
+    params: &BeamParams,
+    nside: u32,
+    ordering: Ordering,
+    frame: HealpixFrame,
+    freqs_hz: &[u32],
+    observer: Observer,
+    product: ImageProduct,
+) -> Result<HealpixMap, HealpixError> {
+    // Nested indices need nside to be a power of 2, and (following healpy)
+    // we require the same of ring maps.
+    if !nside.is_power_of_two() || nside > (1 << 29) {
+        return Err(HealpixError::BadNside(nside));
+    }
+    if freqs_hz.is_empty() {
+        return Err(HealpixError::NoFreqs);
+    }
+
+    let npix = nside_to_npix(nside);
+    let (pixels, (az_rad, za_rad)): (Vec<usize>, (Vec<f64>, Vec<f64>)) = beam.install(|| {
+        (0..npix)
+            .into_par_iter()
+            .filter_map(|ipix| {
+                let (theta, phi) = pix2ang(nside, ordering, ipix);
+                let (az, za) = match frame {
+                    HealpixFrame::Horizon => (phi, theta),
+                    HealpixFrame::Equatorial => {
+                        let azel = RADec::from_radians(phi, FRAC_PI_2 - theta)
+                            .to_hadec(observer.lst_rad)
+                            .to_azel(observer.latitude_rad);
+                        (azel.az.rem_euclid(2.0 * PI), azel.za())
+                    }
+                };
+                (za <= FRAC_PI_2).then_some((ipix, (az, za)))
+            })
+            .unzip()
+    });
+
+    let planes = product.plane_names().len();
+    let mut data = Array3::from_elem((planes, freqs_hz.len(), npix), f32::NAN);
+    let mut values = vec![0.0; planes];
+    for (i_freq, &freq_hz) in freqs_hz.iter().enumerate() {
+        let jones =
+            beam.calc_jones_array_pair(&az_rad, &za_rad, freq_hz, params, observer.latitude_rad)?;
+        for (&ipix, &j) in pixels.iter().zip(jones.iter()) {
+            product.fill(j, &mut values);
+            for (i_plane, &v) in values.iter().enumerate() {
+                data[(i_plane, i_freq, ipix)] = v;
+            }
+        }
+    }
+
+    Ok(HealpixMap {
+        nside,
+        ordering,
+        frame,
+        product,
+        observer,
+        freqs_hz: freqs_hz.to_vec(),
+        data,
+        header: FitsHeader::new(),
+    })
+}
+
+impl HealpixMap {
+    /// Write the maps to a HEALPix-standard FITS file (readable by e.g.
+    /// `healpy.read_map`). Each plane and frequency is a column of a binary
+    /// table; with more than one frequency, column names are suffixed with
+    /// the frequency index (e.g. `XX_0`), and the frequencies are given by the
+    /// `FREQn` keywords. Masked pixels are written as [`UNSEEN`].
+    pub fn write_fits<P: AsRef<Path>>(&self, path: P) -> Result<(), HealpixError> {
+        let mut f = BufWriter::new(File::create(path)?);
+        self.write_fits_to(&mut f)?;
+        f.flush()?;
+        Ok(())
+    }
+
+    /// Write the maps as FITS to any writer. See [`HealpixMap::write_fits`].
+    pub fn write_fits_to<W: Write>(&self, writer: &mut W) -> Result<(), HealpixError> {
+        let npix = nside_to_npix(self.nside);
+        let mut h = FitsHeader::new();
+        h.push("PIXTYPE", "HEALPIX", "HEALPIX pixelisation");
+        h.push("ORDERING", self.ordering.as_str(), "pixel ordering scheme");
+        h.push("NSIDE", i64::from(self.nside), "resolution parameter");
+        h.push("FIRSTPIX", 0, "first pixel index (0-based)");
+        h.push("LASTPIX", npix - 1, "last pixel index (0-based)");
+        h.push("INDXSCHM", "IMPLICIT", "indexing: implicit or explicit");
+        h.push("OBJECT", "FULLSKY", "sky coverage");
+        match self.frame {
+            HealpixFrame::Horizon => {
+                h.push_comment("Horizon coordinates: theta is the zenith angle, phi the azimuth")
+            }
+            HealpixFrame::Equatorial => {
+                h.push("COORDSYS", "C", "equatorial (of date)");
+                h.push(
+                    "LST",
+                    self.observer.lst_rad.to_degrees(),
+                    "local sidereal time [deg]",
+                );
+                if let Some(date_obs) = self.observer.date_obs() {
+                    h.push("DATE-OBS", date_obs, "UTC");
+                }
+            }
+        }
+        h.push(
+            "LATITUDE",
+            self.observer.latitude_rad.to_degrees(),
+            "telescope latitude [deg]",
+        );
+        h.push("BAD_DATA", f64::from(UNSEEN), "value of masked pixels");
+        for (i, &f) in self.freqs_hz.iter().enumerate() {
+            h.push(&format!("FREQ{i}"), f64::from(f), "[Hz]");
+        }
+        for card in self.header.cards() {
+            h.push_card(card);
+        }
+        h.push_history(&format!(
+            "Created by mwa_hyperbeam {}",
+            env!("CARGO_PKG_VERSION")
+        ));
+
+        let names = self.product.plane_names();
+        let mut columns = Vec::with_capacity(names.len() * self.freqs_hz.len());
+        for i_freq in 0..self.freqs_hz.len() {
+            for (i_plane, name) in names.iter().enumerate() {
+                let name = if self.freqs_hz.len() == 1 {
+                    name.to_string()
+                } else {
+                    format!("{name}_{i_freq}")
+                };
+                let values: Vec<f32> = self
+                    .data
+                    .slice(s![i_plane, i_freq, ..])
+                    .iter()
+                    .map(|&v| if v.is_nan() { UNSEEN } else { v })
+                    .collect();
+                columns.push((name, values));
+            }
+        }
+        let columns: Vec<(&str, &[f32])> = columns
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.as_slice()))
+            .collect();
+
+        write_empty_primary(writer, &FitsHeader::new())?;
+        write_bintable(writer, &h, &columns)?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HealpixError {
+    #[error("HEALPix nside must be a power of 2 no larger than 2^29 (got {0})")]
+    BadNside(u32),
+
+    #[error("No frequencies were given for the HEALPix map")]
+    NoFreqs,
+
+    #[error(transparent)]
+    Beam(#[from] BeamError),
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::analytic::AnalyticBeam;
+
+    #[test]
+    fn test_pix2ang_ring_known_values() {
+        // nside = 1: the first ring has z = 2/3.
+        let (theta, phi) = pix2ang(1, Ordering::Ring, 0);
+        assert_abs_diff_eq!(theta.cos(), 2.0 / 3.0, epsilon = 1e-15);
+        assert_abs_diff_eq!(phi, PI / 4.0, epsilon = 1e-15);
+        let (theta, phi) = pix2ang(1, Ordering::Ring, 4);
+        assert_abs_diff_eq!(theta, FRAC_PI_2, epsilon = 1e-15);
+        assert_abs_diff_eq!(phi, 0.0, epsilon = 1e-15);
+        let (theta, _) = pix2ang(1, Ordering::Ring, 11);
+        assert_abs_diff_eq!(theta.cos(), -2.0 / 3.0, epsilon = 1e-15);
+
+        // nside = 2, first pixel of the equatorial region.
+        let (theta, phi) = pix2ang(2, Ordering::Ring, 4);
+        assert_abs_diff_eq!(theta.cos(), 2.0 / 3.0, epsilon = 1e-15);
+        assert_abs_diff_eq!(phi, PI / 8.0, epsilon = 1e-15);
+    }
+
+    #[test]
+    fn test_nest2ring_is_a_permutation() {
+        for nside in [1, 2, 4, 16] {
+            let npix = nside_to_npix(nside);
+            let mut seen = vec![false; npix];
+            for ipix in 0..npix {
+                let iring = nest2ring(nside, ipix);
+                assert!(!seen[iring], "nside {nside}: ring pixel {iring} seen twice");
+                seen[iring] = true;
+            }
+        }
+
+        // The first nested pixel is the southernmost corner of face 0, which
+        // lies on the first ring of the equatorial region.
+        assert_eq!(nest2ring(2, 0), 13);
+        // The last nested pixel of face 0 is at the north pole.
+        assert_eq!(nest2ring(2, 3), 0);
+    }
+
+    #[test]
+    fn test_pixels_are_equal_area() {
+        // The mean of cos(theta) over all pixel centres is zero, and every
+        // ring is symmetric about phi.
+        let nside = 8;
+        let npix = nside_to_npix(nside);
+        let mean_z: f64 = (0..npix)
+            .map(|i| pix2ang(nside, Ordering::Nested, i).0.cos())
+            .sum::<f64>()
+            / npix as f64;
+        assert_abs_diff_eq!(mean_z, 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_healpix_map() {
+        let beam = Beam::from(AnalyticBeam::new());
+        let params = BeamParams {
+            delays: &[0; 16],
+            amps: &[1.0; 16],
+            norm_to_zenith: true,
+            parallactic: true,
+            iau_order: true,
+        };
+        let observer = Observer {
+            lst_rad: 1.0,
+            latitude_rad: -27f64.to_radians(),
+            epoch: None,
+        };
+        let nside = 8;
+        let npix = nside_to_npix(nside);
+
+        let horizon = make_healpix_map(
+            &beam,
+            &params,
+            nside,
+            Ordering::Ring,
+            HealpixFrame::Horizon,
+            &[150_000_000],
+            observer,
+            ImageProduct::Stokes,
+        )
+        .unwrap();
+        assert_eq!(horizon.data.dim(), (4, 1, npix));
+        // Half the sky (the southern hemisphere and part of the equator in
+        // HEALPix terms) is below the horizon.
+        let num_masked = horizon
+            .data
+            .slice(s![0, 0, ..])
+            .iter()
+            .filter(|v| v.is_nan())
+            .count();
+        assert_eq!(num_masked, npix / 2 - 4 * nside as usize / 2);
+        // The zenith-normalised Stokes I is largest near zenith (the first
+        // ring pixels are about 6 degrees away).
+        let max_i = horizon
+            .data
+            .slice(s![0, 0, ..])
+            .iter()
+            .filter(|v| !v.is_nan())
+            .fold(f32::MIN, |m, &v| m.max(v));
+        assert_eq!(max_i, horizon.data[(0, 0, 0)]);
+        assert!(max_i > 0.8 && max_i < 1.0);
+
+        let eq = make_healpix_map(
+            &beam,
+            &params,
+            nside,
+            Ordering::Nested,
+            HealpixFrame::Equatorial,
+            &[150_000_000, 200_000_000],
+            observer,
+            ImageProduct::Power,
+        )
+        .unwrap();
+        // Roughly half the sky is masked.
+        let num_masked = eq
+            .data
+            .slice(s![0, 1, ..])
+            .iter()
+            .filter(|v| v.is_nan())
+            .count();
+        assert!((num_masked as f64 / npix as f64 - 0.5).abs() < 0.05);
+        // The maximum is close to (RA, Dec) = (LST, latitude).
+        let (i_max, _) = eq
+            .data
+            .slice(s![0, 0, ..])
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| !v.is_nan())
+            .fold(
+                (0, f32::MIN),
+                |(im, m), (i, &v)| if v > m { (i, v) } else { (im, m) },
+            );
+        let (theta, phi) = pix2ang(nside, Ordering::Nested, i_max);
+        assert_abs_diff_eq!(phi, 1.0, epsilon = 0.2);
+        assert_abs_diff_eq!(FRAC_PI_2 - theta, -27f64.to_radians(), epsilon = 0.2);
+
+        let mut fits = vec![];
+        eq.write_fits_to(&mut fits).unwrap();
+        assert_eq!(fits.len() % 2880, 0);
+        let header = String::from_utf8_lossy(&fits[2880..2 * 2880]);
+        assert!(header.contains("ORDERING= 'NESTED  '"));
+        assert!(header.contains("TTYPE5  = 'XX_1    '"));
+        assert!(header.contains("PIXTYPE = 'HEALPIX '"));
+    }
+
+    #[test]
+    fn test_bad_nside() {
+        let beam = Beam::from(AnalyticBeam::new());
+        let params = BeamParams {
+            delays: &[0; 16],
+            amps: &[1.0; 16],
+            norm_to_zenith: false,
+            parallactic: true,
+            iau_order: true,
+        };
+        let observer = Observer {
+            lst_rad: 0.0,
+            latitude_rad: 0.0,
+            epoch: None,
+        };
+        let result = make_healpix_map(
+            &beam,
+            &params,
+            3,
+            Ordering::Ring,
+            HealpixFrame::Horizon,
+            &[150_000_000],
+            observer,
+            ImageProduct::Power,
+        );
+        assert!(matches!(result, Err(HealpixError::BadNside(3))));
+    }
+}
@@ -0,0 +1,647 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Images of the primary beam on a FITS (WCS) pixel grid, e.g. for
+//! primary-beam correction.
+//!
+//! Only zenithal (SIN and ZEA) projections are supported. The pixel
+//! coordinates are treated as RA/Dec of date, i.e. no precession or
+//! aberration is applied when converting them to azimuth and zenith angle.
+
+use std::{
+    f64::consts::{FRAC_PI_2, PI},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use marlu::{
+    hifitime::{Duration, Epoch},
+    precession::get_lmst,
+    RADec,
+};
+use ndarray::prelude::*;
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    beam::{Beam, BeamError, BeamParams},
+    fits::{write_primary_image, FitsHeader},
+    Jones,
+};
+
+/// A zenithal projection of the celestial sphere onto an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// Slant orthographic.
+    Sin,
+
+    /// Zenithal equal area.
+    Zea,
+}
+
+impl Projection {
+    fn as_str(self) -> &'static str {
+        match self {
+            Projection::Sin => "SIN",
+            Projection::Zea => "ZEA",
+        }
+    }
+
+    /// Get the native latitude (theta) [radians] for a distance `r_rad` from
+    /// the reference pixel in the (intermediate) image plane. `None` is
+    /// returned if the distance is outside the projection.
+    fn theta(self, r_rad: f64) -> Option<f64> {
+        match self {
+            Projection::Sin if r_rad <= 1.0 => Some(r_rad.acos()),
+            Projection::Zea if r_rad <= 2.0 => Some(FRAC_PI_2 - 2.0 * (r_rad / 2.0).asin()),
+            _ => None,
+        }
+    }
+}
+
+/// The definition of an image's pixel grid. The reference pixel is at the
+/// centre of the image (the FITS convention of `CRPIX = N / 2 + 1`), and RA
+/// increases to the left.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageWcs {
+    pub projection: Projection,
+
+    /// The RA of the reference pixel [degrees].
+    pub ra_deg: f64,
+
+    /// The Dec of the reference pixel [degrees].
+    pub dec_deg: f64,
+
+    /// The angular size of each pixel at the reference pixel [degrees].
+    pub pixel_scale_deg: f64,
+
+    /// The number of pixels along the RA axis.
+    pub width: usize,
+
+    /// The number of pixels along the Dec axis.
+    pub height: usize,
+}
+
+impl ImageWcs {
+    /// The (1-based) reference pixels along the RA and Dec axes.
+    fn crpix(&self) -> (f64, f64) {
+        ((self.width / 2 + 1) as f64, (self.height / 2 + 1) as f64)
+    }
+
+    /// Get the sky coordinates of a pixel, with `x` indexing the RA axis and
+    /// `y` the Dec axis (both 0-based). `None` is returned if the pixel is
+    /// outside the projection.
+    pub fn pixel_to_radec(&self, x: usize, y: usize) -> Option<RADec> {
+        let (crpix1, crpix2) = self.crpix();
+        let cdelt = self.pixel_scale_deg.to_radians();
+        // The intermediate world coordinates.
+        let l = -cdelt * (x as f64 + 1.0 - crpix1);
+        let m = cdelt * (y as f64 + 1.0 - crpix2);
+
+        // Native spherical coordinates.
+        let theta = self.projection.theta(l.hypot(m))?;
+        let phi = l.atan2(-m);
+
+        // Rotate to celestial coordinates; for zenithal projections, the
+        // native pole is at the reference point and LONPOLE is 180 degrees.
+        let (s_t, c_t) = theta.sin_cos();
+        let (s_d0, c_d0) = self.dec_deg.to_radians().sin_cos();
+        let (s_dp, c_dp) = (phi - PI).sin_cos();
+        let dec = (s_t * s_d0 + c_t * c_d0 * c_dp).clamp(-1.0, 1.0).asin();
+        let ra = self.ra_deg.to_radians() + (-c_t * s_dp).atan2(s_t * c_d0 - c_t * s_d0 * c_dp);
+        Some(RADec::from_radians(ra.rem_euclid(2.0 * PI), dec))
+    }
+}
+
+/// Where and when the beam is observed from.
+#[derive(Debug, Clone, Copy)]
+pub struct Observer {
+    /// The local sidereal time [radians].
+    pub lst_rad: f64,
+
+    /// The latitude of the telescope [radians].
+    pub latitude_rad: f64,
+
+    /// The time of the observation, if it's known. It's written to FITS
+    /// headers as DATE-OBS.
+    pub epoch: Option<Epoch>,
+}
+
+impl Observer {
+    /// Get an [`Observer`] from a UTC time and the telescope's location.
+    pub fn from_epoch(epoch: Epoch, longitude_rad: f64, latitude_rad: f64) -> Observer {
+        Observer {
+            lst_rad: get_lmst(longitude_rad, epoch, Duration::from_seconds(0.0)),
+            latitude_rad,
+            epoch: Some(epoch),
+        }
+    }
+
+    /// The time of the observation as a FITS DATE-OBS value (UTC), if it's
+    /// known.
+    pub(crate) fn date_obs(&self) -> Option<String> {
+        self.epoch.map(|epoch| {
+            let (y, mo, d, h, mi, s, ns) = epoch.to_gregorian_utc();
+            format!(
+                "{y:04}-{mo:02}-{d:02}T{h:02}:{mi:02}:{s:02}.{:03}",
+                ns / 1_000_000
+            )
+        })
+    }
+}
+
+/// What each plane of a beam image contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProduct {
+    /// The instrumental power for an unpolarised source of unit brightness:
+    /// XX, YY, Re(XY) and Im(XY). YX is the complex conjugate of XY, so these
+    /// planes hold all four products. As the last two planes aren't FITS
+    /// Stokes parameters, FITS images use a custom POWER axis labelled by
+    /// PLANEn keywords.
+    Power,
+
+    /// The instrumental Stokes I, Q, U and V for an unpolarised source of unit
+    /// brightness, i.e. (XX + YY) / 2, (XX - YY) / 2, Re(XY) and Im(XY) (FITS
+    /// Stokes codes 1 to 4).
+    Stokes,
+
+    /// The real and imaginary parts of each Jones matrix element: J00 re, J00
+    /// im, J01 re, J01 im, J10 re, J10 im, J11 re, J11 im.
+    Jones,
+}
+
+impl ImageProduct {
+    /// The names of the planes in an image of this product.
+    pub fn plane_names(self) -> &'static [&'static str] {
+        match self {
+            ImageProduct::Power => &["XX", "YY", "XY_RE", "XY_IM"],
+            ImageProduct::Stokes => &["I", "Q", "U", "V"],
+            ImageProduct::Jones => &[
+                "J00_RE", "J00_IM", "J01_RE", "J01_IM", "J10_RE", "J10_IM", "J11_RE", "J11_IM",
+            ],
+        }
+    }
+
+    /// Fill `out` (one element per plane) with the values for a Jones matrix.
+    pub(crate) fn fill(self, j: Jones<f64>, out: &mut [f32]) {
+        let xx = j[0].norm_sqr() + j[1].norm_sqr();
+        let yy = j[2].norm_sqr() + j[3].norm_sqr();
+        let xy = j[0] * j[2].conj() + j[1] * j[3].conj();
+        match self {
+            ImageProduct::Power => {
+                out.copy_from_slice(&[xx as f32, yy as f32, xy.re as f32, xy.im as f32]);
+            }
+            ImageProduct::Stokes => {
+                out.copy_from_slice(&[
+                    ((xx + yy) / 2.0) as f32,
+                    ((xx - yy) / 2.0) as f32,
+                    xy.re as f32,
+                    xy.im as f32,
+                ]);
+            }
+            ImageProduct::Jones => {
+                for (o, v) in out.iter_mut().zip(j.to_float_array()) {
+                    *o = v as f32;
+                }
+            }
+        }
+    }
+}
+
+/// Beam images for one or more frequencies.
+#[derive(Debug, Clone)]
+pub struct BeamImage {
+    pub wcs: ImageWcs,
+    pub product: ImageProduct,
+    pub observer: Observer,
+
+    /// The frequencies of the images [Hz]. These are evenly spaced.
+    pub freqs_hz: Vec<u32>,
+
+    /// The image data, with dimensions (plane, frequency, Dec, RA). Pixels
+    /// outside the projection or below the horizon are NaN.
+    pub data: Array4<f32>,
+
+    /// Extra cards to add to FITS headers, e.g. describing the beam.
+    pub header: FitsHeader,
+}
+
+/// Generate beam images on the pixel grid given by `wcs`. Each frequency in
+/// `freqs_hz` becomes a channel of the spectral axis, so they must be evenly
+/// spaced.
+pub fn make_beam_image(
+    beam: &Beam,
+    params: &BeamParams,
+    wcs: ImageWcs,
+    freqs_hz: &[u32],
+    observer: Observer,
+    product: ImageProduct,
+) -> Result<BeamImage, ImageError> {
+    if wcs.width == 0 || wcs.height == 0 {
+        return Err(ImageError::EmptyImage);
+    }
+    if wcs.pixel_scale_deg.is_nan() || wcs.pixel_scale_deg <= 0.0 {
+        return Err(ImageError::BadPixelScale(wcs.pixel_scale_deg));
+    }
+    if freqs_hz.is_empty() {
+        return Err(ImageError::NoFreqs);
+    }
+    let freq_step = freqs_hz.get(1).map(|&f| f as i64 - freqs_hz[0] as i64);
+    if let Some(step) = freq_step {
+        if step == 0
+            || freqs_hz
+                .windows(2)
+                .any(|w| w[1] as i64 - w[0] as i64 != step)
+        {
+            return Err(ImageError::IrregularFreqs);
+        }
+    }
+
+    // Find the pixels that are above the horizon.
+    let (pixels, (az_rad, za_rad)): (Vec<usize>, (Vec<f64>, Vec<f64>)) = beam.install(|| {
+        (0..wcs.width * wcs.height)
+            .into_par_iter()
+            .filter_map(|i_pixel| {
+                let radec = wcs.pixel_to_radec(i_pixel % wcs.width, i_pixel / wcs.width)?;
+                let azel = radec
+                    .to_hadec(observer.lst_rad)
+                    .to_azel(observer.latitude_rad);
+                let za = azel.za();
+                (za <= FRAC_PI_2).then_some((i_pixel, (azel.az, za)))
+            })
+            .unzip()
+    });
+
+    let planes = product.plane_names().len();
+    let mut data = Array4::from_elem((planes, freqs_hz.len(), wcs.height, wcs.width), f32::NAN);
+    let mut values = vec![0.0; planes];
+    for (i_freq, &freq_hz) in freqs_hz.iter().enumerate() {
+        let jones =
+            beam.calc_jones_array_pair(&az_rad, &za_rad, freq_hz, params, observer.latitude_rad)?;
+        for (&i_pixel, &j) in pixels.iter().zip(jones.iter()) {
+            product.fill(j, &mut values);
+            let (x, y) = (i_pixel % wcs.width, i_pixel / wcs.width);
+            for (i_plane, &v) in values.iter().enumerate() {
+                data[(i_plane, i_freq, y, x)] = v;
+            }
+        }
+    }
+
+    Ok(BeamImage {
+        wcs,
+        product,
+        observer,
+        freqs_hz: freqs_hz.to_vec(),
+        data,
+        header: FitsHeader::new(),
+    })
+}
+
+impl BeamImage {
+    /// Write the images to a FITS file. The axes are RA, Dec, frequency and
+    /// then the plane (Stokes for power images).
+    pub fn write_fits<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        let mut f = BufWriter::new(File::create(path)?);
+        self.write_fits_to(&mut f)?;
+        f.flush()?;
+        Ok(())
+    }
+
+    /// Write the images as FITS to any writer. See [`BeamImage::write_fits`].
+    pub fn write_fits_to<W: Write>(&self, writer: &mut W) -> Result<(), ImageError> {
+        let wcs = &self.wcs;
+        let (crpix1, crpix2) = wcs.crpix();
+        let proj = wcs.projection.as_str();
+        let mut h = FitsHeader::new();
+        h.push("CTYPE1", format!("RA---{proj}"), "");
+        h.push("CRVAL1", wcs.ra_deg, "");
+        h.push("CDELT1", -wcs.pixel_scale_deg, "");
+        h.push("CRPIX1", crpix1, "");
+        h.push("CUNIT1", "deg", "");
+        h.push("CTYPE2", format!("DEC--{proj}"), "");
+        h.push("CRVAL2", wcs.dec_deg, "");
+        h.push("CDELT2", wcs.pixel_scale_deg, "");
+        h.push("CRPIX2", crpix2, "");
+        h.push("CUNIT2", "deg", "");
+        h.push("CTYPE3", "FREQ", "");
+        h.push("CRVAL3", f64::from(self.freqs_hz[0]), "");
+        let cdelt3 = match self.freqs_hz.as_slice() {
+            [f1, f2, ..] => f64::from(*f2) - f64::from(*f1),
+            _ => 1.0,
+        };
+        h.push("CDELT3", cdelt3, "");
+        h.push("CRPIX3", 1.0, "");
+        h.push("CUNIT3", "Hz", "");
+        match self.product {
+            ImageProduct::Stokes => {
+                h.push("CTYPE4", "STOKES", "");
+                h.push("CRVAL4", 1.0, "I");
+                h.push("CDELT4", 1.0, "");
+                h.push("CRPIX4", 1.0, "");
+            }
+            ImageProduct::Power | ImageProduct::Jones => {
+                let ctype4 = match self.product {
+                    ImageProduct::Power => "POWER",
+                    _ => "JONES",
+                };
+                h.push("CTYPE4", ctype4, "see PLANEn keywords");
+                h.push("CRVAL4", 1.0, "");
+                h.push("CDELT4", 1.0, "");
+                h.push("CRPIX4", 1.0, "");
+                for (i, name) in self.product.plane_names().iter().enumerate() {
+                    h.push(&format!("PLANE{}", i + 1), *name, "");
+                }
+            }
+        }
+        h.push("LONPOLE", 180.0, "");
+        // The pixel coordinates are apparent RA/Dec of date, not J2000.
+        h.push("RADESYS", "GAPPT", "apparent coordinates of date");
+        if let Some(date_obs) = self.observer.date_obs() {
+            h.push("DATE-OBS", date_obs, "UTC");
+        }
+        h.push(
+            "LST",
+            self.observer.lst_rad.to_degrees(),
+            "local sidereal time [deg]",
+        );
+        h.push(
+            "LATITUDE",
+            self.observer.latitude_rad.to_degrees(),
+            "telescope latitude [deg]",
+        );
+        for card in self.header.cards() {
+            h.push_card(card);
+        }
+        h.push_history(&format!(
+            "Created by mwa_hyperbeam {}",
+            env!("CARGO_PKG_VERSION")
+        ));
+
+        let (planes, freqs, height, width) = self.data.dim();
+        let data = self.data.as_standard_layout();
+        write_primary_image(
+            writer,
+            &h,
+            &[width, height, freqs, planes],
+            data.as_slice().expect("is in standard layout"),
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ImageError {
+    #[error("Beam images must have at least one pixel along each axis")]
+    EmptyImage,
+
+    #[error("The pixel scale must be positive (got {0} degrees)")]
+    BadPixelScale(f64),
+
+    #[error("No frequencies were given for the beam image")]
+    NoFreqs,
+
+    #[error("Beam image frequencies must be evenly spaced and increasing to make a spectral axis")]
+    IrregularFreqs,
+
+    #[error(transparent)]
+    Beam(#[from] BeamError),
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use num_complex::Complex64 as c64;
+
+    use super::*;
+    use crate::analytic::{AnalyticBeam, AnalyticType};
+
+    fn wcs(projection: Projection, dec_deg: f64) -> ImageWcs {
+        ImageWcs {
+            projection,
+            ra_deg: 60.0,
+            dec_deg,
+            pixel_scale_deg: 1.0,
+            width: 200,
+            height: 200,
+        }
+    }
+
+    #[test]
+    fn test_reference_pixel() {
+        for proj in [Projection::Sin, Projection::Zea] {
+            let radec = wcs(proj, -27.0).pixel_to_radec(100, 100).unwrap();
+            assert_abs_diff_eq!(radec.ra.to_degrees(), 60.0, epsilon = 1e-10);
+            assert_abs_diff_eq!(radec.dec.to_degrees(), -27.0, epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_pixel_directions() {
+        for proj in [Projection::Sin, Projection::Zea] {
+            let wcs = wcs(proj, 0.0);
+            // Increasing x goes to lower RA, increasing y goes to higher Dec.
+            let left = wcs.pixel_to_radec(90, 100).unwrap();
+            let up = wcs.pixel_to_radec(100, 110).unwrap();
+            assert!(left.ra.to_degrees() > 60.0);
+            assert_abs_diff_eq!(left.dec, 0.0, epsilon = 1e-10);
+            assert!(up.dec > 0.0);
+            assert_abs_diff_eq!(up.ra.to_degrees(), 60.0, epsilon = 1e-10);
+        }
+
+        // Along the Dec axis from a pole-free reference at the equator, SIN
+        // gives sin(dec) = m.
+        let up = wcs(Projection::Sin, 0.0).pixel_to_radec(100, 130).unwrap();
+        assert_abs_diff_eq!(up.dec.sin(), 30f64.to_radians(), epsilon = 1e-10);
+    }
+
+    #[test]
+    fn test_outside_projection() {
+        // 1 degree pixels; SIN only covers a hemisphere (r <= 57.3 pixels).
+        let wcs = wcs(Projection::Sin, -27.0);
+        assert!(wcs.pixel_to_radec(100, 150).is_some());
+        assert!(wcs.pixel_to_radec(100, 199).is_none());
+        assert!(wcs.pixel_to_radec(0, 0).is_none());
+
+        let zea = ImageWcs {
+            projection: Projection::Zea,
+            ..wcs
+        };
+        assert!(zea.pixel_to_radec(100, 199).is_some());
+    }
+
+    #[test]
+    fn test_fill_planes() {
+        let j = Jones::from([
+            c64::new(0.5, 0.1),
+            c64::new(0.02, -0.01),
+            c64::new(-0.03, 0.2),
+            c64::new(0.4, -0.2),
+        ]);
+        // XX = |j00|^2 + |j01|^2, YY = |j10|^2 + |j11|^2 and
+        // XY = j00 j10* + j01 j11*.
+        let xx = 0.26 + 0.0005;
+        let yy = 0.0409 + 0.2;
+        let xy = c64::new(-0.015 + 0.02 + 0.008 + 0.002, -0.1 - 0.003 - 0.004 + 0.004);
+
+        let mut out = [0.0; 4];
+        ImageProduct::Power.fill(j, &mut out);
+        assert_abs_diff_eq!(out[0], xx as f32, epsilon = 1e-7);
+        assert_abs_diff_eq!(out[1], yy as f32, epsilon = 1e-7);
+        assert_abs_diff_eq!(out[2], xy.re as f32, epsilon = 1e-7);
+        assert_abs_diff_eq!(out[3], xy.im as f32, epsilon = 1e-7);
+
+        ImageProduct::Stokes.fill(j, &mut out);
+        assert_abs_diff_eq!(out[0], ((xx + yy) / 2.0) as f32, epsilon = 1e-7);
+        assert_abs_diff_eq!(out[1], ((xx - yy) / 2.0) as f32, epsilon = 1e-7);
+        assert_abs_diff_eq!(out[2], xy.re as f32, epsilon = 1e-7);
+        assert_abs_diff_eq!(out[3], xy.im as f32, epsilon = 1e-7);
+
+        let mut out = [0.0; 8];
+        ImageProduct::Jones.fill(j, &mut out);
+        assert_eq!(
+            out,
+            [0.5, 0.1, 0.02, -0.01, -0.03, 0.2, 0.4, -0.2].map(|v: f64| v as f32)
+        );
+    }
+
+    #[test]
+    fn test_date_obs() {
+        let epoch = Epoch::from_gregorian_utc(2013, 8, 1, 12, 34, 56, 789_000_000);
+        let observer = Observer::from_epoch(epoch, 0.0, 0.0);
+        assert_eq!(
+            observer.date_obs().as_deref(),
+            Some("2013-08-01T12:34:56.789")
+        );
+
+        let observer = Observer {
+            epoch: None,
+            ..observer
+        };
+        assert!(observer.date_obs().is_none());
+    }
+
+    #[test]
+    fn test_beam_image() {
+        let beam = Beam::from(AnalyticBeam::new_custom(
+            AnalyticType::MwaPb,
+            AnalyticType::MwaPb.get_default_dipole_height(),
+            4,
+        ));
+        let params = BeamParams {
+            delays: &[0; 16],
+            amps: &[1.0; 16],
+            norm_to_zenith: true,
+            parallactic: true,
+            iau_order: true,
+        };
+        // Point the image at zenith.
+        let latitude_rad = -27f64.to_radians();
+        let observer = Observer {
+            lst_rad: 1.0,
+            latitude_rad,
+            epoch: None,
+        };
+        let wcs = ImageWcs {
+            projection: Projection::Sin,
+            ra_deg: 1f64.to_degrees(),
+            dec_deg: latitude_rad.to_degrees(),
+            pixel_scale_deg: 0.5,
+            width: 21,
+            height: 11,
+        };
+        let image = make_beam_image(
+            &beam,
+            &params,
+            wcs,
+            &[150_000_000, 160_000_000],
+            observer,
+            ImageProduct::Power,
+        )
+        .unwrap();
+        assert_eq!(image.data.dim(), (4, 2, 11, 21));
+        // The zenith-normalised beam has unit power at zenith.
+        assert_abs_diff_eq!(image.data[(0, 0, 5, 10)], 1.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(image.data[(1, 1, 5, 10)], 1.0, epsilon = 1e-4);
+        assert!(image.data[(0, 0, 0, 0)] < image.data[(0, 0, 5, 10)]);
+
+        let mut fits = vec![];
+        image.write_fits_to(&mut fits).unwrap();
+        assert_eq!(fits.len() % 2880, 0);
+        let header = String::from_utf8_lossy(&fits[..2880 * 2]);
+        assert!(header.contains("CTYPE1  = 'RA---SIN'"));
+        assert!(header.contains("CTYPE4  = 'POWER   '"));
+        assert!(header.contains("PLANE3  = 'XY_RE   '"));
+        assert!(header.contains("PLANE4  = 'XY_IM   '"));
+        assert!(header.contains("RADESYS = 'GAPPT   '"));
+        assert!(!header.contains("EQUINOX"));
+    }
+
+    #[test]
+    fn test_horizon_is_masked() {
+        let beam = Beam::from(AnalyticBeam::new());
+        let params = BeamParams {
+            delays: &[0; 16],
+            amps: &[1.0; 16],
+            norm_to_zenith: false,
+            parallactic: true,
+            iau_order: true,
+        };
+        let observer = Observer {
+            lst_rad: 0.0,
+            latitude_rad: -27f64.to_radians(),
+            epoch: None,
+        };
+        // An image of the whole hemisphere around the north celestial pole,
+        // which is always below the horizon at this latitude.
+        let wcs = ImageWcs {
+            projection: Projection::Zea,
+            ra_deg: 0.0,
+            dec_deg: 90.0,
+            pixel_scale_deg: 2.0,
+            width: 10,
+            height: 10,
+        };
+        let image = make_beam_image(
+            &beam,
+            &params,
+            wcs,
+            &[150_000_000],
+            observer,
+            ImageProduct::Jones,
+        )
+        .unwrap();
+        assert_eq!(image.data.dim().0, 8);
+        assert!(image.data.iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn test_irregular_freqs() {
+        let beam = Beam::from(AnalyticBeam::new());
+        let params = BeamParams {
+            delays: &[0; 16],
+            amps: &[1.0; 16],
+            norm_to_zenith: false,
+            parallactic: true,
+            iau_order: true,
+        };
+        let observer = Observer {
+            lst_rad: 0.0,
+            latitude_rad: 0.0,
+            epoch: None,
+        };
+        let result = make_beam_image(
+            &beam,
+            &params,
+            wcs(Projection::Sin, 0.0),
+            &[100, 200, 400],
+            observer,
+            ImageProduct::Power,
+        );
+        assert!(matches!(result, Err(ImageError::IrregularFreqs)));
+    }
+}
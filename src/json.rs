@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Helpers for writing the small JSON reports produced by hyperbeam (e.g.
+//! [`FEEBeam::validate`](crate::fee::FEEBeam::validate) and
+//! [`FEEBeam::metadata`](crate::fee::FEEBeam::metadata)) without needing a
+//! serialisation library.
+//!
+//! This isn't part of the public API; it's only public so that hyperbeam's
+//! binaries can use it.
+
+use std::fmt::Write;
+
+/// Quote and escape a string for JSON.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Format a float for JSON, which has no representation of NaN or infinity
+/// (these become `null`).
+pub fn json_f64(f: f64) -> String {
+    if f.is_finite() {
+        format!("{f:e}")
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("abc"), "\"abc\"");
+        assert_eq!(
+            json_string("a \"b\"\\c\nd\te\u{1}"),
+            "\"a \\\"b\\\"\\\\c\\nd\\te\\u0001\""
+        );
+    }
+
+    #[test]
+    fn test_json_f64() {
+        assert_eq!(json_f64(0.5), "5e-1");
+        assert_eq!(json_f64(f64::NAN), "null");
+        assert_eq!(json_f64(f64::NEG_INFINITY), "null");
+    }
+}
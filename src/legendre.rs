@@ -4,6 +4,11 @@
 
 //! Code for Legendre polynomials.
 
+use std::collections::{hash_map::Entry, HashMap};
+
+use num_traits::Float;
+use rayon::prelude::*;
+
 /// Evaluates the Legendre polynomial Pm(n,m) at x. x must satisfy |x| <= 1.
 ///
 /// # Arguments
@@ -66,6 +71,7 @@ fn _legendre_values(n: usize, m: usize, x: &[f64]) -> Vec<f64> {
 // The C code distributed under the GNU LGPL license, and thus this function is
 // also licensed under the LGPL. A copy of the LGPL license can be found at
 // https://www.gnu.org/licenses/lgpl-3.0.en.html
+#[cfg(test)]
 pub(crate) fn legendre_single(n: usize, m: usize, x: f64) -> Vec<f64> {
     let mut v = vec![0.0; n + 1];
 
@@ -95,88 +101,499 @@ pub(crate) fn legendre_single(n: usize, m: usize, x: f64) -> Vec<f64> {
     v
 }
 
-/// Returns list of Legendre polynomial values calculated up to order n_max.
-// This function is a re-write of P1SIN within the RTS file mwa_tile.c.
-pub(crate) fn p1sin(n_max: u8, theta: f64) -> (Vec<f64>, Vec<f64>) {
+/// The number of modes with N up to `n_max`, i.e. the length of the outputs
+/// of [`p1sin_into`].
+pub(crate) fn num_modes(n_max: u8) -> usize {
     let n_max = usize::from(n_max);
-    let mut all_vals = vec![0.0; (n_max + 1) * (n_max + 2) / 2];
+    n_max * n_max + 2 * n_max
+}
 
-    let size = n_max * n_max + 2 * n_max;
-    let mut p1sin_out = vec![0.0; size];
-    let mut p1_out = vec![0.0; size];
+/// Calculate the associated Legendre values used by the FEE beam for all modes
+/// with N up to `n_max`, without allocating. For each mode (N, M), in order of
+/// N and then M (from -N to N), `p1sin_out` gets P_N^|M|(cos theta) / sin
+/// theta and `p1_out` gets P_N^(|M|+1)(cos theta). The outputs must be at
+/// least [`num_modes`] long; the values of a mode don't depend on `n_max`.
+///
+/// Each order M is found by the standard upward recurrence in N, so this is
+/// O(n_max^2).
+// This function replaces a re-write of P1SIN within the RTS file mwa_tile.c;
+// the results are identical.
+pub(crate) fn p1sin_into(n_max: u8, theta: f64, p1sin_out: &mut [f64], p1_out: &mut [f64]) {
+    let size = num_modes(n_max);
+    let p1sin_out = &mut p1sin_out[..size];
+    let p1_out = &mut p1_out[..size];
+    let n_max = usize::from(n_max);
+    // The index of mode (N, ±M) in the outputs.
+    let index = |n: usize, m: usize| (n * n + n - 1 - m, n * n + n - 1 + m);
 
     let (s_theta, u) = theta.sin_cos();
-    let delta_u = 1e-6;
+    // Floating point comparisons are pretty awful, but this is what the C++
+    // code does, so...
+    #[allow(clippy::float_cmp)]
+    let at_pole = u == 1.0 || u == -1.0;
+    let sqrt_1_minus_u2 = (1.0 - u * u).sqrt();
+
+    // Store P_N^M for N >= 1.
+    let mut store = |n: usize, m: usize, p: f64| {
+        let (neg, pos) = index(n, m);
+        let p_sin = if at_pole { 0.0 } else { p / s_theta };
+        p1sin_out[neg] = p_sin;
+        p1sin_out[pos] = p_sin;
+        if m == n {
+            p1_out[neg] = 0.0;
+            p1_out[pos] = 0.0;
+        }
+        if m > 0 {
+            let (neg, pos) = index(n, m - 1);
+            p1_out[neg] = p;
+            p1_out[pos] = p;
+        }
+    };
 
-    let mut pm_in = u;
-    let mut m_incr = 0;
+    // P_M^M.
+    let mut p_mm = 1.0;
     for m in 0..=n_max {
-        let pm_vals = legendre_single(n_max, m, pm_in);
-        for i in m..=n_max {
-            if !(i == 0 && m == 0) {
-                all_vals[(i - m) + m_incr] = pm_vals[i];
+        if m > 0 {
+            p_mm *= -((2 * m - 1) as f64) * sqrt_1_minus_u2;
+            store(m, m, p_mm);
+        }
+        if m == n_max {
+            break;
+        }
+
+        let mut p_prev = p_mm;
+        let mut p = u * (2 * m + 1) as f64 * p_mm;
+        store(m + 1, m, p);
+        for n in (m + 2)..=n_max {
+            let (ni, mi) = (n as isize, m as isize);
+            let p_next =
+                ((2 * n - 1) as f64 * u * p + (-ni - mi + 1) as f64 * p_prev) / (ni - mi) as f64;
+            p_prev = p;
+            p = p_next;
+            store(n, m, p);
+        }
+    }
+
+    // 1 / sin theta is infinite at the poles; in its place, P_N^1 / sin theta
+    // is approximated with a finite difference of P_N^0.
+    if at_pole {
+        let delta_u = 1e-6;
+        let u_d = u - delta_u;
+        // P_0^0 and P_1^0.
+        let (mut p_prev, mut p) = (1.0, u);
+        let (mut p_d_prev, mut p_d) = (1.0, u_d);
+        for n in 1..=n_max {
+            if n > 1 {
+                let nf = n as f64;
+                let p_next = ((2 * n - 1) as f64 * u * p + (-nf + 1.0) * p_prev) / nf;
+                let p_d_next = ((2 * n - 1) as f64 * u_d * p_d + (-nf + 1.0) * p_d_prev) / nf;
+                p_prev = p;
+                p = p_next;
+                p_d_prev = p_d;
+                p_d = p_d_next;
             }
+            #[allow(clippy::float_cmp)]
+            let p_sin = if u == 1.0 {
+                -(p - p_d) / delta_u
+            } else {
+                -(p_d - p) / delta_u
+            };
+            let (neg, pos) = index(n, 1);
+            p1sin_out[neg] = p_sin;
+            p1sin_out[pos] = p_sin;
         }
-        m_incr += n_max - m + 1;
     }
+}
+
+/// The same as [`p1sin_into`], but the outputs are allocated.
+#[cfg(test)]
+pub(crate) fn p1sin(n_max: u8, theta: f64) -> (Vec<f64>, Vec<f64>) {
+    let size = num_modes(n_max);
+    let mut p1sin_out = vec![0.0; size];
+    let mut p1_out = vec![0.0; size];
+    p1sin_into(n_max, theta, &mut p1sin_out, &mut p1_out);
+    (p1sin_out, p1_out)
+}
 
-    let mut p = vec![0.0; n_max + 1];
-    let mut pm1 = vec![0.0; n_max + 1];
-    let mut pm_sin = vec![0.0; n_max + 1];
-    for n in 1..=n_max {
-        m_incr = 0;
-        for order in 0..=n {
-            let index = n + m_incr;
-            p[order] = all_vals[index];
-            if order > 0 {
-                pm1[order - 1] = all_vals[index];
+/// Coefficients for calculating the values of [`p1sin_into`] multiplied by
+/// the FEE normalisation of each mode, `sqrt((2N+1)/2 (N-|M|)! / (N+|M|)!) /
+/// sqrt(N (N+1))`, for `L` directions at a time. Unlike the unnormalised
+/// values, these are never large, so they can be calculated with `f32`.
+///
+/// The recurrence for fully normalised associated Legendre functions is used,
+/// and P_N^M / sin theta is found directly (rather than by dividing by sin
+/// theta), so no special treatment is needed at the poles. The values for M =
+/// 0 of `p1sin` are 0; they're always multiplied by M.
+pub(crate) struct NormalisedLegendre<T> {
+    n_max: u8,
+    /// For each M (from 2), the factor between P_M^M / sin theta and
+    /// P_(M-1)^(M-1) / sin theta, divided by sin theta.
+    diag: Vec<f64>,
+    /// For each (N, M) with 1 <= M < N, in order of M and then N, the
+    /// coefficients of P_(N-1)^M and P_(N-2)^M in the recurrence for P_N^M.
+    a: Vec<T>,
+    b: Vec<T>,
+    /// For each N, 1 / sqrt(N (N+1)).
+    e: Vec<T>,
+    /// For each (N, M) with 0 <= M < N, in order of N and then M, the factor
+    /// between the normalised P_N^(M+1) and the value of `p1` for (N, M).
+    d: Vec<T>,
+}
+
+impl<T: Float> NormalisedLegendre<T> {
+    pub(crate) fn new(n_max: u8) -> NormalisedLegendre<T> {
+        let cast = |v: f64| T::from(v).expect("representable");
+        let n_max_u = usize::from(n_max);
+        let diag = (0..=n_max_u)
+            .map(|m| match m {
+                0 => 0.0,
+                _ => -((2 * m + 1) as f64 / (2 * m) as f64).sqrt(),
+            })
+            .collect();
+        let (mut a, mut b) = (vec![], vec![]);
+        let a_nm = |n: usize, m: usize| {
+            let (n, m) = (n as f64, m as f64);
+            ((4.0 * n * n - 1.0) / (n * n - m * m)).sqrt()
+        };
+        for m in 1..n_max_u {
+            a.push(cast((2 * m + 3) as f64).sqrt());
+            b.push(T::zero());
+            for n in (m + 2)..=n_max_u {
+                a.push(cast(a_nm(n, m)));
+                b.push(cast(a_nm(n, m) / a_nm(n - 1, m)));
             }
-            if order == n {
-                pm1[order] = 0.0;
+        }
+        let e = (0..=n_max_u)
+            .map(|n| cast(1.0 / ((n * (n + 1)) as f64).sqrt()))
+            .collect();
+        let mut d = vec![];
+        for n in 1..=n_max_u {
+            for m in 0..n {
+                d.push(cast(
+                    (((n + m + 1) * (n - m)) as f64 / (n * (n + 1)) as f64).sqrt(),
+                ));
             }
-            pm_sin[order] = 0.0;
-            m_incr += n_max - order;
         }
+        NormalisedLegendre {
+            n_max,
+            diag,
+            a,
+            b,
+            e,
+            d,
+        }
+    }
 
-        // Floating point comparisons are pretty awful, but this is what the C++
-        // code does, so...
-        #[allow(clippy::float_cmp)]
-        if u == 1.0 {
-            pm_in = u - delta_u;
-            let pm_vals = legendre_single(n, 0, pm_in);
-            pm_sin[1] = -(p[0] - pm_vals[n]) / delta_u;
-        } else if u == -1.0 {
-            pm_in = u - delta_u;
-            let pm_vals = legendre_single(n, 0, pm_in);
-            pm_sin[1] = -(pm_vals[n] - p[0]) / delta_u;
-        } else {
-            pm_sin
-                .iter_mut()
-                .zip(p.iter().copied())
-                .for_each(|(pm_sin, p)| *pm_sin = p / s_theta);
+    /// Calculate the normalised values for the zenith angles `za_rad`. `p1sin`
+    /// and `p1` must be at least [`num_modes`] long.
+    pub(crate) fn fill<const L: usize>(
+        &self,
+        za_rad: &[f64; L],
+        p1sin: &mut [[T; L]],
+        p1: &mut [[T; L]],
+    ) {
+        let n_max = usize::from(self.n_max);
+        let index = |n: usize, m: usize| (n * n + n - 1 - m, n * n + n - 1 + m);
+        let sin_cos = za_rad.map(f64::sin_cos);
+        let s_f64 = sin_cos.map(|(s, _)| s);
+        let s = s_f64.map(|s| T::from(s).unwrap_or_else(T::zero));
+        let u = sin_cos.map(|(_, c)| T::from(c).unwrap_or_else(T::zero));
+
+        for n in 1..=n_max {
+            let (neg, pos) = index(n, n);
+            p1[neg] = [T::zero(); L];
+            p1[pos] = [T::zero(); L];
+            p1sin[index(n, 0).0] = [T::zero(); L];
         }
 
-        let ind_start = (n - 1) * (n - 1) + 2 * (n - 1);
-        let ind_stop = n * n + 2 * n;
-        for i in ind_start..ind_stop {
-            let index = i - ind_start;
-            let j = if index < n { n - index } else { index - n };
-            p1sin_out[i] = pm_sin[j];
-            p1_out[i] = pm1[j];
+        let (a, b, d, e) = (&self.a, &self.b, &self.d, &self.e);
+        // The index of (N, M) in `d`.
+        let d_index = |n: usize, m: usize| n * (n - 1) / 2 + m;
+        let mut store = |n: usize, m: usize, q: &[T; L]| {
+            let (neg, pos) = index(n, m);
+            let p1sin_v: [T; L] = std::array::from_fn(|l| e[n] * q[l]);
+            p1sin[neg] = p1sin_v;
+            p1sin[pos] = p1sin_v;
+            let d = d[d_index(n, m - 1)];
+            let p1_v: [T; L] = std::array::from_fn(|l| d * s[l] * q[l]);
+            let (neg, pos) = index(n, m - 1);
+            p1[neg] = p1_v;
+            p1[pos] = p1_v;
+        };
+
+        // P_M^M / sin theta; it's found with f64 as it can be tiny.
+        let mut q_mm = [-(3.0_f64.sqrt()) / 2.0; L];
+        let mut i_coeff = 0;
+        for m in 1..=n_max {
+            if m > 1 {
+                for (q, &s) in q_mm.iter_mut().zip(s_f64.iter()) {
+                    *q *= self.diag[m] * s;
+                }
+            }
+            // Values this small don't matter, and would otherwise be slow
+            // (subnormal) with f32.
+            let q_mm_t = q_mm.map(|q| {
+                if q.abs() < 1e-30 {
+                    T::zero()
+                } else {
+                    T::from(q).unwrap_or_else(T::zero)
+                }
+            });
+            store(m, m, &q_mm_t);
+            if m == n_max {
+                break;
+            }
+
+            let mut q_prev = q_mm_t;
+            let mut q: [T; L] = std::array::from_fn(|l| a[i_coeff] * u[l] * q_mm_t[l]);
+            i_coeff += 1;
+            store(m + 1, m, &q);
+            for n in (m + 2)..=n_max {
+                let (a, b) = (a[i_coeff], b[i_coeff]);
+                i_coeff += 1;
+                let q_next: [T; L] = std::array::from_fn(|l| a * u[l] * q[l] - b * q_prev[l]);
+                q_prev = q;
+                q = q_next;
+                store(n, m, &q);
+            }
         }
     }
+}
 
-    (p1sin_out, p1_out)
+/// The values of [`p1sin_into`] for a set of zenith angles, calculated once
+/// for each distinct angle. Directions on image-like grids often share zenith
+/// angles, and then these values needn't be calculated for every direction
+/// (or tile, or frequency).
+pub(crate) struct LegendreTable {
+    n_max: u8,
+    /// The row of each zenith angle (keyed by its bits).
+    rows: HashMap<u64, usize>,
+    p1sin: Vec<f64>,
+    p1: Vec<f64>,
+}
+
+impl LegendreTable {
+    /// The most memory that a table may use [bytes].
+    const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+    /// Make a table for the zenith angles `za_rad` with N up to `n_max`. `None`
+    /// is returned if a table isn't worthwhile, i.e. fewer than half of the
+    /// zenith angles are repeats, or the table would be too big.
+    pub(crate) fn new<I>(n_max: u8, za_rad: I) -> Option<LegendreTable>
+    where
+        I: ExactSizeIterator<Item = f64>,
+    {
+        let num_modes = num_modes(n_max);
+        let max_rows = (za_rad.len() / 2).min(Self::MAX_BYTES / (16 * num_modes.max(1)));
+        let mut rows = HashMap::new();
+        let mut unique = vec![];
+        for za in za_rad {
+            if let Entry::Vacant(e) = rows.entry(za.to_bits()) {
+                if unique.len() == max_rows {
+                    return None;
+                }
+                e.insert(unique.len());
+                unique.push(za);
+            }
+        }
+
+        let mut p1sin = vec![0.0; unique.len() * num_modes];
+        let mut p1 = vec![0.0; unique.len() * num_modes];
+        if num_modes > 0 {
+            p1sin
+                .par_chunks_exact_mut(num_modes)
+                .zip(p1.par_chunks_exact_mut(num_modes))
+                .zip(unique.par_iter())
+                .for_each(|((p1sin, p1), &za)| p1sin_into(n_max, za, p1sin, p1));
+        }
+        Some(LegendreTable {
+            n_max,
+            rows,
+            p1sin,
+            p1,
+        })
+    }
+
+    /// Get the values for a zenith angle with N up to `n_max`, if the table
+    /// has them.
+    pub(crate) fn get(&self, n_max: u8, za_rad: f64) -> Option<(&[f64], &[f64])> {
+        if n_max > self.n_max {
+            return None;
+        }
+        let row = *self.rows.get(&za_rad.to_bits())?;
+        let stride = num_modes(self.n_max);
+        let range = row * stride..row * stride + num_modes(n_max);
+        Some((&self.p1sin[range.clone()], &self.p1[range]))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_4};
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4, PI};
 
     use super::*;
     use approx::*;
     use ndarray::prelude::*;
 
+    /// The original, allocating re-write of P1SIN within the RTS file
+    /// mwa_tile.c.
+    fn p1sin_reference(n_max: u8, theta: f64) -> (Vec<f64>, Vec<f64>) {
+        let n_max = usize::from(n_max);
+        let mut all_vals = vec![0.0; (n_max + 1) * (n_max + 2) / 2];
+
+        let size = n_max * n_max + 2 * n_max;
+        let mut p1sin_out = vec![0.0; size];
+        let mut p1_out = vec![0.0; size];
+
+        let (s_theta, u) = theta.sin_cos();
+        let delta_u = 1e-6;
+
+        let mut pm_in = u;
+        let mut m_incr = 0;
+        for m in 0..=n_max {
+            let pm_vals = legendre_single(n_max, m, pm_in);
+            for i in m..=n_max {
+                if !(i == 0 && m == 0) {
+                    all_vals[(i - m) + m_incr] = pm_vals[i];
+                }
+            }
+            m_incr += n_max - m + 1;
+        }
+
+        let mut p = vec![0.0; n_max + 1];
+        let mut pm1 = vec![0.0; n_max + 1];
+        let mut pm_sin = vec![0.0; n_max + 1];
+        for n in 1..=n_max {
+            m_incr = 0;
+            for order in 0..=n {
+                let index = n + m_incr;
+                p[order] = all_vals[index];
+                if order > 0 {
+                    pm1[order - 1] = all_vals[index];
+                }
+                if order == n {
+                    pm1[order] = 0.0;
+                }
+                pm_sin[order] = 0.0;
+                m_incr += n_max - order;
+            }
+
+            // Floating point comparisons are pretty awful, but this is what the C++
+            // code does, so...
+            #[allow(clippy::float_cmp)]
+            if u == 1.0 {
+                pm_in = u - delta_u;
+                let pm_vals = legendre_single(n, 0, pm_in);
+                pm_sin[1] = -(p[0] - pm_vals[n]) / delta_u;
+            } else if u == -1.0 {
+                pm_in = u - delta_u;
+                let pm_vals = legendre_single(n, 0, pm_in);
+                pm_sin[1] = -(pm_vals[n] - p[0]) / delta_u;
+            } else {
+                pm_sin
+                    .iter_mut()
+                    .zip(p.iter().copied())
+                    .for_each(|(pm_sin, p)| *pm_sin = p / s_theta);
+            }
+
+            let ind_start = (n - 1) * (n - 1) + 2 * (n - 1);
+            let ind_stop = n * n + 2 * n;
+            for i in ind_start..ind_stop {
+                let index = i - ind_start;
+                let j = n.abs_diff(index);
+                p1sin_out[i] = pm_sin[j];
+                p1_out[i] = pm1[j];
+            }
+        }
+
+        (p1sin_out, p1_out)
+    }
+
+    #[test]
+    fn test_p1sin_into_matches_reference() {
+        let mut p1sin_out = vec![f64::NAN; num_modes(40)];
+        let mut p1_out = vec![f64::NAN; num_modes(40)];
+        for n_max in [1, 5, 16, 40] {
+            for theta in [0.0, 1e-8, 0.1, FRAC_PI_4, 1.0, 1.5, FRAC_PI_2, 2.5, PI] {
+                let (expected_p1sin, expected_p1) = p1sin_reference(n_max, theta);
+                p1sin_into(n_max, theta, &mut p1sin_out, &mut p1_out);
+                let size = num_modes(n_max);
+                // The same operations are done in the same order, so the
+                // results should be identical.
+                for (a, b) in p1sin_out[..size].iter().zip(expected_p1sin.iter()) {
+                    assert_eq!(a.to_bits(), b.to_bits(), "n_max {n_max}, theta {theta}");
+                }
+                for (a, b) in p1_out[..size].iter().zip(expected_p1.iter()) {
+                    assert_eq!(a.to_bits(), b.to_bits(), "n_max {n_max}, theta {theta}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_normalised_legendre() {
+        let n_max = 40;
+        let norms: Vec<f64> = (1..=usize::from(n_max))
+            .flat_map(|n| {
+                (-(n as isize)..=n as isize).map(move |m| {
+                    let m_abs = m.unsigned_abs();
+                    let c_mn = (0.5 * (2 * n + 1) as f64 * crate::factorial::FACTORIAL[n - m_abs]
+                        / crate::factorial::FACTORIAL[n + m_abs])
+                        .sqrt();
+                    c_mn / ((n * (n + 1)) as f64).sqrt()
+                })
+            })
+            .collect();
+        // At za = pi, the finite difference used by `p1sin_into` has the wrong
+        // sign, so it isn't compared.
+        let za = [0.0, 1e-3, 0.1, FRAC_PI_4, 1.0, FRAC_PI_2, 2.5, 3.0];
+        let normalised = NormalisedLegendre::<f64>::new(n_max);
+        let mut p1sin_out = vec![[0.0; 8]; num_modes(n_max)];
+        let mut p1_out = vec![[0.0; 8]; num_modes(n_max)];
+        normalised.fill(&za, &mut p1sin_out, &mut p1_out);
+        for (l, &za) in za.iter().enumerate() {
+            let (expected_p1sin, expected_p1) = p1sin(n_max, za);
+            // The finite difference used at the poles isn't as accurate.
+            let epsilon = if l == 0 { 1e-4 } else { 1e-10 };
+            let mut i = 0;
+            for n in 1..=i32::from(n_max) {
+                for m in -n..=n {
+                    if m != 0 {
+                        assert_abs_diff_eq!(
+                            p1sin_out[i][l],
+                            expected_p1sin[i] * norms[i],
+                            epsilon = epsilon * f64::from(n * n)
+                        );
+                    }
+                    assert_abs_diff_eq!(
+                        p1_out[i][l],
+                        expected_p1[i] * norms[i],
+                        epsilon = epsilon * f64::from(n * n)
+                    );
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_legendre_table() {
+        let za = [0.1, 0.2, 0.1, 0.1, 0.2, 0.3];
+        let table = LegendreTable::new(8, za.iter().copied()).unwrap();
+        for &za in &za {
+            let (expected_p1sin, expected_p1) = p1sin(5, za);
+            let (p1sin, p1) = table.get(5, za).unwrap();
+            assert_eq!(p1sin, expected_p1sin);
+            assert_eq!(p1, expected_p1);
+        }
+        // N is too big, or the zenith angle isn't in the table.
+        assert!(table.get(9, 0.1).is_none());
+        assert!(table.get(8, 0.4).is_none());
+
+        // No repeats.
+        assert!(LegendreTable::new(8, [0.1, 0.2, 0.3].into_iter()).is_none());
+    }
+
     #[test]
     fn test_legendre_single() {
         let result = Array1::from(legendre_single(5, 0, -1.0));
@@ -5,11 +5,22 @@
 //! Primary beam code for the Murchison Widefield Array.
 
 pub mod analytic;
+pub mod beam;
+mod build_info;
 mod constants;
 mod factorial;
 pub mod fee;
 mod ffi;
+pub mod fits;
+pub mod grid;
+pub mod healpix;
+pub mod image;
+#[doc(hidden)]
+pub mod json;
 mod legendre;
+pub mod npy;
+pub mod parallelism;
+pub mod stream;
 mod types;
 
 #[cfg(feature = "python")]
@@ -25,7 +36,9 @@ cfg_if::cfg_if! {
     }
 }
 
+pub use build_info::{build_info, BuildInfo};
 pub use marlu::{AzEl, Jones}; // So that callers can have a different version of Marlu.
+pub use parallelism::Parallelism;
 
 use ndarray::ArrayView1;
 
@@ -0,0 +1,426 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Minimal reading and writing of NumPy `.npy` files.
+//!
+//! Only what hyperbeam needs is supported: little-endian, C-ordered arrays of
+//! `f32`, `f64` and complex `f64`. See
+//! <https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html>
+//! for the format specification.
+
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+};
+
+use num_complex::Complex64 as c64;
+use thiserror::Error;
+
+/// The magic string at the start of every `.npy` file.
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// The total length of the preamble (magic, version and header) is padded to a
+/// multiple of this value.
+const HEADER_ALIGN: usize = 64;
+
+/// Types that can be written to a `.npy` file.
+pub trait NpyElement: Copy {
+    /// The NumPy type description, e.g. `<f8`.
+    const DESCR: &'static str;
+
+    /// The number of bytes of each value.
+    const SIZE: usize;
+
+    /// Append this value to `buf` as little-endian bytes.
+    fn push_le(self, buf: &mut Vec<u8>);
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+    const SIZE: usize = 4;
+
+    fn push_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for f64 {
+    const DESCR: &'static str = "<f8";
+    const SIZE: usize = 8;
+
+    fn push_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for u32 {
+    const DESCR: &'static str = "<u4";
+    const SIZE: usize = 4;
+
+    fn push_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl NpyElement for c64 {
+    const DESCR: &'static str = "<c16";
+    const SIZE: usize = 16;
+
+    fn push_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.re.to_le_bytes());
+        buf.extend_from_slice(&self.im.to_le_bytes());
+    }
+}
+
+/// Get the little-endian bytes of `data`, so that they can be written all at
+/// once.
+fn to_le_bytes<T: NpyElement>(data: &[T]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(data.len() * T::SIZE);
+    for &d in data {
+        d.push_le(&mut buf);
+    }
+    buf
+}
+
+#[derive(Error, Debug)]
+pub enum NpyError {
+    #[error("Not a .npy file (bad magic string)")]
+    BadMagic,
+
+    #[error("Unsupported .npy format version {0}.{1}")]
+    UnsupportedVersion(u8, u8),
+
+    #[error("Couldn't parse the .npy header: {0}")]
+    BadHeader(String),
+
+    #[error("Unsupported .npy data type '{0}'; only '<f4' and '<f8' can be read")]
+    UnsupportedDescr(String),
+
+    #[error("Fortran-ordered .npy arrays are not supported")]
+    FortranOrder,
+
+    #[error("The .npy shape {shape:?} implies {expected} elements, but {got} were given")]
+    ShapeMismatch {
+        shape: Vec<usize>,
+        expected: usize,
+        got: usize,
+    },
+
+    #[error("Data written to a .npy array must be whole rows of {row_len} elements, but {got} were given")]
+    PartialRow { row_len: usize, got: usize },
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+/// Create the `.npy` preamble (magic, version, header length and header) for
+/// the given type description and shape.
+fn make_preamble(descr: &str, shape: &[usize]) -> Vec<u8> {
+    make_padded_preamble(descr, shape, 0)
+}
+
+/// Create the `.npy` preamble for the given type description and shape,
+/// padding the header so that the preamble is at least `min_len` bytes long.
+fn make_padded_preamble(descr: &str, shape: &[usize], min_len: usize) -> Vec<u8> {
+    let shape_str = match shape {
+        [n] => format!("({n},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header =
+        format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape_str}, }}");
+    // The magic (6 bytes), version (2 bytes) and header length (2 bytes) are
+    // followed by the header, which is padded with spaces and terminated with
+    // a newline.
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    let padded = unpadded.max(min_len).div_ceil(HEADER_ALIGN) * HEADER_ALIGN;
+    let padding = padded - unpadded;
+    header.extend(std::iter::repeat(' ').take(padding));
+    header.push('\n');
+
+    let mut preamble = Vec::with_capacity(MAGIC.len() + 4 + header.len());
+    preamble.extend_from_slice(MAGIC);
+    preamble.extend_from_slice(&[1, 0]);
+    preamble.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    preamble.extend_from_slice(header.as_bytes());
+    preamble
+}
+
+/// Write `data` to `writer` as a `.npy` array with the given shape. The
+/// product of the shape's dimensions must equal the length of `data`.
+pub fn write_npy<T: NpyElement, W: Write>(
+    writer: &mut W,
+    shape: &[usize],
+    data: &[T],
+) -> Result<(), NpyError> {
+    let expected = shape.iter().product();
+    if data.len() != expected {
+        return Err(NpyError::ShapeMismatch {
+            shape: shape.to_vec(),
+            expected,
+            got: data.len(),
+        });
+    }
+
+    writer.write_all(&make_preamble(T::DESCR, shape))?;
+    writer.write_all(&to_le_bytes(data))?;
+    Ok(())
+}
+
+/// Write a `.npy` array in pieces, without knowing its length in advance. The
+/// array's first dimension grows as data is written; the other dimensions are
+/// fixed. The header is written again with the final shape by
+/// [`NpyWriter::finish`], so the file is only valid after that.
+pub struct NpyWriter<T: NpyElement, W: Write + Seek> {
+    writer: W,
+    /// The dimensions after the first.
+    trailing_shape: Vec<usize>,
+    /// The position of the start of the array in `writer`.
+    start: u64,
+    /// The length of the preamble, which is fixed.
+    preamble_len: usize,
+    /// The number of elements written.
+    num_elements: usize,
+    _element: PhantomData<T>,
+}
+
+impl<T: NpyElement, W: Write + Seek> NpyWriter<T, W> {
+    /// Start writing a `.npy` array whose dimensions after the first are
+    /// `trailing_shape` (which may be empty) at the current position of
+    /// `writer`.
+    pub fn new(mut writer: W, trailing_shape: &[usize]) -> Result<NpyWriter<T, W>, NpyError> {
+        // Leave enough room in the header for any length.
+        let preamble_len = make_preamble(T::DESCR, &shape_with(usize::MAX, trailing_shape)).len();
+        let start = writer.stream_position()?;
+        writer.write_all(&make_padded_preamble(
+            T::DESCR,
+            &shape_with(0, trailing_shape),
+            preamble_len,
+        ))?;
+        Ok(NpyWriter {
+            writer,
+            trailing_shape: trailing_shape.to_vec(),
+            start,
+            preamble_len,
+            num_elements: 0,
+            _element: PhantomData,
+        })
+    }
+
+    /// Write more of the array. The length of `data` must be a multiple of
+    /// the product of the trailing dimensions.
+    pub fn write(&mut self, data: &[T]) -> Result<(), NpyError> {
+        let row_len: usize = self.trailing_shape.iter().product();
+        if data.len() % row_len.max(1) != 0 || (row_len == 0 && !data.is_empty()) {
+            return Err(NpyError::PartialRow {
+                row_len,
+                got: data.len(),
+            });
+        }
+        self.writer.write_all(&to_le_bytes(data))?;
+        self.num_elements += data.len();
+        Ok(())
+    }
+
+    /// Finish the array by writing its final shape, returning the writer
+    /// (positioned after the array).
+    pub fn finish(mut self) -> Result<W, NpyError> {
+        let row_len: usize = self.trailing_shape.iter().product();
+        let shape = shape_with(self.num_elements / row_len.max(1), &self.trailing_shape);
+        let preamble = make_padded_preamble(T::DESCR, &shape, self.preamble_len);
+        debug_assert_eq!(preamble.len(), self.preamble_len);
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        self.writer.write_all(&preamble)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Prepend `first` to `trailing_shape`.
+fn shape_with(first: usize, trailing_shape: &[usize]) -> Vec<usize> {
+    std::iter::once(first)
+        .chain(trailing_shape.iter().copied())
+        .collect()
+}
+
+/// Read a `.npy` array of `f32` or `f64` values, returning its shape and the
+/// (C-ordered) values as `f64`.
+pub fn read_npy_f64<R: Read>(reader: &mut R) -> Result<(Vec<usize>, Vec<f64>), NpyError> {
+    let mut magic = [0; 6];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(NpyError::BadMagic);
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let header_len = match version {
+        [1, 0] => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            usize::from(u16::from_le_bytes(len))
+        }
+        [2 | 3, 0] => {
+            let mut len = [0; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        [major, minor] => return Err(NpyError::UnsupportedVersion(major, minor)),
+    };
+    let mut header = vec![0; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8_lossy(&header);
+
+    let descr = get_header_value(&header, "descr")?
+        .split(['\'', '"'])
+        .nth(1)
+        .ok_or_else(|| NpyError::BadHeader(header.to_string()))?
+        .to_string();
+    if get_header_value(&header, "fortran_order")?.starts_with("True") {
+        return Err(NpyError::FortranOrder);
+    }
+    let shape_str = get_header_value(&header, "shape")?;
+    let shape_str = shape_str
+        .strip_prefix('(')
+        .and_then(|s| s.split(')').next())
+        .ok_or_else(|| NpyError::BadHeader(header.to_string()))?;
+    let shape = shape_str
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .map_err(|_| NpyError::BadHeader(header.to_string()))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    let num_elements = shape.iter().product();
+
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let data: Vec<f64> = match descr.as_str() {
+        "<f8" => bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        "<f4" => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+            .collect(),
+        _ => return Err(NpyError::UnsupportedDescr(descr)),
+    };
+    if data.len() != num_elements {
+        return Err(NpyError::ShapeMismatch {
+            shape,
+            expected: num_elements,
+            got: data.len(),
+        });
+    }
+
+    Ok((shape, data))
+}
+
+/// Get the (unparsed) value associated with a key in a `.npy` header
+/// dictionary.
+fn get_header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, NpyError> {
+    let pattern = format!("'{key}':");
+    header
+        .find(&pattern)
+        .map(|i| header[i + pattern.len()..].trim_start())
+        .ok_or_else(|| NpyError::BadHeader(header.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preamble_is_aligned() {
+        for shape in [vec![1], vec![10, 2], vec![3, 100, 4], vec![123456, 7]] {
+            let preamble = make_preamble("<f8", &shape);
+            assert_eq!(preamble.len() % HEADER_ALIGN, 0);
+            assert_eq!(*preamble.last().unwrap(), b'\n');
+        }
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() {
+        let data = [1.0, -2.5, 3.25, 1e-300, f64::MAX, 0.0];
+        let mut buf = vec![];
+        write_npy(&mut buf, &[3, 2], &data).unwrap();
+
+        let (shape, read) = read_npy_f64(&mut buf.as_slice()).unwrap();
+        assert_eq!(shape, [3, 2]);
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn test_read_f32() {
+        let data = [1.0f32, 2.0, 3.0];
+        let mut buf = vec![];
+        write_npy(&mut buf, &[3], &data).unwrap();
+
+        let (shape, read) = read_npy_f64(&mut buf.as_slice()).unwrap();
+        assert_eq!(shape, [3]);
+        assert_eq!(read, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        let mut buf = vec![];
+        let result = write_npy(&mut buf, &[2, 2], &[1.0, 2.0, 3.0]);
+        assert!(matches!(
+            result,
+            Err(NpyError::ShapeMismatch {
+                expected: 4,
+                got: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_npy_writer() {
+        let mut buf = std::io::Cursor::new(vec![]);
+        let mut writer = NpyWriter::<f64, _>::new(&mut buf, &[2]).unwrap();
+        writer.write(&[1.0, 2.0]).unwrap();
+        writer.write(&[]).unwrap();
+        writer.write(&[3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert!(matches!(
+            writer.write(&[7.0]),
+            Err(NpyError::PartialRow { row_len: 2, got: 1 })
+        ));
+        writer.finish().unwrap();
+
+        let buf = buf.into_inner();
+        let (shape, read) = read_npy_f64(&mut buf.as_slice()).unwrap();
+        assert_eq!(shape, [3, 2]);
+        assert_eq!(read, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        // The header is the same as that of an array written all at once,
+        // except for padding.
+        assert_eq!((buf.len() - 48) % HEADER_ALIGN, 0);
+
+        // Empty arrays are fine too.
+        let mut buf = std::io::Cursor::new(vec![]);
+        NpyWriter::<f32, _>::new(&mut buf, &[])
+            .unwrap()
+            .finish()
+            .unwrap();
+        let (shape, read) = read_npy_f64(&mut buf.into_inner().as_slice()).unwrap();
+        assert_eq!(shape, [0]);
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let result = read_npy_f64(&mut b"\x93NUMPZ\x01\x00".as_slice());
+        assert!(matches!(result, Err(NpyError::BadMagic)));
+    }
+}
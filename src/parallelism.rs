@@ -0,0 +1,121 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Controlling the threads used by beam calculations.
+//!
+//! By default, beam calculations are spread over rayon's global thread pool,
+//! whose size can only be controlled with `RAYON_NUM_THREADS`. Programs with
+//! their own thread pools (or that run many processes per node, e.g. with MPI)
+//! can instead give each beam object its own [`Parallelism`].
+
+use std::sync::Arc;
+
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+/// The threads that a beam object uses for its calculations. See
+/// [`FEEBeam::set_parallelism`](crate::fee::FEEBeam::set_parallelism) and
+/// [`AnalyticBeam::set_parallelism`](crate::analytic::AnalyticBeam::set_parallelism).
+#[derive(Debug, Clone, Default)]
+pub enum Parallelism {
+    /// Use rayon's global thread pool (the default). The number of threads can
+    /// be controlled by setting `RAYON_NUM_THREADS`.
+    #[default]
+    Global,
+
+    /// Use a thread pool with this many threads, owned by the beam object. If
+    /// this is 0, rayon picks the number of threads (usually the number of
+    /// CPU cores).
+    Threads(usize),
+
+    /// Use the given thread pool, which may be shared with other beam objects
+    /// or the rest of the program.
+    Pool(Arc<ThreadPool>),
+
+    /// Do all calculations on a single thread. The thread belongs to the beam
+    /// object, so the calculations never use more than one CPU core and never
+    /// wait for other work in rayon's global thread pool.
+    Serial,
+}
+
+impl Parallelism {
+    /// Get the [`Parallelism`] for a number of threads, as used by the C and
+    /// Python interfaces: 0 is [`Parallelism::Global`], 1 is
+    /// [`Parallelism::Serial`] and anything else is
+    /// [`Parallelism::Threads`].
+    pub fn from_num_threads(num_threads: usize) -> Parallelism {
+        match num_threads {
+            0 => Parallelism::Global,
+            1 => Parallelism::Serial,
+            n => Parallelism::Threads(n),
+        }
+    }
+}
+
+/// A [`Parallelism`] with its thread pool, if any.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ThreadControl {
+    parallelism: Parallelism,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl ThreadControl {
+    /// Create the thread pool needed by `parallelism`.
+    pub(crate) fn new(parallelism: Parallelism) -> Result<ThreadControl, ThreadPoolBuildError> {
+        let pool = match &parallelism {
+            Parallelism::Global => None,
+            Parallelism::Threads(num_threads) => Some(Arc::new(build_pool(*num_threads)?)),
+            Parallelism::Pool(pool) => Some(Arc::clone(pool)),
+            Parallelism::Serial => Some(Arc::new(build_pool(1)?)),
+        };
+        Ok(ThreadControl { parallelism, pool })
+    }
+
+    pub(crate) fn get(&self) -> &Parallelism {
+        &self.parallelism
+    }
+
+    /// Run `op` so that any parallel work inside it uses these threads.
+    pub(crate) fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
+
+fn build_pool(num_threads: usize) -> Result<ThreadPool, ThreadPoolBuildError> {
+    ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|i| format!("hyperbeam-{i}"))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_control() {
+        let threads = ThreadControl::new(Parallelism::from_num_threads(1)).unwrap();
+        assert!(matches!(threads.get(), Parallelism::Serial));
+        assert_eq!(threads.install(rayon::current_num_threads), 1);
+        let name = threads.install(|| std::thread::current().name().map(|n| n.to_string()));
+        assert_eq!(name.as_deref(), Some("hyperbeam-0"));
+
+        let threads = ThreadControl::new(Parallelism::from_num_threads(3)).unwrap();
+        assert_eq!(threads.install(rayon::current_num_threads), 3);
+
+        let pool = Arc::new(ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        let threads = ThreadControl::new(Parallelism::Pool(Arc::clone(&pool))).unwrap();
+        assert_eq!(threads.install(rayon::current_num_threads), 2);
+        assert!(threads.install(|| pool.current_thread_index().is_some()));
+
+        let threads = ThreadControl::new(Parallelism::from_num_threads(0)).unwrap();
+        assert!(matches!(threads.get(), Parallelism::Global));
+        assert_eq!(
+            threads.install(rayon::current_num_threads),
+            rayon::current_num_threads()
+        );
+    }
+}
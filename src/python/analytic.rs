@@ -4,18 +4,25 @@
 
 //! Python interface to hyperbeam analytic beam code.
 
+#[cfg(any(feature = "cuda", feature = "hip"))]
 use self::ndarray::prelude::*;
-use marlu::c64;
+use marlu::{c64, Jones};
 use numpy::*;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use pyo3::types::PyType;
 
-use crate::analytic::{AnalyticBeam as AnalyticBeamRust, AnalyticType};
+use super::{calc_in_chunks, get_directions, jones_to_pyarray, thread_pool_err, Directions};
+use crate::{
+    analytic::{AnalyticBeam as AnalyticBeamRust, AnalyticType},
+    Parallelism,
+};
 #[cfg(any(feature = "cuda", feature = "hip"))]
 use crate::{GpuComplex, GpuFloat};
 
 /// A Python class interfacing with the hyperbeam analytic beam code written in
 /// Rust.
-#[pyclass]
+#[pyclass(module = "mwa_hyperbeam")]
 pub(super) struct AnalyticBeam {
     beam: AnalyticBeamRust,
 }
@@ -49,13 +56,85 @@ impl AnalyticBeam {
         }
     }
 
+    /// Support pickling; the beam is reconstructed from its configuration.
+    fn __reduce__(slf: &Bound<'_, Self>) -> (Py<PyType>, (bool, f64, u8)) {
+        let beam = &slf.borrow().beam;
+        (
+            slf.get_type().unbind(),
+            (
+                matches!(beam.get_beam_type(), AnalyticType::Rts),
+                beam.get_dipole_height(),
+                beam.get_bowties_per_row(),
+            ),
+        )
+    }
+
+    /// Whether this beam has RTS behaviour (otherwise it has mwa_pb
+    /// behaviour).
+    #[getter]
+    fn rts_behaviour(&self) -> bool {
+        matches!(self.beam.get_beam_type(), AnalyticType::Rts)
+    }
+
+    /// The height of the MWA dipoles used by this beam [metres].
+    #[getter]
+    fn dipole_height(&self) -> f64 {
+        self.beam.get_dipole_height()
+    }
+
+    /// The number of bowties in a row of a tile used by this beam.
+    #[getter]
+    fn bowties_per_row(&self) -> u8 {
+        self.beam.get_bowties_per_row()
+    }
+
+    /// The analytic beam doesn't cache anything, so this does nothing. It
+    /// exists so that the analytic and FEE beams have the same interface.
+    fn empty_cache(&self) {
+        self.beam.empty_cache()
+    }
+
+    /// The analytic beam can be used at any frequency, so unlike
+    /// `FEEBeam.get_beam_freqs`, this returns an empty array. It exists so
+    /// that the analytic and FEE beams have the same interface.
+    fn get_beam_freqs<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u32>> {
+        PyArray1::from_vec_bound(py, vec![])
+    }
+
+    /// The analytic beam can be used at any frequency, so unlike
+    /// `FEEBeam.closest_freq`, this returns the given frequency (rounded to
+    /// the nearest Hz).
+    #[pyo3(text_signature = "(freq_hz)")]
+    fn closest_freq(&self, freq_hz: f64) -> u32 {
+        freq_hz.round() as _
+    }
+
+    /// Set the number of threads used to calculate beam responses on the CPU.
+    /// 0 (the default) uses a pool of threads shared by all beam objects,
+    /// whose size can be set with the `RAYON_NUM_THREADS` environment
+    /// variable. 1 does all calculations on a single thread, and any other
+    /// number gives the beam its own pool with that many threads. This setting
+    /// isn't pickled.
+    #[pyo3(signature = (num_threads=0))]
+    fn set_num_threads(&mut self, num_threads: usize) -> PyResult<()> {
+        self.beam
+            .set_parallelism(Parallelism::from_num_threads(num_threads))
+            .map_err(thread_pool_err)
+    }
+
     /// Calculate the Jones matrix for a single direction given a pointing.
     /// `delays` must have `bowties_per_row * bowties_per_row` ints (which
     /// was declared when `AnalyticBeam` was created), whereas `amps` can have
     /// this number or double; if the former is given, then  these map 1:1
     /// with bowties. If double are given, then the *smallest* of the two amps
     /// corresponding to a bowtie's dipoles is used.
-    #[pyo3(signature = (az_rad, za_rad, freq_hz, delays, amps, latitude_rad, norm_to_zenith=None))]
+    ///
+    /// `latitude_rad` must be given for RTS-style beams; mwa_pb-style beams
+    /// don't use it. If `iau_order` is true, the Jones matrix is arranged
+    /// [NS-NS NS-EW EW-NS EW-EW], otherwise it's left in the order of the beam
+    /// type (mwa_pb-style responses have the EW dipole first and RTS-style
+    /// responses the NS dipole first).
+    #[pyo3(signature = (az_rad, za_rad, freq_hz, delays, amps, latitude_rad=None, norm_to_zenith=None, iau_order=None))]
     #[allow(clippy::too_many_arguments)]
     fn calc_jones<'py>(
         &self,
@@ -65,10 +144,12 @@ impl AnalyticBeam {
         freq_hz: f64,
         delays: Vec<u32>,
         amps: Vec<f64>,
-        latitude_rad: f64,
+        latitude_rad: Option<f64>,
         norm_to_zenith: Option<bool>,
+        iau_order: Option<bool>,
     ) -> PyResult<Bound<'py, PyArray1<c64>>> {
-        let jones = self.beam.calc_jones_pair(
+        let latitude_rad = self.beam.get_latitude(latitude_rad)?;
+        let mut jones = self.beam.calc_jones_pair(
             az_rad,
             za_rad,
             // hyperbeam expects an int for the frequency. By specifying that
@@ -80,6 +161,10 @@ impl AnalyticBeam {
             latitude_rad,
             norm_to_zenith.unwrap_or(false),
         )?;
+        if iau_order.unwrap_or(false) {
+            self.beam
+                .set_jones_order(true, std::slice::from_mut(&mut jones));
+        }
         let jones_py: Vec<c64> = jones.iter().map(|c| c64::new(c.re, c.im)).collect();
         let np_array = PyArray1::from_vec_bound(py, jones_py);
         Ok(np_array)
@@ -87,48 +172,143 @@ impl AnalyticBeam {
 
     /// Calculate the Jones matrices for multiple directions given a pointing.
     /// Each direction is calculated in parallel by Rust. The number of parallel
-    /// threads used can be controlled by setting RAYON_NUM_THREADS. `delays`
+    /// threads used can be controlled with `set_num_threads`. `delays`
     /// must have `bowties_per_row * bowties_per_row` ints (which was declared
     /// when `AnalyticBeam` was created), whereas `amps` can have this number
     /// or double; if the former is given, then  these map 1:1 with bowties. If
     /// double are given, then the *smallest* of the two amps corresponding to a
     /// bowtie's dipoles is used.
-    #[pyo3(signature = (az_rad, za_rad, freq_hz, delays, amps, latitude_rad, norm_to_zenith=None))]
+    ///
+    /// `az_rad` and `za_rad` can have any shape (e.g. that of an image), as
+    /// long as they're the same. The returned array has the shape of the
+    /// directions with an extra axis of length 4, or two extra axes of length
+    /// 2 if `as_matrices` is true. Float64 arrays are used without copying.
+    #[pyo3(signature = (az_rad, za_rad, freq_hz, delays, amps, latitude_rad=None, norm_to_zenith=None, as_matrices=None, iau_order=None))]
     #[allow(clippy::too_many_arguments)]
     fn calc_jones_array<'py>(
         &self,
         py: Python<'py>,
-        az_rad: Vec<f64>,
-        za_rad: Vec<f64>,
+        az_rad: Directions<'py>,
+        za_rad: Directions<'py>,
         freq_hz: f64,
         delays: Vec<u32>,
         amps: Vec<f64>,
-        latitude_rad: f64,
+        latitude_rad: Option<f64>,
         norm_to_zenith: Option<bool>,
-    ) -> PyResult<Bound<'py, PyArray2<c64>>> {
-        let jones = self.beam.calc_jones_array_pair(
+        as_matrices: Option<bool>,
+        iau_order: Option<bool>,
+    ) -> PyResult<Bound<'py, PyArrayDyn<c64>>> {
+        let latitude_rad = self.beam.get_latitude(latitude_rad)?;
+        let shape = az_rad.shape().to_vec();
+        let (az_rad, za_rad) = get_directions(&az_rad, &za_rad)?;
+        let mut jones = vec![Jones::default(); az_rad.len()];
+        calc_in_chunks(
+            py,
             &az_rad,
             &za_rad,
-            freq_hz.round() as _,
-            &delays,
-            &amps,
-            latitude_rad,
-            norm_to_zenith.unwrap_or(false),
+            &mut jones,
+            |az_rad, za_rad, results| {
+                self.beam.calc_jones_array_pair_inner(
+                    az_rad,
+                    za_rad,
+                    freq_hz.round() as _,
+                    &delays,
+                    &amps,
+                    latitude_rad,
+                    norm_to_zenith.unwrap_or(false),
+                    results,
+                )
+            },
         )?;
+        if iau_order.unwrap_or(false) {
+            self.beam.set_jones_order(true, &mut jones);
+        }
+        Ok(jones_to_pyarray(
+            py,
+            jones,
+            &shape,
+            as_matrices.unwrap_or(false),
+        ))
+    }
 
-        // Convert to a 2D array of c64 from Jones (one row per beam response).
-        // Use unsafe code to ensure that no useless copying is done!
-        // https://users.rust-lang.org/t/sound-conversion-from-vec-num-complex-complex64-4-to-ndarray-array2-num-complex-complex64-without-copying/78973/2
-        let mut jones = std::mem::ManuallyDrop::new(jones);
+    /// Calculate the Jones matrices for multiple directions given a pointing
+    /// and multiple frequencies on the CPU. This has the same interface and
+    /// output layout as `calc_jones_gpu`, i.e. the returned array has shape
+    /// `(tiles, freqs, *dirs, 4)` (or `(tiles, freqs, *dirs, 2, 2)` if
+    /// `as_matrices` is true).
+    ///
+    /// `delays_array` and `amps_array` must have the same number of rows;
+    /// these correspond to tile configurations (i.e. each tile is allowed
+    /// to have distinct delays and amps). The number of elements per row of
+    /// `delays_array` and `amps_array` have the same restrictions as `delays`
+    /// and `amps` in `calc_jones`. `az_rad` and `za_rad` can have any shape,
+    /// as long as they're the same.
+    #[pyo3(signature = (az_rad, za_rad, freqs_hz, delays_array, amps_array, latitude_rad=None, norm_to_zenith=None, as_matrices=None, iau_order=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn calc_jones_cpu<'py>(
+        &self,
+        py: Python<'py>,
+        az_rad: Directions<'py>,
+        za_rad: Directions<'py>,
+        freqs_hz: Vec<f64>,
+        delays_array: PyArrayLike2<'py, u32, AllowTypeChange>,
+        amps_array: PyArrayLike2<'py, f64, AllowTypeChange>,
+        latitude_rad: Option<f64>,
+        norm_to_zenith: Option<bool>,
+        as_matrices: Option<bool>,
+        iau_order: Option<bool>,
+    ) -> PyResult<Bound<'py, PyArrayDyn<c64>>> {
+        let latitude_rad = self.beam.get_latitude(latitude_rad)?;
+        let dir_shape = az_rad.shape().to_vec();
+        let (az_rad, za_rad) = get_directions(&az_rad, &za_rad)?;
+        let delays = delays_array.as_array();
+        let amps = amps_array.as_array();
+        let delays = delays.as_standard_layout();
+        let amps = amps.as_standard_layout();
+        if delays.nrows() != amps.nrows() {
+            return Err(PyValueError::new_err(format!(
+                "delays_array and amps_array must have the same number of rows; got {} and {}",
+                delays.nrows(),
+                amps.nrows()
+            )));
+        }
 
-        let old_len = jones.len();
-        let new_len = old_len * 4;
-        let new_cap = jones.capacity() * 4;
-        let new_ptr = jones.as_mut_ptr() as *mut c64;
-        // SAFETY: new_cap == old_cap * N, align_of::<C64>() == align_of::<Jones>()
-        let flat = unsafe { Vec::from_raw_parts(new_ptr, new_len, new_cap) };
-        let a2 = Array2::from_shape_vec((old_len, 4), flat).unwrap();
-        Ok(a2.into_pyarray_bound(py))
+        let num_dirs = az_rad.len();
+        let mut jones = vec![Jones::default(); delays.nrows() * freqs_hz.len() * num_dirs];
+        let mut chunks = jones.chunks_exact_mut(num_dirs.max(1));
+        for (tile_delays, tile_amps) in delays.outer_iter().zip(amps.outer_iter()) {
+            for &freq_hz in &freqs_hz {
+                // An empty set of directions has nothing to calculate.
+                let Some(results) = chunks.next() else { break };
+                let tile_delays = tile_delays.as_slice().expect("is contiguous");
+                let tile_amps = tile_amps.as_slice().expect("is contiguous");
+                calc_in_chunks(py, &az_rad, &za_rad, results, |az_rad, za_rad, results| {
+                    self.beam.calc_jones_array_pair_inner(
+                        az_rad,
+                        za_rad,
+                        freq_hz.round() as _,
+                        tile_delays,
+                        tile_amps,
+                        latitude_rad,
+                        norm_to_zenith.unwrap_or(false),
+                        results,
+                    )
+                })?;
+            }
+        }
+
+        if iau_order.unwrap_or(false) {
+            self.beam.set_jones_order(true, &mut jones);
+        }
+
+        let mut shape = vec![delays.nrows(), freqs_hz.len()];
+        shape.extend(dir_shape);
+        Ok(jones_to_pyarray(
+            py,
+            jones,
+            &shape,
+            as_matrices.unwrap_or(false),
+        ))
     }
 
     /// Calculate the Jones matrices for multiple directions given a pointing
@@ -140,7 +320,7 @@ impl AnalyticBeam {
     /// `delays_array` and `amps_array` have the same restrictions as `delays`
     /// and `amps` in `calc_jones`.
     #[cfg(any(feature = "cuda", feature = "hip"))]
-    #[pyo3(signature = (az_rad, za_rad, freqs_hz, delays_array, amps_array, latitude_rad, norm_to_zenith=None))]
+    #[pyo3(signature = (az_rad, za_rad, freqs_hz, delays_array, amps_array, latitude_rad=None, norm_to_zenith=None, iau_order=None))]
     #[allow(clippy::too_many_arguments)]
     fn calc_jones_gpu<'py>(
         &self,
@@ -150,9 +330,11 @@ impl AnalyticBeam {
         freqs_hz: Vec<f64>,
         delays_array: Vec<u32>,
         amps_array: Vec<f64>,
-        latitude_rad: f64,
+        latitude_rad: Option<f64>,
         norm_to_zenith: Option<bool>,
+        iau_order: Option<bool>,
     ) -> PyResult<Bound<'py, PyArray4<GpuComplex>>> {
+        let latitude_rad = self.beam.get_latitude(latitude_rad)?;
         // hyperbeam expects ints for the frequencies. Convert them to make sure
         // everything's OK.
         let freqs: Vec<u32> = freqs_hz.iter().map(|&f| f.round() as _).collect();
@@ -170,13 +352,17 @@ impl AnalyticBeam {
         let zas: Vec<_> = za_rad.into_iter().map(|f| f as _).collect();
 
         let gpu_beam = unsafe { self.beam.gpu_prepare(delays.view(), amps.view())? };
-        let jones = gpu_beam.calc_jones_pair(
+        let mut jones = gpu_beam.calc_jones_pair(
             &azs,
             &zas,
             &freqs,
             latitude_rad as GpuFloat,
             norm_to_zenith.unwrap_or(false),
         )?;
+        if iau_order.unwrap_or(false) {
+            self.beam
+                .set_jones_order(true, jones.as_slice_mut().expect("is contiguous"));
+        }
 
         // Convert to a 4D array of Complex from Jones.
         // Use unsafe code to ensure that no useless copying is done!
@@ -6,43 +6,106 @@
 
 use std::path::PathBuf;
 
+#[cfg(any(feature = "cuda", feature = "hip"))]
 use self::ndarray::prelude::*;
+use marlu::Jones;
 use num_complex::Complex64 as c64;
 use numpy::*;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBytes, PyDict, PyList};
 
-use crate::fee::FEEBeam as FEEBeamRust;
+use super::{calc_in_chunks, get_directions, jones_to_pyarray, thread_pool_err, Directions};
 #[cfg(any(feature = "cuda", feature = "hip"))]
 use crate::GpuComplex;
+use crate::{
+    fee::{FEEBeam as FEEBeamRust, FreqPrecedence},
+    Parallelism,
+};
 
 /// A Python class interfacing with the hyperbeam FEE beam code written in Rust.
-#[pyclass]
+#[pyclass(module = "mwa_hyperbeam")]
 #[allow(clippy::upper_case_acronyms)]
 pub(super) struct FEEBeam {
     beam: FEEBeamRust,
+    /// How overlapping frequencies were handled when the beam was made from
+    /// several files; this is needed to pickle the beam.
+    precedence: FreqPrecedence,
 }
 
 #[pymethods]
 impl FEEBeam {
     /// Create a new `FEEBeam` object. This object is used for all beam
-    /// calculations. If the path to the beam HDF5 file is not given, then the
-    /// `MWA_BEAM_FILE` environment variable is used.
+    /// calculations. `hdf5_file` can be the path to the beam HDF5 file, the
+    /// contents of the file as `bytes`, or a file-like object (e.g.
+    /// `io.BytesIO`) from which the contents are read. If it is not given,
+    /// then the file is searched for; first the `MWA_BEAM_FILE` environment
+    /// variable is used, then the XDG data directories (e.g.
+    /// `~/.local/share/mwa_hyperbeam`).
     #[new]
     #[pyo3(signature = (hdf5_file))]
-    fn new(hdf5_file: Option<PyObject>) -> PyResult<Self> {
+    fn new(hdf5_file: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
         let strct = match hdf5_file {
             Some(f) => {
-                let result = Python::with_gil(|py| {
-                    let f: PathBuf = f
-                        .extract(py)
-                        .expect("can convert python string to rust path");
-                    FEEBeamRust::new(f)
-                });
-                result?
+                if let Ok(bytes) = f.downcast::<PyBytes>() {
+                    FEEBeamRust::from_bytes(bytes.as_bytes())?
+                } else if f.hasattr("read")? {
+                    let contents = f.call_method0("read")?;
+                    FEEBeamRust::from_bytes(contents.downcast::<PyBytes>()?.as_bytes())?
+                } else {
+                    let f: PathBuf = f.extract()?;
+                    FEEBeamRust::new(f)?
+                }
             }
-            None => FEEBeamRust::new_from_env()?,
+            None => FEEBeamRust::new_from_search::<PathBuf>(&[])?,
+        };
+        Ok(FEEBeam {
+            beam: strct,
+            precedence: FreqPrecedence::Error,
+        })
+    }
+
+    /// Create a new `FEEBeam` object from several FEE beam HDF5 files, e.g. a
+    /// simulation delivered as one file per band. The frequencies of all
+    /// files are available. `precedence` determines what happens if more than
+    /// one file defines a frequency: "error" (the default) raises an
+    /// exception, whereas "first" or "last" use the first or last file (in the
+    /// order given) defining the frequency. See `freq_overlaps`.
+    #[staticmethod]
+    #[pyo3(signature = (hdf5_files, precedence=None))]
+    fn new_multi(hdf5_files: Vec<PathBuf>, precedence: Option<&str>) -> PyResult<Self> {
+        let precedence = match precedence {
+            Some(p) => p.parse().map_err(PyValueError::new_err)?,
+            None => FreqPrecedence::Error,
+        };
+        Ok(FEEBeam {
+            beam: FEEBeamRust::new_multi(&hdf5_files, precedence)?,
+            precedence,
+        })
+    }
+
+    /// Support pickling; the beam is reconstructed from the path to its HDF5
+    /// file (which must also be available wherever the beam is unpickled), or
+    /// if the beam was read from memory, the contents of the file. Cached
+    /// coefficients are not pickled; they are recalculated when needed.
+    fn __reduce__(slf: &Bound<'_, Self>) -> PyResult<(PyObject, PyObject)> {
+        let py = slf.py();
+        let this = slf.borrow();
+        let beam = &this.beam;
+        let paths = beam.get_file_paths();
+        if paths.len() > 1 {
+            return Ok((
+                slf.get_type().getattr("new_multi")?.unbind(),
+                (paths.to_vec(), this.precedence.as_str()).to_object(py),
+            ));
+        }
+        let arg = match beam.get_file_path() {
+            Some(path) => path.to_object(py),
+            None => PyBytes::new_bound(py, &beam.to_bytes()?)
+                .into_any()
+                .unbind(),
         };
-        Ok(FEEBeam { beam: strct })
+        Ok((slf.get_type().into_any().unbind(), (arg,).to_object(py)))
     }
 
     /// Calculate the beam-response Jones matrix for a given direction and
@@ -100,7 +163,12 @@ impl FEEBeam {
     /// Calculate the beam-response Jones matrices for many directions given a
     /// pointing. This is basically a wrapper around `calc_jones` that
     /// efficiently calculates the Jones matrices in parallel. The number of
-    /// parallel threads used can be controlled by setting `RAYON_NUM_THREADS`
+    /// parallel threads used can be controlled with `set_num_threads`.
+    ///
+    /// `az_rad` and `za_rad` can have any shape (e.g. that of an image), as
+    /// long as they're the same. The returned array has the shape of the
+    /// directions with an extra axis of length 4, or two extra axes of length
+    /// 2 if `as_matrices` is true. Float64 arrays are used without copying.
     ///
     /// `delays` and `amps` apply to each dipole in an MWA tile in the M&C
     /// order; see
@@ -109,52 +177,144 @@ impl FEEBeam {
     /// elements; if 16 are given, then these map 1:1 with dipoles, otherwise
     /// the first 16 are for X dipole elements, and the next 16 are for Y.
     #[pyo3(
-        signature = (az_rad, za_rad, freq_hz, delays, amps, norm_to_zenith, latitude_rad=None, iau_order=None)
+        signature = (az_rad, za_rad, freq_hz, delays, amps, norm_to_zenith, latitude_rad=None, iau_order=None, as_matrices=None)
     )]
     #[allow(clippy::too_many_arguments)]
     fn calc_jones_array<'py>(
         &self,
         py: Python<'py>,
-        az_rad: Vec<f64>,
-        za_rad: Vec<f64>,
+        az_rad: Directions<'py>,
+        za_rad: Directions<'py>,
         freq_hz: f64,
         delays: [u32; 16],
         amps: Vec<f64>,
         norm_to_zenith: bool,
         latitude_rad: Option<f64>,
         iau_order: Option<bool>,
-    ) -> PyResult<Bound<'py, PyArray2<c64>>> {
-        let jones = self.beam.calc_jones_array_pair(
+        as_matrices: Option<bool>,
+    ) -> PyResult<Bound<'py, PyArrayDyn<c64>>> {
+        let shape = az_rad.shape().to_vec();
+        let (az_rad, za_rad) = get_directions(&az_rad, &za_rad)?;
+        let mut jones = vec![Jones::default(); az_rad.len()];
+        calc_in_chunks(
+            py,
             &az_rad,
             &za_rad,
-            freq_hz.round() as _,
-            &delays,
-            &amps,
-            norm_to_zenith,
-            latitude_rad,
-            iau_order.unwrap_or(false),
+            &mut jones,
+            |az_rad, za_rad, results| {
+                self.beam.calc_jones_array_pair_inner(
+                    az_rad,
+                    za_rad,
+                    freq_hz.round() as _,
+                    &delays,
+                    &amps,
+                    norm_to_zenith,
+                    latitude_rad,
+                    iau_order.unwrap_or(false),
+                    results,
+                )
+            },
         )?;
+        Ok(jones_to_pyarray(
+            py,
+            jones,
+            &shape,
+            as_matrices.unwrap_or(false),
+        ))
+    }
 
-        // Convert to a 2D array of c64 from Jones (one row per beam response).
-        // Use unsafe code to ensure that no useless copying is done!
-        // https://users.rust-lang.org/t/sound-conversion-from-vec-num-complex-complex64-4-to-ndarray-array2-num-complex-complex64-without-copying/78973/2
-        let mut jones = std::mem::ManuallyDrop::new(jones);
+    /// Calculate the Jones matrices for multiple directions given a pointing
+    /// and multiple frequencies on the CPU. This has the same interface and
+    /// output layout as `calc_jones_gpu`, i.e. the returned array has shape
+    /// `(tiles, freqs, *dirs, 4)` (or `(tiles, freqs, *dirs, 2, 2)` if
+    /// `as_matrices` is true).
+    ///
+    /// `delays_array` and `amps_array` must have the same number of rows; these
+    /// correspond to tile configurations (i.e. each tile is allowed to have
+    /// distinct delays and amps). `delays_array` must have 16 elements per row,
+    /// but `amps_array` can have 16 or 32 elements per row (see `calc_jones`
+    /// for an explanation). `az_rad` and `za_rad` can have any shape, as long
+    /// as they're the same.
+    #[pyo3(
+        signature = (az_rad, za_rad, freqs_hz, delays_array, amps_array, norm_to_zenith, latitude_rad=None, iau_order=None, as_matrices=None)
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn calc_jones_cpu<'py>(
+        &self,
+        py: Python<'py>,
+        az_rad: Directions<'py>,
+        za_rad: Directions<'py>,
+        freqs_hz: Vec<f64>,
+        delays_array: PyArrayLike2<'py, u32, AllowTypeChange>,
+        amps_array: PyArrayLike2<'py, f64, AllowTypeChange>,
+        norm_to_zenith: bool,
+        latitude_rad: Option<f64>,
+        iau_order: Option<bool>,
+        as_matrices: Option<bool>,
+    ) -> PyResult<Bound<'py, PyArrayDyn<c64>>> {
+        let dir_shape = az_rad.shape().to_vec();
+        let (az_rad, za_rad) = get_directions(&az_rad, &za_rad)?;
+        let delays = delays_array.as_array();
+        let amps = amps_array.as_array();
+        let delays = delays.as_standard_layout();
+        let amps = amps.as_standard_layout();
+        if delays.nrows() != amps.nrows() {
+            return Err(PyValueError::new_err(format!(
+                "delays_array and amps_array must have the same number of rows; got {} and {}",
+                delays.nrows(),
+                amps.nrows()
+            )));
+        }
 
-        let old_len = jones.len();
-        let new_len = old_len * 4;
-        let new_cap = jones.capacity() * 4;
-        let new_ptr = jones.as_mut_ptr() as *mut c64;
-        // SAFETY: new_cap == old_cap * N, align_of::<C64>() == align_of::<Jones>()
-        let flat = unsafe { Vec::from_raw_parts(new_ptr, new_len, new_cap) };
-        let a2 = Array2::from_shape_vec((old_len, 4), flat).unwrap();
-        Ok(a2.into_pyarray_bound(py))
+        let num_dirs = az_rad.len();
+        let mut jones = vec![Jones::default(); delays.nrows() * freqs_hz.len() * num_dirs];
+        // Legendre values depend only on the zenith angles, so they're shared
+        // by all tiles and frequencies.
+        let legendre = py.allow_threads(|| self.beam.legendre_table(&za_rad));
+        let mut chunks = jones.chunks_exact_mut(num_dirs.max(1));
+        for (tile_delays, tile_amps) in delays.outer_iter().zip(amps.outer_iter()) {
+            for &freq_hz in &freqs_hz {
+                // An empty set of directions has nothing to calculate.
+                let Some(results) = chunks.next() else { break };
+                let tile_delays = tile_delays.as_slice().expect("is contiguous");
+                let tile_amps = tile_amps.as_slice().expect("is contiguous");
+                calc_in_chunks(py, &az_rad, &za_rad, results, |az_rad, za_rad, results| {
+                    self.beam.calc_jones_array_pair_with_legendre(
+                        az_rad,
+                        za_rad,
+                        freq_hz.round() as _,
+                        tile_delays,
+                        tile_amps,
+                        norm_to_zenith,
+                        latitude_rad,
+                        iau_order.unwrap_or(false),
+                        legendre.as_ref(),
+                        results,
+                    )
+                })?;
+            }
+        }
+
+        let mut shape = vec![delays.nrows(), freqs_hz.len()];
+        shape.extend(dir_shape);
+        Ok(jones_to_pyarray(
+            py,
+            jones,
+            &shape,
+            as_matrices.unwrap_or(false),
+        ))
     }
 
     /// Get the available frequencies inside the HDF5 file.
-    fn get_fee_beam_freqs<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u32>> {
+    fn get_beam_freqs<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u32>> {
         self.beam.get_freqs().to_vec().into_pyarray_bound(py)
     }
 
+    /// The same as `get_beam_freqs`; this name is kept for compatibility.
+    fn get_fee_beam_freqs<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u32>> {
+        self.get_beam_freqs(py)
+    }
+
     /// Given a frequency in Hz, get the closest available frequency inside the
     /// HDF5 file.
     #[pyo3(text_signature = "(freq_hz)")]
@@ -162,6 +322,67 @@ impl FEEBeam {
         self.beam.find_closest_freq(freq_hz.round() as _)
     }
 
+    /// Get the frequencies that are defined in more than one of the HDF5 files
+    /// used to make this beam (see `new_multi`), as a list of tuples
+    /// `(freq_hz, used_file, ignored_files)`.
+    fn freq_overlaps(&self) -> Vec<(u32, PathBuf, Vec<PathBuf>)> {
+        self.beam
+            .get_freq_overlaps()
+            .iter()
+            .map(|o| (o.freq_hz, o.used.clone(), o.ignored.clone()))
+            .collect()
+    }
+
+    /// Get the SHA-256 checksum of the HDF5 file (as lowercase hex). This is
+    /// calculated the first time it's asked for.
+    fn sha256(&self) -> PyResult<String> {
+        Ok(self.beam.identity()?.sha256.clone())
+    }
+
+    /// Get a description of the HDF5 file as a dict, with the keys `sha256`,
+    /// `size_bytes`, `path` (None if the beam was read from memory),
+    /// `num_modes`, `freqs` (a list of dicts with keys `freq_hz`, `num_coeffs`
+    /// and `n_max`) and `attributes` (a dict of the file's attributes).
+    fn metadata<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let metadata = self.beam.metadata()?;
+        let id = &metadata.identity;
+        let dict = PyDict::new_bound(py);
+        dict.set_item("sha256", &id.sha256)?;
+        dict.set_item("size_bytes", id.size_bytes)?;
+        dict.set_item("path", &id.path)?;
+        dict.set_item("num_modes", metadata.num_modes)?;
+        let freqs = PyList::empty_bound(py);
+        for f in &metadata.freqs {
+            let freq = PyDict::new_bound(py);
+            freq.set_item("freq_hz", f.freq_hz)?;
+            freq.set_item("num_coeffs", f.num_coeffs)?;
+            freq.set_item("n_max", f.n_max)?;
+            freqs.append(freq)?;
+        }
+        dict.set_item("freqs", freqs)?;
+        dict.set_item("attributes", metadata.attributes.into_py_dict_bound(py))?;
+        Ok(dict)
+    }
+
+    /// Empty the cached dipole coefficients and normalisation Jones matrices to
+    /// recover memory.
+    fn empty_cache(&self) {
+        self.beam.empty_cache()
+    }
+
+    /// Set the number of threads used to calculate beam responses on the CPU.
+    /// 0 (the default) uses a pool of threads shared by all beam objects,
+    /// whose size can be set with the `RAYON_NUM_THREADS` environment
+    /// variable. 1 does all calculations on a single thread, and any other
+    /// number gives the beam its own pool with that many threads. This setting
+    /// isn't pickled.
+    #[pyo3(signature = (num_threads=0))]
+    fn set_num_threads(&mut self, num_threads: usize) -> PyResult<()> {
+        self.beam
+            .set_parallelism(Parallelism::from_num_threads(num_threads))
+            .map_err(thread_pool_err)
+    }
+
     /// Calculate the Jones matrices for multiple directions given a pointing
     /// and multiple frequencies on a GPU.
     ///
@@ -7,12 +7,29 @@
 mod analytic;
 mod fee;
 
+use std::borrow::Cow;
+
+use marlu::{c64, Jones};
+use ndarray::{ArrayD, IxDyn};
+use numpy::{AllowTypeChange, IntoPyArray, PyArrayDyn, PyArrayLikeDyn, PyUntypedArrayMethods};
 use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use pyo3::PyTypeInfo;
 
 use crate::analytic::AnalyticBeamError;
 use crate::fee::{FEEBeamError, InitFEEBeamError};
 
+/// NumPy arrays (or anything that can be converted to one) of directions. If
+/// these are already contiguous float64 arrays, no copies are made.
+type Directions<'py> = PyArrayLikeDyn<'py, f64, AllowTypeChange>;
+type DirSlice<'a> = Cow<'a, [f64]>;
+
+/// The number of directions calculated at a time by the Python interface. The
+/// GIL is re-acquired between chunks to check for signals (e.g. Ctrl-C), so
+/// this shouldn't be too big.
+const DIRECTIONS_PER_CHUNK: usize = 100_000;
+
 /// A Python module interfacing with the hyperbeam code written in Rust. This
 /// module depends on and will import numpy.
 #[pymodule]
@@ -21,25 +38,243 @@ fn mwa_hyperbeam(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<fee::FEEBeam>()?;
     m.add_class::<analytic::AnalyticBeam>()?;
     m.add("HyperbeamError", py.get_type_bound::<HyperbeamError>())?;
+    m.add("BeamFileError", py.get_type_bound::<BeamFileError>())?;
+    m.add(
+        "InvalidDelaysError",
+        py.get_type_bound::<InvalidDelaysError>(),
+    )?;
+    m.add("InvalidAmpsError", py.get_type_bound::<InvalidAmpsError>())?;
+    m.add(
+        "BelowHorizonError",
+        py.get_type_bound::<BelowHorizonError>(),
+    )?;
+    m.add("Hdf5Error", py.get_type_bound::<Hdf5Error>())?;
+    m.add("GpuError", py.get_type_bound::<GpuError>())?;
+    m.add(
+        "InvalidArgumentError",
+        py.get_type_bound::<InvalidArgumentError>(),
+    )?;
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    // How hyperbeam was built.
+    let info = crate::build_info();
+    m.add("build_features", info.features().collect::<Vec<_>>())?;
+    m.add("has_gpu", info.has_gpu())?;
+    m.add("gpu_backend", info.gpu_backend)?;
+    m.add("gpu_float_size", info.gpu_float_size)?;
 
     Ok(())
 }
 
-// Add a python exception for hyperbeam.
+// Add python exceptions for hyperbeam. All of them are subclasses of
+// `HyperbeamError`, and carry the relevant details of the Rust error as
+// attributes.
 create_exception!(mwa_hyperbeam, HyperbeamError, pyo3::exceptions::PyException);
+create_exception!(mwa_hyperbeam, BeamFileError, HyperbeamError);
+create_exception!(mwa_hyperbeam, InvalidDelaysError, HyperbeamError);
+create_exception!(mwa_hyperbeam, InvalidAmpsError, HyperbeamError);
+create_exception!(mwa_hyperbeam, BelowHorizonError, HyperbeamError);
+create_exception!(mwa_hyperbeam, Hdf5Error, HyperbeamError);
+create_exception!(mwa_hyperbeam, GpuError, HyperbeamError);
+create_exception!(mwa_hyperbeam, InvalidArgumentError, HyperbeamError);
+
+/// Create a Python exception of type `T` with the message of `err`, and set
+/// the given attributes on it.
+fn new_err<T: PyTypeInfo>(err: &dyn std::fmt::Display, attrs: &[(&str, &dyn ToPyObject)]) -> PyErr {
+    Python::with_gil(|py| {
+        let py_err = PyErr::new::<T, _>(err.to_string());
+        let value = py_err.value_bound(py);
+        for (name, attr) in attrs {
+            // Setting an attribute on a fresh exception can't fail.
+            value
+                .setattr(*name, attr.to_object(py))
+                .expect("can set exception attribute");
+        }
+        py_err
+    })
+}
+
 impl std::convert::From<FEEBeamError> for PyErr {
     fn from(err: FEEBeamError) -> PyErr {
-        HyperbeamError::new_err(err.to_string())
+        match &err {
+            FEEBeamError::S1S2CountMismatch { expected, got }
+            | FEEBeamError::CoeffCountMismatch { expected, got, .. } => {
+                new_err::<BeamFileError>(&err, &[("expected", expected), ("got", got)])
+            }
+            FEEBeamError::DatasetShape { key, exp } => {
+                new_err::<BeamFileError>(&err, &[("key", key), ("expected", exp)])
+            }
+            FEEBeamError::IncorrectAmpsLength(got) => {
+                new_err::<InvalidAmpsError>(&err, &[("got", got), ("expected", &(16, 32))])
+            }
+            FEEBeamError::IncorrectDelaysLength(got)
+            | FEEBeamError::IncorrectDelaysArrayColLength {
+                num_delays: got, ..
+            } => new_err::<InvalidDelaysError>(&err, &[("got", got), ("expected", &16)]),
+            FEEBeamError::InvalidDipole(dipole) => {
+                new_err::<InvalidArgumentError>(&err, &[("dipole", dipole)])
+            }
+            FEEBeamError::TileCountMismatch { delays, amps } => {
+                new_err::<InvalidArgumentError>(&err, &[("delays", delays), ("amps", amps)])
+            }
+            FEEBeamError::DirectionCountMismatch { az, za } => {
+                new_err::<InvalidArgumentError>(&err, &[("az", az), ("za", za)])
+            }
+            FEEBeamError::SubsetFreqNotInFile(freq_hz) => {
+                new_err::<HyperbeamError>(&err, &[("freq_hz", freq_hz)])
+            }
+            FEEBeamError::SubsetNoFreqs | FEEBeamError::SubsetNoModes => {
+                new_err::<HyperbeamError>(&err, &[])
+            }
+            FEEBeamError::MultipleFiles(_) => new_err::<HyperbeamError>(&err, &[]),
+            FEEBeamError::Io(_) => new_err::<BeamFileError>(&err, &[]),
+            FEEBeamError::Hdf5Error(_) => new_err::<Hdf5Error>(&err, &[]),
+            #[cfg(any(feature = "cuda", feature = "hip"))]
+            FEEBeamError::Gpu(_) => new_err::<GpuError>(&err, &[]),
+        }
     }
 }
 impl std::convert::From<InitFEEBeamError> for PyErr {
     fn from(err: InitFEEBeamError) -> PyErr {
-        HyperbeamError::new_err(err.to_string())
+        match &err {
+            InitFEEBeamError::BeamFileDoesntExist(path) => {
+                new_err::<BeamFileError>(&err, &[("path", path)])
+            }
+            InitFEEBeamError::DipoleCountMismatch { expected, got } => {
+                new_err::<BeamFileError>(&err, &[("expected", expected), ("got", got)])
+            }
+            InitFEEBeamError::BeamFileNotFound(paths) => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                new_err::<BeamFileError>(&err, &[("path", &paths)])
+            }
+            InitFEEBeamError::OverlappingFreqs { freq_hz, files } => {
+                new_err::<BeamFileError>(&err, &[("freq_hz", freq_hz), ("path", files)])
+            }
+            InitFEEBeamError::IncompatibleModes { other, .. } => {
+                new_err::<BeamFileError>(&err, &[("path", other), ("key", &"modes")])
+            }
+            InitFEEBeamError::NoBeamFiles => new_err::<InvalidArgumentError>(&err, &[]),
+            InitFEEBeamError::ModesShape => {
+                new_err::<BeamFileError>(&err, &[("key", &"modes"), ("expected", &3)])
+            }
+            InitFEEBeamError::MissingDipole
+            | InitFEEBeamError::NoDipoles
+            | InitFEEBeamError::NoFreqs
+            | InitFEEBeamError::Parse(_)
+            | InitFEEBeamError::MwaBeamFileVarError(_)
+            | InitFEEBeamError::Io(_) => new_err::<BeamFileError>(&err, &[]),
+            InitFEEBeamError::Hdf5(_) => new_err::<Hdf5Error>(&err, &[]),
+        }
     }
 }
 impl std::convert::From<AnalyticBeamError> for PyErr {
     fn from(err: AnalyticBeamError) -> PyErr {
-        HyperbeamError::new_err(err.to_string())
+        match &err {
+            AnalyticBeamError::IncorrectAmpsLength {
+                got,
+                expected1,
+                expected2,
+            } => new_err::<InvalidAmpsError>(
+                &err,
+                &[("got", got), ("expected", &(*expected1, *expected2))],
+            ),
+            AnalyticBeamError::IncorrectDelaysLength { got, expected } => {
+                new_err::<InvalidDelaysError>(&err, &[("got", got), ("expected", expected)])
+            }
+            #[cfg(any(feature = "cuda", feature = "hip"))]
+            AnalyticBeamError::IncorrectDelaysArrayColLength {
+                num_delays: got,
+                expected,
+                ..
+            } => new_err::<InvalidDelaysError>(&err, &[("got", got), ("expected", expected)]),
+            AnalyticBeamError::BelowHorizon { za } => {
+                new_err::<BelowHorizonError>(&err, &[("za", za)])
+            }
+            AnalyticBeamError::MissingLatitude => new_err::<InvalidArgumentError>(&err, &[]),
+            #[cfg(any(feature = "cuda", feature = "hip"))]
+            AnalyticBeamError::Gpu(_) => new_err::<GpuError>(&err, &[]),
+        }
+    }
+}
+
+/// Convert an error from making a beam's thread pool (see `set_num_threads`)
+/// to a Python exception.
+fn thread_pool_err(err: rayon::ThreadPoolBuildError) -> PyErr {
+    new_err::<HyperbeamError>(&err, &[])
+}
+
+/// Get the data of the az and za arrays as slices, after checking that they
+/// have the same shape. The data are only copied if an array isn't contiguous.
+fn get_directions<'a>(
+    az_rad: &'a Directions,
+    za_rad: &'a Directions,
+) -> PyResult<(DirSlice<'a>, DirSlice<'a>)> {
+    if az_rad.shape() != za_rad.shape() {
+        return Err(PyValueError::new_err(format!(
+            "az_rad and za_rad must have the same shape; got {:?} and {:?}",
+            az_rad.shape(),
+            za_rad.shape()
+        )));
+    }
+    let as_slice = |a: &'a Directions| match a.as_slice() {
+        Ok(s) => Cow::Borrowed(s),
+        Err(_) => Cow::Owned(a.as_array().iter().copied().collect()),
+    };
+    Ok((as_slice(az_rad), as_slice(za_rad)))
+}
+
+/// Calculate Jones matrices for `az_rad` and `za_rad` into `results` with the
+/// GIL released, so that other Python threads can run in the meantime. The
+/// directions are split into chunks and pending signals are checked between
+/// them; if a signal handler raises an exception (e.g. `KeyboardInterrupt`),
+/// the remaining chunks aren't calculated and the exception is returned.
+fn calc_in_chunks<E, F>(
+    py: Python,
+    az_rad: &[f64],
+    za_rad: &[f64],
+    results: &mut [Jones<f64>],
+    calc: F,
+) -> PyResult<()>
+where
+    F: Fn(&[f64], &[f64], &mut [Jones<f64>]) -> Result<(), E> + Sync,
+    E: Send,
+    PyErr: From<E>,
+{
+    for ((az_rad, za_rad), results) in az_rad
+        .chunks(DIRECTIONS_PER_CHUNK)
+        .zip(za_rad.chunks(DIRECTIONS_PER_CHUNK))
+        .zip(results.chunks_mut(DIRECTIONS_PER_CHUNK))
+    {
+        py.allow_threads(|| calc(az_rad, za_rad, results))?;
+        py.check_signals()?;
+    }
+    Ok(())
+}
+
+/// Convert Jones matrices into a NumPy array of shape `(*shape, 4)`, or
+/// `(*shape, 2, 2)` if `as_matrices` is true. `shape` must multiply to the
+/// number of Jones matrices.
+fn jones_to_pyarray<'py>(
+    py: Python<'py>,
+    jones: Vec<Jones<f64>>,
+    shape: &[usize],
+    as_matrices: bool,
+) -> Bound<'py, PyArrayDyn<c64>> {
+    // Use unsafe code to ensure that no useless copying is done!
+    // https://users.rust-lang.org/t/sound-conversion-from-vec-num-complex-complex64-4-to-ndarray-array2-num-complex-complex64-without-copying/78973/2
+    let mut jones = std::mem::ManuallyDrop::new(jones);
+
+    let new_len = jones.len() * 4;
+    let new_cap = jones.capacity() * 4;
+    let new_ptr = jones.as_mut_ptr() as *mut c64;
+    // SAFETY: new_cap == old_cap * N, align_of::<C64>() == align_of::<Jones>()
+    let flat = unsafe { Vec::from_raw_parts(new_ptr, new_len, new_cap) };
+
+    let mut shape = shape.to_vec();
+    if as_matrices {
+        shape.extend([2, 2]);
+    } else {
+        shape.push(4);
     }
+    let a = ArrayD::from_shape_vec(IxDyn(&shape), flat).expect("shape matches the number of Jones");
+    a.into_pyarray_bound(py)
 }
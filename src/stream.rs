@@ -0,0 +1,243 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Calculating beam responses for more directions than fit in memory.
+//!
+//! [`JonesChunks`] takes directions from any iterator (e.g. zipped slices, a
+//! generator or a closure wrapped with [`std::iter::from_fn`]) and yields the
+//! beam responses a chunk at a time, so memory use is bounded by the chunk
+//! size. The beam (and therefore its caches) is shared by all chunks. The
+//! results can be written straight to disk with [`JonesChunks::write_npy`] or
+//! [`JonesChunks::write_raw`].
+
+use std::io::{Seek, Write};
+
+use num_complex::Complex64 as c64;
+use thiserror::Error;
+
+use crate::{
+    beam::{Beam, BeamError, BeamParams},
+    npy::{NpyError, NpyWriter},
+    Jones,
+};
+
+/// An iterator over the beam responses of directions, a chunk at a time. Made
+/// with [`Beam::calc_jones_chunks`].
+pub struct JonesChunks<'a, I: Iterator<Item = (f64, f64)>> {
+    beam: &'a Beam,
+    params: &'a BeamParams<'a>,
+    freq_hz: u32,
+    latitude_rad: f64,
+    directions: I,
+    chunk_size: usize,
+    /// Buffers for the directions of a chunk.
+    az_rad: Vec<f64>,
+    za_rad: Vec<f64>,
+}
+
+impl Beam {
+    /// Calculate beam responses for the (azimuth, zenith angle) directions of
+    /// `directions` (in radians), `chunk_size` directions at a time (except
+    /// for the last chunk, which may be smaller). Directions are only taken
+    /// from `directions` when the next chunk is needed. See
+    /// [`Beam::calc_jones_array_pair`] for the meaning of the other arguments.
+    pub fn calc_jones_chunks<'a, I: IntoIterator<Item = (f64, f64)>>(
+        &'a self,
+        directions: I,
+        freq_hz: u32,
+        params: &'a BeamParams<'a>,
+        latitude_rad: f64,
+        chunk_size: usize,
+    ) -> JonesChunks<'a, I::IntoIter> {
+        let chunk_size = chunk_size.max(1);
+        JonesChunks {
+            beam: self,
+            params,
+            freq_hz,
+            latitude_rad,
+            directions: directions.into_iter(),
+            chunk_size,
+            az_rad: Vec::with_capacity(chunk_size),
+            za_rad: Vec::with_capacity(chunk_size),
+        }
+    }
+}
+
+impl<I: Iterator<Item = (f64, f64)>> Iterator for JonesChunks<'_, I> {
+    type Item = Result<Vec<Jones<f64>>, BeamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.az_rad.clear();
+        self.za_rad.clear();
+        for (az, za) in self.directions.by_ref().take(self.chunk_size) {
+            self.az_rad.push(az);
+            self.za_rad.push(za);
+        }
+        if self.az_rad.is_empty() {
+            return None;
+        }
+        Some(self.beam.calc_jones_array_pair(
+            &self.az_rad,
+            &self.za_rad,
+            self.freq_hz,
+            self.params,
+            self.latitude_rad,
+        ))
+    }
+}
+
+impl<I: Iterator<Item = (f64, f64)>> JonesChunks<'_, I> {
+    /// Calculate all of the beam responses and write them to a `.npy` file as
+    /// an array of complex `f64` with shape `[num_directions, 4]`. Only one
+    /// chunk is held in memory at a time. The number of directions is
+    /// returned.
+    pub fn write_npy<W: Write + Seek>(self, writer: W) -> Result<usize, StreamError> {
+        let mut writer = NpyWriter::<c64, W>::new(writer, &[4])?;
+        let mut buffer = vec![];
+        let num_dirs = self.write_with(|jones| {
+            buffer.clear();
+            buffer.extend(jones.iter().flat_map(|j| j.iter().copied()));
+            writer.write(&buffer)
+        })?;
+        writer.finish()?;
+        Ok(num_dirs)
+    }
+
+    /// Calculate all of the beam responses and write them as raw
+    /// little-endian complex `f64` values (4 per direction, i.e. the same
+    /// layout as the data of [`JonesChunks::write_npy`]). Only one chunk is
+    /// held in memory at a time. The number of directions is returned.
+    pub fn write_raw<W: Write>(self, mut writer: W) -> Result<usize, StreamError> {
+        let mut bytes = vec![];
+        let num_dirs = self.write_with(|jones| {
+            bytes.clear();
+            for c in jones.iter().flat_map(|j| j.iter()) {
+                bytes.extend_from_slice(&c.re.to_le_bytes());
+                bytes.extend_from_slice(&c.im.to_le_bytes());
+            }
+            writer.write_all(&bytes)
+        })?;
+        writer.flush()?;
+        Ok(num_dirs)
+    }
+
+    /// Pass every chunk to `write`, returning the number of directions.
+    fn write_with<E>(
+        self,
+        mut write: impl FnMut(&[Jones<f64>]) -> Result<(), E>,
+    ) -> Result<usize, StreamError>
+    where
+        StreamError: From<E>,
+    {
+        let mut num_dirs = 0;
+        for jones in self {
+            let jones = jones?;
+            write(&jones)?;
+            num_dirs += jones.len();
+        }
+        Ok(num_dirs)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum StreamError {
+    #[error(transparent)]
+    Beam(#[from] BeamError),
+
+    #[error(transparent)]
+    Npy(#[from] NpyError),
+
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use marlu::constants::MWA_LAT_RAD;
+
+    use super::*;
+    use crate::analytic::AnalyticBeam;
+
+    fn params() -> BeamParams<'static> {
+        BeamParams {
+            delays: &[0; 16],
+            amps: &[1.0; 16],
+            norm_to_zenith: true,
+            parallactic: false,
+            iau_order: false,
+        }
+    }
+
+    fn directions() -> (Vec<f64>, Vec<f64>) {
+        let az = (0..1000).map(|i| i as f64 * 0.01).collect();
+        let za = (0..1000).map(|i| i as f64 * 0.0015).collect();
+        (az, za)
+    }
+
+    #[test]
+    fn test_chunks_match_array() {
+        let beam = Beam::from(AnalyticBeam::new());
+        let params = params();
+        let (az, za) = directions();
+        let expected = beam
+            .calc_jones_array_pair(&az, &za, 180_000_000, &params, MWA_LAT_RAD)
+            .unwrap();
+
+        let directions = az.iter().copied().zip(za.iter().copied());
+        let chunks = beam
+            .calc_jones_chunks(directions, 180_000_000, &params, MWA_LAT_RAD, 300)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            chunks.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            [300, 300, 300, 100]
+        );
+        assert_eq!(chunks.concat(), expected);
+    }
+
+    #[test]
+    fn test_chunk_sinks() {
+        let beam = Beam::from(AnalyticBeam::new());
+        let params = params();
+        let (az, za) = directions();
+        let expected = beam
+            .calc_jones_array_pair(&az, &za, 180_000_000, &params, MWA_LAT_RAD)
+            .unwrap();
+
+        // Directions from a callback.
+        let mut i = 0;
+        let directions = std::iter::from_fn(|| {
+            i += 1;
+            (i <= az.len()).then(|| (az[i - 1], za[i - 1]))
+        });
+        let mut npy = std::io::Cursor::new(vec![]);
+        let num_dirs = beam
+            .calc_jones_chunks(directions, 180_000_000, &params, MWA_LAT_RAD, 128)
+            .write_npy(&mut npy)
+            .unwrap();
+        assert_eq!(num_dirs, 1000);
+        let npy = npy.into_inner();
+        let header_len = usize::from(u16::from_le_bytes([npy[8], npy[9]]));
+        let header = std::str::from_utf8(&npy[10..10 + header_len]).unwrap();
+        assert!(header.contains("'descr': '<c16'"), "{header}");
+        assert!(header.contains("'shape': (1000, 4)"), "{header}");
+
+        let mut raw = vec![];
+        let num_dirs = beam
+            .calc_jones_chunks(
+                az.iter().copied().zip(za.iter().copied()),
+                180_000_000,
+                &params,
+                MWA_LAT_RAD,
+                128,
+            )
+            .write_raw(&mut raw)
+            .unwrap();
+        assert_eq!(num_dirs, 1000);
+        assert_eq!(raw.len(), 1000 * 4 * 16);
+        assert_eq!(&npy[10 + header_len..], raw.as_slice());
+        let first = f64::from_le_bytes(raw[..8].try_into().unwrap());
+        assert_eq!(first, expected[0][0].re);
+    }
+}
@@ -9,9 +9,12 @@ use std::{
     hash::{Hash, Hasher},
 };
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) enum Pol {
+/// A dipole polarisation of an MWA bowtie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pol {
+    /// The East-West dipole.
     X,
+    /// The North-South dipole.
     Y,
 }
 
@@ -37,10 +40,12 @@ impl CacheKey {
     ///
     /// It hashes the input parameters for a unique hash. If these parameters
     /// are re-used, the same hash will be generated, and we can use the cache
-    /// that these [`CacheKey`]s guard.
-    pub(crate) fn new(freq: u32, delays: &[u32; 16], amps: &[f64; 32]) -> Self {
+    /// that these [`CacheKey`]s guard. Truncated coefficients (see
+    /// `FEEBeam::set_mode_truncation`) are kept apart from untruncated ones.
+    pub(crate) fn new(freq: u32, delays: &[u32; 16], amps: &[f64; 32], truncated: bool) -> Self {
         let mut hasher = DefaultHasher::new();
         freq.hash(&mut hasher);
+        truncated.hash(&mut hasher);
         delays.hash(&mut hasher);
         // We can't hash f64 values, but we can hash their bits.
         for amp in amps {
@@ -92,47 +97,47 @@ mod tests {
     #[test]
     fn same() {
         let s1 = settings_1();
-        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2).0;
+        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2, false).0;
 
         let s2 = settings_1();
-        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2).0;
+        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2, false).0;
         assert_eq!(hash1, hash2);
     }
 
     #[test]
     fn different1() {
         let s1 = settings_1();
-        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2).0;
+        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2, false).0;
 
         let s2 = settings_2();
-        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2).0;
+        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2, false).0;
         assert_ne!(hash1, hash2);
     }
 
     #[test]
     fn different2() {
         let s1 = settings_1();
-        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2).0;
+        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2, false).0;
 
         let s2 = settings_3();
-        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2).0;
+        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2, false).0;
         assert_ne!(hash1, hash2);
     }
 
     #[test]
     fn different3() {
         let s1 = settings_2();
-        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2).0;
+        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2, false).0;
 
         let s2 = settings_3();
-        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2).0;
+        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2, false).0;
         assert_ne!(hash1, hash2);
     }
 
     #[test]
     fn different4() {
         let s1 = settings_1();
-        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2).0;
+        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2, false).0;
 
         // This situation is a little unrealistic; when the settings are being
         // used for the FEE beam, the frequency will be "rounded" to the nearest
@@ -142,17 +147,25 @@ mod tests {
         // (which is what happens in the real code), we expect a difference.
         let mut s2 = settings_1();
         s2.0 += 1;
-        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2).0;
+        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2, false).0;
         assert_ne!(hash1, hash2);
     }
 
     #[test]
     fn different5() {
         let s1 = settings_3();
-        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2).0;
+        let hash1 = CacheKey::new(s1.0, &s1.1, &s1.2, false).0;
 
         let s2 = settings_4();
-        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2).0;
+        let hash2 = CacheKey::new(s2.0, &s2.1, &s2.2, false).0;
+        assert_ne!(hash1, hash2);
+    }
+
+    #[test]
+    fn different_truncation() {
+        let s = settings_1();
+        let hash1 = CacheKey::new(s.0, &s.1, &s.2, false).0;
+        let hash2 = CacheKey::new(s.0, &s.1, &s.2, true).0;
         assert_ne!(hash1, hash2);
     }
 }